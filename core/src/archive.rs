@@ -0,0 +1,138 @@
+//! Codegen units of the archives replaced by the CI-integrated object files, and
+//! patching of the archives.
+
+use std::fmt::Write;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use tracing::debug;
+
+use crate::llvm::{LlvmToolchain, LlvmUtility};
+use crate::paths::PathExt;
+use crate::CIResult;
+
+/// Magic string of the thin archives.
+const THIN_MAGIC: &[u8; 8] = b"!<thin>\n";
+
+/// Codegen unit of a crate, a member of its rlib or of a static library.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CodegenUnit {
+    /// Name of the member in the archive.
+    pub member: String,
+    /// Object file of the member saved by `rustc -C save-temps`.
+    pub object: PathBuf,
+    /// CI-integrated object file replacing the member, if integrated.
+    pub ci_object: PathBuf,
+}
+
+impl CodegenUnit {
+    /// Returns true if the codegen unit is integrated.
+    pub fn integrated(&self) -> bool {
+        self.ci_object.is_file()
+    }
+}
+
+/// Returns true if the member is the object file of a codegen unit not integrated yet,
+/// e.g. `demo-hash.demo.hash-cgu.0.rcgu.o` but not `demo-hash.demo.hash-cgu.0.rcgu-ci.o`.
+pub fn is_codegen_unit(member: &str) -> bool {
+    member.ends_with(".rcgu.o")
+}
+
+/// Gets the path to the file of a member.
+///
+/// Members of thin archives are stored as paths relative to the archive, while
+/// members of regular archives are the object files saved next to the archive
+/// by `rustc -C save-temps`.
+pub fn member_path<P: AsRef<Path>>(path: P, member: &str) -> CIResult<PathBuf> {
+    let member = Path::new(member);
+    if member.is_absolute() {
+        return Ok(member.to_path_buf());
+    }
+    Ok(path.parent()?.join(member))
+}
+
+/// Gets every codegen unit in the members of the archive, as a crate compiled with
+/// several codegen units has one member for each of them.
+pub fn codegen_units<P: AsRef<Path>>(path: P, members: &[String]) -> CIResult<Vec<CodegenUnit>> {
+    let path = path.as_ref();
+    members
+        .iter()
+        .filter(|member| is_codegen_unit(member))
+        .map(|member| {
+            let object = member_path(path, member)?;
+            let ci_object = object.append_suffix("ci")?;
+            Ok(CodegenUnit {
+                member: member.clone(),
+                object,
+                ci_object,
+            })
+        })
+        .collect()
+}
+
+/// Returns true if the archive is a thin archive, which references its members
+/// by path instead of containing them.
+pub fn is_thin<P: AsRef<Path>>(path: P) -> CIResult<bool> {
+    let path = path.as_ref();
+    let mut magic = [0; 8];
+    File::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .with_context(|| format!("failed to read archive `{}`", path.display()))?;
+    Ok(&magic == THIN_MAGIC)
+}
+
+/// Lists the member names of the archive.
+pub fn members<P: AsRef<Path>>(toolchain: &LlvmToolchain, path: P) -> CIResult<Vec<String>> {
+    let output = LlvmUtility::Archiver
+        .process_builder(toolchain)
+        .arg("t")
+        .arg(path.as_ref())
+        .exec_with_output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    Ok(stdout.lines().map(str::to_string).collect())
+}
+
+/// Writes a copy of the archive with the members replaced by the files in a single pass.
+///
+/// The copy is written through an MRI script of `llvm-ar` to a temporary file next to
+/// the output, so that members of a thin archive stay relative to the same directory,
+/// and renamed to the output when complete. The symbol table is regenerated on save.
+pub fn replace_members<P: AsRef<Path>>(
+    toolchain: &LlvmToolchain,
+    path: P,
+    output: P,
+    replacements: &[(String, PathBuf)],
+) -> CIResult<()> {
+    let path = path.as_ref();
+    let output = output.as_ref();
+    let tmp = output.with_extension(format!("{}.tmp", std::process::id()));
+
+    let mut script = String::new();
+    let create = if is_thin(path)? {
+        "CREATETHIN"
+    } else {
+        "CREATE"
+    };
+    let _ = writeln!(script, "{} {}", create, tmp.display());
+    let _ = writeln!(script, "ADDLIB {}", path.display());
+    for (member, _) in replacements {
+        let _ = writeln!(script, "DELETE {}", member);
+    }
+    for (_, file) in replacements {
+        let _ = writeln!(script, "ADDMOD {}", file.display());
+    }
+    script.push_str("SAVE\nEND\n");
+    debug!(%script);
+
+    LlvmUtility::Archiver
+        .process_builder(toolchain)
+        .arg("-M")
+        .stdin(script)
+        .exec_with_output()?;
+    fs::rename(&tmp, output)
+        .with_context(|| format!("failed to write archive `{}`", output.display()))?;
+
+    Ok(())
+}
@@ -0,0 +1,250 @@
+//! Content-addressed cache of the CI-integrated object files.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use anyhow::{bail, Context};
+use cargo_util::paths;
+use tracing::{debug, info, warn};
+
+use crate::config::{self, Config};
+use crate::error::CIError;
+use crate::remote_cache::RemoteCache;
+use crate::CIResult;
+
+/// Default maximum size of the cache in bytes.
+pub const DEFAULT_CACHE_MAX_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+
+/// Default zstd compression level of the cache entries.
+pub const DEFAULT_CACHE_COMPRESSION_LEVEL: i32 = 3;
+
+/// Cache of the CI-integrated object files.
+#[derive(Debug)]
+pub struct Cache {
+    /// Directory of the cache entries.
+    dir: PathBuf,
+    /// Maximum size of the cache in bytes.
+    max_size: u64,
+    /// zstd compression level of the entries.
+    compression_level: i32,
+    /// Remote cache shared between machines.
+    remote: Option<RemoteCache>,
+    /// Digests of the files in the cache keys, computed once per build.
+    digests: Mutex<HashMap<PathBuf, String>>,
+}
+
+/// Statistics of the cache.
+#[derive(Copy, Clone, Debug)]
+pub struct CacheStats {
+    /// Number of entries.
+    pub entries: usize,
+    /// Total size of the entries in bytes.
+    pub size: u64,
+}
+
+impl Cache {
+    /// Opens the cache given the configuration.
+    pub fn open(config: &Config) -> CIResult<Self> {
+        let dir = match &config.cache_dir {
+            Some(dir) => dir.clone(),
+            None => Config::dir()?.join("cache"),
+        };
+        paths::create_dir_all(&dir)?;
+        let max_size = config.cache_max_size.unwrap_or(DEFAULT_CACHE_MAX_SIZE);
+        let compression_level = config
+            .cache_compression_level
+            .unwrap_or(DEFAULT_CACHE_COMPRESSION_LEVEL);
+        let remote = match &config.cache_remote {
+            Some(remote) => Some(RemoteCache::new(config::parse_cache_remote(remote)?)),
+            None => None,
+        };
+        debug!(?dir, max_size, compression_level, ?remote);
+        Ok(Self {
+            dir,
+            max_size,
+            compression_level,
+            remote,
+            digests: Mutex::default(),
+        })
+    }
+
+    /// Gets the directory of the cache.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Computes the cache key of an LLVM IR file and everything that affects its output.
+    pub fn key<P: AsRef<Path>>(ir_file: P, inputs: &[&str]) -> CIResult<String> {
+        let ir_file = ir_file.as_ref();
+        let mut context = md5::Context::new();
+        // stream the file through the hasher as LLVM IR files can be hundreds of MB
+        File::open(ir_file)
+            .and_then(|mut file| io::copy(&mut file, &mut context))
+            .with_context(|| format!("failed to read `{}`", ir_file.display()))?;
+        for input in inputs {
+            // separate the inputs so that ["ab", "c"] and ["a", "bc"] are different
+            context.consume([0]);
+            context.consume(input.as_bytes());
+        }
+        Ok(format!("{:x}", context.compute()))
+    }
+
+    /// Computes the digest of a file affecting the output, e.g. of a pass plugin, or an
+    /// empty string if missing.
+    pub fn digest(&self, path: &Path) -> String {
+        let mut digests = self.digests.lock().expect("failed to acquire lock");
+        digests
+            .entry(path.to_path_buf())
+            .or_insert_with(|| Self::key(path, &[]).unwrap_or_default())
+            .clone()
+    }
+
+    /// Path to the entry of the key, relative to the directory of the cache.
+    fn entry_path(key: &str) -> String {
+        format!("{}/{}.o.zst", &key[..2], key)
+    }
+
+    /// Path to the entry of the key.
+    fn entry(&self, key: &str) -> PathBuf {
+        self.dir.join(Self::entry_path(key))
+    }
+
+    /// Decompresses the cached object file to the destination if exists, downloading it
+    /// from the remote cache if only there.
+    pub fn get<P: AsRef<Path>>(&self, key: &str, dest: P) -> CIResult<bool> {
+        let entry = self.entry(key);
+        let dest = dest.as_ref();
+        if !entry.is_file() {
+            return self.get_remote(key, dest);
+        }
+        let reader = File::open(&entry)
+            .with_context(|| format!("failed to open cache entry `{}`", entry.display()))?;
+        let writer =
+            File::create(dest).with_context(|| format!("failed to create `{}`", dest.display()))?;
+        if let Err(error) = zstd::stream::copy_decode(reader, writer) {
+            // the next build integrates the crate again
+            let _ = fs::remove_file(&entry);
+            bail!(CIError::CacheCorrupted(entry, error.into()));
+        }
+        // bump the modification time for the least-recently-used eviction
+        let _ = filetime::set_file_mtime(&entry, filetime::FileTime::now());
+        Ok(true)
+    }
+
+    /// Decompresses the object file of the remote cache to the destination if exists,
+    /// keeping the entry in the local cache.
+    fn get_remote(&self, key: &str, dest: &Path) -> CIResult<bool> {
+        let data = match self
+            .remote
+            .as_ref()
+            .and_then(|r| r.get(&Self::entry_path(key)))
+        {
+            Some(data) => data,
+            None => return Ok(false),
+        };
+        let writer =
+            File::create(dest).with_context(|| format!("failed to create `{}`", dest.display()))?;
+        if let Err(error) = zstd::stream::copy_decode(data.as_slice(), writer) {
+            // another machine may replace the entry, integrate the crate meanwhile
+            warn!("corrupted remote cache entry `{}`: {}", key, error);
+            return Ok(false);
+        }
+        info!("remote cache hit: {}", key);
+        if let Err(error) = self.store(key, |writer| writer.write_all(&data)) {
+            warn!(
+                "failed to keep the remote cache entry `{}`: {:#}",
+                key, error
+            );
+        }
+        Ok(true)
+    }
+
+    /// Stores the object file compressed as the entry of the key, uploading it to the
+    /// remote cache.
+    pub fn put<P: AsRef<Path>>(&self, key: &str, src: P) -> CIResult<()> {
+        let src = src.as_ref();
+        let reader =
+            File::open(src).with_context(|| format!("failed to open `{}`", src.display()))?;
+        let entry = self
+            .store(key, |writer| {
+                zstd::stream::copy_encode(reader, writer, self.compression_level)
+            })
+            .with_context(|| format!("failed to compress `{}`", src.display()))?;
+        if let Some(remote) = &self.remote {
+            remote.put(&Self::entry_path(key), &paths::read_bytes(&entry)?);
+        }
+        Ok(())
+    }
+
+    /// Stores the entry of the key written by the closure, returning its path.
+    fn store<F>(&self, key: &str, write: F) -> CIResult<PathBuf>
+    where
+        F: FnOnce(&mut File) -> io::Result<()>,
+    {
+        let entry = self.entry(key);
+        paths::create_dir_all(entry.parent().context("invalid cache entry")?)?;
+        // write to a temporary file so that concurrent builds never see a partial entry
+        let tmp = entry.with_extension(format!("{}.tmp", std::process::id()));
+        let mut writer =
+            File::create(&tmp).with_context(|| format!("failed to create `{}`", tmp.display()))?;
+        write(&mut writer)?;
+        fs::rename(&tmp, &entry)
+            .with_context(|| format!("failed to store cache entry `{}`", entry.display()))?;
+        Ok(entry)
+    }
+
+    /// Lists the entries of the cache with their size and modification time.
+    fn entries(&self) -> Vec<(PathBuf, u64, SystemTime)> {
+        walkdir::WalkDir::new(&self.dir)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| {
+                let metadata = e.metadata().ok()?;
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                Some((e.into_path(), metadata.len(), modified))
+            })
+            .collect()
+    }
+
+    /// Gets the statistics of the cache.
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries();
+        CacheStats {
+            entries: entries.len(),
+            size: entries.iter().map(|(_, size, _)| size).sum(),
+        }
+    }
+
+    /// Evicts the least-recently-used entries until the cache fits in its maximum size.
+    pub fn evict(&self) -> CIResult<()> {
+        let mut entries = self.entries();
+        let mut size = entries.iter().map(|(_, size, _)| size).sum::<u64>();
+        if size <= self.max_size {
+            return Ok(());
+        }
+
+        entries.sort_by_key(|(_, _, modified)| *modified);
+        for (path, entry_size, _) in entries {
+            if size <= self.max_size {
+                break;
+            }
+            debug!("evicting cache entry: {}", path.display());
+            paths::remove_file(&path)?;
+            size -= entry_size;
+        }
+        info!("cache size after eviction: {}", size);
+
+        Ok(())
+    }
+
+    /// Removes all entries of the local cache, keeping the ones of the remote cache.
+    pub fn clear(&self) -> CIResult<()> {
+        paths::remove_dir_all(&self.dir)?;
+        paths::create_dir_all(&self.dir)
+    }
+}
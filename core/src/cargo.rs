@@ -0,0 +1,678 @@
+//! Parsers of the `cargo` and `rustc` logs for post-processing the build.
+//!
+//! `cargo build` reports neither the linker invocations nor the output files of the
+//! compilation units, so they are parsed from the debug logs enabled with
+//! [`LINKER_LOG`] in `RUSTC_LOG` and [`compilation_files_log`] in `CARGO_LOG`.
+//!
+//! The formats of the logs changed across the versions of the toolchain:
+//!
+//! * `cargo` logged with `env_logger`, e.g. `[<time> DEBUG <target>] <message>`, before
+//!   moving to `tracing`, e.g. `<elapsed>s DEBUG <target>: <message>`.
+//! * `cargo` renamed the log target of the output files from `context` to
+//!   `build_runner` in 1.78.
+//! * older `cargo` had `Linkable { rmeta: bool }` instead of `Linkable` and `Rmeta`.
+//! * newer `rustc` prints the environment variables of the linker before the program,
+//!   e.g. `LC_ALL="C" "cc" "-m64" ...`.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use cargo_util::ProcessBuilder;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::paths::PathExt;
+use crate::{pipeline, CIResult};
+
+/// `RUSTC_LOG` directive to log the linker invocations.
+pub const LINKER_LOG: &str = "rustc_codegen_ssa::back::link=info";
+
+/// Log target of `rustc` for the linker invocations.
+const LINKER_TARGET: &str = "rustc_codegen_ssa::back::link";
+
+/// Message of `cargo` preceding the output files of a compilation unit.
+const OUTPUT_FILES_MESSAGE: &str = "Target filenames: ";
+
+/// Gets the version of `cargo` in the directory.
+pub fn cargo_version(dir: &Path) -> CIResult<Version> {
+    let mut cmd = ProcessBuilder::new("cargo");
+    cmd.cwd(dir);
+    cmd.arg("--version");
+    let output = cmd.exec_with_output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    // cargo 1.64.0 (387270bc7 2022-09-16)
+    let version = stdout
+        .split_ascii_whitespace()
+        .nth(1)
+        .context("failed to parse the version of `cargo`")?;
+    Ok(Version::parse(version)?)
+}
+
+/// Gets the log target of `cargo` for the output files of the compilation units.
+pub fn compilation_files_target(version: &Version) -> &'static str {
+    // pre-releases of 1.78 already have the new name
+    if (version.major, version.minor) >= (1, 78) {
+        "cargo::core::compiler::build_runner::compilation_files"
+    } else {
+        "cargo::core::compiler::context::compilation_files"
+    }
+}
+
+/// Gets the `CARGO_LOG` directive to log the output files of the compilation units.
+pub fn compilation_files_log(version: &Version) -> String {
+    format!("{}=debug", compilation_files_target(version))
+}
+
+/// Returns true if the line is a log of the linker invocations or the output files,
+/// rather than a diagnostic for the user.
+pub fn is_log_line(line: &str) -> bool {
+    line.contains(LINKER_TARGET) || line.contains(OUTPUT_FILES_MESSAGE)
+}
+
+/// Linker invocation.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Linker {
+    /// Linker program name.
+    pub program: String,
+    /// Environment variables set for the linker.
+    pub env: Vec<(String, String)>,
+    /// Arguments for the linker.
+    pub args: LinkerArgs,
+}
+
+/// Arguments of a linker invocation.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct LinkerArgs {
+    /// List of input files.
+    pub input_files: Vec<String>,
+    /// Path to the output file.
+    pub output_file: String,
+    /// List of rlib files.
+    pub rlib_files: Vec<String>,
+    /// List of directories for library search.
+    pub library_dirs: Vec<String>,
+    /// Other flags.
+    pub flags: Vec<String>,
+    /// Kinds of the arguments in the order of the invocation.
+    pub order: Vec<ArgKind>,
+    /// Flavor of the linker, whose syntax the arguments are built with.
+    pub flavor: LinkerFlavor,
+}
+
+/// Flavor of a linker, after the syntax of its arguments.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum LinkerFlavor {
+    /// `cc`, `ld` and `ld.lld`, e.g. `-o <file>` and `-L <dir>`.
+    #[default]
+    Gnu,
+    /// `link.exe` of MSVC and `lld-link`, e.g. `/OUT:<file>` and `/LIBPATH:<dir>`.
+    Msvc,
+}
+
+impl LinkerFlavor {
+    /// Gets the arguments of the output file.
+    fn output_file(self, file: String) -> Vec<String> {
+        match self {
+            LinkerFlavor::Gnu => vec!["-o".to_string(), file],
+            LinkerFlavor::Msvc => vec![format!("/OUT:{}", file)],
+        }
+    }
+
+    /// Gets the arguments of a library directory.
+    fn library_dir(self, dir: String) -> Vec<String> {
+        match self {
+            LinkerFlavor::Gnu => vec!["-L".to_string(), dir],
+            LinkerFlavor::Msvc => vec![format!("/LIBPATH:{}", dir)],
+        }
+    }
+}
+
+/// Kind of a linker argument.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArgKind {
+    /// Input file.
+    InputFile,
+    /// `-o` and the output file, or `/OUT:` with it.
+    OutputFile,
+    /// rlib file.
+    RlibFile,
+    /// `-L` and the library directory, or `/LIBPATH:` with it.
+    LibraryDir,
+    /// Other flag.
+    Flag,
+}
+
+impl LinkerArgs {
+    /// Adds an input file before the rlib files, e.g. an object file of another
+    /// language, so that the startup objects stay at the end.
+    pub fn add_input_file(&mut self, file: String) {
+        if self.order.is_empty() {
+            self.input_files.push(file);
+            return;
+        }
+        let pos = self
+            .order
+            .iter()
+            .position(|kind| *kind == ArgKind::RlibFile)
+            .unwrap_or(self.order.len());
+        let idx = self.order[..pos]
+            .iter()
+            .filter(|kind| **kind == ArgKind::InputFile)
+            .count();
+        self.input_files.insert(idx, file);
+        self.order.insert(pos, ArgKind::InputFile);
+    }
+
+    /// Build a complete linker arguments.
+    ///
+    /// The arguments keep the order of the invocation, which matters to static
+    /// linking, e.g. the startup objects `crtend.o` and `crtn.o` of musl come last.
+    /// Arguments without an order are added by kind.
+    pub fn build(self) -> Vec<String> {
+        let LinkerArgs {
+            input_files,
+            output_file,
+            rlib_files,
+            library_dirs,
+            flags,
+            order,
+            flavor,
+        } = self;
+        let mut input_files = input_files.into_iter();
+        let mut output_file = Some(output_file);
+        let mut rlib_files = rlib_files.into_iter();
+        let mut library_dirs = library_dirs.into_iter();
+        let mut flags = flags.into_iter();
+
+        let mut all = Vec::new();
+        for kind in order {
+            match kind {
+                ArgKind::InputFile => all.extend(input_files.next()),
+                ArgKind::OutputFile => {
+                    if let Some(output_file) = output_file.take() {
+                        all.extend(flavor.output_file(output_file));
+                    }
+                }
+                ArgKind::RlibFile => all.extend(rlib_files.next()),
+                ArgKind::LibraryDir => {
+                    if let Some(dir) = library_dirs.next() {
+                        all.extend(flavor.library_dir(dir));
+                    }
+                }
+                ArgKind::Flag => all.extend(flags.next()),
+            }
+        }
+
+        all.extend(input_files);
+        if let Some(output_file) = output_file {
+            all.extend(flavor.output_file(output_file));
+        }
+        all.extend(rlib_files);
+        for dir in library_dirs {
+            all.extend(flavor.library_dir(dir));
+        }
+        all.extend(flags);
+        all
+    }
+}
+
+impl Linker {
+    /// Parses the linker invocation from a log line of `rustc`, returning `None` if the
+    /// line is not an invocation linking a Rust binary.
+    pub fn parse(line: &str) -> CIResult<Option<Linker>> {
+        // the standard library is always linked into a binary, skipping the other logs
+        // of the target such as `preparing Executable to ...` and `linker stderr:`
+        let line = match line.split_once(LINKER_TARGET) {
+            Some((_, line)) if line.contains("libcompiler_builtins") => line,
+            _ => return Ok(None),
+        };
+        // skip the rest of the target, e.g. `::link_natively:`
+        let line = line.trim_start_matches(|c: char| !c.is_whitespace());
+
+        let mut program = None;
+        let mut env = Vec::new();
+        let mut tokens = Vec::new();
+        for token in tokenize(line)? {
+            match token {
+                // environment variables only precede the program
+                Token::Env(key, value) if program.is_none() => env.push((key, value)),
+                Token::Quoted(arg) if program.is_none() => program = Some(arg),
+                Token::Quoted(arg) => tokens.push(arg),
+                Token::Env(..) => bail!("unexpected environment variable after the program"),
+            }
+        }
+        let program = program.context("missing linker program name")?;
+        let args = LinkerArgs::parse(tokens)?;
+
+        Ok(Some(Linker { program, env, args }))
+    }
+}
+
+impl LinkerArgs {
+    /// Parses the arguments given to the linker by `rustc`.
+    ///
+    /// The arguments are of `link.exe` if the output file is given by `/OUT:`, as
+    /// `rustc` does for the MSVC targets, and of `cc` otherwise.
+    pub fn parse<I: IntoIterator<Item = String>>(tokens: I) -> CIResult<LinkerArgs> {
+        let tokens = tokens.into_iter().collect::<Vec<_>>();
+        let mut args = LinkerArgs {
+            flavor: if tokens.iter().any(|arg| msvc_arg(arg, "OUT").is_some()) {
+                LinkerFlavor::Msvc
+            } else {
+                LinkerFlavor::Gnu
+            },
+            ..LinkerArgs::default()
+        };
+        let msvc = args.flavor == LinkerFlavor::Msvc;
+        let mut iter = tokens.into_iter();
+        while let Some(arg) = iter.next() {
+            let kind = if let Some(file) = msvc_arg(&arg, "OUT").filter(|_| msvc) {
+                args.output_file = file.to_string();
+                ArgKind::OutputFile
+            } else if let Some(dir) = msvc_arg(&arg, "LIBPATH").filter(|_| msvc) {
+                args.library_dirs.push(dir.to_string());
+                ArgKind::LibraryDir
+            } else if arg == "-o" && !msvc {
+                args.output_file = iter.next().context("missing output file")?;
+                ArgKind::OutputFile
+            } else if arg == "-L" && !msvc {
+                args.library_dirs
+                    .push(iter.next().context("missing library dir")?);
+                ArgKind::LibraryDir
+            } else if is_file(&arg, args.flavor) {
+                if Path::new(&arg).extension().unwrap_or_default() == "rlib" {
+                    args.rlib_files.push(arg);
+                    ArgKind::RlibFile
+                } else {
+                    args.input_files.push(arg);
+                    ArgKind::InputFile
+                }
+            } else {
+                args.flags.push(arg);
+                ArgKind::Flag
+            };
+            args.order.push(kind);
+        }
+        if args.output_file.is_empty() {
+            bail!("missing output file");
+        }
+
+        Ok(args)
+    }
+}
+
+/// Token of a linker invocation logged by `rustc`.
+enum Token {
+    /// `"<arg>"`
+    Quoted(String),
+    /// `<key>="<value>"`
+    Env(String, String),
+}
+
+/// Splits the linker invocation into the quoted tokens, unescaping them.
+fn tokenize(line: &str) -> CIResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|&c| c != '"' && !c.is_whitespace()) {
+            key.push(c);
+        }
+        if chars.next() != Some('"') {
+            // not an invocation, e.g. a message of the log target
+            bail!("unexpected unquoted token `{}`", key);
+        }
+
+        let mut value = String::new();
+        loop {
+            match chars.next() {
+                Some('"') => break,
+                Some('\\') => value.push(chars.next().context("unterminated escape")?),
+                Some(c) => value.push(c),
+                None => bail!("unterminated quoted token"),
+            }
+        }
+
+        match key.strip_suffix('=') {
+            Some(key) => tokens.push(Token::Env(key.to_string(), value)),
+            None if key.is_empty() => tokens.push(Token::Quoted(value)),
+            None => bail!("unexpected unquoted token `{}`", key),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Returns true if the argument of the linker is a file.
+///
+/// Files of the temporary directory of `rustc`, e.g. `symbols.o`, are already
+/// removed after the build, so the paths are also recognized by their separator.
+fn is_file(arg: &str, flavor: LinkerFlavor) -> bool {
+    match flavor {
+        LinkerFlavor::Gnu => {
+            !arg.starts_with('-')
+                && (arg.contains(std::path::MAIN_SEPARATOR) || Path::new(arg).is_file())
+        }
+        // the options of `link.exe` start with `/` as the absolute paths of the hosts
+        // cross-linking with `lld-link` do, but their names have no separator, e.g.
+        // `/NATVIS:C:\rust\etc\intrinsic.natvis`
+        LinkerFlavor::Msvc => {
+            let option = arg
+                .strip_prefix('/')
+                .is_some_and(|option| !option.split(':').next().unwrap_or_default().contains('/'));
+            !arg.starts_with('-')
+                && !option
+                && (arg.contains(['/', '\\']) || Path::new(arg).is_file())
+        }
+    }
+}
+
+/// Gets the value of an option of `link.exe`, e.g. the file of `/OUT:<file>`.
+///
+/// The options are case-insensitive and may start with `-` as well.
+fn msvc_arg<'a>(arg: &'a str, name: &str) -> Option<&'a str> {
+    let option = arg.strip_prefix(['/', '-'])?;
+    let (option, value) = option.split_once(':')?;
+    option.eq_ignore_ascii_case(name).then_some(value)
+}
+
+/// Parses the linker invocations from the log lines, ignoring the other lines.
+pub fn parse_linkers<S: AsRef<str>>(lines: &[S]) -> CIResult<Vec<Linker>> {
+    debug!("parsing linkers");
+    let mut linkers = Vec::new();
+    for line in lines {
+        if let Some(linker) = Linker::parse(line.as_ref())? {
+            linkers.push(linker);
+        }
+    }
+    debug!(?linkers);
+    Ok(linkers)
+}
+
+/// Information of a file flavor.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FileFlavor {
+    /// Not a special file type.
+    Normal,
+    /// Like `Normal`, but not directly executable.
+    /// For example, a `.wasm` file paired with the "normal" `.js` file.
+    Auxiliary,
+    /// Something you can link against (e.g., a library).
+    Linkable,
+    /// An `.rmeta` Rust metadata file.
+    Rmeta,
+    /// Piece of external debug information (e.g., `.dSYM`/`.pdb` file).
+    DebugInfo,
+}
+
+/// Member of the workspace.
+#[derive(Clone, Debug)]
+pub struct Package {
+    /// Name of the package.
+    pub name: String,
+    /// Version of the package.
+    pub version: String,
+    /// Directory of the manifest of the package.
+    pub manifest_dir: PathBuf,
+    /// Crate names of the targets, e.g. `demo` of the binary `demo` or `my_tool` of
+    /// the binary `my-tool`.
+    pub targets: HashSet<String>,
+    /// Crate names of the binary targets.
+    pub binaries: HashSet<String>,
+}
+
+impl Package {
+    /// Returns true if the output file, e.g. `deps/demo-0123456789abcdef` or the
+    /// CI-integrated `deps/demo-0123456789abcdef-ci`, is a target of the package.
+    pub fn owns<P: AsRef<Path>>(&self, output_file: P) -> bool {
+        pipeline::crate_name(output_file).is_ok_and(|crate_name| self.targets.contains(&crate_name))
+    }
+}
+
+/// Gets the members of the workspace from the output of `cargo metadata`.
+pub fn packages(metadata: &serde_json::Value) -> Vec<Package> {
+    // crate names of the targets, of the given kind if any
+    let targets = |member: &serde_json::Value, kind: Option<&str>| {
+        member["targets"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|target| {
+                kind.is_none_or(|kind| {
+                    target["kind"]
+                        .as_array()
+                        .is_some_and(|kinds| kinds.iter().any(|k| k == kind))
+                })
+            })
+            .filter_map(|target| target["name"].as_str())
+            .map(|name| name.replace('-', "_"))
+            .collect()
+    };
+    metadata["packages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|member| Package {
+            name: member["name"].as_str().unwrap_or_default().to_string(),
+            version: member["version"].as_str().unwrap_or_default().to_string(),
+            manifest_dir: member["manifest_path"]
+                .as_str()
+                .and_then(|path| Path::new(path).parent())
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            targets: targets(member, None),
+            binaries: targets(member, Some("bin")),
+        })
+        .collect()
+}
+
+/// Information of an output file.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct OutputFile {
+    /// Absolute path to the file that will be produced by the build process.
+    pub path: PathBuf,
+    /// If it should be linked into `target`, and what it should be called
+    /// (e.g., without metadata).
+    pub hardlink: Option<PathBuf>,
+    /// If `--out-dir` is specified, the absolute path to the exported file.
+    pub export_path: Option<PathBuf>,
+    /// Type of the file (library / debug symbol / else).
+    pub flavor: FileFlavor,
+}
+
+impl OutputFile {
+    /// Parses the output files from a log line of `cargo`, returning `None` if the
+    /// line does not report output files.
+    pub fn parse(line: &str) -> CIResult<Option<Vec<OutputFile>>> {
+        let line = match line.split_once(OUTPUT_FILES_MESSAGE) {
+            Some((_, line)) => line,
+            None => return Ok(None),
+        };
+        // the `Debug` output of the structs is close enough to RON
+        let line = line
+            .trim()
+            .replace("Linkable { rmeta: true }", "Rmeta")
+            .replace("Linkable { rmeta: false }", "Linkable")
+            .replace('{', "(")
+            .replace('}', ")");
+        let output_files = ron::from_str(&line)
+            .with_context(|| format!("failed to parse the output files `{}`", line))?;
+        Ok(Some(output_files))
+    }
+}
+
+/// Parses the output files from the log lines, ignoring the other lines.
+pub fn parse_output_files<S: AsRef<str>>(lines: &[S]) -> CIResult<Vec<OutputFile>> {
+    debug!("parsing output files");
+    let mut output_files = Vec::new();
+    for line in lines {
+        if let Some(mut files) = OutputFile::parse(line.as_ref())? {
+            output_files.append(&mut files);
+        }
+    }
+    debug!(?output_files);
+    Ok(output_files)
+}
+
+/// Kinds of the targets only run on the host, i.e. the build scripts and the procedural
+/// macros, which are not integrated.
+const HOST_ONLY_KINDS: [&str; 2] = ["custom-build", "proc-macro"];
+
+/// Gets the compilation units of the targets only run on the host, e.g.
+/// `build_script_build-0123456789abcdef`, from the `compiler-artifact` messages of
+/// `cargo`.
+///
+/// The targets are classified by their kind in the messages rather than by the layout
+/// of the target directory or their name, and the files of the messages, e.g. the
+/// hardlink `build-script-build`, are matched against the output files of the units.
+pub fn host_only_units(
+    messages: &[serde_json::Value],
+    output_files: &[OutputFile],
+) -> HashSet<String> {
+    let filenames = messages
+        .iter()
+        .filter(|message| message["reason"] == "compiler-artifact")
+        .filter(|message| {
+            message["target"]["kind"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .any(|kind| HOST_ONLY_KINDS.iter().any(|host_only| kind == host_only))
+        })
+        .flat_map(|message| message["filenames"].as_array().into_iter().flatten())
+        .filter_map(serde_json::Value::as_str)
+        .map(PathBuf::from)
+        .collect::<HashSet<_>>();
+
+    let mut units = HashSet::new();
+    for file in output_files {
+        let reported = [
+            Some(&file.path),
+            file.hardlink.as_ref(),
+            file.export_path.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|path| filenames.contains(path));
+        if reported {
+            if let Ok(unit) = file.path.unit_name() {
+                units.insert(unit);
+            }
+        }
+    }
+    debug!(?units);
+    units
+}
+
+/// Gets the `compiler-artifact` message of a CI-integrated binary, e.g.
+/// `target/debug/demo-ci`, from the one of its original binary `target/debug/demo` in
+/// the messages, with the paths of the CI-integrated binary. Returns `None` if the
+/// original binary has no message.
+pub fn ci_artifact(
+    messages: &[serde_json::Value],
+    binary: &Path,
+) -> CIResult<Option<serde_json::Value>> {
+    let file_stem = PathExt::file_stem(&binary)?;
+    let file_stem = file_stem.strip_suffix("-ci").unwrap_or(&file_stem);
+    let original = binary.with_file_name(match PathExt::extension(&binary) {
+        Ok(extension) => format!("{}.{}", file_stem, extension),
+        Err(_) => file_stem.to_string(),
+    });
+    let artifact = messages.iter().find(|message| {
+        message["executable"].as_str().map(Path::new) == Some(&original)
+            || message["filenames"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .any(|filename| filename.as_str().map(Path::new) == Some(&original))
+    });
+    let Some(artifact) = artifact else {
+        return Ok(None);
+    };
+
+    let mut artifact = artifact.clone();
+    let binary = binary.to_string()?;
+    artifact["executable"] = binary.clone().into();
+    artifact["filenames"] = vec![binary].into();
+    artifact["fresh"] = false.into();
+    Ok(Some(artifact))
+}
+
+/// Gets the extension of the LLVM IR files emitted by `rustc`, `bc` for LLVM bitcode.
+pub fn llvm_ir_extension(bitcode: bool) -> &'static str {
+    if bitcode {
+        "bc"
+    } else {
+        "ll"
+    }
+}
+
+/// Gets the target directory of the build, e.g. `target/debug`, or `target/<profile>` of
+/// a custom profile, from the output files, leaving out the units only run on the host.
+pub fn target_dir(
+    output_files: &[OutputFile],
+    host_only_units: &HashSet<String>,
+) -> CIResult<PathBuf> {
+    debug!("parsing target directory");
+
+    let mut target_dirs = Vec::new();
+    let mut targets = Vec::new();
+    let mut modes = Vec::new();
+    for file in output_files {
+        if let Some(hardlink) = &file.hardlink {
+            debug!(?hardlink);
+
+            if host_only_units.contains(&file.path.unit_name()?) {
+                continue;
+            }
+            let mut dir = hardlink.parent()?;
+            if dir.file_name()? == "examples" {
+                dir = dir.parent()?;
+            }
+            modes.push(dir.file_name()?);
+            dir = dir.parent()?;
+            if dir.file_name()? == "target" {
+                target_dirs.push(dir);
+            } else {
+                targets.push(dir.file_name()?);
+                target_dirs.push(dir.parent()?);
+            }
+        }
+    }
+
+    let target_dir = target_dirs
+        .first()
+        .context("failed to parse target directory")?;
+    let mode = &modes[0];
+    let target = targets.first();
+    debug!(?target_dir);
+    debug!(?mode);
+    debug!(?target);
+
+    // sanity check
+    for dir in &target_dirs {
+        if dir.file_name()? != target_dir.file_name()? {
+            bail!("failed to parse target directory");
+        }
+    }
+
+    if !modes.iter().all(|e| e == mode) {
+        bail!("failed to parse target directory");
+    }
+
+    if let Some(target) = target {
+        if !targets.iter().all(|e| e == target) {
+            bail!("failed to parse target directory");
+        }
+
+        Ok(target_dir.join(target).join(mode))
+    } else {
+        Ok(target_dir.join(mode))
+    }
+}
@@ -0,0 +1,738 @@
+//! Handles configuration for the Compiler Interrupts library.
+
+use anyhow::{bail, Context};
+use cargo_util::paths;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use crate::llvm::{LlvmToolchain, PassManager};
+use crate::paths::PathExt;
+use crate::plugin::{self, PassPlugin};
+use crate::CIResult;
+
+/// Configuration for the Compiler Interrupts library.
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct Config {
+    /// Path to the library.
+    pub library_path: PathBuf,
+    /// Path to the debug-enabled library.
+    pub library_debug_path: PathBuf,
+    /// Arguments for the library.
+    pub library_args: Vec<String>,
+    /// Symbol names of the functions excluded from the probes.
+    pub skip_functions: Vec<String>,
+    /// LLVM version used to compile the library.
+    pub llvm_version: String,
+    /// Directory of the LLVM utilities chosen with `--llvm-version`, or `None` to find
+    /// them in `PATH`.
+    pub llvm_bin_dir: Option<PathBuf>,
+    /// Checksum of the source code.
+    pub checksum: String,
+    /// Remote URL for the source code, or for its git repository.
+    pub url: String,
+    /// SHA-256 digest of the source code, if verified by a checksum or a signature.
+    pub sha256: Option<String>,
+    /// URL to the detached signature of the source code, verified with `gpg`.
+    pub signature_url: Option<String>,
+    /// URL to the assets of the release the prebuilt libraries are downloaded from, if
+    /// installed with `--release-tag`.
+    pub release_url: Option<String>,
+    /// Path to the vendored source code installed offline, or `None` for the default
+    /// `CompilerInterrupt.cpp` in the configuration directory.
+    pub vendored_source: Option<PathBuf>,
+    /// Shell command to run after each build.
+    pub notify_command: Option<String>,
+    /// URL to post the build summary to after each build.
+    pub notify_url: Option<String>,
+    /// Log file the logs of each command are appended to, relative to the configuration
+    /// directory.
+    pub log_file: Option<PathBuf>,
+    /// Directory of the cache of the CI-integrated object files.
+    pub cache_dir: Option<PathBuf>,
+    /// Maximum size of the cache in bytes.
+    pub cache_max_size: Option<u64>,
+    /// zstd compression level of the cache entries.
+    pub cache_compression_level: Option<i32>,
+    /// Remote cache of the CI-integrated object files shared between machines, a
+    /// directory, an HTTP endpoint, or an S3 bucket.
+    pub cache_remote: Option<String>,
+    /// Optimization level of `llc`, overriding the one of the cargo profile.
+    pub llc_opt_level: Option<String>,
+    /// Code model of `llc`, overriding the one of the target.
+    pub llc_code_model: Option<String>,
+    /// CPU of `llc`, e.g. `native` or `neoverse-n1`.
+    pub llc_cpu: Option<String>,
+    /// Pass manager of `opt`, overriding the default one of the LLVM version.
+    pub pass_manager: Option<PassManager>,
+    /// Shell command to run before the integration starts.
+    pub pre_integration_hook: Option<String>,
+    /// Shell command to run after each crate is integrated, before `llc`.
+    pub post_crate_hook: Option<String>,
+    /// Shell command to run after each CI-integrated binary is linked.
+    pub post_link_hook: Option<String>,
+    /// Backend of the integration.
+    pub backend: Backend,
+    /// Other LLVM pass plugins run after Compiler Interrupts.
+    pub plugins: Vec<PassPlugin>,
+    /// Git checkout of the source code if installed from the git repository.
+    pub git: Option<GitSource>,
+    /// Arguments for the library added to the library arguments of each crate, by the
+    /// name of the crate.
+    pub crate_args: BTreeMap<String, Vec<String>>,
+    /// Libraries compiled for each LLVM major version, including the one in use,
+    /// serialized last as a table of tables.
+    pub libraries: BTreeMap<String, Library>,
+    /// Crates to skip the integration, set by the project.
+    #[serde(skip)]
+    pub skip_crates: Vec<String>,
+    /// Whether to enable the debugging mode, set by the project.
+    #[serde(skip)]
+    pub debug: bool,
+}
+
+/// Configuration of a project in `[package.metadata.compiler-interrupts]` of its
+/// `Cargo.toml`, overriding the global configuration.
+#[derive(Deserialize, Default, PartialEq, Eq, Debug)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ProjectConfig {
+    /// Arguments for the library.
+    pub library_args: Option<Vec<String>>,
+    /// Crates to skip the integration.
+    pub skip: Option<Vec<String>>,
+    /// Functions excluded from the probes.
+    pub skip_fn: Option<Vec<String>>,
+    /// Whether to enable the debugging mode.
+    pub debug: Option<bool>,
+}
+
+impl ProjectConfig {
+    /// Parses the configuration from the content of `Cargo.toml`, returning `None`
+    /// if the manifest has no such section.
+    pub fn parse(manifest: &str) -> CIResult<Option<Self>> {
+        let manifest = toml::from_str::<toml::Value>(manifest)?;
+        manifest
+            .get("package")
+            .and_then(|package| package.get("metadata"))
+            .and_then(|metadata| metadata.get("compiler-interrupts"))
+            .map(|config| config.clone().try_into())
+            .transpose()
+            .context("invalid `[package.metadata.compiler-interrupts]`")
+    }
+
+    /// Sets the crates to skip in the content of `Cargo.toml`, keeping its formatting.
+    ///
+    /// The crates are written to `skip` of `[package.metadata.compiler-interrupts]`, or
+    /// removed from it if there are none.
+    pub fn set_skip(manifest: &str, skip: &[String]) -> CIResult<String> {
+        let mut document = manifest.parse::<toml_edit::Document>()?;
+        let package = document
+            .get_mut("package")
+            .and_then(toml_edit::Item::as_table_like_mut)
+            .context("no `[package]` to write the configuration to, e.g. a virtual manifest")?;
+        let metadata = package
+            .entry("metadata")
+            .or_insert_with(|| {
+                // only `[package.metadata.compiler-interrupts]` is written
+                let mut table = toml_edit::Table::new();
+                table.set_implicit(true);
+                toml_edit::Item::Table(table)
+            })
+            .as_table_like_mut()
+            .context("invalid `[package.metadata]`")?;
+        let config = metadata
+            .entry("compiler-interrupts")
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+            .context("invalid `[package.metadata.compiler-interrupts]`")?;
+        if skip.is_empty() {
+            config.remove("skip");
+        } else {
+            config.insert(
+                "skip",
+                toml_edit::value(skip.iter().collect::<toml_edit::Array>()),
+            );
+        }
+        Ok(document.to_string())
+    }
+
+    /// Saves the crates to skip to `Cargo.toml`.
+    pub fn save_skip<P: AsRef<Path>>(manifest_path: P, skip: &[String]) -> CIResult<()> {
+        let manifest_path = manifest_path.as_ref();
+        let manifest = paths::read(manifest_path)?;
+        let manifest = Self::set_skip(&manifest, skip)
+            .with_context(|| format!("failed to edit `{}`", manifest_path.display()))?;
+        paths::write(manifest_path, manifest)
+    }
+
+    /// Loads the configuration from `Cargo.toml` if any.
+    pub fn load<P: AsRef<Path>>(manifest_path: P) -> CIResult<Option<Self>> {
+        let manifest_path = manifest_path.as_ref();
+        if !manifest_path.is_file() {
+            return Ok(None);
+        }
+        let manifest = paths::read(manifest_path)?;
+        Self::parse(&manifest)
+            .with_context(|| format!("failed to parse `{}`", manifest_path.display()))
+    }
+}
+
+/// Library compiled for an LLVM version.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+#[serde(default)]
+pub struct Library {
+    /// Path to the library.
+    pub library_path: PathBuf,
+    /// Path to the debug-enabled library.
+    pub library_debug_path: PathBuf,
+    /// LLVM version used to compile the library.
+    pub llvm_version: String,
+    /// Directory of the LLVM utilities used to compile the library, if chosen.
+    pub llvm_bin_dir: Option<PathBuf>,
+    /// Checksum of the source code.
+    pub checksum: String,
+}
+
+/// Gets the major version of the LLVM version, e.g. `14` for `14.0.6`.
+fn major_version(version: &str) -> Option<String> {
+    version
+        .split('.')
+        .next()
+        .filter(|major| !major.is_empty())
+        .map(String::from)
+}
+
+/// Sets an argument of an LLVM pass in the arguments, replacing the one of the same
+/// name, e.g. `-inst-gran` of `-inst-gran=2`, as an option of `opt` can only be given
+/// once.
+fn set_arg(args: &mut Vec<String>, arg: String) {
+    let name = arg.split('=').next().unwrap_or_default().to_string();
+    match args
+        .iter_mut()
+        .find(|existing| existing.split('=').next() == Some(name.as_str()))
+    {
+        Some(existing) => *existing = arg,
+        None => args.push(arg),
+    }
+}
+
+/// Parses the arguments of a crate, e.g. `serde=-inst-gran=3 -commit-intv=1000`, into
+/// the name of the crate and its space-delimited arguments.
+pub fn parse_crate_args(s: &str) -> CIResult<(String, Vec<String>)> {
+    let (crate_name, args) = s
+        .split_once('=')
+        .with_context(|| format!("invalid `{}`, expected `CRATE=ARGS`", s))?;
+    let crate_name = crate_name.trim();
+    if crate_name.is_empty() || crate_name.starts_with('-') {
+        bail!("invalid `{}`, missing the name of the crate", s);
+    }
+    let args = args.split_ascii_whitespace().map(String::from).collect();
+    Ok((crate_name.replace('-', "_"), args))
+}
+
+/// Parses a size in bytes with an optional `K`, `M`, `G`, or `T` binary suffix.
+pub fn parse_size(s: &str) -> CIResult<u64> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => s.split_at(idx),
+        None => (s, ""),
+    };
+    let shift = match unit
+        .trim()
+        .to_ascii_uppercase()
+        .trim_end_matches("IB")
+        .trim_end_matches('B')
+    {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => bail!("invalid size unit `{}`", unit),
+    };
+    let number = number
+        .parse::<u64>()
+        .with_context(|| format!("invalid size `{}`", s))?;
+    number
+        .checked_mul(1 << shift)
+        .with_context(|| format!("size is too large `{}`", s))
+}
+
+/// Remote cache of the CI-integrated object files.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CacheRemote {
+    /// Directory shared between the machines, e.g. on a network file system.
+    Dir(PathBuf),
+    /// HTTP endpoint getting and putting the entries under its URL.
+    Http(String),
+    /// S3 bucket storing the entries under the prefix.
+    S3 {
+        /// Name of the bucket.
+        bucket: String,
+        /// Prefix of the keys of the entries, without the trailing `/`.
+        prefix: String,
+    },
+}
+
+/// Parses the remote cache from a path, an `http(s)://` URL, or an `s3://bucket/prefix` URL.
+pub fn parse_cache_remote(s: &str) -> CIResult<CacheRemote> {
+    let s = s.trim();
+    if s.is_empty() {
+        bail!("empty remote cache");
+    }
+    let (scheme, rest) = match s.split_once("://") {
+        Some((scheme, rest)) => (scheme.to_ascii_lowercase(), rest),
+        None => return Ok(CacheRemote::Dir(PathBuf::from(s))),
+    };
+    match scheme.as_str() {
+        "file" => Ok(CacheRemote::Dir(PathBuf::from(rest))),
+        "http" | "https" => {
+            if rest.trim_matches('/').is_empty() {
+                bail!("invalid remote cache `{}`, expected a host", s);
+            }
+            Ok(CacheRemote::Http(s.trim_end_matches('/').to_string()))
+        }
+        "s3" => {
+            let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+            if bucket.is_empty() {
+                bail!("invalid remote cache `{}`, expected a bucket", s);
+            }
+            Ok(CacheRemote::S3 {
+                bucket: bucket.to_string(),
+                prefix: prefix.trim_matches('/').to_string(),
+            })
+        }
+        _ => bail!(
+            "unsupported remote cache `{}`, expected a path, an `http(s)://` URL, \
+            or an `s3://` URL",
+            s
+        ),
+    }
+}
+
+/// Kind of the value of a configuration key.
+#[derive(Clone, Copy, Debug)]
+enum KeyKind {
+    /// Any string, or a path.
+    String,
+    /// Space-delimited strings.
+    List,
+    /// Integer.
+    Integer,
+    /// Size in bytes with an optional binary suffix.
+    Size,
+    /// Version with a major, a minor, and a patch number.
+    Version,
+    /// SHA-256 digest in hexadecimal.
+    Digest,
+    /// One of the values.
+    OneOf(&'static [&'static str]),
+}
+
+/// Default log file of `--log-file` in the configuration directory.
+pub const DEFAULT_LOG_FILE: &str = "log/cargo-ci.log";
+
+/// Keys of the configuration editable with `cargo-lib-ci config --get`, `--set`, and
+/// `--unset`, named like in the configuration file.
+///
+/// The checksum, the libraries of the LLVM versions, the release, the git checkout, and
+/// the plugins are written by the installation and are not editable. The arguments of the crates
+/// are edited with `cargo-lib-ci config --crate-args`.
+const KEYS: &[(&str, KeyKind)] = &[
+    ("library_path", KeyKind::String),
+    ("library_debug_path", KeyKind::String),
+    ("library_args", KeyKind::List),
+    ("skip_functions", KeyKind::List),
+    ("llvm_version", KeyKind::Version),
+    ("llvm_bin_dir", KeyKind::String),
+    ("url", KeyKind::String),
+    ("sha256", KeyKind::Digest),
+    ("signature_url", KeyKind::String),
+    ("vendored_source", KeyKind::String),
+    ("notify_command", KeyKind::String),
+    ("notify_url", KeyKind::String),
+    ("log_file", KeyKind::String),
+    ("cache_dir", KeyKind::String),
+    ("cache_max_size", KeyKind::Size),
+    ("cache_compression_level", KeyKind::Integer),
+    ("cache_remote", KeyKind::String),
+    ("llc_opt_level", KeyKind::OneOf(&["0", "1", "2", "3"])),
+    (
+        "llc_code_model",
+        KeyKind::OneOf(&["tiny", "small", "kernel", "medium", "large"]),
+    ),
+    ("llc_cpu", KeyKind::String),
+    ("pass_manager", KeyKind::OneOf(&["legacy", "new"])),
+    ("pre_integration_hook", KeyKind::String),
+    ("post_crate_hook", KeyKind::String),
+    ("post_link_hook", KeyKind::String),
+    (
+        "backend",
+        KeyKind::OneOf(&["save-temps", "driver", "capture"]),
+    ),
+];
+
+/// Finds the editable key, accepting `-` for `_`.
+fn find_key(key: &str) -> CIResult<(&'static str, KeyKind)> {
+    let key = key.trim().replace('-', "_");
+    match KEYS.iter().find(|(name, _)| *name == key) {
+        Some(&entry) => Ok(entry),
+        None => bail!(
+            "unknown config key `{}`, expected one of: {}",
+            key,
+            Config::keys().collect::<Vec<_>>().join(", ")
+        ),
+    }
+}
+
+/// Git checkout of the source code of the library.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+#[serde(default)]
+pub struct GitSource {
+    /// Branch or tag checked out, or the default branch if none.
+    pub reference: Option<String>,
+    /// Revision pinned instead of a branch or a tag.
+    pub rev: Option<String>,
+    /// Commit the library is built from.
+    pub commit: String,
+}
+
+/// Backend of the integration.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// Integrate the LLVM IR saved by `rustc -C save-temps` with `opt` and relink
+    /// the binaries from the linker invocations of the build.
+    #[default]
+    SaveTemps,
+    /// Run the pass in the codegen of `rustc` loading the library as a pass plugin.
+    Driver,
+    /// Integrate the LLVM IR files and relink the binaries recorded by the wrapper of
+    /// `rustc` set as the linker.
+    Capture,
+}
+
+impl Backend {
+    /// Gets the name of the backend.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Backend::SaveTemps => "save-temps",
+            Backend::Driver => "driver",
+            Backend::Capture => "capture",
+        }
+    }
+}
+
+impl Config {
+    /// Loads the global configuration, merged with the configuration of the project
+    /// in `Cargo.toml` of the current directory.
+    pub fn load() -> CIResult<Self> {
+        let mut config = Self::load_global()?;
+        if let Some(project) = ProjectConfig::load("Cargo.toml")? {
+            debug!(?project);
+            config.merge(project);
+        }
+        Ok(config)
+    }
+
+    /// Loads the global configuration.
+    pub fn load_global() -> CIResult<Self> {
+        let default = Self::default();
+        let mut path = Config::dir()?;
+        path.push("default.cfg");
+        let file = match paths::read(&path) {
+            Ok(file) => file,
+            Err(error) => {
+                Self::save(&default).context("failed to save default config")?;
+
+                warn!("config file not found, use default config");
+                debug!(?error);
+
+                return Ok(default);
+            }
+        };
+        match toml::from_str(&file) {
+            Ok(config) => Ok(config),
+            Err(error) => {
+                let old_path = path.append_suffix("old")?;
+                paths::copy(&path, &old_path).context("failed to backup the old config")?;
+                Self::save(&default)?;
+
+                warn!("found incompatible config file, replaced with default config");
+                warn!("old config file can be found at: {}", old_path.display());
+                debug!(?error);
+
+                Self::load_global()
+            }
+        }
+    }
+
+    /// Overrides the configuration with the one of the project.
+    pub fn merge(&mut self, project: ProjectConfig) {
+        if let Some(library_args) = project.library_args {
+            self.library_args = library_args;
+        }
+        if let Some(skip_crates) = project.skip {
+            self.skip_crates = skip_crates;
+        }
+        if let Some(skip_functions) = project.skip_fn {
+            self.skip_functions = skip_functions;
+        }
+        if let Some(debug) = project.debug {
+            self.debug = debug;
+        }
+    }
+
+    /// Gets the library in use.
+    pub fn library(&self) -> Library {
+        Library {
+            library_path: self.library_path.clone(),
+            library_debug_path: self.library_debug_path.clone(),
+            llvm_version: self.llvm_version.clone(),
+            llvm_bin_dir: self.llvm_bin_dir.clone(),
+            checksum: self.checksum.clone(),
+        }
+    }
+
+    /// Uses the library and keeps it as the one of its LLVM major version.
+    pub fn add_library(&mut self, library: Library) {
+        // the library in use of a configuration saved before is kept as well
+        if self.library_path.is_file() {
+            if let Some(major) = major_version(&self.llvm_version) {
+                let active = self.library();
+                self.libraries.entry(major).or_insert(active);
+            }
+        }
+        if let Some(major) = major_version(&library.llvm_version) {
+            self.libraries.insert(major, library.clone());
+        }
+        self.library_path = library.library_path;
+        self.library_debug_path = library.library_debug_path;
+        self.llvm_version = library.llvm_version;
+        self.llvm_bin_dir = library.llvm_bin_dir;
+        self.checksum = library.checksum;
+    }
+
+    /// Uses the library of the LLVM major version if installed, returning whether it
+    /// is installed.
+    pub fn select_library(&mut self, major: u64) -> bool {
+        let major = major.to_string();
+        if self.library_path.is_file() && major_version(&self.llvm_version) == Some(major.clone()) {
+            return true;
+        }
+        match self.libraries.get(&major) {
+            Some(library) if library.library_path.is_file() => {
+                let library = library.clone();
+                self.add_library(library);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Sets an argument of the library, e.g. `-inst-gran=2`, replacing its value if the
+    /// library arguments already have it.
+    pub fn set_library_arg<T: std::fmt::Display>(&mut self, name: &str, value: T) {
+        set_arg(&mut self.library_args, format!("{}={}", name, value));
+    }
+
+    /// Gets the arguments for the library added to the library arguments of the crate.
+    ///
+    /// The names of the crates match with either `-` or `_`, e.g. `serde_json` and
+    /// `serde-json`.
+    pub fn crate_args(&self, crate_name: &str) -> &[String] {
+        let crate_name = crate_name.replace('-', "_");
+        self.crate_args
+            .iter()
+            .find(|(name, _)| name.replace('-', "_") == crate_name)
+            .map_or(&[], |(_, args)| args.as_slice())
+    }
+
+    /// Gets the arguments for the Compiler Interrupts pass, followed by the functions
+    /// excluded from the probes.
+    pub fn pass_args(&self) -> Vec<String> {
+        self.with_skip_functions(self.library_args.clone())
+    }
+
+    /// Gets the arguments for the Compiler Interrupts pass on the crate, whose own
+    /// arguments replace the library arguments of the same name, e.g. `-inst-gran=1`
+    /// replacing `-inst-gran=2`, followed by the functions excluded from the probes.
+    pub fn crate_pass_args(&self, crate_name: &str) -> Vec<String> {
+        let mut args = self.library_args.clone();
+        for arg in self.crate_args(crate_name) {
+            set_arg(&mut args, arg.clone());
+        }
+        self.with_skip_functions(args)
+    }
+
+    /// Appends the arguments excluding the functions from the probes.
+    fn with_skip_functions(&self, mut args: Vec<String>) -> Vec<String> {
+        args.extend(
+            self.skip_functions
+                .iter()
+                .map(|function| format!("{}={}", plugin::CI_SKIP_FN_ARG, function)),
+        );
+        args
+    }
+
+    /// Gets the pass plugins to run in order, starting with Compiler Interrupts.
+    pub fn pass_plugins(&self) -> Vec<PassPlugin> {
+        self.pass_plugins_with(self.pass_args())
+    }
+
+    /// Gets the pass plugins to run in order on the crate, starting with Compiler
+    /// Interrupts with the arguments of the crate.
+    pub fn crate_pass_plugins(&self, crate_name: &str) -> Vec<PassPlugin> {
+        self.pass_plugins_with(self.crate_pass_args(crate_name))
+    }
+
+    /// Gets the pass plugins, starting with Compiler Interrupts with the arguments.
+    fn pass_plugins_with(&self, args: Vec<String>) -> Vec<PassPlugin> {
+        let mut plugins = vec![PassPlugin::compiler_interrupts(self, args)];
+        plugins.extend(self.plugins.iter().cloned());
+        plugins
+    }
+
+    /// Gets the pass manager of `opt` to load the pass plugins with.
+    pub fn pass_manager(&self, toolchain: &LlvmToolchain) -> PassManager {
+        self.pass_manager
+            .unwrap_or_else(|| toolchain.pass_manager())
+    }
+
+    /// Gets the keys editable with [`Config::get`], [`Config::set`], and
+    /// [`Config::unset`].
+    pub fn keys() -> impl Iterator<Item = &'static str> {
+        KEYS.iter().map(|(name, _)| *name)
+    }
+
+    /// Gets the value of the key, or `None` if it is unset.
+    ///
+    /// Lists are space-delimited like the arguments of `cargo-lib-ci config`.
+    pub fn get(&self, key: &str) -> CIResult<Option<String>> {
+        let (key, _) = find_key(key)?;
+        let table = self.to_table()?;
+        let value = match table.get(key) {
+            Some(toml::Value::String(s)) => s.clone(),
+            Some(toml::Value::Array(values)) => values
+                .iter()
+                .map(|value| {
+                    value
+                        .as_str()
+                        .map_or_else(|| value.to_string(), String::from)
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+            Some(value) => value.to_string(),
+            None => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+
+    /// Sets the key to the value, validated against the type of its field.
+    pub fn set(&mut self, key: &str, value: &str) -> CIResult<()> {
+        let (key, kind) = find_key(key)?;
+        let value = value.trim();
+        if value.is_empty() && !matches!(kind, KeyKind::List) {
+            bail!("empty value for config key `{}`, unset it instead", key);
+        }
+        let value = match kind {
+            KeyKind::String => toml::Value::String(value.to_string()),
+            KeyKind::List => toml::Value::Array(
+                value
+                    .split_whitespace()
+                    .map(|s| toml::Value::String(s.to_string()))
+                    .collect(),
+            ),
+            KeyKind::Integer => toml::Value::Integer(
+                value
+                    .parse()
+                    .with_context(|| format!("invalid integer `{}` for `{}`", value, key))?,
+            ),
+            KeyKind::Size => {
+                let size = parse_size(value)?;
+                toml::Value::Integer(
+                    i64::try_from(size)
+                        .with_context(|| format!("size is too large `{}`", value))?,
+                )
+            }
+            KeyKind::Version => {
+                semver::Version::parse(value)
+                    .with_context(|| format!("invalid version `{}` for `{}`", value, key))?;
+                toml::Value::String(value.to_string())
+            }
+            KeyKind::Digest => {
+                if value.len() != 64 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+                    bail!("invalid SHA-256 digest `{}` for `{}`", value, key);
+                }
+                toml::Value::String(value.to_ascii_lowercase())
+            }
+            KeyKind::OneOf(values) => {
+                if !values.contains(&value) {
+                    bail!(
+                        "invalid value `{}` for `{}`, expected one of: {}",
+                        value,
+                        key,
+                        values.join(", ")
+                    );
+                }
+                toml::Value::String(value.to_string())
+            }
+        };
+        let mut table = self.to_table()?;
+        table.insert(key.to_string(), value);
+        self.replace_with(table)
+            .with_context(|| format!("invalid value for config key `{}`", key))
+    }
+
+    /// Unsets the key, restoring the default value of its field.
+    pub fn unset(&mut self, key: &str) -> CIResult<()> {
+        let (key, _) = find_key(key)?;
+        let mut table = self.to_table()?;
+        table.remove(key);
+        self.replace_with(table)
+    }
+
+    /// Serializes the configuration as a table.
+    fn to_table(&self) -> CIResult<toml::value::Table> {
+        match toml::Value::try_from(self).context("failed to serialize the config")? {
+            toml::Value::Table(table) => Ok(table),
+            _ => bail!("failed to serialize the config"),
+        }
+    }
+
+    /// Replaces the configuration with the one of the table, keeping the settings of
+    /// the project.
+    fn replace_with(&mut self, table: toml::value::Table) -> CIResult<()> {
+        let mut config: Self = toml::Value::Table(table).try_into()?;
+        config.skip_crates = std::mem::take(&mut self.skip_crates);
+        config.debug = self.debug;
+        *self = config;
+        Ok(())
+    }
+
+    /// Saves the configuration.
+    pub fn save(config: &Self) -> CIResult<()> {
+        let mut path = Config::dir()?;
+        path.push("default.cfg");
+        let s = toml::to_string_pretty(config).context("failed to parse the config")?;
+        paths::write(path, s).context("failed to save the config")
+    }
+
+    /// Gets the configuration directory.
+    pub fn dir() -> CIResult<PathBuf> {
+        let mut path = dirs::config_dir().context("failed to get the config directory")?;
+        path.push("cargo-compiler-interrupts");
+        paths::create_dir_all(&path)?;
+        Ok(path)
+    }
+
+    /// Gets the path to the vendored source code installed offline.
+    pub fn vendored_source_path(&self) -> CIResult<PathBuf> {
+        match &self.vendored_source {
+            Some(path) => Ok(path.clone()),
+            None => Ok(Self::dir()?.join("CompilerInterrupt.cpp")),
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Integration of the builds of `cross`, running `cargo` in a container.
+//!
+//! `cross` mounts the target directory, the workspace, and the sysroot of the host in
+//! the container at `/target`, `/project`, and `/rust`. The LLVM IR files and the object
+//! files are written to the host, so they are integrated by `opt` and `llc` of the host
+//! once the container paths in the logs are mapped back to the host. The linkers of the
+//! target only exist in the image, so the binaries are linked in a container of the
+//! image with the same directories mounted at their host paths.
+
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use cargo_util::{paths, ProcessBuilder};
+use tracing::debug;
+
+use crate::cargo::{Linker, OutputFile};
+use crate::CIResult;
+
+/// Name of the `cross` program.
+pub const PROGRAM: &str = "cross";
+
+/// Space-delimited environment variables passed to the container by `cross`.
+const PASSTHROUGH_ENV: &str = "CROSS_BUILD_ENV_PASSTHROUGH";
+
+/// Container engine of `cross`.
+const ENGINE_ENV: &str = "CROSS_CONTAINER_ENGINE";
+
+/// Configuration file of `cross`.
+const CONFIG_ENV: &str = "CROSS_CONFIG";
+
+/// Container of `cross` for the target.
+#[derive(Clone, Debug)]
+pub struct Cross {
+    /// Container engine, e.g. `docker` or `podman`.
+    engine: String,
+    /// Image of the target.
+    image: String,
+    /// Paths in the container and the directories of the host mounted there.
+    mounts: Vec<(String, PathBuf)>,
+    /// Root directory of the workspace.
+    workspace_dir: PathBuf,
+}
+
+impl Cross {
+    /// Locates the image of the target and the directories mounted by `cross`.
+    pub fn new(target: &str, target_dir: PathBuf) -> CIResult<Self> {
+        let workspace_dir = std::env::current_dir()?;
+        let engine = std::env::var(ENGINE_ENV).unwrap_or_else(|_| "docker".to_string());
+        let image = image(target, &workspace_dir)?;
+        debug!(%engine, %image);
+
+        let output = ProcessBuilder::new("rustc")
+            .args(&["--print", "sysroot"])
+            .exec_with_output()?;
+        let sysroot = PathBuf::from(String::from_utf8(output.stdout)?.trim());
+
+        Ok(Self {
+            engine,
+            image,
+            mounts: vec![
+                ("/target".to_string(), target_dir),
+                ("/project".to_string(), workspace_dir.clone()),
+                ("/rust".to_string(), sysroot),
+            ],
+            workspace_dir,
+        })
+    }
+
+    /// Passes the environment variables of the command to the container, besides the
+    /// ones configured by the user.
+    pub fn passthrough(cmd: &mut ProcessBuilder, keys: &[&str]) {
+        let mut passthrough = std::env::var(PASSTHROUGH_ENV).unwrap_or_default();
+        for key in keys {
+            passthrough.push(' ');
+            passthrough.push_str(key);
+        }
+        cmd.env(PASSTHROUGH_ENV, passthrough.trim_start());
+    }
+
+    /// Maps the container paths in the argument to the host, e.g. `/target/debug/demo`
+    /// and the `/rust` directories of `PATH`, leaving the other paths as is.
+    pub fn to_host(&self, arg: &str) -> String {
+        let mut mapped = String::with_capacity(arg.len());
+        let mut rest = arg;
+        // a path starts the argument, or follows `=`, `,`, or `:`
+        let mut boundary = true;
+        while let Some(c) = rest.chars().next() {
+            let mount = self.mounts.iter().find(|(container, _)| {
+                rest.strip_prefix(container.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', ':', ',']))
+            });
+            match mount {
+                Some((container, host)) if boundary => {
+                    mapped.push_str(&host.to_string_lossy());
+                    rest = &rest[container.len()..];
+                    boundary = false;
+                }
+                _ => {
+                    mapped.push(c);
+                    rest = &rest[c.len_utf8()..];
+                    boundary = matches!(c, '=' | ',' | ':');
+                }
+            }
+        }
+        mapped
+    }
+
+    /// Maps the paths of the linker invocation logged in the container to the host.
+    pub fn map_linker(&self, linker: &mut Linker) {
+        for (_, value) in &mut linker.env {
+            *value = self.to_host(value);
+        }
+        let args = &mut linker.args;
+        args.output_file = self.to_host(&args.output_file);
+        for arg in args
+            .input_files
+            .iter_mut()
+            .chain(&mut args.rlib_files)
+            .chain(&mut args.library_dirs)
+            .chain(&mut args.flags)
+        {
+            *arg = self.to_host(arg);
+        }
+    }
+
+    /// Maps the paths of the output file logged in the container to the host.
+    pub fn map_output_file(&self, file: &mut OutputFile) {
+        let map = |path: &Path| PathBuf::from(self.to_host(&path.to_string_lossy()));
+        file.hardlink = file.hardlink.as_deref().map(map);
+        file.export_path = file.export_path.as_deref().map(map);
+        file.path = map(&file.path);
+    }
+
+    /// Creates the command running the linker in a container of the image.
+    ///
+    /// The directories are mounted at their host paths, so the mapped paths of the
+    /// linker invocation are valid in the container. The linker runs as the owner of
+    /// the target directory, like `cross` does, so the binaries are not owned by root.
+    pub fn linker_command(&self, linker: Linker) -> ProcessBuilder {
+        let mut cmd = ProcessBuilder::new(&self.engine);
+        cmd.args(&["run", "--rm"]);
+        #[cfg(unix)]
+        if let Some((_, target_dir)) = self.mounts.first() {
+            if let Ok(metadata) = fs::metadata(target_dir) {
+                cmd.arg("--user");
+                cmd.arg(format!("{}:{}", metadata.uid(), metadata.gid()));
+            }
+        }
+        for (_, host) in &self.mounts {
+            // e.g. the target directory in the workspace
+            let nested = self
+                .mounts
+                .iter()
+                .any(|(_, other)| other != host && host.starts_with(other));
+            if !nested {
+                cmd.arg("--volume");
+                cmd.arg(format!("{0}:{0}", host.display()));
+            }
+        }
+        cmd.arg("--workdir");
+        cmd.arg(&self.workspace_dir);
+        for (key, value) in &linker.env {
+            cmd.arg("--env");
+            cmd.arg(format!("{}={}", key, value));
+        }
+        cmd.arg(&self.image);
+        cmd.arg(&linker.program);
+        cmd.args(&linker.args.build());
+        cmd
+    }
+}
+
+/// Gets the image of `cross` for the target.
+///
+/// The image is set by `CROSS_TARGET_<TRIPLE>_IMAGE`, or `target.<triple>.image` in
+/// `Cross.toml`, and is otherwise the image of the target released with `cross`.
+fn image(target: &str, workspace_dir: &Path) -> CIResult<String> {
+    let env = format!(
+        "CROSS_TARGET_{}_IMAGE",
+        target.to_uppercase().replace(['-', '.'], "_")
+    );
+    if let Ok(image) = std::env::var(env) {
+        return Ok(image);
+    }
+
+    let config_path = std::env::var_os(CONFIG_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| workspace_dir.join("Cross.toml"));
+    if config_path.is_file() {
+        let config = paths::read(&config_path)?
+            .parse::<toml::Value>()
+            .with_context(|| format!("failed to parse `{}`", config_path.display()))?;
+        let image = config
+            .get("target")
+            .and_then(|targets| targets.get(target))
+            .and_then(|target| target.get("image"));
+        // `image = "<name>"` or `image.name = "<name>"`
+        if let Some(image) =
+            image.and_then(|image| image.as_str().or_else(|| image.get("name")?.as_str()))
+        {
+            return Ok(image.to_string());
+        }
+    }
+
+    let output = ProcessBuilder::new(PROGRAM)
+        .arg("--version")
+        .exec_with_output()
+        .context("failed to run `cross`, install it with `cargo install cross`")?;
+    let stdout = String::from_utf8(output.stdout)?;
+    // cross 0.2.5
+    // cross 0.2.5 (4090bec 2023-02-07)
+    let line = stdout.lines().next().unwrap_or_default();
+    let version = line
+        .split_ascii_whitespace()
+        .nth(1)
+        .context("failed to parse the version of `cross`")?;
+    // images of the unreleased versions are tagged `main`
+    let tag = if line.contains('(') || version.contains('-') {
+        "main"
+    } else {
+        version
+    };
+    Ok(format!("ghcr.io/cross-rs/{}:{}", target, tag))
+}
@@ -0,0 +1,464 @@
+//! Errors related to the Compiler Interrupts integration.
+//!
+//! Failures of the subcommands and the integration are `anyhow` errors whose chain
+//! contains a [`CIError`] when they belong to a failure class, found by
+//! [`CIError::find`]. The code of each class is stable and used as the exit code,
+//! and as the diagnostic code `CI<NNNN>` explained by `cargo-lib-ci explain`.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use semver::Version;
+use thiserror::Error;
+
+/// Exit code for failures that do not belong to any failure class.
+pub const EXIT_FAILURE: u8 = 1;
+
+/// Exit codes of the failure classes, shown in `--help`.
+pub const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0   Success
+  1   Unclassified failure
+  2   Invalid command-line usage
+  10  Compiler Interrupts library is not installed
+  11  Compiler Interrupts library is already installed
+  12  LLVM version mismatch between Rust and Compiler Interrupts library
+  13  Source code of the library failed the verification
+  20  LLVM toolchain is not installed
+  21  LLVM version mismatch between Rust and LLVM toolchain
+  22  LLVM version is not supported
+  23  Target is not supported
+  24  Option is not supported on the host
+  30  Package does not have any binaries
+  31  Package does not have any CI-integrated binaries
+  32  Requested binary is not available
+  33  Could not determine which binary to run
+  40  `cargo build` failed
+  41  Integration (`opt`/`llc`) failed on a crate
+  42  Linking the CI-integrated binary failed
+  43  `opt`/`llc` crashed on a crate
+  44  Process exceeded the time limit
+  45  CI-integrated binary failed the verification
+  50  Cache entry is corrupted
+  130 Interrupted
+
+Run `cargo-lib-ci explain <CODE>` for the remediation of a failure, e.g. `CI0010` for 10.";
+
+/// Boxed source of an error.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Failure classes of the Compiler Interrupts integration.
+///
+/// New classes may be added in minor releases, but the codes of the existing ones
+/// do not change.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum CIError {
+    /// Compiler Interrupts library is not installed.
+    #[error(
+        "Compiler Interrupts library is not installed\n\
+        Run `cargo-lib-ci install` to install the library"
+    )]
+    LibraryNotInstalled,
+
+    /// Compiler Interrupts library is already installed.
+    #[error("Compiler Interrupts library is already installed")]
+    LibraryAlreadyInstalled,
+
+    /// LLVM version of the library does not match the one of Rust toolchain.
+    #[error(
+        "Compiler Interrupts library is compiled with LLVM {0}, but Rust toolchain uses LLVM {1}\n\
+        Run `cargo-lib-ci install` or pass `--auto-reinstall` to install the library for it"
+    )]
+    LibraryVersionNotMatch(String, Version),
+
+    /// Source code of the library does not match its checksum or signature.
+    #[error("Source code of the Compiler Interrupts library failed the verification: {0}")]
+    SourceVerificationFailed(String),
+
+    /// LLVM version between Rust and LLVM toolchain does not match.
+    #[error(
+        "LLVM version from Rust toolchain ({0}) does not match with \
+        the version from LLVM toolchain ({1})"
+    )]
+    LLVMVersionNotMatch(Version, Version),
+
+    /// LLVM toolchain is not installed.
+    #[error("Unable to locate the LLVM compiler toolchain")]
+    LLVMNotInstalled,
+
+    /// LLVM version is not supported.
+    #[error(
+        "LLVM version {0} is not supported. Supported LLVM versions are from {} to before {}",
+        crate::llvm::LLVM_MIN_VERSION,
+        crate::llvm::LLVM_MAX_VERSION
+    )]
+    LLVMNotSupported(Version),
+
+    /// Target of the build is not supported.
+    #[error(
+        "Target `{0}` is not supported by the Compiler Interrupts integration\n\
+        Build it with `cargo build` instead"
+    )]
+    TargetNotSupported(String),
+
+    /// Option relies on a feature the host does not have, e.g. Unix domain sockets.
+    #[error("`{0}` is not supported on this host, as it relies on {1}")]
+    HostNotSupported(String, String),
+
+    /// Binary is not available.
+    #[error(
+        "Failed to execute the binary '{0}'\n\
+        Available binaries: {1}"
+    )]
+    BinaryNotAvailable(String, String),
+
+    /// Failed to determine which binary to run.
+    #[error(
+        "Could not determine which binary to run\n\
+        Run `cargo-run-ci --bin <NAME>` to specify a binary\n\
+        Available binaries: {0}"
+    )]
+    BinaryNotDetermine(String),
+
+    /// Package does not have any available CI-integrated binaries.
+    #[error(
+        "Package does not have any available CI-integrated binaries\n\
+        Run `cargo-build-ci` to build the package first"
+    )]
+    IntegratedBinaryNotFound,
+
+    /// Package does not have any available binaries.
+    #[error("Package does not have any available binaries")]
+    BinaryNotFound,
+
+    /// `cargo build` invocation failed.
+    #[error("failed to execute `cargo build`")]
+    CargoBuildFailed,
+
+    /// Integration of the crates failed, caused by the failure of a crate.
+    #[error(
+        "Consider filing an issue report on \
+        https://github.com/bitslab/CompilerInterrupts \
+        with the LLVM IR file and log attached.\n\
+        Path to the log: {}",
+        .0.display()
+    )]
+    IntegrationFailed(PathBuf, #[source] BoxError),
+
+    /// Linking of the CI-integrated binaries failed, caused by the failure of a binary.
+    #[error(
+        "Failed to link the CI-integrated binaries\n\
+        Path to the log: {}",
+        .0.display()
+    )]
+    LinkingFailed(PathBuf, #[source] BoxError),
+
+    /// `opt` or `llc` crashed on the crate.
+    #[error("`opt`/`llc` crashed on the crate `{0}`")]
+    PassCrashed(String, #[source] BoxError),
+
+    /// CI-integrated binary failed the verification of `--verify`.
+    #[error("CI-integrated binary `{0}` failed the verification: {1}")]
+    VerificationFailed(String, String),
+
+    /// Cache entry is corrupted and has been removed.
+    #[error(
+        "Cache entry `{}` is corrupted and has been removed\n\
+        Run the build again to integrate the crate",
+        .0.display()
+    )]
+    CacheCorrupted(PathBuf, #[source] BoxError),
+
+    /// Process exceeded the time limit and has been killed.
+    #[error("`{0}` exceeded the time limit of {1:?} and has been killed")]
+    TimedOut(String, Duration),
+
+    /// Interrupted by the user or cancelled by a failure of another task.
+    #[error("Interrupted")]
+    Cancelled,
+}
+
+impl CIError {
+    /// Gets the stable code of the error, also used as the exit code.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CIError::LibraryNotInstalled => 10,
+            CIError::LibraryAlreadyInstalled => 11,
+            CIError::LibraryVersionNotMatch(..) => 12,
+            CIError::SourceVerificationFailed(_) => 13,
+            CIError::LLVMNotInstalled => 20,
+            CIError::LLVMVersionNotMatch(..) => 21,
+            CIError::LLVMNotSupported(_) => 22,
+            CIError::TargetNotSupported(_) => 23,
+            CIError::HostNotSupported(..) => 24,
+            CIError::BinaryNotFound => 30,
+            CIError::IntegratedBinaryNotFound => 31,
+            CIError::BinaryNotAvailable(..) => 32,
+            CIError::BinaryNotDetermine(_) => 33,
+            CIError::CargoBuildFailed => 40,
+            CIError::IntegrationFailed(..) => 41,
+            CIError::LinkingFailed(..) => 42,
+            CIError::PassCrashed(..) => 43,
+            CIError::TimedOut(..) => 44,
+            CIError::VerificationFailed(..) => 45,
+            CIError::CacheCorrupted(..) => 50,
+            CIError::Cancelled => 130,
+        }
+    }
+
+    /// Gets the stable diagnostic code of the error, e.g. `CI0010`.
+    pub fn code(&self) -> String {
+        diagnostic_code(self.exit_code())
+    }
+
+    /// Finds the outermost failure class in the chain of the error.
+    pub fn find(error: &anyhow::Error) -> Option<&CIError> {
+        // the context of an `anyhow` error is only found by its own downcast
+        error.downcast_ref::<CIError>().or_else(|| {
+            error
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<CIError>())
+        })
+    }
+}
+
+/// Gets the exit code of a failed subcommand.
+pub fn exit_code(error: &anyhow::Error) -> u8 {
+    CIError::find(error).map_or(EXIT_FAILURE, CIError::exit_code)
+}
+
+/// Gets the diagnostic code of an exit code, e.g. `CI0010` for 10.
+pub fn diagnostic_code(exit_code: u8) -> String {
+    format!("CI{:04}", exit_code)
+}
+
+/// Parses a diagnostic code, e.g. `CI0010`, `ci10`, or `10`, to its exit code.
+pub fn parse_code(code: &str) -> Option<u8> {
+    let code = code.trim();
+    let number = match code.get(..2) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("CI") => &code[2..],
+        _ => code,
+    };
+    number.parse().ok()
+}
+
+/// Gets the explanation of a failure class and its remediation, by its code.
+pub fn explain(exit_code: u8) -> Option<&'static str> {
+    let explanation = match exit_code {
+        10 => {
+            "\
+Compiler Interrupts library is not installed.
+
+The integration runs the Compiler Interrupts pass of the library with `opt`, so
+the library must be installed before building.
+
+Run `cargo-lib-ci install` to download, compile and install the library, or
+`cargo-lib-ci install --offline` to build it from the vendored source code."
+        }
+        11 => {
+            "\
+Compiler Interrupts library is already installed.
+
+`cargo-lib-ci install` does not overwrite an installed library.
+
+Run `cargo-lib-ci update` to build the latest source code, or
+`cargo-lib-ci uninstall` and then `cargo-lib-ci install` to install it again,
+e.g. with other options."
+        }
+        12 => {
+            "\
+LLVM version mismatch between Rust and the Compiler Interrupts library.
+
+The library is compiled against an LLVM toolchain, and its pass is loaded by the
+`opt` of the same version as the LLVM of `rustc`. Updating the Rust toolchain
+may change the version of its LLVM.
+
+Run `cargo-lib-ci install` to install the library for the LLVM of `rustc`, or
+pass `--auto-reinstall` to `cargo-build-ci` to do it without asking."
+        }
+        13 => {
+            "\
+Source code of the library failed the verification.
+
+The source code did not match the SHA-256 digest set by `--sha256`, or its
+detached signature set by `--signature` was not verified by `gpg`. A prebuilt
+library downloaded with `--release-tag` did not match its digest in the
+`SHA256SUMS` of the release.
+
+Check that the URL of the source code is the expected one, update the digest
+with `cargo-lib-ci config` if the source code changed upstream, and import the
+public key of the signer with `gpg --import`."
+        }
+        20 => {
+            "\
+LLVM toolchain is not installed.
+
+The integration runs `opt`, `llc` and `llvm-config` of the LLVM toolchain of the
+same major version as the LLVM of `rustc`, shown by `rustc -vV`.
+
+Install the LLVM toolchain of that version, e.g. `apt install llvm-15` or
+`brew install llvm@15`, and make its utilities available in `PATH`."
+        }
+        21 => {
+            "\
+LLVM version mismatch between Rust and the LLVM toolchain.
+
+The LLVM IR files written by `rustc` are read by the `opt` of the LLVM toolchain,
+which must have the same major version as the LLVM of `rustc`.
+
+Install the LLVM toolchain of the version of `rustc -vV`, or switch to a Rust
+toolchain using the LLVM version installed, e.g. with `rustup override set`."
+        }
+        22 => {
+            "\
+LLVM version is not supported.
+
+The Compiler Interrupts library supports a range of LLVM versions, shown in the
+message of the error.
+
+Use a Rust toolchain whose LLVM version is in the supported range."
+        }
+        23 => {
+            "\
+Target is not supported.
+
+The target of the build cannot be integrated, e.g. WebAssembly targets, which
+have neither the threads the handler relies on nor a linker to relink with.
+
+Build the target with `cargo build` instead."
+        }
+        24 => {
+            "\
+Option is not supported on the host.
+
+The daemon of `cargo-lib-ci daemon` and the event stream of `--events` talk over
+Unix domain sockets, and `--cross` runs the linkers in containers as the owner
+of the target directory, so they are only available on Unix hosts.
+
+Run the subcommand without the option, or on a Unix host, e.g. in WSL."
+        }
+        30 => {
+            "\
+Package does not have any binaries.
+
+`cargo-run-ci` runs the binaries of the package, but the build did not produce
+any executable.
+
+Add a binary target to `Cargo.toml`, or run `cargo-run-ci` in a package of the
+workspace with binaries."
+        }
+        31 => {
+            "\
+Package does not have any CI-integrated binaries.
+
+`cargo-run-ci` runs the CI-integrated binaries written next to the original
+ones, e.g. `target/debug/<binary>-ci`, which were not found.
+
+Run `cargo-build-ci` with the same features and profile first."
+        }
+        32 => {
+            "\
+Requested binary is not available.
+
+The binary given to `--bin` is not one of the CI-integrated binaries of the
+package, listed in the message of the error.
+
+Pass one of the available binaries to `--bin`, or run `cargo-build-ci` to
+integrate the binary first."
+        }
+        33 => {
+            "\
+Could not determine which binary to run.
+
+The package has several CI-integrated binaries and `cargo-run-ci` does not know
+which one to run.
+
+Pass one of the available binaries listed in the message to `--bin <NAME>`."
+        }
+        40 => {
+            "\
+`cargo build` failed.
+
+The package failed to compile before the integration started, with the errors
+of `rustc` printed above.
+
+Fix the errors of the build, checking that `cargo build` succeeds with the same
+arguments."
+        }
+        41 => {
+            "\
+Integration failed on a crate.
+
+`opt` or `llc` exited with an error on an LLVM IR file of a crate, whose log is
+written to the path in the message of the error.
+
+Skip the crate with `--skip <CRATES>`, list the logs with `cargo-lib-ci logs`,
+and consider filing an issue with the LLVM IR file and the log attached."
+        }
+        42 => {
+            "\
+Linking the CI-integrated binary failed.
+
+The linker invocation of `rustc` was run again with the CI-integrated object
+files, and failed with the log written to the path in the message of the error.
+
+Check the log for undefined symbols or linker flags, and that `cargo build`
+links the binary with the same linker."
+        }
+        43 => {
+            "\
+`opt` or `llc` crashed on a crate.
+
+The LLVM utility was killed by a signal or reported an internal error while
+processing an LLVM IR file of the crate.
+
+Skip the crate with `--skip <CRATES>` or the functions with `--skip-fn <NAMES>`,
+and consider filing an issue with the LLVM IR file attached."
+        }
+        44 => {
+            "\
+Process exceeded the time limit.
+
+An `opt`, `llc`, or linker process ran longer than the `--timeout` of
+`cargo-build-ci`, or the download exceeded the `--timeout` of `cargo-lib-ci`,
+and has been killed.
+
+Raise the time limit, or skip the crates taking too long with `--skip <CRATES>`."
+        }
+        45 => {
+            "\
+CI-integrated binary failed the verification.
+
+`--verify` checks that each CI-integrated binary defines the handler
+`intvActionHook` of the Compiler Interrupts, and that its object files have at
+least one probe loading it. A binary without probes links and runs, but never
+calls the handler.
+
+Check that the crates with the code of the binary are not skipped with `--skip`
+or `--only`, that the library arguments do not disable the probes, and that the
+binary depends on the `compiler-interrupts` crate defining the handler."
+        }
+        50 => {
+            "\
+Cache entry is corrupted.
+
+A CI-integrated object file of the cache failed to be read or decompressed, and
+has been removed.
+
+Run the build again to integrate the crate. If it keeps happening, clear the
+cache with `cargo-lib-ci cache --clear` or build with `--no-cache`."
+        }
+        130 => {
+            "\
+Interrupted.
+
+The build was stopped by Ctrl-C, or cancelled by the failure of another task.
+
+Run the command again, or pass `--keep-going` to `cargo-build-ci` to continue
+with the crates and binaries not depending on a failed one."
+        }
+        _ => return None,
+    };
+    Some(explanation)
+}
@@ -0,0 +1,132 @@
+//! Check of the Compiler Interrupts handler linked into the CI-integrated binaries.
+//!
+//! A binary that never calls `compiler_interrupts::register` runs like the original
+//! one, as the probes call the initial handler. The check is a small object file with
+//! a constructor reading [`HANDLER_ENV`] at startup:
+//!
+//! * `warn` reports at exit when the handler of the main thread is still the initial
+//!   one, i.e. no handler was registered.
+//! * `log` registers a default handler logging the interrupts to stderr, replaced by
+//!   the handler of the program once registered, and reports at exit if it was not.
+//!
+//! Nothing is done when it is not set, so the binaries behave the same outside of
+//! `cargo-run-ci`, which sets it to `warn` by default.
+
+use std::path::{Path, PathBuf};
+
+use cargo_util::paths;
+use tracing::debug;
+
+use crate::llvm::{LlvmToolchain, LlvmUtility};
+use crate::CIResult;
+
+/// Mode of the handler check, i.e. `warn`, `log`, or `off`.
+pub const HANDLER_ENV: &str = "CARGO_CI_HANDLER";
+
+/// Source code of the handler check.
+///
+/// It declares the few functions of the C library it calls instead of including the
+/// headers, so it compiles for any target without its sysroot.
+const SOURCE: &str = r#"
+typedef void (*ci_handler)(long);
+
+extern __thread ci_handler intvActionHook;
+
+extern char *getenv(const char *name);
+extern int atexit(void (*function)(void));
+extern long write(int fd, const void *buf, unsigned long count);
+
+static ci_handler cargo_ci_initial_handler;
+static unsigned long cargo_ci_interrupts;
+
+static int cargo_ci_equals(const char *a, const char *b) {
+    while (*a && *a == *b) {
+        a++;
+        b++;
+    }
+    return *a == *b;
+}
+
+static void cargo_ci_print(const char *message) {
+    unsigned long len = 0;
+    while (message[len]) {
+        len++;
+    }
+    write(2, message, len);
+}
+
+static void cargo_ci_print_number(unsigned long n) {
+    char buf[24];
+    int i = sizeof(buf) - 1;
+    buf[i] = '\0';
+    do {
+        buf[--i] = (char)('0' + n % 10);
+        n /= 10;
+    } while (n);
+    cargo_ci_print(&buf[i]);
+}
+
+static void cargo_ci_default_handler(long ic) {
+    cargo_ci_interrupts++;
+    /* only the powers of two, not to flood the output */
+    if (cargo_ci_interrupts & (cargo_ci_interrupts - 1)) {
+        return;
+    }
+    cargo_ci_print("[cargo-ci] compiler interrupt #");
+    cargo_ci_print_number(cargo_ci_interrupts);
+    cargo_ci_print(" with instruction count ");
+    cargo_ci_print_number((unsigned long)ic);
+    cargo_ci_print("\n");
+}
+
+static void cargo_ci_check(void) {
+    if (intvActionHook == cargo_ci_default_handler) {
+        cargo_ci_print("[cargo-ci] no handler registered with `compiler_interrupts::register`, "
+                       "the default handler received ");
+        cargo_ci_print_number(cargo_ci_interrupts);
+        cargo_ci_print(" interrupt(s)\n");
+    } else if (intvActionHook == cargo_ci_initial_handler) {
+        cargo_ci_print("[cargo-ci] warning: no handler registered with "
+                       "`compiler_interrupts::register` on the main thread, the interrupts "
+                       "were ignored; set CARGO_CI_HANDLER=log to log them\n");
+    }
+}
+
+__attribute__((constructor)) static void cargo_ci_init(void) {
+    const char *mode = getenv("CARGO_CI_HANDLER");
+    if (!mode) {
+        return;
+    }
+    cargo_ci_initial_handler = intvActionHook;
+    if (cargo_ci_equals(mode, "log")) {
+        intvActionHook = cargo_ci_default_handler;
+    } else if (!cargo_ci_equals(mode, "warn")) {
+        return;
+    }
+    atexit(cargo_ci_check);
+}
+"#;
+
+/// Compiles the handler check for the target into the directory, returning the path
+/// to the object file.
+pub fn compile(toolchain: &LlvmToolchain, target: Option<&str>, dir: &Path) -> CIResult<PathBuf> {
+    let src_file = dir.join("ci-handler.c");
+    let object_file = dir.join("ci-handler.o");
+    paths::write_if_changed(&src_file, SOURCE)?;
+    if paths::mtime(&object_file).ok() >= paths::mtime(&src_file).ok() {
+        return Ok(object_file);
+    }
+
+    debug!("compiling the handler check: {}", object_file.display());
+    let mut clang = LlvmUtility::Clang.process_builder(toolchain);
+    if let Some(target) = target {
+        clang.arg(format!("--target={}", target));
+    }
+    clang
+        .args(&["-c", "-O2", "-fPIC", "-w"])
+        .arg(&src_file)
+        .arg("-o")
+        .arg(&object_file)
+        .exec_with_output()?;
+    Ok(object_file)
+}
@@ -0,0 +1,51 @@
+//! User-defined shell commands run at the stages of the integration.
+
+use std::process::Stdio;
+
+use anyhow::Context;
+use cargo_util::{ProcessBuilder, ProcessError};
+use tracing::{debug, info};
+
+use crate::CIResult;
+
+/// Runs the shell command of the hook with the environment variables.
+///
+/// Unlike the notifications, a failed hook fails the build, as the following stages
+/// may depend on its changes.
+pub fn run(hook: &str, command: &str, env: &[(&str, &str)]) -> CIResult<()> {
+    info!("running {} hook: {}", hook, command);
+    exec_shell(command, env).with_context(|| format!("{} hook failed", hook))
+}
+
+/// Runs the shell command with the environment variables, by `sh -c`, or by `cmd /C`
+/// on Windows.
+///
+/// The standard output of the command is written to the standard error, so that the
+/// standard output only has the JSON messages of the build if requested.
+pub fn exec_shell(command: &str, env: &[(&str, &str)]) -> CIResult<()> {
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = ProcessBuilder::new("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut cmd = ProcessBuilder::new("sh");
+        cmd.arg("-c");
+        cmd
+    };
+    cmd.arg(command);
+    for (key, val) in env {
+        cmd.env(key, val);
+    }
+    debug!(?cmd);
+
+    let status = cmd
+        .build_command()
+        .stdout(Stdio::from(std::io::stderr()))
+        .status()
+        .with_context(|| format!("could not execute process {}", cmd))?;
+    if !status.success() {
+        let msg = format!("process didn't exit successfully: {}", cmd);
+        return Err(ProcessError::new(&msg, Some(status), None).into());
+    }
+    Ok(())
+}
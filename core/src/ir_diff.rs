@@ -0,0 +1,181 @@
+//! Differences between the LLVM IR of the crates and their CI-integrated LLVM IR.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use cargo_util::paths;
+
+use crate::llvm::{LlvmToolchain, LlvmUtility};
+use crate::paths::PathExt;
+use crate::probes;
+use crate::CIResult;
+
+/// Extension of the differences written for each LLVM IR file.
+const DIFF_EXTENSION: &str = "diff";
+
+/// Function changed by the passes.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ChangedFunction {
+    /// Symbol name of the function.
+    pub symbol: String,
+    /// Number of instructions added to the function.
+    pub added: usize,
+    /// Number of instructions removed from the function.
+    pub removed: usize,
+    /// Whether the function only exists in the CI-integrated LLVM IR.
+    pub new: bool,
+}
+
+/// Writes the differences between the LLVM IR file and its CI-integrated LLVM IR file
+/// to `<dir>/<file>.diff`, returning the path to it.
+///
+/// `llvm-diff` compares the functions of the same name instruction by instruction, so
+/// the renumbered values do not show up as changes, and reads LLVM bitcode as well.
+pub fn write<P: AsRef<Path>>(
+    toolchain: &LlvmToolchain,
+    file: P,
+    ci_file: P,
+    dir: &Path,
+) -> CIResult<PathBuf> {
+    let output = LlvmUtility::Diff
+        .process_builder(toolchain)
+        .arg(file.as_ref())
+        .arg(ci_file.as_ref())
+        .output()?;
+    // `llvm-diff` exits with 1 if the modules differ, and prints the differences to
+    // stderr
+    if !matches!(output.status.code(), Some(0 | 1)) {
+        bail!(
+            "`llvm-diff` failed on `{}`: {}",
+            file.as_ref().display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let path = dir.join(format!("{}.{}", file.file_stem()?, DIFF_EXTENSION));
+    paths::write(&path, &output.stderr)
+        .with_context(|| format!("failed to write the IR diff `{}`", path.display()))?;
+    Ok(path)
+}
+
+/// Parses the functions changed in the output of `llvm-diff`.
+///
+/// ``` text
+/// function @intvActionHook exists only in right module
+/// in function _ZN4demo4main17h0123456789abcdefE:
+///   in block %start:
+///     >   %0 = load i64, ptr @LocalLC, align 8
+/// ```
+pub fn parse(diff: &str) -> Vec<ChangedFunction> {
+    let mut functions: Vec<ChangedFunction> = Vec::new();
+    for line in diff.lines() {
+        if let Some(symbol) = line
+            .strip_prefix("function @")
+            .and_then(|line| line.strip_suffix(" exists only in right module"))
+        {
+            functions.push(ChangedFunction {
+                symbol: symbol.to_string(),
+                added: 0,
+                removed: 0,
+                new: true,
+            });
+        } else if let Some(symbol) = line
+            .strip_prefix("in function ")
+            .and_then(|line| line.strip_suffix(':'))
+        {
+            functions.push(ChangedFunction {
+                symbol: symbol.to_string(),
+                added: 0,
+                removed: 0,
+                new: false,
+            });
+        } else if let Some(function) = functions.last_mut().filter(|function| !function.new) {
+            match line.trim_start().chars().next() {
+                Some('>') => function.added += 1,
+                Some('<') => function.removed += 1,
+                _ => {}
+            }
+        }
+    }
+    functions
+}
+
+/// Writes the summary of the functions changed in each crate from the differences in
+/// the directory to `<dir>/summary.txt`, returning the path to it.
+pub fn write_summary(dir: &Path) -> CIResult<PathBuf> {
+    let mut crates = BTreeMap::<String, Vec<ChangedFunction>>::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if matches!(path.extension().as_deref(), Ok(DIFF_EXTENSION)) {
+            let crate_name = crate::pipeline::crate_name(&path)?;
+            let diff = paths::read(&path)?;
+            crates.entry(crate_name).or_default().extend(parse(&diff));
+        }
+    }
+
+    let width = crates
+        .keys()
+        .map(String::len)
+        .chain(std::iter::once("total".len()))
+        .max()
+        .unwrap_or_default();
+
+    let mut s = String::new();
+    let _ = writeln!(
+        s,
+        "{:<width$}  {:>8}  {:>8}  function",
+        "crate", "added", "removed"
+    );
+    for (crate_name, functions) in &crates {
+        let _ = writeln!(
+            s,
+            "{:<width$}  {:>8}  {:>8}  ({} functions)",
+            crate_name,
+            functions
+                .iter()
+                .map(|function| function.added)
+                .sum::<usize>(),
+            functions
+                .iter()
+                .map(|function| function.removed)
+                .sum::<usize>(),
+            functions.len()
+        );
+        for function in functions {
+            let (added, removed) = if function.new {
+                ("new".to_string(), String::new())
+            } else {
+                (function.added.to_string(), function.removed.to_string())
+            };
+            let _ = writeln!(
+                s,
+                "{:<width$}  {:>8}  {:>8}  {}",
+                "",
+                added,
+                removed,
+                probes::demangle(&function.symbol)
+            );
+        }
+    }
+    let functions = crates.values().flatten().collect::<Vec<_>>();
+    let _ = writeln!(
+        s,
+        "{:<width$}  {:>8}  {:>8}  ({} crates)",
+        "total",
+        functions
+            .iter()
+            .map(|function| function.added)
+            .sum::<usize>(),
+        functions
+            .iter()
+            .map(|function| function.removed)
+            .sum::<usize>(),
+        crates.len()
+    );
+
+    let path = dir.join("summary.txt");
+    paths::write(&path, s).context("failed to write the IR diff summary")?;
+    Ok(path)
+}
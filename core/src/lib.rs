@@ -0,0 +1,67 @@
+//! Core library of [`cargo-compiler-interrupts`][cargo-compiler-interrupts].
+//!
+//! Provides the Compiler Interrupts integration independently of the cargo subcommands:
+//! the integration pipeline that runs the passes on the LLVM IR and links the binaries,
+//! its metrics and observers, the cache of the integrated objects, the LLVM toolchain
+//! detection, the parsers of the `cargo` and `rustc` logs, the configuration of the
+//! library and the pass plugins, the error types, the platform conventions, the symbol
+//! analysis of the object files, the codegen units of the archives, the statistics of
+//! the interrupts, the reports and the `cargo-nextest` archives of the test harnesses,
+//! and the path utilities. The `cargo-*-ci` binaries build the crates with `cargo` and
+//! hand the outputs to [`pipeline::run`].
+//!
+//! [cargo-compiler-interrupts]: https://github.com/bitslab/cargo-compiler-interrupts
+
+#![warn(
+    missing_copy_implementations,
+    missing_debug_implementations,
+    missing_docs,
+    trivial_casts,
+    trivial_numeric_casts,
+    unsafe_code,
+    unstable_features,
+    unused_extern_crates,
+    unused_import_braces,
+    unused_qualifications,
+    clippy::cast_possible_truncation,
+    clippy::cast_possible_wrap,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss,
+    clippy::clone_on_ref_ptr,
+    clippy::missing_docs_in_private_items,
+    clippy::mut_mut,
+    clippy::print_stdout,
+    clippy::unseparated_literal_suffix,
+    clippy::unwrap_used
+)]
+
+/// Compiler Interrupts result.
+type CIResult<T> = anyhow::Result<T>;
+
+pub mod archive;
+pub mod cache;
+pub mod cargo;
+pub mod config;
+pub mod cross;
+pub mod error;
+pub mod handler;
+pub mod hooks;
+pub mod ir_diff;
+pub mod libtest;
+pub mod llvm;
+pub mod logs;
+pub mod metrics;
+pub mod nextest;
+pub mod observer;
+pub mod paths;
+pub mod perf;
+pub mod pipeline;
+pub mod platform;
+pub mod plugin;
+pub mod probes;
+pub mod remote_cache;
+pub mod scheduler;
+pub mod stats;
+pub mod symbols;
+pub mod tasks;
+pub mod util;
@@ -0,0 +1,285 @@
+//! Results of the test harnesses of libtest, and their JUnit XML and JSON reports.
+//!
+//! `--format json` of libtest is unstable, so the results are parsed from the lines of
+//! the default `pretty` format printed by the harnesses:
+//!
+//! ```text
+//! running 3 tests
+//! test tests::fails ... FAILED
+//! test tests::slow ... ignored, needs the network
+//! test tests::works ... ok
+//!
+//! failures:
+//!
+//! ---- tests::fails stdout ----
+//! thread 'tests::fails' panicked at src/lib.rs:10:9:
+//! ...
+//!
+//! test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.00s
+//! ```
+//!
+//! The `terse` format of `--quiet` does not print the names of the passed tests, so
+//! they are missing from the reports.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{pipeline, CIResult};
+
+/// Prefix of the result of a test.
+const TEST_PREFIX: &str = "test ";
+
+/// Separator between the name and the outcome of a test.
+const OUTCOME_SEPARATOR: &str = " ... ";
+
+/// Prefix of the summary of a harness.
+const RESULT_PREFIX: &str = "test result: ";
+
+/// Outcome of a test.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TestOutcome {
+    /// Test passed, or panicked as expected by `#[should_panic]`.
+    Passed,
+    /// Test failed.
+    Failed,
+    /// Test is ignored by `#[ignore]`.
+    Ignored,
+}
+
+/// Result of a test.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct TestCase {
+    /// Path of the test in the crate, e.g. `tests::works`.
+    pub name: String,
+    /// Outcome of the test.
+    pub outcome: TestOutcome,
+    /// Reason of `#[ignore = "<reason>"]`.
+    pub reason: Option<String>,
+    /// Captured output of the test, printed by libtest for the failed tests, and for
+    /// the passed ones with `--show-output`.
+    pub output: Option<String>,
+}
+
+/// Results of the tests of a harness.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct TestSuite {
+    /// Name of the harness, e.g. the name of its crate.
+    pub name: String,
+    /// Results of the tests in the order printed by the harness.
+    pub tests: Vec<TestCase>,
+    /// Time of the run in seconds, from the summary of the harness.
+    pub time: Option<f64>,
+}
+
+impl TestSuite {
+    /// Parses the results of the tests printed by the harness, e.g. the CI-integrated
+    /// `deps/demo-0123456789abcdef-ci`, into the suite named after its crate, e.g. `demo`.
+    pub fn parse_harness<P: AsRef<Path>, S: AsRef<str>>(
+        harness: P,
+        lines: &[S],
+    ) -> CIResult<TestSuite> {
+        Ok(TestSuite::parse(&pipeline::crate_name(harness)?, lines))
+    }
+
+    /// Parses the results of the tests from the lines printed by the harness on
+    /// stdout, ignoring the other lines.
+    pub fn parse<S: AsRef<str>>(name: &str, lines: &[S]) -> TestSuite {
+        let mut suite = TestSuite {
+            name: name.to_string(),
+            ..TestSuite::default()
+        };
+        // test of the output block being read, and its lines
+        let mut block: Option<(String, Vec<&str>)> = None;
+        for line in lines {
+            let line = line.as_ref();
+            let end_of_block = line == "successes:"
+                || line == "failures:"
+                || line.starts_with(RESULT_PREFIX)
+                || output_header(line).is_some();
+            if end_of_block {
+                if let Some((name, output)) = block.take() {
+                    suite.set_output(&name, &output);
+                }
+            }
+
+            if let Some(name) = output_header(line) {
+                block = Some((name.to_string(), Vec::new()));
+            } else if let Some((_, output)) = block.as_mut() {
+                output.push(line);
+            } else if let Some(test) = parse_test(line) {
+                suite.tests.push(test);
+            } else if let Some(summary) = line.strip_prefix(RESULT_PREFIX) {
+                suite.time = summary
+                    .rsplit_once("finished in ")
+                    .and_then(|(_, time)| time.trim().strip_suffix('s')?.parse().ok());
+            }
+        }
+        if let Some((name, output)) = block.take() {
+            suite.set_output(&name, &output);
+        }
+        suite
+    }
+
+    /// Counts the tests of the outcome.
+    pub fn count(&self, outcome: TestOutcome) -> usize {
+        self.tests
+            .iter()
+            .filter(|test| test.outcome == outcome)
+            .count()
+    }
+
+    /// Sets the captured output of the test, without the surrounding empty lines.
+    fn set_output(&mut self, name: &str, lines: &[&str]) {
+        let start = lines.iter().position(|line| !line.trim().is_empty());
+        let end = lines.iter().rposition(|line| !line.trim().is_empty());
+        if let (Some(start), Some(end), Some(test)) = (
+            start,
+            end,
+            self.tests.iter_mut().find(|test| test.name == name),
+        ) {
+            test.output = Some(lines[start..=end].join("\n"));
+        }
+    }
+}
+
+/// Gets the name of the test of a header of its output, e.g. `---- tests::fails stdout ----`.
+fn output_header(line: &str) -> Option<&str> {
+    line.strip_prefix("---- ")?.strip_suffix(" stdout ----")
+}
+
+/// Parses the result of a test, e.g. `test tests::works ... ok`.
+fn parse_test(line: &str) -> Option<TestCase> {
+    let (name, outcome) = line
+        .strip_prefix(TEST_PREFIX)?
+        .rsplit_once(OUTCOME_SEPARATOR)?;
+    // `#[should_panic]` tests are suffixed
+    let name = name.strip_suffix(" - should panic").unwrap_or(name);
+    let (outcome, reason) = match outcome.trim_end() {
+        "ok" => (TestOutcome::Passed, None),
+        "FAILED" => (TestOutcome::Failed, None),
+        "ignored" => (TestOutcome::Ignored, None),
+        outcome => {
+            let reason = outcome.strip_prefix("ignored, ")?;
+            (TestOutcome::Ignored, Some(reason.to_string()))
+        }
+    };
+    Some(TestCase {
+        name: name.to_string(),
+        outcome,
+        reason,
+        output: None,
+    })
+}
+
+/// Report of the results of the harnesses of a run.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct TestReport {
+    /// Results of the harnesses in the order they ran.
+    pub suites: Vec<TestSuite>,
+}
+
+impl TestReport {
+    /// Counts the tests of the outcome in every harness.
+    pub fn count(&self, outcome: TestOutcome) -> usize {
+        self.suites.iter().map(|suite| suite.count(outcome)).sum()
+    }
+
+    /// Writes the report in the JUnit XML format read by Jenkins and GitLab, a
+    /// `<testsuite>` per harness and a `<testcase>` per test.
+    pub fn to_junit(&self) -> String {
+        let tests = self
+            .suites
+            .iter()
+            .map(|suite| suite.tests.len())
+            .sum::<usize>();
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            xml,
+            "<testsuites tests=\"{}\" failures=\"{}\" skipped=\"{}\">",
+            tests,
+            self.count(TestOutcome::Failed),
+            self.count(TestOutcome::Ignored)
+        );
+        for suite in &self.suites {
+            let _ = write!(
+                xml,
+                "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\"",
+                escape(&suite.name),
+                suite.tests.len(),
+                suite.count(TestOutcome::Failed),
+                suite.count(TestOutcome::Ignored)
+            );
+            if let Some(time) = suite.time {
+                let _ = write!(xml, " time=\"{}\"", time);
+            }
+            xml.push_str(">\n");
+            for test in &suite.tests {
+                let _ = write!(
+                    xml,
+                    "    <testcase name=\"{}\" classname=\"{}\"",
+                    escape(&test.name),
+                    escape(&suite.name)
+                );
+                let output = test.output.as_deref().map(escape);
+                match (test.outcome, &output) {
+                    (TestOutcome::Passed, None) => xml.push_str("/>\n"),
+                    (TestOutcome::Passed, Some(output)) => {
+                        let _ = writeln!(xml, ">\n      <system-out>{}</system-out>", output);
+                        xml.push_str("    </testcase>\n");
+                    }
+                    (TestOutcome::Failed, output) => {
+                        let _ = writeln!(
+                            xml,
+                            ">\n      <failure message=\"test failed\">{}</failure>",
+                            output.as_deref().unwrap_or_default()
+                        );
+                        xml.push_str("    </testcase>\n");
+                    }
+                    (TestOutcome::Ignored, _) => {
+                        match &test.reason {
+                            Some(reason) => {
+                                let _ = writeln!(
+                                    xml,
+                                    ">\n      <skipped message=\"{}\"/>",
+                                    escape(reason)
+                                );
+                            }
+                            None => xml.push_str(">\n      <skipped/>\n"),
+                        }
+                        xml.push_str("    </testcase>\n");
+                    }
+                }
+            }
+            xml.push_str("  </testsuite>\n");
+        }
+        xml.push_str("</testsuites>\n");
+        xml
+    }
+
+    /// Writes the report in JSON.
+    pub fn to_json(&self) -> CIResult<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Escapes the text for the content and the attributes of XML, dropping the control
+/// characters not allowed in XML 1.0, e.g. the escape sequences of colored output.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
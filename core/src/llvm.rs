@@ -0,0 +1,255 @@
+//! LLVM toolchain utilities.
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use cargo_util::ProcessBuilder;
+use semver::{Comparator, Op, Version};
+use serde::{Deserialize, Serialize};
+
+use crate::error::CIError;
+use crate::{platform, CIResult};
+
+/// Minimum LLVM version support.
+pub const LLVM_MIN_VERSION: Version = Version::new(9, 0, 0);
+
+/// Maximum LLVM version support.
+pub const LLVM_MAX_VERSION: Version = Version::new(15, 0, 0);
+
+/// LLVM utility.
+#[derive(Copy, Clone, Debug)]
+pub enum LlvmUtility {
+    /// LLVM archiver.
+    Archiver,
+    /// LLVM config utility.
+    Config,
+    /// LLVM C language family front-end compiler.
+    Clang,
+    /// LLVM structural IR differ.
+    Diff,
+    /// LLVM bitcode disassembler.
+    Disassembler,
+    /// LLVM bitcode and symbols utility.
+    NameMangling,
+    /// LLVM optimizer.
+    Optimizer,
+    /// LLVM static compiler.
+    StaticCompiler,
+}
+
+impl LlvmUtility {
+    /// Gets the binary name of the utility.
+    pub fn as_str(&self) -> &str {
+        match self {
+            LlvmUtility::Archiver => "llvm-ar",
+            LlvmUtility::Config => "llvm-config",
+            LlvmUtility::Clang => "clang",
+            LlvmUtility::Diff => "llvm-diff",
+            LlvmUtility::Disassembler => "llvm-dis",
+            LlvmUtility::NameMangling => "llvm-nm",
+            LlvmUtility::Optimizer => "opt",
+            LlvmUtility::StaticCompiler => "llc",
+        }
+    }
+
+    /// Gets the process builder given the toolchain information.
+    pub fn process_builder(&self, toolchain: &LlvmToolchain) -> ProcessBuilder {
+        if let Some(bin_dir) = &toolchain.bin_dir {
+            ProcessBuilder::new(bin_dir.join(self.as_str()))
+        } else if toolchain.suffix {
+            ProcessBuilder::new(platform::versioned_utility(
+                self.as_str(),
+                toolchain.version.major,
+            ))
+        } else {
+            ProcessBuilder::new(self.as_str())
+        }
+    }
+}
+
+/// LLVM pass manager of `opt`.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum PassManager {
+    /// Legacy pass manager, loading the plugins with `-load`.
+    Legacy,
+    /// New pass manager, loading the plugins with `-load-pass-plugin`.
+    New,
+}
+
+impl PassManager {
+    /// Gets the name of the pass manager.
+    pub fn as_str(&self) -> &str {
+        match self {
+            PassManager::Legacy => "legacy",
+            PassManager::New => "new",
+        }
+    }
+}
+
+/// LLVM toolchain.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LlvmToolchain {
+    /// LLVM version.
+    pub version: Version,
+
+    /// LLVM utility contains version suffix.
+    suffix: bool,
+
+    /// Directory of the LLVM utilities, or `None` to find them in `PATH`.
+    pub bin_dir: Option<PathBuf>,
+}
+
+impl LlvmToolchain {
+    /// Gets the default pass manager of `opt` in the toolchain.
+    ///
+    /// `opt` runs the new pass manager by default since LLVM 13, and the legacy
+    /// one is disabled by some distributions of later versions.
+    pub fn pass_manager(&self) -> PassManager {
+        if self.version.major >= 13 {
+            PassManager::New
+        } else {
+            PassManager::Legacy
+        }
+    }
+}
+
+/// Creates the process builder of the program in the directory with the environment
+/// variables.
+fn command<T: AsRef<OsStr>>(program: T, dir: &Path, env: &[(String, String)]) -> ProcessBuilder {
+    let mut cmd = ProcessBuilder::new(program);
+    cmd.cwd(dir);
+    for (key, val) in env {
+        cmd.env(key, val);
+    }
+    cmd
+}
+
+/// Gets the LLVM version of `rustc` in the directory with the environment variables.
+pub fn rustc_version(dir: &Path, env: &[(String, String)]) -> CIResult<Version> {
+    let output = command("rustc", dir, env).arg("-vV").exec_with_output()?;
+    let stdout = String::from_utf8(output.stdout)?;
+    let version = Version::parse(
+        stdout
+            .lines()
+            .find_map(|line| line.strip_prefix("LLVM version: "))
+            .context("expect `LLVM version` field")?
+            .trim(),
+    )?;
+    Ok(version)
+}
+
+/// Probes the LLVM toolchain of the major version, e.g. for `llvm-config-14`, and
+/// resolves the directory of its utilities.
+pub fn probe_version(dir: &Path, env: &[(String, String)], major: u64) -> CIResult<LlvmToolchain> {
+    let output = command(platform::versioned_utility("llvm-config", major), dir, env)
+        .arg("--bindir")
+        .exec_with_output()
+        .map_err(|_| CIError::LLVMNotInstalled)?;
+    let bin_dir = PathBuf::from(String::from_utf8(output.stdout)?.trim());
+    probe(dir, env, Some(&bin_dir))
+}
+
+/// Probes the LLVM toolchain in the directory with the environment variables,
+/// which select the `rustc` toolchain through `rustup`.
+///
+/// The utilities are taken from `bin_dir` if given, or found in `PATH` otherwise.
+pub fn probe(
+    dir: &Path,
+    env: &[(String, String)],
+    bin_dir: Option<&Path>,
+) -> CIResult<LlvmToolchain> {
+    let process = |program: String| command(program, dir, env);
+
+    let rustc_llvm_version = rustc_version(dir, env)?;
+
+    if rustc_llvm_version < LLVM_MIN_VERSION || rustc_llvm_version >= LLVM_MAX_VERSION {
+        bail!(CIError::LLVMNotSupported(rustc_llvm_version))
+    }
+
+    // get llvm version from llvm-config with and without version suffix
+    let config = process("llvm-config".to_string())
+        .arg("--version")
+        .exec_with_output();
+    let config_suffix = process(platform::versioned_utility(
+        "llvm-config",
+        rustc_llvm_version.major,
+    ))
+    .arg("--version")
+    .exec_with_output();
+
+    let comparator = Comparator {
+        op: Op::Exact,
+        major: rustc_llvm_version.major,
+        minor: Some(rustc_llvm_version.minor),
+        patch: None,
+        pre: Default::default(),
+    };
+
+    if let Some(bin_dir) = bin_dir {
+        let output = command(bin_dir.join("llvm-config"), dir, env)
+            .arg("--version")
+            .exec_with_output()
+            .map_err(|_| CIError::LLVMNotInstalled)?;
+        let llvm_version = Version::parse(String::from_utf8(output.stdout)?.trim())?;
+        if !comparator.matches(&llvm_version) {
+            bail!(CIError::LLVMVersionNotMatch(
+                rustc_llvm_version,
+                llvm_version
+            ));
+        }
+        return Ok(LlvmToolchain {
+            version: rustc_llvm_version,
+            suffix: false,
+            bin_dir: Some(bin_dir.to_path_buf()),
+        });
+    }
+
+    // check if rustc and llvm are compatible and add version suffix if needed
+    let add_suffix = match (config, config_suffix) {
+        (Ok(out), Ok(out_suffix)) => {
+            let llvm_version = Version::parse(String::from_utf8(out.stdout)?.trim())?;
+            let llvm_version_suffix = Version::parse(String::from_utf8(out_suffix.stdout)?.trim())?;
+            if comparator.matches(&llvm_version) {
+                false
+            } else if comparator.matches(&llvm_version_suffix) {
+                true
+            } else {
+                bail!(CIError::LLVMVersionNotMatch(
+                    rustc_llvm_version,
+                    llvm_version
+                ));
+            }
+        }
+        (Ok(out), Err(_)) => {
+            let llvm_version = Version::parse(String::from_utf8(out.stdout)?.trim())?;
+            if !comparator.matches(&llvm_version) {
+                bail!(CIError::LLVMVersionNotMatch(
+                    rustc_llvm_version,
+                    llvm_version
+                ));
+            }
+            false
+        }
+        (Err(_), Ok(out_suffix)) => {
+            let llvm_version_suffix = Version::parse(String::from_utf8(out_suffix.stdout)?.trim())?;
+            if !comparator.matches(&llvm_version_suffix) {
+                bail!(CIError::LLVMVersionNotMatch(
+                    rustc_llvm_version,
+                    llvm_version_suffix
+                ));
+            }
+            true
+        }
+        (Err(_), Err(_)) => {
+            bail!(CIError::LLVMNotInstalled);
+        }
+    };
+
+    Ok(LlvmToolchain {
+        version: rustc_llvm_version,
+        suffix: add_suffix,
+        bin_dir: None,
+    })
+}
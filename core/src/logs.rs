@@ -0,0 +1,109 @@
+//! Failure logs of the integration.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use cargo_util::paths;
+
+use crate::config::Config;
+use crate::paths::PathExt;
+use crate::CIResult;
+
+/// Prefix of the failure log file names.
+const LOG_PREFIX: &str = "CI-";
+
+/// Format of the timestamp in the failure log file names.
+const LOG_TIMESTAMP_FORMAT: &str = "%y%m%dT%H%M%S";
+
+/// Prefix of the line containing the crate name of an error.
+pub const CRATE_PREFIX: &str = "crate: ";
+
+/// Prefix of the line containing the command line.
+const COMMAND_PREFIX: &str = "command: ";
+
+/// Failure log.
+#[derive(Debug)]
+pub struct Log {
+    /// Path to the log.
+    pub path: PathBuf,
+    /// Time of the failure.
+    pub time: Option<chrono::NaiveDateTime>,
+    /// Command line of the failed build.
+    pub command: Option<String>,
+    /// Crates failed to integrate or link.
+    pub crates: Vec<String>,
+}
+
+/// Gets the path for a new failure log.
+pub fn new_path() -> CIResult<PathBuf> {
+    let timestamp = chrono::Local::now().format(LOG_TIMESTAMP_FORMAT);
+    let mut path = Config::dir()?;
+    path.push(format!("{}{}.log", LOG_PREFIX, timestamp));
+    Ok(path)
+}
+
+/// Gets the name of the crate an error is attributed to, if any.
+pub fn crate_name(error: &anyhow::Error) -> Option<String> {
+    error.chain().find_map(|cause| {
+        cause
+            .to_string()
+            .strip_prefix(CRATE_PREFIX)
+            .map(str::to_string)
+    })
+}
+
+/// Appends an error to the failure log, writing the header first if the log is new.
+pub fn append<P: AsRef<Path>>(path: P, error: &anyhow::Error) -> CIResult<()> {
+    let path = path.as_ref();
+    let mut s = String::new();
+    if !path.exists() {
+        let command = std::env::args().collect::<Vec<_>>().join(" ");
+        let directory = std::env::current_dir()?;
+        let _ = writeln!(s, "{}{}", COMMAND_PREFIX, command);
+        let _ = writeln!(s, "directory: {}", directory.display());
+    }
+    s.push('\n');
+    for cause in error.chain() {
+        let _ = writeln!(s, "{}", cause);
+    }
+    paths::append(path, s.as_bytes()).context("failed to write the failure log")
+}
+
+/// Lists the failure logs, the most recent first.
+pub fn list() -> CIResult<Vec<Log>> {
+    let mut files = Config::dir()?.read_dir(|path| {
+        let file_name = path.file_name().unwrap_or_default();
+        file_name.starts_with(LOG_PREFIX) && path.extension().unwrap_or_default() == "log"
+    })?;
+    files.sort();
+    files.reverse();
+
+    files.into_iter().map(parse).collect()
+}
+
+/// Parses the failure log.
+fn parse(path: PathBuf) -> CIResult<Log> {
+    let time = path
+        .file_stem()?
+        .strip_prefix(LOG_PREFIX)
+        .and_then(|s| chrono::NaiveDateTime::parse_from_str(s, LOG_TIMESTAMP_FORMAT).ok());
+    let s = paths::read(&path)?;
+    let command = s
+        .lines()
+        .find_map(|line| line.strip_prefix(COMMAND_PREFIX))
+        .map(str::to_string);
+    let mut crates = Vec::new();
+    for name in s.lines().filter_map(|line| line.strip_prefix(CRATE_PREFIX)) {
+        if !crates.iter().any(|e| e == name) {
+            crates.push(name.to_string());
+        }
+    }
+
+    Ok(Log {
+        path,
+        time,
+        command,
+        crates,
+    })
+}
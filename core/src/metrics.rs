@@ -0,0 +1,445 @@
+//! Build statistics exported for external monitoring.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::Context;
+use cargo_util::paths;
+
+use crate::config::Config;
+use crate::error::CIError;
+use crate::observer::IntegrationPhase;
+use crate::paths::PathExt;
+use crate::{util, CIResult};
+
+/// Gets the ratio of two counts.
+#[allow(clippy::cast_precision_loss)]
+fn ratio(numerator: usize, denominator: usize) -> f64 {
+    numerator as f64 / denominator as f64
+}
+
+/// Phase of the build.
+#[derive(Copy, Clone, Debug)]
+pub enum Phase {
+    /// `cargo build` invocation.
+    Cargo,
+    /// Running `opt` and `llc` on the LLVM IR files.
+    Integration,
+    /// Relinking the binaries.
+    Linking,
+}
+
+impl Phase {
+    /// Gets the label value of the phase.
+    fn as_str(&self) -> &str {
+        match self {
+            Phase::Cargo => "cargo",
+            Phase::Integration => "integration",
+            Phase::Linking => "linking",
+        }
+    }
+}
+
+/// Result of the integration of a crate, the most relevant one of its codegen units.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum CrateResult {
+    /// Every codegen unit of the crate is skipped.
+    Skipped,
+    /// A codegen unit of the crate is integrated.
+    Integrated,
+    /// The crate failed to integrate or link.
+    Failed,
+}
+
+/// Durations of the phases of a crate or a binary, and the size of its LLVM IR files.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct CrateTimings {
+    /// Duration of `opt` on the LLVM IR files.
+    pub opt: Duration,
+    /// Duration of `llc` on the CI-integrated LLVM IR files.
+    pub llc: Duration,
+    /// Duration of the linker relinking the binary.
+    pub link: Duration,
+    /// Size of the LLVM IR files processed.
+    pub ir_size: u64,
+}
+
+impl CrateTimings {
+    /// Gets the total duration of the phases.
+    pub fn total(&self) -> Duration {
+        self.opt + self.llc + self.link
+    }
+}
+
+/// Statistics collected during a build.
+#[derive(Default, Debug)]
+pub struct Metrics {
+    /// Duration of each finished phase.
+    phases: Mutex<Vec<(Phase, Duration)>>,
+    /// Crate name -> result of its integration, counted once per crate rather than per
+    /// codegen unit.
+    results: Mutex<BTreeMap<String, CrateResult>>,
+    /// Number of cache hits.
+    pub cache_hits: AtomicUsize,
+    /// Number of cache misses.
+    pub cache_misses: AtomicUsize,
+    /// Linked CI-integrated binaries.
+    pub binaries: Mutex<Vec<PathBuf>>,
+    /// Crate or binary name -> durations of its phases, summed over its codegen units.
+    crates: Mutex<BTreeMap<String, CrateTimings>>,
+}
+
+impl Metrics {
+    /// Records the duration of a phase.
+    pub fn record(&self, phase: Phase, duration: Duration) {
+        self.phases
+            .lock()
+            .expect("failed to acquire lock")
+            .push((phase, duration));
+    }
+
+    /// Records the duration of a phase of the crate.
+    pub fn time(&self, crate_name: &str, phase: IntegrationPhase, duration: Duration) {
+        let mut crates = self.crates.lock().expect("failed to acquire lock");
+        let timings = crates.entry(crate_name.to_string()).or_default();
+        match phase {
+            IntegrationPhase::Integrating => timings.opt += duration,
+            IntegrationPhase::StaticCompiling => timings.llc += duration,
+            IntegrationPhase::Linking => timings.link += duration,
+        }
+    }
+
+    /// Records the result of a codegen unit of the crate, a failure overriding the
+    /// integration of its other codegen units, which overrides their skipping.
+    pub fn set_result(&self, crate_name: &str, result: CrateResult) {
+        let mut results = self.results.lock().expect("failed to acquire lock");
+        let entry = results.entry(crate_name.to_string()).or_insert(result);
+        *entry = (*entry).max(result);
+    }
+
+    /// Gets the number of crates with the result.
+    pub fn count(&self, result: CrateResult) -> usize {
+        self.results
+            .lock()
+            .expect("failed to acquire lock")
+            .values()
+            .filter(|&&r| r == result)
+            .count()
+    }
+
+    /// Records the size of an LLVM IR file of the crate.
+    pub fn add_ir_size(&self, crate_name: &str, size: u64) {
+        let mut crates = self.crates.lock().expect("failed to acquire lock");
+        crates.entry(crate_name.to_string()).or_default().ir_size += size;
+    }
+
+    /// Gets the timings of the crates and the binaries, the slowest first.
+    pub fn timings(&self) -> Vec<(String, CrateTimings)> {
+        let mut timings = self
+            .crates
+            .lock()
+            .expect("failed to acquire lock")
+            .iter()
+            .map(|(crate_name, timings)| (crate_name.clone(), *timings))
+            .collect::<Vec<_>>();
+        timings.sort_by_key(|(_, timings)| std::cmp::Reverse(timings.total()));
+        timings
+    }
+
+    /// Gets the timings as a table of the crates, the slowest first, limited to the
+    /// number of rows if any.
+    pub fn timings_table(&self, limit: Option<usize>) -> String {
+        let timings = self.timings();
+        let rows = limit.unwrap_or(timings.len()).min(timings.len());
+        let width = timings[..rows]
+            .iter()
+            .map(|(crate_name, _)| crate_name.len())
+            .chain(std::iter::once("total".len()))
+            .max()
+            .unwrap_or_default();
+        let duration = |duration: Duration| {
+            if duration.is_zero() {
+                "-".to_string()
+            } else {
+                util::human_duration(duration)
+            }
+        };
+
+        let mut s = String::new();
+        let _ = writeln!(
+            s,
+            "{:<width$}  {:>9}  {:>9}  {:>9}  {:>10}",
+            "crate", "opt", "llc", "link", "IR size"
+        );
+        for (crate_name, timings) in &timings[..rows] {
+            let _ = writeln!(
+                s,
+                "{:<width$}  {:>9}  {:>9}  {:>9}  {:>10}",
+                crate_name,
+                duration(timings.opt),
+                duration(timings.llc),
+                duration(timings.link),
+                util::human_size(timings.ir_size)
+            );
+        }
+        if rows < timings.len() {
+            let _ = writeln!(s, "... and {} more", timings.len() - rows);
+        }
+        let total = timings
+            .iter()
+            .fold(CrateTimings::default(), |mut total, (_, timings)| {
+                total.opt += timings.opt;
+                total.llc += timings.llc;
+                total.link += timings.link;
+                total.ir_size += timings.ir_size;
+                total
+            });
+        let _ = writeln!(
+            s,
+            "{:<width$}  {:>9}  {:>9}  {:>9}  {:>10}",
+            "total",
+            duration(total.opt),
+            duration(total.llc),
+            duration(total.link),
+            util::human_size(total.ir_size)
+        );
+        s
+    }
+
+    /// Gets the timings of the crates as JSON, with the durations in seconds.
+    pub fn timings_json(&self) -> serde_json::Value {
+        let crates = self
+            .timings()
+            .into_iter()
+            .map(|(crate_name, timings)| {
+                serde_json::json!({
+                    "crate": crate_name,
+                    "opt": timings.opt.as_secs_f64(),
+                    "llc": timings.llc.as_secs_f64(),
+                    "link": timings.link.as_secs_f64(),
+                    "total": timings.total().as_secs_f64(),
+                    "ir_size": timings.ir_size,
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::json!({ "crates": crates })
+    }
+
+    /// Increments a counter by one.
+    pub fn inc(counter: &AtomicUsize) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Gets the JSON summary of the build.
+    pub fn summary(&self, total: Duration, result: &CIResult<()>) -> serde_json::Value {
+        let count = |counter: &AtomicUsize| counter.load(Ordering::Relaxed);
+        let phases = self
+            .phases
+            .lock()
+            .expect("failed to acquire lock")
+            .iter()
+            .map(|(phase, duration)| (phase.as_str().to_string(), duration.as_secs_f64().into()))
+            .collect::<serde_json::Map<_, _>>();
+        let binaries = self.binaries.lock().expect("failed to acquire lock");
+
+        serde_json::json!({
+            "success": result.is_ok(),
+            "duration_seconds": total.as_secs_f64(),
+            "phases": phases,
+            "crates": {
+                "integrated": self.count(CrateResult::Integrated),
+                "skipped": self.count(CrateResult::Skipped),
+                "failed": self.count(CrateResult::Failed),
+            },
+            "cache": {
+                "hits": count(&self.cache_hits),
+                "misses": count(&self.cache_misses),
+            },
+            "binaries": *binaries,
+            "error": result.as_ref().err().map(|error| format!("{:#}", error)),
+            "code": result.as_ref().err().and_then(CIError::find).map(CIError::code),
+        })
+    }
+
+    /// Gets the markdown summary of the build for CI pipelines.
+    pub fn markdown(&self, total: Duration, result: &CIResult<()>, config: &Config) -> String {
+        let count = |counter: &AtomicUsize| counter.load(Ordering::Relaxed);
+        let binaries = self
+            .binaries
+            .lock()
+            .expect("failed to acquire lock")
+            .iter()
+            .filter_map(|binary| binary.file_name().ok())
+            .map(|name| format!("`{}`", name))
+            .collect::<Vec<_>>();
+        let phases = self
+            .phases
+            .lock()
+            .expect("failed to acquire lock")
+            .iter()
+            .map(|(phase, duration)| {
+                format!("{} {}", phase.as_str(), util::human_duration(*duration))
+            })
+            .collect::<Vec<_>>();
+
+        let mut s = String::from("### Compiler Interrupts integration\n\n| | |\n|---|---|\n");
+        let mut row = |key: &str, value: String| {
+            let _ = writeln!(s, "| {} | {} |", key, value);
+        };
+        row(
+            "Result",
+            if result.is_ok() { "success" } else { "failure" }.to_string(),
+        );
+        row("Duration", util::human_duration(total));
+        if !phases.is_empty() {
+            row("Phases", phases.join(", "));
+        }
+        row(
+            "Crates integrated",
+            self.count(CrateResult::Integrated).to_string(),
+        );
+        row(
+            "Crates skipped",
+            self.count(CrateResult::Skipped).to_string(),
+        );
+        row("Crates failed", self.count(CrateResult::Failed).to_string());
+        let (hits, misses) = (count(&self.cache_hits), count(&self.cache_misses));
+        if hits + misses > 0 {
+            row("Cache", format!("{} hits, {} misses", hits, misses));
+        }
+        if !binaries.is_empty() {
+            row("Binaries", binaries.join(", "));
+        }
+        row(
+            "Library",
+            format!(
+                "LLVM {}, checksum `{}`",
+                config.llvm_version, config.checksum
+            ),
+        );
+        row(
+            "Library arguments",
+            format!("`{}`", config.library_args.join(" ")),
+        );
+
+        s
+    }
+
+    /// Writes the metrics in the Prometheus textfile format.
+    ///
+    /// The file is written to a temporary file first and then renamed so the
+    /// textfile collector never reads a partially written file.
+    pub fn write_prometheus<P: AsRef<Path>>(
+        &self,
+        path: P,
+        total: Duration,
+        success: bool,
+    ) -> CIResult<()> {
+        let path = path.as_ref();
+        let mut s = String::new();
+
+        let mut gauge = |name: &str, help: &str, samples: &[(Option<&str>, String)]| {
+            let _ = writeln!(s, "# HELP cargo_ci_{} {}", name, help);
+            let _ = writeln!(s, "# TYPE cargo_ci_{} gauge", name);
+            for (label, value) in samples {
+                match label {
+                    Some(label) => {
+                        let _ = writeln!(s, "cargo_ci_{}{{{}}} {}", name, label, value);
+                    }
+                    None => {
+                        let _ = writeln!(s, "cargo_ci_{} {}", name, value);
+                    }
+                }
+            }
+        };
+
+        gauge(
+            "build_duration_seconds",
+            "Total duration of the build.",
+            &[(None, total.as_secs_f64().to_string())],
+        );
+
+        let phases = self.phases.lock().expect("failed to acquire lock");
+        let labels = phases
+            .iter()
+            .map(|(phase, _)| format!("phase=\"{}\"", phase.as_str()))
+            .collect::<Vec<_>>();
+        let samples = phases
+            .iter()
+            .zip(labels.iter())
+            .map(|((_, duration), label)| {
+                (Some(label.as_str()), duration.as_secs_f64().to_string())
+            })
+            .collect::<Vec<_>>();
+        gauge(
+            "phase_duration_seconds",
+            "Duration of each phase of the build.",
+            &samples,
+        );
+
+        let crates = |result: CrateResult| self.count(result).to_string();
+        gauge(
+            "crates",
+            "Number of crates by integration result.",
+            &[
+                (
+                    Some("result=\"integrated\""),
+                    crates(CrateResult::Integrated),
+                ),
+                (Some("result=\"skipped\""), crates(CrateResult::Skipped)),
+                (Some("result=\"failed\""), crates(CrateResult::Failed)),
+            ],
+        );
+        let count = |counter: &AtomicUsize| counter.load(Ordering::Relaxed).to_string();
+        gauge(
+            "cache_lookups",
+            "Number of cache lookups by result.",
+            &[
+                (Some("result=\"hit\""), count(&self.cache_hits)),
+                (Some("result=\"miss\""), count(&self.cache_misses)),
+            ],
+        );
+        let hits = self.cache_hits.load(Ordering::Relaxed);
+        let lookups = hits + self.cache_misses.load(Ordering::Relaxed);
+        if lookups > 0 {
+            gauge(
+                "cache_hit_ratio",
+                "Ratio of cache hits to cache lookups.",
+                &[(None, format!("{:.4}", ratio(hits, lookups)))],
+            );
+        }
+        gauge(
+            "binaries_linked",
+            "Number of linked CI-integrated binaries.",
+            &[(
+                None,
+                self.binaries
+                    .lock()
+                    .expect("failed to acquire lock")
+                    .len()
+                    .to_string(),
+            )],
+        );
+        gauge(
+            "build_success",
+            "Whether the build has succeeded.",
+            &[(None, u8::from(success).to_string())],
+        );
+        gauge(
+            "build_timestamp_seconds",
+            "Unix timestamp of the build completion.",
+            &[(None, chrono::Utc::now().timestamp().to_string())],
+        );
+
+        let tmp_path = path.append_suffix("tmp")?;
+        paths::write(&tmp_path, s).context("failed to write the metrics")?;
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("failed to save the metrics `{}`", path.display()))?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,349 @@
+//! Archives of the test harnesses in the format of `cargo nextest archive`.
+//!
+//! An archive is a tarball of the files of the target directory under `target/`,
+//! compressed with zstd by the caller, so that `cargo nextest run --archive-file` runs
+//! the harnesses on another machine. Besides the harnesses, it has the metadata read
+//! by `cargo-nextest`:
+//!
+//! * [`CARGO_METADATA_PATH`], the output of `cargo metadata --format-version=1`.
+//! * [`BINARIES_METADATA_PATH`], the harnesses and the layout of the target directory.
+//!
+//! The harnesses are identified by their crate root, the first file of the dep-info
+//! written next to them by `rustc`, e.g. `deps/demo-0123456789abcdef.d`, as the unit
+//! tests of a library and of a binary of the same name have the same crate name.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::paths::PathExt;
+use crate::CIResult;
+
+/// Path to the metadata of `cargo` in the archive.
+pub const CARGO_METADATA_PATH: &str = "target/nextest/cargo-metadata.json";
+
+/// Path to the metadata of the harnesses in the archive.
+pub const BINARIES_METADATA_PATH: &str = "target/nextest/binaries-metadata.json";
+
+/// Directory of the files of the target directory in the archive.
+pub const TARGET_DIR: &str = "target";
+
+/// Extension of the archives read by `cargo-nextest`.
+pub const ARCHIVE_EXTENSION: &str = ".tar.zst";
+
+/// Size of the blocks of a tarball.
+const BLOCK_SIZE: usize = 512;
+
+/// Metadata of the harnesses, `binaries-metadata.json`.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct BinaryList {
+    /// Layout of the target directory.
+    pub rust_build_meta: RustBuildMeta,
+    /// Binary ID -> harness.
+    pub rust_binaries: BTreeMap<String, RustTestBinary>,
+}
+
+/// Layout of the target directory of the build.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct RustBuildMeta {
+    /// Absolute path to the target directory.
+    pub target_directory: PathBuf,
+    /// Directories of the build modes relative to the target directory, e.g. `debug`.
+    pub base_output_directories: BTreeSet<PathBuf>,
+    /// Package ID -> binaries given to the integration tests by `CARGO_BIN_EXE_<name>`.
+    pub non_test_binaries: BTreeMap<String, BTreeSet<RustNonTestBinary>>,
+    /// Package ID -> `OUT_DIR` of its build script, relative to the target directory.
+    pub build_script_out_dirs: BTreeMap<String, PathBuf>,
+    /// Directories of the native libraries, relative to the target directory.
+    pub linked_paths: BTreeSet<PathBuf>,
+    /// Target triple given to `cargo` by `--target`, or the host if none.
+    pub target_platform: Option<String>,
+}
+
+/// Binary of a package that is not a harness.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct RustNonTestBinary {
+    /// Name of the binary target.
+    pub name: String,
+    /// Kind of the target, e.g. `bin`.
+    pub kind: String,
+    /// Path to the binary relative to the target directory.
+    pub path: PathBuf,
+}
+
+/// Test harness.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct RustTestBinary {
+    /// ID of the harness, e.g. `demo` of the unit tests of the library, `demo::bin/demo`
+    /// of the ones of the binary, and `demo::integration` of an integration test.
+    pub binary_id: String,
+    /// Name of the target.
+    pub binary_name: String,
+    /// ID of the package in the metadata of `cargo`.
+    pub package_id: String,
+    /// Kind of the target, e.g. `lib`, `bin`, or `test`.
+    pub kind: String,
+    /// Absolute path to the harness.
+    pub binary_path: PathBuf,
+    /// Platform the harness is built for, `target` or `host` of the procedural macros.
+    pub build_platform: String,
+}
+
+impl RustTestBinary {
+    /// Finds the target of the workspace member with the crate root in the metadata of
+    /// `cargo`, returning `None` if there is none.
+    ///
+    /// The crate roots of the members are relative to the workspace root, where `cargo`
+    /// runs `rustc`.
+    pub fn find(
+        metadata: &serde_json::Value,
+        crate_root: &Path,
+        binary_path: PathBuf,
+    ) -> Option<RustTestBinary> {
+        let crate_root = Path::new(metadata["workspace_root"].as_str()?).join(crate_root);
+        let crate_root = crate_root.as_path();
+        let members = metadata["workspace_members"].as_array()?;
+        let packages = metadata["packages"].as_array()?;
+        for package in packages {
+            let package_id = package["id"].as_str()?;
+            if !members.iter().any(|member| member == package_id) {
+                continue;
+            }
+            let target = package["targets"]
+                .as_array()?
+                .iter()
+                .find(|target| target["src_path"].as_str().map(Path::new) == Some(crate_root));
+            if let Some(target) = target {
+                let package_name = package["name"].as_str()?;
+                let name = target["name"].as_str()?;
+                let kind = binary_kind(target["kind"].as_array()?.first()?.as_str()?);
+                let binary_id = match kind {
+                    "lib" | "proc-macro" => package_name.to_string(),
+                    "test" => format!("{}::{}", package_name, name),
+                    kind => format!("{}::{}/{}", package_name, kind, name),
+                };
+                return Some(RustTestBinary {
+                    binary_id,
+                    binary_name: name.to_string(),
+                    package_id: package_id.to_string(),
+                    kind: kind.to_string(),
+                    binary_path,
+                    build_platform: if kind == "proc-macro" {
+                        "host".to_string()
+                    } else {
+                        "target".to_string()
+                    },
+                });
+            }
+        }
+        None
+    }
+}
+
+/// Gets the binaries of the workspace members built in the directory of the build
+/// mode, e.g. `debug`, which `cargo` builds for their integration tests.
+pub fn non_test_binaries(
+    metadata: &serde_json::Value,
+    target_dir: &Path,
+    base_dir: &Path,
+) -> BTreeMap<String, BTreeSet<RustNonTestBinary>> {
+    let members = metadata["workspace_members"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let mut binaries = BTreeMap::<String, BTreeSet<RustNonTestBinary>>::new();
+    for package in metadata["packages"].as_array().into_iter().flatten() {
+        let package_id = match package["id"].as_str() {
+            Some(package_id) if members.iter().any(|member| member == package_id) => package_id,
+            _ => continue,
+        };
+        for target in package["targets"].as_array().into_iter().flatten() {
+            let is_bin = target["kind"]
+                .as_array()
+                .is_some_and(|kind| kind.iter().any(|kind| kind == "bin"));
+            let name = match target["name"].as_str() {
+                Some(name) if is_bin => name,
+                _ => continue,
+            };
+            let path = base_dir.join(format!("{}{}", name, std::env::consts::EXE_SUFFIX));
+            if target_dir.join(&path).is_file() {
+                binaries
+                    .entry(package_id.to_string())
+                    .or_default()
+                    .insert(RustNonTestBinary {
+                        name: name.to_string(),
+                        kind: "bin".to_string(),
+                        path,
+                    });
+            }
+        }
+    }
+    binaries
+}
+
+/// Gets the kind of a harness from the kind of its target, the crate types of the
+/// libraries being all `lib`.
+fn binary_kind(kind: &str) -> &str {
+    match kind {
+        "rlib" | "dylib" | "cdylib" | "staticlib" => "lib",
+        kind => kind,
+    }
+}
+
+/// Gets the crate root from the dep-info written by `rustc`, the first dependency of
+/// its first line, e.g. `src/lib.rs` of
+/// `/home/user/demo/target/debug/deps/demo-0123456789abcdef.d: src/lib.rs`.
+pub fn crate_root(dep_info: &str) -> Option<PathBuf> {
+    let (_, deps) = dep_info.lines().next()?.split_once(": ")?;
+    // spaces in the paths are escaped
+    let mut root = String::new();
+    let mut chars = deps.trim_start().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(' ') => root.push(' '),
+                Some(c) => {
+                    root.push('\\');
+                    root.push(c);
+                }
+                None => root.push('\\'),
+            },
+            c if c.is_whitespace() => break,
+            c => root.push(c),
+        }
+    }
+    Some(PathBuf::from(root)).filter(|root| !root.as_os_str().is_empty())
+}
+
+/// Writer of the tarball of an archive, in the `ustar` format.
+#[derive(Debug)]
+pub struct ArchiveWriter<W: Write> {
+    /// Output of the tarball.
+    writer: W,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Creates the writer of the tarball to the output.
+    pub fn new(writer: W) -> Self {
+        ArchiveWriter { writer }
+    }
+
+    /// Appends the file at the path in the archive, keeping its executable mode.
+    pub fn append_file(&mut self, path: &str, file: &Path) -> CIResult<()> {
+        let reader =
+            File::open(file).with_context(|| format!("failed to read `{}`", file.display()))?;
+        let metadata = reader
+            .metadata()
+            .with_context(|| format!("failed to read `{}`", file.display()))?;
+        let mode = if file.executable() {
+            0o755
+        } else {
+            0o644
+        };
+        let mtime = metadata.modified().unwrap_or(UNIX_EPOCH);
+        let size = metadata.len();
+        self.append_header(path, size, mode, mtime)?;
+        // exactly the size in the header, even if the file is changed meanwhile
+        let copied = io::copy(&mut reader.take(size), &mut self.writer)?;
+        if copied != size {
+            bail!(
+                "`{}` was truncated while being archived, {} of {} bytes read",
+                file.display(),
+                copied,
+                size
+            );
+        }
+        self.pad(size)
+    }
+
+    /// Appends the data as a file at the path in the archive.
+    pub fn append_data(&mut self, path: &str, data: &[u8]) -> CIResult<()> {
+        let size = u64::try_from(data.len())?;
+        self.append_header(path, size, 0o644, SystemTime::now())?;
+        self.writer.write_all(data)?;
+        self.pad(size)
+    }
+
+    /// Writes the end of the tarball, returning the output.
+    pub fn finish(mut self) -> CIResult<W> {
+        self.writer.write_all(&[0; BLOCK_SIZE * 2])?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    /// Writes the header of a regular file.
+    fn append_header(
+        &mut self,
+        path: &str,
+        size: u64,
+        mode: u32,
+        mtime: SystemTime,
+    ) -> CIResult<()> {
+        let (prefix, name) = split_path(path)?;
+        if size >= 1 << 33 {
+            bail!("`{}` is too large for the archive", path);
+        }
+        let mtime = mtime
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |mtime| mtime.as_secs());
+
+        let mut header = [0; BLOCK_SIZE];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        write_octal(&mut header[100..108], u64::from(mode));
+        write_octal(&mut header[108..116], 0);
+        write_octal(&mut header[116..124], 0);
+        write_octal(&mut header[124..136], size);
+        write_octal(&mut header[136..148], mtime);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+        // the checksum is computed with its own field filled with spaces
+        header[148..156].fill(b' ');
+        let checksum = header.iter().map(|&byte| u64::from(byte)).sum::<u64>();
+        write_octal(&mut header[148..155], checksum);
+        header[155] = b' ';
+
+        self.writer.write_all(&header)?;
+        Ok(())
+    }
+
+    /// Pads the data of a file to the size of the blocks.
+    fn pad(&mut self, size: u64) -> CIResult<()> {
+        let rest = usize::try_from(size % BLOCK_SIZE as u64)?;
+        if rest != 0 {
+            self.writer.write_all(&[0; BLOCK_SIZE][rest..])?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits the path into the prefix and the name fields of `ustar`, of at most 155 and
+/// 100 bytes.
+fn split_path(path: &str) -> CIResult<(&str, &str)> {
+    if path.len() <= 100 {
+        return Ok(("", path));
+    }
+    path.char_indices()
+        .filter(|&(_, c)| c == '/')
+        .map(|(idx, _)| (&path[..idx], &path[idx + 1..]))
+        .find(|(prefix, name)| prefix.len() <= 155 && !name.is_empty() && name.len() <= 100)
+        .with_context(|| format!("path `{}` is too long for the archive", path))
+}
+
+/// Writes the number in octal to the field, ending with a NUL.
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+}
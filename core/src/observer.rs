@@ -0,0 +1,87 @@
+//! Observer of the integration progress for the progress bar and other front-ends.
+
+/// Phase of a crate in the integration.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum IntegrationPhase {
+    /// Integrating the LLVM IR file with `opt`.
+    Integrating,
+    /// Compiling the integrated LLVM IR file to an object file with `llc`.
+    StaticCompiling,
+    /// Linking the CI-integrated binary.
+    Linking,
+}
+
+impl IntegrationPhase {
+    /// Gets the name of the phase.
+    pub fn as_str(&self) -> &str {
+        match self {
+            IntegrationPhase::Integrating => "integrating",
+            IntegrationPhase::StaticCompiling => "static_compiling",
+            IntegrationPhase::Linking => "linking",
+        }
+    }
+}
+
+/// Observer of the integration progress.
+///
+/// Methods are called from the worker threads as the crates progress, so they
+/// should return quickly. Every method does nothing by default.
+#[allow(unused_variables)]
+pub trait IntegrationObserver: Send + Sync {
+    /// Called when a phase of the crate is started.
+    fn on_crate_started(&self, crate_name: &str, phase: IntegrationPhase) {}
+
+    /// Called when a phase of the crate is finished.
+    fn on_phase_finished(&self, crate_name: &str, phase: IntegrationPhase) {}
+
+    /// Called when the integration of the crate is skipped.
+    fn on_skipped(&self, crate_name: &str) {}
+
+    /// Called when the object file of the crate is restored from the cache.
+    fn on_cached(&self, crate_name: &str) {}
+
+    /// Called when a tool succeeded on the crate but printed diagnostics.
+    fn on_warning(&self, crate_name: &str, message: &str) {}
+
+    /// Called when a phase of the crate failed.
+    fn on_error(&self, crate_name: &str, message: &str) {}
+
+    /// Called when all integration and linking jobs are done.
+    fn on_finished(&self) {}
+}
+
+impl IntegrationObserver for Vec<Box<dyn IntegrationObserver>> {
+    fn on_crate_started(&self, crate_name: &str, phase: IntegrationPhase) {
+        self.iter()
+            .for_each(|observer| observer.on_crate_started(crate_name, phase));
+    }
+
+    fn on_phase_finished(&self, crate_name: &str, phase: IntegrationPhase) {
+        self.iter()
+            .for_each(|observer| observer.on_phase_finished(crate_name, phase));
+    }
+
+    fn on_skipped(&self, crate_name: &str) {
+        self.iter()
+            .for_each(|observer| observer.on_skipped(crate_name));
+    }
+
+    fn on_cached(&self, crate_name: &str) {
+        self.iter()
+            .for_each(|observer| observer.on_cached(crate_name));
+    }
+
+    fn on_warning(&self, crate_name: &str, message: &str) {
+        self.iter()
+            .for_each(|observer| observer.on_warning(crate_name, message));
+    }
+
+    fn on_error(&self, crate_name: &str, message: &str) {
+        self.iter()
+            .for_each(|observer| observer.on_error(crate_name, message));
+    }
+
+    fn on_finished(&self) {
+        self.iter().for_each(|observer| observer.on_finished());
+    }
+}
@@ -1,10 +1,13 @@
 //! Paths utilities
 
+use std::ffi::OsString;
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 
-use crate::CIResult;
+use crate::{platform, CIResult};
 
 /// Extension trait for `AsRef<Path>`.
 pub trait PathExt {
@@ -29,6 +32,10 @@ pub trait PathExt {
     /// Appends the suffix to the file stem of a path.
     fn append_suffix(&self, suffix: &str) -> CIResult<PathBuf>;
 
+    /// Gets the compilation unit of an artifact, e.g. `foo-0123456789abcdef` for both
+    /// `foo-0123456789abcdef.foo.a1b2c3-cgu.0.rcgu.ll` and `libfoo-0123456789abcdef.rlib`.
+    fn unit_name(&self) -> CIResult<String>;
+
     /// Reads the directory for files matching the predicate.
     fn read_dir<P>(&self, predicate: P) -> CIResult<Vec<PathBuf>>
     where
@@ -79,10 +86,7 @@ where
     }
 
     fn executable(&self) -> bool {
-        use std::os::unix::prelude::*;
-        std::fs::metadata(self.as_ref())
-            .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
-            .unwrap_or(false)
+        platform::is_executable(self.as_ref())
     }
 
     fn append_suffix(&self, suffix: &str) -> CIResult<PathBuf> {
@@ -96,6 +100,16 @@ where
         Ok(self.as_ref().with_file_name(file_name))
     }
 
+    fn unit_name(&self) -> CIResult<String> {
+        let file_name = self.file_name()?;
+        let unit = file_name.split('.').next().unwrap_or_default();
+        let unit = match self.extension().as_deref() {
+            Ok("rlib" | "rmeta" | "a" | "so" | "dylib") => unit.strip_prefix("lib").unwrap_or(unit),
+            _ => unit,
+        };
+        Ok(unit.to_string())
+    }
+
     fn read_dir<P>(&self, predicate: P) -> CIResult<Vec<PathBuf>>
     where
         P: FnMut(&PathBuf) -> bool,
@@ -110,3 +124,40 @@ where
             .collect::<Vec<_>>())
     }
 }
+
+/// Rotates the file once it reaches the size, renaming it to `<path>.1` and the older
+/// ones up to `<path>.<count>`, removing the oldest one. Returns true if rotated.
+///
+/// A file already rotated by another process is skipped.
+pub fn rotate<P: AsRef<Path>>(path: P, max_size: u64, count: usize) -> CIResult<bool> {
+    let path = path.as_ref();
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.len() >= max_size => {}
+        _ => return Ok(false),
+    }
+    let rotated = |n: usize| {
+        let mut path = OsString::from(path);
+        path.push(format!(".{}", n));
+        PathBuf::from(path)
+    };
+    if count == 0 {
+        ignore_not_found(fs::remove_file(path))?;
+        return Ok(true);
+    }
+    ignore_not_found(fs::remove_file(rotated(count)))?;
+    for n in (1..count).rev() {
+        ignore_not_found(fs::rename(rotated(n), rotated(n + 1)))?;
+    }
+    ignore_not_found(fs::rename(path, rotated(1)))?;
+    Ok(true)
+}
+
+/// Ignores the error of a file not found.
+fn ignore_not_found(result: io::Result<()>) -> CIResult<()> {
+    match result {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => {
+            Err(error).context("failed to rotate the file")
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,111 @@
+//! Integration with the `perf` profiler.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use cargo_util::{paths, ProcessBuilder};
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::paths::PathExt;
+use crate::probes::FunctionProbes;
+use crate::CIResult;
+
+/// CI configuration used to record a `perf.data`.
+#[derive(Serialize, Debug)]
+struct PerfTag<'a> {
+    /// Path to the recorded binary.
+    binary: String,
+    /// Arguments for the library.
+    library_args: &'a [String],
+    /// LLVM version used to compile the library.
+    llvm_version: &'a str,
+    /// Checksum of the library source code.
+    checksum: &'a str,
+    /// Time of the recording.
+    recorded_at: String,
+}
+
+/// Gets the path to the `perf probe` commands of a CI-integrated binary, next to it.
+pub fn probes_path<P: AsRef<Path>>(binary: P) -> PathBuf {
+    binary.as_ref().with_extension("perf-probes")
+}
+
+/// Writes the `perf probe` commands adding a probe on each probed function of a
+/// CI-integrated binary, one per line, e.g. to be run with `sh`.
+///
+/// `perf` resolves the symbols in the binary itself, so the probes are placed at the
+/// runtime addresses of the functions, also in position-independent executables.
+pub fn write_probes(
+    binary: impl AsRef<Path>,
+    functions: &[FunctionProbes],
+    path: impl AsRef<Path>,
+) -> CIResult<()> {
+    let binary = binary.as_ref();
+    let mut s = String::from("# perf probe commands of the probed functions\n");
+    for function in functions.iter().filter(|f| f.probes > 0) {
+        let _ = writeln!(s, "{}", probe_command(binary, &function.symbol));
+    }
+    paths::write(path, s).context("failed to write the perf probe commands")
+}
+
+/// Gets an example `perf` command line to record a CI-integrated binary.
+pub fn record_command<P: AsRef<Path>>(binary: P) -> String {
+    let binary = binary.as_ref();
+    format!(
+        "perf record -g -o {}.perf.data -- {}",
+        binary.display(),
+        binary.display()
+    )
+}
+
+/// Gets an example `perf` command line to add a probe on a function of a binary.
+pub fn probe_command<P: AsRef<Path>>(binary: P, symbol: &str) -> String {
+    format!(
+        "perf probe -x {} --add '{}'",
+        binary.as_ref().display(),
+        symbol
+    )
+}
+
+/// Records a CI-integrated binary with `perf`, saving the CI configuration
+/// used next to the `perf.data` as `<perf.data>.ci.toml`.
+pub fn record(
+    config: &Config,
+    binary: impl AsRef<Path>,
+    binary_args: &[String],
+    output: impl AsRef<Path>,
+    cwd: Option<&Path>,
+    launcher: &[String],
+) -> CIResult<()> {
+    let binary = binary.as_ref();
+    let output = output.as_ref();
+
+    let tag = PerfTag {
+        binary: binary.to_string()?,
+        library_args: &config.library_args,
+        llvm_version: &config.llvm_version,
+        checksum: &config.checksum,
+        recorded_at: chrono::Local::now().to_rfc3339(),
+    };
+    let tag_file = format!("{}.ci.toml", output.display());
+    let s = toml::to_string_pretty(&tag).context("failed to serialize the CI configuration")?;
+    paths::write(&tag_file, s).context("failed to save the CI configuration")?;
+
+    // the launcher, e.g. pinning to CPUs, applies to `perf` and the recorded binary
+    let mut cmd = match launcher {
+        [program, launcher_args @ ..] => {
+            let mut cmd = ProcessBuilder::new(program);
+            cmd.args(launcher_args).arg("perf");
+            cmd
+        }
+        [] => ProcessBuilder::new("perf"),
+    };
+    cmd.args(&["record", "-g", "-o"]).arg(output).arg("--");
+    cmd.arg(binary).args(binary_args);
+    if let Some(cwd) = cwd {
+        cmd.cwd(cwd);
+    }
+    cmd.exec_replace()
+}
@@ -0,0 +1,1241 @@
+//! Integration of the LLVM IR files of a build, and relinking of its binaries.
+//!
+//! [`run`] integrates the LLVM IR files emitted by `rustc` with `opt` running the pass
+//! plugins, compiles them to object files with `llc`, relinks the binaries with them,
+//! and archives the static libraries with them. The tasks run on a pool of threads in
+//! the order of the [`Scheduler`], report their progress to an [`IntegrationObserver`],
+//! and record their statistics in [`Metrics`].
+//!
+//! The build emitting the LLVM IR files and logging the linker invocations, i.e.
+//! `cargo build` with `cargo-build-ci` as `RUSTC_WRAPPER`, is run by the front-ends
+//! before, and gives its output as a [`BuildOutput`].
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+use cargo_util::{paths, ProcessBuilder, ProcessError};
+use crossbeam_utils::thread;
+use tracing::{debug, info, warn};
+
+use crate::archive::{self, CodegenUnit};
+use crate::cache::Cache;
+use crate::cargo::{self, FileFlavor, Linker, OutputFile};
+use crate::config::Config;
+use crate::cross::Cross;
+use crate::error::{BoxError, CIError};
+use crate::llvm::{LlvmToolchain, LlvmUtility, PassManager};
+use crate::metrics::{CrateResult, Metrics, Phase};
+use crate::observer::{IntegrationObserver, IntegrationPhase};
+use crate::paths::PathExt;
+use crate::platform::{self, Target};
+use crate::plugin::PassPlugin;
+use crate::probes::{self, Report};
+use crate::scheduler::{Scheduler, Task};
+use crate::symbols::{self, SymbolCache};
+use crate::tasks::{CancellationToken, TaskGroup};
+use crate::{handler, hooks, ir_diff, logs, perf, util, CIResult};
+
+/// Options of the integration, given by the arguments of `cargo-build-ci` or by the
+/// builders of the front-ends.
+///
+/// The default options are the ones of `cargo-build-ci` without arguments.
+#[derive(Clone, Debug)]
+pub struct IntegrationOptions {
+    /// Crates to skip the integration, matched by [`util::is_skipped`].
+    pub skip_crates: Vec<String>,
+    /// Crates to integrate exclusively, or all the crates not skipped if empty.
+    pub only_crates: Vec<String>,
+    /// Minimum number of instructions of the LLVM IR of a crate without loops to integrate
+    /// it, or 0 to integrate every crate.
+    pub min_instructions: usize,
+    /// The crates emit LLVM bitcode instead of textual LLVM IR.
+    pub bitcode: bool,
+    /// Verify that the binaries define the handler and have probes.
+    pub verify: bool,
+    /// Write the probes of the binaries next to them, e.g. `target/debug/demo-ci.probes`.
+    pub probe_map: bool,
+    /// Write the `perf probe` commands of the probed functions of the binaries next to
+    /// them, e.g. `target/debug/demo-ci.perf-probes`.
+    pub perf_probes: bool,
+    /// Analyze the probes of the integrated crates into the returned [`Report`].
+    pub probe_report: bool,
+    /// Directory of the diffs of the LLVM IR of the integrated crates, if written.
+    pub diffs_dir: Option<PathBuf>,
+    /// Disable the cache of the CI-integrated object files.
+    pub no_cache: bool,
+    /// Continue with the crates and binaries not depending on a failed one.
+    pub keep_going: bool,
+    /// Number of parallel jobs, the number of CPUs by default.
+    pub jobs: Option<u32>,
+    /// Time limit of each `opt`, `llc`, and linker process.
+    pub timeout: Option<Duration>,
+    /// Memory budget of the parallel jobs in bytes, 3/4 of the total memory by default.
+    pub memory_budget: Option<u64>,
+}
+
+impl Default for IntegrationOptions {
+    fn default() -> Self {
+        Self {
+            skip_crates: Vec::new(),
+            only_crates: Vec::new(),
+            min_instructions: 200,
+            bitcode: false,
+            verify: false,
+            probe_map: false,
+            perf_probes: false,
+            probe_report: false,
+            diffs_dir: None,
+            no_cache: false,
+            keep_going: false,
+            jobs: None,
+            timeout: None,
+            memory_budget: None,
+        }
+    }
+}
+
+/// Output of the build to integrate.
+#[derive(Clone, Default, Debug)]
+pub struct BuildOutput {
+    /// LLVM IR files of the crates, integrated unless skipped.
+    pub llvm_ir_files: Vec<PathBuf>,
+    /// Linker invocations of the binaries to relink.
+    pub linkers: Vec<Linker>,
+    /// Static libraries to archive, and their hard links in the target directory, from
+    /// [`static_library`].
+    pub static_libraries: Vec<(PathBuf, PathBuf)>,
+    /// Output files of the compilation units.
+    pub output_files: Vec<OutputFile>,
+    /// Target directory of the build, e.g. `target/debug`.
+    pub target_dir: PathBuf,
+    /// Target triple of the build, or `None` for the host.
+    pub target: Option<String>,
+    /// Flags for `llc` from [`llc_flags`].
+    pub llc_flags: Vec<String>,
+    /// Container of `cross` running the linkers instead of the host.
+    pub cross: Option<Cross>,
+}
+
+impl BuildOutput {
+    /// Gets the number of tasks of the integration: `opt` and `llc` on every LLVM IR
+    /// file, a linker invocation for every binary, and an archive for every static
+    /// library, plus one for the whole build.
+    pub fn tasks(&self) -> usize {
+        self.llvm_ir_files.len() * 2 + self.linkers.len() + self.static_libraries.len() + 1
+    }
+}
+
+/// Integrates the LLVM IR files of the build and relinks its binaries, returning the
+/// report of the probes if requested by [`IntegrationOptions::probe_report`].
+///
+/// The CI-integrated binaries are added to [`Metrics::binaries`]. The analysis of the
+/// symbols is not saved, so that the front-ends share it, e.g. with a daemon.
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    config: &Config,
+    toolchain: &LlvmToolchain,
+    options: &IntegrationOptions,
+    build: BuildOutput,
+    symbols: &SymbolCache,
+    metrics: &Metrics,
+    observer: &dyn IntegrationObserver,
+    token: &CancellationToken,
+) -> CIResult<Report> {
+    if !config.library_path.is_file() {
+        bail!(CIError::LibraryNotInstalled);
+    }
+
+    let BuildOutput {
+        llvm_ir_files,
+        linkers,
+        static_libraries,
+        output_files,
+        target_dir,
+        target,
+        llc_flags,
+        cross,
+    } = build;
+
+    if let Some(hook) = &config.pre_integration_hook {
+        hooks::run(
+            "pre-integration",
+            hook,
+            &[("CI_TARGET_DIR", &target_dir.to_string()?)],
+        )?;
+    }
+
+    // the cache does not keep the integrated IR needed for the probe analysis,
+    // nor knows what the post-crate hook does to the integrated IR
+    let cache = if options.no_cache
+        || options.probe_map
+        || options.perf_probes
+        || options.probe_report
+        || options.diffs_dir.is_some()
+        || config.post_crate_hook.is_some()
+    {
+        None
+    } else {
+        Some(Cache::open(config)?)
+    };
+    let cache_ref = cache.as_ref();
+
+    let report = Mutex::new(Report::default());
+    let report_ref = &report;
+
+    let diffs_dir_ref = options.diffs_dir.as_deref();
+
+    let memory_budget = options
+        .memory_budget
+        .unwrap_or_else(|| platform::total_memory().map_or(u64::MAX, |memory| memory / 4 * 3));
+    debug!(memory_budget);
+
+    let scheduler = Scheduler::new(llvm_ir_files, linkers, memory_budget);
+    let scheduler_ref = &scheduler;
+
+    let llc_flags_ref = llc_flags.as_slice();
+
+    let cross_ref = cross.as_ref();
+    let output_files_ref = output_files.as_slice();
+
+    // the handler check is optional, e.g. `clang` may not have the target
+    let handler = handler::compile(toolchain, target.as_deref(), &target_dir)
+        .map_err(|error| warn!("failed to compile the handler check: {:#}", error))
+        .ok();
+    let handler_ref = handler.as_deref();
+
+    // a failed task cancels the others unless keeping going
+    let group = TaskGroup::new(token.child(), options.timeout);
+    let group_ref = &group;
+
+    // the tokens of the jobserver of `make` or `cargo` running `cargo-build-ci` limit
+    // the running tasks with the other jobs, besides the implicit token of this process
+    let jobserver = util::jobserver();
+    let jobserver_ref = jobserver.as_ref();
+    let jobs = options
+        .jobs
+        .map_or_else(num_cpus::get, |jobs| jobs as usize);
+    debug!(jobs, jobserver = jobserver.is_some());
+
+    thread::scope(move |s| -> CIResult<()> {
+        let group = group_ref;
+        let report = report_ref;
+        let cache = cache_ref;
+        let scheduler = scheduler_ref;
+        let llc_flags = llc_flags_ref;
+        let cross = cross_ref;
+        let output_files = output_files_ref;
+        let handler = handler_ref;
+        let diffs_dir = diffs_dir_ref;
+        let path = logs::new_path()?;
+
+        let verify = |mut errors: Vec<anyhow::Error>,
+                      failure: fn(PathBuf, BoxError) -> CIError|
+         -> CIResult<()> {
+            for error in &errors {
+                if let Some(crate_name) = logs::crate_name(error) {
+                    metrics.set_result(&crate_name, CrateResult::Failed);
+                }
+                logs::append(&path, error)?;
+            }
+
+            if errors.is_empty() {
+                return Ok(());
+            }
+
+            // a crash is the most relevant cause for the consumers of the error
+            let idx = errors
+                .iter()
+                .position(|error| matches!(CIError::find(error), Some(CIError::PassCrashed(..))))
+                .unwrap_or_default();
+            let source = errors.swap_remove(idx);
+            // a binary failing the verification did link, so it is reported by itself
+            if matches!(
+                CIError::find(&source),
+                Some(CIError::VerificationFailed(..))
+            ) {
+                return Err(source);
+            }
+            bail!(failure(path.clone(), source.into()));
+        };
+
+        // integration and linking
+        let mut threads = Vec::new();
+        for idx in 0..jobs {
+            let thread = s.spawn(move |_| {
+                let mut integration_errors = Vec::new();
+                let mut linking_errors = Vec::new();
+                while let Some(task) = scheduler.next() {
+                    // released once the task is done
+                    let _acquired = match jobserver_ref.filter(|_| idx > 0) {
+                        Some(client) => match client.acquire() {
+                            Ok(acquired) => Some(acquired),
+                            Err(error) => {
+                                warn!("failed to acquire a jobserver token: {}", error);
+                                None
+                            }
+                        },
+                        None => None,
+                    };
+                    let result = match task {
+                        Task::Integrate(file) => {
+                            let result = integrate(
+                                config, options, toolchain, metrics, report, cache, symbols,
+                                llc_flags, diffs_dir, group, observer, &file,
+                            );
+                            scheduler.finish(&file, result.is_ok());
+                            result.map_err(|error| integration_errors.push(error))
+                        }
+                        Task::Link(linker) => link(
+                            config,
+                            options,
+                            toolchain,
+                            metrics,
+                            symbols,
+                            llc_flags,
+                            cross,
+                            output_files,
+                            handler,
+                            group,
+                            observer,
+                            linker,
+                        )
+                        .map_err(|error| linking_errors.push(error)),
+                    };
+
+                    if result.is_err() && !options.keep_going {
+                        group.token.cancel();
+                    }
+                    if group.token.is_cancelled() {
+                        scheduler.cancel();
+                    }
+                }
+                (integration_errors, linking_errors)
+            });
+            threads.push(thread);
+        }
+
+        let mut integration_errors = Vec::new();
+        let mut linking_errors = Vec::new();
+        for thread in threads {
+            let (mut integration, mut linking) = thread.join().expect("worker thread panicked");
+            integration_errors.append(&mut integration);
+            linking_errors.append(&mut linking);
+        }
+        observer.on_finished();
+        let (integration_time, linking_time) = scheduler.durations();
+        metrics.record(Phase::Integration, integration_time);
+        metrics.record(Phase::Linking, linking_time);
+
+        token.check()?;
+        // the tasks cancelled after a failure did not fail by themselves
+        let failures = |errors: Vec<anyhow::Error>| {
+            errors
+                .into_iter()
+                .filter(|error| !matches!(CIError::find(error), Some(CIError::Cancelled)))
+                .collect::<Vec<_>>()
+        };
+        verify(failures(integration_errors), CIError::IntegrationFailed)?;
+        verify(failures(linking_errors), CIError::LinkingFailed)?;
+
+        // the object files of a static library are integrated with its crate and its
+        // dependencies, so the archive is patched once all of them are done
+        let archiving_errors = static_libraries
+            .iter()
+            .filter_map(|(path, hardlink)| {
+                archive_static_library(toolchain, metrics, path, hardlink).err()
+            })
+            .collect();
+        verify(archiving_errors, CIError::LinkingFailed)?;
+
+        Ok(())
+    })
+    .expect("main scoped thread panicked")?;
+
+    if let Some(cache) = &cache {
+        cache.evict()?;
+    }
+
+    Ok(report.into_inner().expect("failed to acquire lock"))
+}
+
+/// Gets the pass plugins to run on the crate.
+pub fn pass_plugins(config: &Config, crate_name: &str) -> Vec<PassPlugin> {
+    config
+        .crate_pass_plugins(crate_name)
+        .into_iter()
+        .filter(|plugin| !util::is_skipped(&plugin.skip_crates, crate_name))
+        .collect()
+}
+
+/// Creates the `opt` commands running the plugins in order on the LLVM IR file,
+/// each one rewriting the output file of the previous one.
+///
+/// LLVM bitcode files are written as bitcode, and textual LLVM IR files as text.
+pub fn opt_commands(
+    toolchain: &LlvmToolchain,
+    pass_manager: PassManager,
+    plugins: &[PassPlugin],
+    file: &Path,
+    output: &Path,
+) -> CIResult<Vec<ProcessBuilder>> {
+    let mut commands = Vec::with_capacity(plugins.len());
+    for (idx, plugin) in plugins.iter().enumerate() {
+        debug!("running plugin `{}` on: {}", plugin.name, file.display());
+        let mut opt = LlvmUtility::Optimizer.process_builder(toolchain);
+        if file.extension().is_none_or(|extension| extension != "bc") {
+            opt.arg("-S");
+        }
+        opt.args(&plugin.opt_args(pass_manager)?);
+        opt.arg(if idx == 0 { file } else { output });
+        opt.arg("-o");
+        opt.arg(output);
+        commands.push(opt);
+    }
+    Ok(commands)
+}
+
+/// Flags for `llc` to compile the integrated IR to an object file for the target,
+/// or the host if `None`.
+///
+/// The code model and the CPU of the configuration override the ones of the target.
+pub fn llc_flags(config: &Config, opt_level: &str, target: Option<&str>) -> Vec<String> {
+    // `llc` has no size optimization levels
+    let opt_level = match opt_level {
+        "s" | "z" => "2",
+        opt_level => opt_level,
+    };
+    let mut flags = vec!["-filetype=obj".to_string(), format!("-O{}", opt_level)];
+
+    // the triple of the LLVM IR files, given explicitly when cross-compiling
+    if let Some(triple) = target.and_then(platform::llvm_triple) {
+        flags.push(format!("-mtriple={}", triple));
+    }
+
+    let target = target.map_or_else(Target::host, Target::parse);
+    flags.extend(target.llc_flags());
+
+    if let Some(code_model) = &config.llc_code_model {
+        flags.retain(|flag| !flag.starts_with("-code-model="));
+        flags.push(format!("-code-model={}", code_model));
+    }
+    if let Some(cpu) = &config.llc_cpu {
+        flags.push(format!("-mcpu={}", cpu));
+    }
+
+    flags
+}
+
+/// Verifies that the CI-integrated binary defines the Compiler Interrupts handler, and
+/// that its CI-integrated object files have probes, returning the number of probe sites.
+///
+/// The symbols of a stripped binary are not checked, the probes of its object files
+/// still are.
+fn verify_binary(binary: &str, objects: &[&PathBuf]) -> CIResult<usize> {
+    let failed = |reason: &str| CIError::VerificationFailed(binary.to_string(), reason.to_string());
+
+    match symbols::defines_ci_hook(&paths::read_bytes(Path::new(binary))?)? {
+        Some(true) => {}
+        Some(false) => bail!(failed("the handler `intvActionHook` is not defined")),
+        None => debug!("no symbols to verify, stripped: {}", binary),
+    }
+
+    let mut sites = 0;
+    for object in objects {
+        sites += symbols::probe_sites(&paths::read_bytes(object)?)
+            .with_context(|| format!("failed to read the probes of `{}`", object.display()))?;
+    }
+    if sites == 0 {
+        bail!(failed(
+            "no probe calls the handler, as none of its crates was integrated"
+        ));
+    }
+    Ok(sites)
+}
+
+/// Gets the temporary files of the integrated LLVM IR files not needed by the next
+/// builds: the bitcode of the other stages of `rustc` saved by `-C save-temps`, e.g.
+/// `*.rcgu.no-opt.bc`, and the CI-integrated LLVM IR files compiled by `llc`.
+///
+/// The LLVM IR files and the object files are kept, as the crates not rebuilt by
+/// `cargo` are integrated again from them, and the binaries are relinked with them.
+pub fn temporary_files(llvm_ir_files: &[PathBuf]) -> CIResult<Vec<PathBuf>> {
+    let inputs = llvm_ir_files.iter().collect::<HashSet<_>>();
+    let mut temps = Vec::new();
+    // output directory -> compilation units
+    let mut units = BTreeMap::<PathBuf, HashSet<String>>::new();
+    for file in llvm_ir_files {
+        let ci_file = file.append_suffix("ci")?;
+        if ci_file.is_file() {
+            temps.push(ci_file);
+        }
+        units
+            .entry(file.parent()?)
+            .or_default()
+            .insert(file.unit_name()?);
+    }
+    for (dir, units) in units {
+        temps.append(&mut dir.read_dir(|path| {
+            path.extension().is_ok_and(|extension| extension == "bc")
+                && !inputs.contains(path)
+                && matches!(path.unit_name(), Ok(unit) if units.contains(&unit))
+        })?);
+    }
+    temps.sort();
+    temps.dedup();
+    debug!(?temps);
+    Ok(temps)
+}
+
+/// Gets the reason to skip the integration of the LLVM IR file of the crate, or `None`
+/// if the plugins run on it.
+pub fn skip_reason(
+    options: &IntegrationOptions,
+    toolchain: &LlvmToolchain,
+    symbols: &SymbolCache,
+    plugins: &[PassPlugin],
+    crate_name: &str,
+    file: &Path,
+) -> CIResult<Option<String>> {
+    let object = symbols.analyze(&[file.with_extension("o")])?;
+    if object.iter().any(|object| object.ci_hook) {
+        // skip the crate that has CI symbols defined
+        return Ok(Some("defines the Compiler Interrupts symbols".to_string()));
+    }
+
+    if util::is_skipped(&options.skip_crates, crate_name) {
+        // skip the given crates
+        return Ok(Some("skipped by `--skip`".to_string()));
+    }
+
+    if !util::is_only(&options.only_crates, crate_name) {
+        // integrate the given crates only
+        return Ok(Some("not selected by `--only`".to_string()));
+    }
+
+    // every plugin may skip the crate by itself
+    if plugins.is_empty() {
+        return Ok(Some("skipped by the plugins".to_string()));
+    }
+
+    if options.min_instructions > 0 {
+        // nothing worth instrumenting, e.g. only declarations or tiny glue code, while
+        // the loops are integrated however few instructions they have
+        let summary = probes::summarize(toolchain, file)?;
+        debug!(?summary, "scanned: {}", file.display());
+        if summary.loops == 0 && summary.instructions < options.min_instructions {
+            return Ok(Some(format!(
+                "{} instructions without loops",
+                summary.instructions
+            )));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Handle the integration process.
+#[allow(clippy::too_many_arguments)]
+fn integrate(
+    config: &Config,
+    options: &IntegrationOptions,
+    toolchain: &LlvmToolchain,
+    metrics: &Metrics,
+    report: &Mutex<Report>,
+    cache: Option<&Cache>,
+    symbols: &SymbolCache,
+    llc_flags: &[String],
+    diffs_dir: Option<&Path>,
+    group: &TaskGroup,
+    observer: &dyn IntegrationObserver,
+    file: &Path,
+) -> CIResult<()> {
+    let crate_name = crate_name(file)?;
+    let ci_file = file.append_suffix("ci")?;
+
+    let plugins = pass_plugins(config, &crate_name);
+    let integrate =
+        skip_reason(options, toolchain, symbols, &plugins, &crate_name, file)?.is_none();
+    let pass_manager = config.pass_manager(toolchain);
+
+    // everything affecting the object file is part of the cache key
+    let key = match cache {
+        Some(cache) => {
+            let mut inputs = vec![
+                if integrate { "integrate" } else { "skip" }.to_string(),
+                config.checksum.clone(),
+                config.llvm_version.clone(),
+            ];
+            if integrate {
+                for plugin in &plugins {
+                    // the path differs between the machines sharing the remote cache,
+                    // the content of the library does not
+                    let library_path = plugin.library_path.to_string()?;
+                    inputs.extend(
+                        plugin
+                            .opt_args(pass_manager)?
+                            .into_iter()
+                            .map(|arg| arg.replace(&library_path, &plugin.name)),
+                    );
+                    inputs.push(cache.digest(&plugin.library_path));
+                }
+            }
+            inputs.extend(llc_flags.iter().cloned());
+            let inputs = inputs.iter().map(String::as_str).collect::<Vec<_>>();
+            Some(Cache::key(file, &inputs)?)
+        }
+        None => None,
+    };
+    let ci_obj_file = ci_file.with_extension("o");
+
+    if let (Some(cache), Some(key)) = (cache, &key) {
+        if cache.get(key, &ci_obj_file)? {
+            info!("cache hit: {}", file.display());
+            Metrics::inc(&metrics.cache_hits);
+            metrics.set_result(
+                &crate_name,
+                if integrate {
+                    CrateResult::Integrated
+                } else {
+                    CrateResult::Skipped
+                },
+            );
+            observer.on_cached(&crate_name);
+            return Ok(());
+        }
+        Metrics::inc(&metrics.cache_misses);
+    }
+
+    if let Ok(metadata) = file.metadata() {
+        metrics.add_ir_size(&crate_name, metadata.len());
+    }
+
+    if integrate {
+        info!("integrating: {}", file.display());
+        observer.on_crate_started(&crate_name, IntegrationPhase::Integrating);
+        let started = Instant::now();
+
+        // do not write through the hard link to the original file of a skipped build
+        if ci_file.is_file() {
+            paths::remove_file(&ci_file)?;
+        }
+        for opt in opt_commands(toolchain, pass_manager, &plugins, file, &ci_file)? {
+            let output = group.exec_with_output(&opt);
+            handle_output(observer, IntegrationPhase::Integrating, output, &ci_file)
+                .with_context(|| format!("{}{}", logs::CRATE_PREFIX, crate_name))?;
+        }
+        metrics.time(
+            &crate_name,
+            IntegrationPhase::Integrating,
+            started.elapsed(),
+        );
+        metrics.set_result(&crate_name, CrateResult::Integrated);
+
+        if let Some(hook) = &config.post_crate_hook {
+            hooks::run(
+                "post-crate",
+                hook,
+                &[
+                    ("CI_CRATE_NAME", &crate_name),
+                    ("CI_IR_FILE", &ci_file.to_string()?),
+                    ("CI_ORIGINAL_IR_FILE", &file.to_string()?),
+                ],
+            )
+            .with_context(|| format!("{}{}", logs::CRATE_PREFIX, crate_name))?;
+        }
+
+        if options.probe_report {
+            let functions = probes::analyze(toolchain, &ci_file)?;
+            report
+                .lock()
+                .expect("failed to acquire lock")
+                .add(&crate_name, functions);
+        }
+
+        if let Some(dir) = diffs_dir {
+            ir_diff::write(toolchain, file, &ci_file, dir)
+                .with_context(|| format!("{}{}", logs::CRATE_PREFIX, crate_name))?;
+        }
+
+        observer.on_phase_finished(&crate_name, IntegrationPhase::Integrating);
+    } else {
+        info!("integration skipped: {}", file.display());
+        observer.on_skipped(&crate_name);
+        metrics.set_result(&crate_name, CrateResult::Skipped);
+        // `llc` only reads the LLVM IR file, so it does not need a copy
+        paths::link_or_copy(file, &ci_file)?;
+    }
+
+    // `llc` transforms integrated IR bitcode to object file
+    debug!("run llc on: {}", ci_file.display());
+    observer.on_crate_started(&crate_name, IntegrationPhase::StaticCompiling);
+
+    let mut llc = LlvmUtility::StaticCompiler.process_builder(toolchain);
+    llc.args(llc_flags);
+    llc.arg(&ci_file);
+
+    let started = Instant::now();
+    let output = group.exec_with_output(&llc);
+    handle_output(
+        observer,
+        IntegrationPhase::StaticCompiling,
+        output,
+        &ci_file,
+    )
+    .with_context(|| format!("{}{}", logs::CRATE_PREFIX, crate_name))?;
+    metrics.time(
+        &crate_name,
+        IntegrationPhase::StaticCompiling,
+        started.elapsed(),
+    );
+
+    if let (Some(cache), Some(key)) = (cache, &key) {
+        cache.put(key, &ci_obj_file)?;
+    }
+
+    observer.on_phase_finished(&crate_name, IntegrationPhase::StaticCompiling);
+
+    Ok(())
+}
+
+/// Handle the linking process.
+#[allow(clippy::too_many_arguments)]
+fn link(
+    config: &Config,
+    options: &IntegrationOptions,
+    toolchain: &LlvmToolchain,
+    metrics: &Metrics,
+    symbols: &SymbolCache,
+    llc_flags: &[String],
+    cross: Option<&Cross>,
+    output_files: &[OutputFile],
+    handler: Option<&Path>,
+    group: &TaskGroup,
+    observer: &dyn IntegrationObserver,
+    mut linker: Linker,
+) -> CIResult<()> {
+    let output_file = linker.args.output_file.clone();
+    let crate_name = crate_name(&output_file)?;
+    info!("linking: {}", crate_name);
+
+    observer.on_crate_started(&crate_name, IntegrationPhase::Linking);
+
+    // CI-integrated LLVM IR files linked into the binary
+    let mut ir_files = Vec::new();
+    let llvm_ir_extension = cargo::llvm_ir_extension(options.bitcode);
+    // CI-integrated object files, and rlibs with their replaced members, compiled
+    // again if the linker rejects their relocations
+    let mut ci_objects = Vec::new();
+    let mut ci_rlibs = Vec::new();
+
+    // analyze all object files of the binary at once, leaving the archives such as the
+    // import libraries of MinGW as is
+    let mut objects = linker
+        .args
+        .input_files
+        .iter_mut()
+        .filter(|file| file.contains("deps") && is_object(file))
+        .collect::<Vec<_>>();
+    let analyses = symbols.analyze(&objects)?;
+    // the handler check needs the CI handler of the `compiler-interrupts` crate
+    let mut ci_hook = analyses.iter().any(|object| object.ci_hook);
+    if !analyses.iter().any(|object| object.allocator) {
+        // e.g. a `no_std` binary without an allocator, all of its object files are
+        // integrated
+        debug!("no allocator shim: {}", output_file);
+    }
+    for (file, object) in objects.iter_mut().zip(analyses) {
+        if object.allocator {
+            // the allocator shim generated by `rustc` has no LLVM IR
+            debug!("found allocator shim: {}", file);
+        } else {
+            let ci_file = file.append_suffix("ci")?;
+            // object files of the skipped crates without LLVM IR are linked as is
+            if ci_file.is_file() {
+                ir_files.push(ci_file.with_extension(llvm_ir_extension));
+                **file = ci_file.to_string()?;
+                ci_objects.push(ci_file);
+            }
+        }
+    }
+
+    // make a copy and replace *.o with *-ci.o in the rlib files
+    for file in &mut linker.args.rlib_files {
+        if !file.contains("deps") {
+            continue;
+        }
+
+        debug!("original rlib: {}", file);
+        let ci_file = file.append_suffix("ci")?;
+
+        // replace the object files of every codegen unit of the crate, as it has one
+        // member for each with `-C codegen-units`
+        let units = archive::codegen_units(&file, &archive::members(toolchain, &file)?)?;
+        for unit in &units {
+            if unit.object.is_file() && !ci_hook {
+                ci_hook = symbols
+                    .analyze(&[&unit.object])?
+                    .iter()
+                    .any(|object| object.ci_hook);
+            }
+        }
+        let (integrated, missing): (Vec<_>, Vec<_>) =
+            units.into_iter().partition(CodegenUnit::integrated);
+
+        if integrated.is_empty() {
+            // rlibs of the skipped crates without LLVM IR are linked as is
+            debug!("no object files to replace for rlib: {}", file);
+            continue;
+        }
+        if !missing.is_empty() {
+            // the binary would mix integrated and not integrated code of the crate
+            warn!(
+                "{} of {} codegen units of `{}` are not integrated: {}",
+                missing.len(),
+                integrated.len() + missing.len(),
+                file,
+                missing
+                    .iter()
+                    .map(|unit| unit.member.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        let replacements = integrated
+            .into_iter()
+            .map(|unit| {
+                ir_files.push(unit.ci_object.with_extension(llvm_ir_extension));
+                (unit.member, unit.ci_object)
+            })
+            .collect::<Vec<_>>();
+
+        debug!("replacing object files for rlib: {}", ci_file.display());
+        archive::replace_members(toolchain, &PathBuf::from(&file), &ci_file, &replacements)?;
+
+        ci_rlibs.push(CiRlib {
+            rlib: PathBuf::from(&file),
+            ci_rlib: ci_file.clone(),
+            replacements,
+        });
+        *file = ci_file.to_string()?;
+    }
+
+    if let (Some(handler), true) = (handler, ci_hook) {
+        linker.args.add_input_file(handler.to_string()?);
+    }
+
+    let output_ci_file = output_file.append_suffix("ci")?.to_string()?;
+    linker.args.output_file = output_ci_file.clone();
+
+    // execute the linker
+    debug!("linker: {:#?}", linker);
+    let started = Instant::now();
+    let mut output = group.exec_with_output(&linker_command(cross, linker.clone()));
+
+    // the code model fixing the relocations depends on the linker and the libraries
+    // of the system, so the other ones are tried on the CI-integrated object files
+    if is_relocation_failure(&output) {
+        let failed = code_model(llc_flags);
+        for code_model in platform::CODE_MODELS {
+            if failed.as_deref().unwrap_or("small") == code_model {
+                continue;
+            }
+            info!("relinking {} with code model: {}", crate_name, code_model);
+            let linker = match with_code_model(
+                toolchain,
+                options,
+                llc_flags,
+                group,
+                &linker,
+                &ci_objects,
+                &ci_rlibs,
+                code_model,
+            ) {
+                Ok(linker) => linker,
+                Err(error) => {
+                    debug!(
+                        "failed to compile with code model {}: {:#}",
+                        code_model, error
+                    );
+                    continue;
+                }
+            };
+            // the first failure is reported if none of the code models links
+            let retried = group.exec_with_output(&linker_command(cross, linker));
+            if retried.is_ok() {
+                let message = format!(
+                    "linked with `-code-model={}` after relocation errors with the {}; \
+                    save it with `cargo-lib-ci config --llc-code-model {}`",
+                    code_model,
+                    failed.map_or_else(
+                        || "default code model".to_string(),
+                        |failed| format!("`-code-model={}`", failed)
+                    ),
+                    code_model
+                );
+                warn!("{}: {}", crate_name, message);
+                observer.on_warning(&crate_name, &message);
+                output = retried;
+                break;
+            }
+        }
+    }
+    handle_output(observer, IntegrationPhase::Linking, output, &output_ci_file)
+        .with_context(|| format!("{}{}", logs::CRATE_PREFIX, crate_name))?;
+    metrics.time(&crate_name, IntegrationPhase::Linking, started.elapsed());
+
+    if options.verify {
+        let objects = ci_objects
+            .iter()
+            .chain(
+                ci_rlibs
+                    .iter()
+                    .flat_map(|rlib| rlib.replacements.iter().map(|(_, object)| object)),
+            )
+            .collect::<Vec<_>>();
+        let sites = verify_binary(&output_ci_file, &objects)?;
+        info!("verified {}: {} probe sites", crate_name, sites);
+    }
+
+    // hard link the CI-integrated binary file next to the hardlink of `cargo`, e.g.
+    // `target/debug/my-app-ci` for `target/debug/my-app`, unless `cargo` leaves the
+    // binary in `deps` like the test harnesses, which would clash with the binary of
+    // the crate
+    let link_file = match output_files
+        .iter()
+        .find(|file| file.path == Path::new(&output_file))
+    {
+        Some(OutputFile {
+            hardlink: Some(hardlink),
+            ..
+        }) => hardlink.append_suffix("ci")?,
+        Some(_) => PathBuf::from(&output_ci_file),
+        None => {
+            let mut link_file = output_file
+                .parent()?
+                .parent()?
+                .join(crate_name.append_suffix("ci")?);
+            if let Ok(extension) = output_file.extension() {
+                link_file.set_extension(extension);
+            }
+            link_file
+        }
+    };
+    let uplifted = link_file != Path::new(&output_ci_file);
+    debug!(?output_file);
+    debug!(?link_file);
+    if uplifted {
+        paths::link_or_copy(&output_ci_file, &link_file)?;
+    }
+    if options.probe_map || options.perf_probes {
+        let mut functions = Vec::new();
+        for ir_file in ir_files.iter().filter(|file| file.is_file()) {
+            functions.append(&mut probes::analyze(toolchain, ir_file)?);
+        }
+
+        if options.probe_map {
+            let map_file = link_file.with_extension("probes");
+            debug!(?map_file);
+            probes::write_map(&map_file, &functions)?;
+        }
+
+        if options.perf_probes {
+            let probes_file = perf::probes_path(&link_file);
+            debug!(?probes_file);
+            perf::write_probes(&link_file, &functions, &probes_file)?;
+        }
+    }
+
+    if let Some(hook) = &config.post_link_hook {
+        hooks::run(
+            "post-link",
+            hook,
+            &[
+                ("CI_CRATE_NAME", &crate_name),
+                ("CI_BINARY", &link_file.to_string()?),
+            ],
+        )
+        .with_context(|| format!("{}{}", logs::CRATE_PREFIX, crate_name))?;
+    }
+
+    metrics
+        .binaries
+        .lock()
+        .expect("failed to acquire lock")
+        .push(link_file);
+
+    observer.on_phase_finished(&crate_name, IntegrationPhase::Linking);
+
+    Ok(())
+}
+
+/// Creates the command of the linker invocation, run in the image of `cross` if any.
+pub fn linker_command(cross: Option<&Cross>, linker: Linker) -> ProcessBuilder {
+    match cross {
+        // the linker of the target is in the image of `cross`
+        Some(cross) => cross.linker_command(linker),
+        None => {
+            let mut builder = ProcessBuilder::new(&linker.program);
+            for (key, value) in &linker.env {
+                builder.env(key, value);
+            }
+            builder.args(&linker.args.build());
+            builder
+        }
+    }
+}
+
+/// Returns true if the linker failed on the relocations of the object files.
+fn is_relocation_failure(output: &anyhow::Result<Output>) -> bool {
+    match output {
+        Ok(_) => false,
+        Err(error) => error
+            .downcast_ref::<ProcessError>()
+            .and_then(|error| error.stderr.as_deref())
+            .is_some_and(|stderr| platform::is_relocation_error(&String::from_utf8_lossy(stderr))),
+    }
+}
+
+/// Gets the code model given to `llc`, or `None` for the default one of the target.
+fn code_model(llc_flags: &[String]) -> Option<String> {
+    llc_flags
+        .iter()
+        .rev()
+        .find_map(|flag| flag.strip_prefix("-code-model="))
+        .map(str::to_string)
+}
+
+/// Copy of an rlib with its members replaced by the CI-integrated object files.
+#[derive(Debug)]
+struct CiRlib {
+    /// Original rlib.
+    rlib: PathBuf,
+    /// CI-integrated copy of the rlib.
+    ci_rlib: PathBuf,
+    /// Members of the rlib -> CI-integrated object files replacing them.
+    replacements: Vec<(String, PathBuf)>,
+}
+
+/// Compiles the CI-integrated object files linked into the binary again with the code
+/// model, e.g. `foo.rcgu-ci-medium.o`, and returns the linker invocation using them and
+/// the copies of the rlibs with them.
+///
+/// The CI-integrated LLVM IR files are required, so the object files restored from
+/// the cache without them cannot be compiled again.
+#[allow(clippy::too_many_arguments)]
+fn with_code_model(
+    toolchain: &LlvmToolchain,
+    options: &IntegrationOptions,
+    llc_flags: &[String],
+    group: &TaskGroup,
+    linker: &Linker,
+    ci_objects: &[PathBuf],
+    ci_rlibs: &[CiRlib],
+    code_model: &str,
+) -> CIResult<Linker> {
+    let mut flags = llc_flags
+        .iter()
+        .filter(|flag| !flag.starts_with("-code-model="))
+        .cloned()
+        .collect::<Vec<_>>();
+    flags.push(format!("-code-model={}", code_model));
+    let llvm_ir_extension = cargo::llvm_ir_extension(options.bitcode);
+    let compile = |ci_object: &Path| -> CIResult<PathBuf> {
+        let ci_file = ci_object.with_extension(llvm_ir_extension);
+        if !ci_file.is_file() {
+            bail!(
+                "the CI-integrated LLVM IR file does not exist: {}",
+                ci_file.display()
+            );
+        }
+        let output = ci_object.append_suffix(code_model)?;
+        let mut llc = LlvmUtility::StaticCompiler.process_builder(toolchain);
+        llc.args(&flags);
+        llc.arg("-o").arg(&output).arg(&ci_file);
+        group.exec_with_output(&llc)?;
+        Ok(output)
+    };
+
+    let mut linker = linker.clone();
+    for ci_object in ci_objects {
+        let output = compile(ci_object)?.to_string()?;
+        let ci_object = ci_object.to_string()?;
+        for file in &mut linker.args.input_files {
+            if *file == ci_object {
+                *file = output.clone();
+            }
+        }
+    }
+    for CiRlib {
+        rlib,
+        ci_rlib,
+        replacements,
+    } in ci_rlibs
+    {
+        let mut compiled = Vec::with_capacity(replacements.len());
+        for (member, ci_object) in replacements {
+            compiled.push((member.clone(), compile(ci_object)?));
+        }
+        let output = rlib.append_suffix(&format!("ci-{}", code_model))?;
+        archive::replace_members(toolchain, rlib, &output, &compiled)?;
+        let output = output.to_string()?;
+        let ci_rlib = ci_rlib.to_string()?;
+        for file in &mut linker.args.rlib_files {
+            if *file == ci_rlib {
+                *file = output.clone();
+            }
+        }
+    }
+    Ok(linker)
+}
+
+/// Gets the paths of the output file of `cargo` and its hard link in the target
+/// directory if it is a static library.
+pub fn static_library(file: &OutputFile) -> Option<(PathBuf, PathBuf)> {
+    match (
+        &file.hardlink,
+        file.flavor,
+        file.path.extension().as_deref(),
+    ) {
+        (Some(hardlink), FileFlavor::Normal, Ok("a" | "lib")) => {
+            Some((file.path.clone(), hardlink.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// Makes a copy of the static library with its object files replaced by the
+/// CI-integrated ones, e.g. `target/debug/libfoo-ci.a` for `target/debug/libfoo.a`.
+fn archive_static_library(
+    toolchain: &LlvmToolchain,
+    metrics: &Metrics,
+    path: &Path,
+    hardlink: &Path,
+) -> CIResult<()> {
+    let crate_name = crate_name(path)?;
+    info!("archiving: {}", crate_name);
+
+    // members of the crate and of its dependencies have the names of the object files
+    // in `deps`, while the ones of the standard library have no LLVM IR
+    let replacements = archive::codegen_units(path, &archive::members(toolchain, path)?)?
+        .into_iter()
+        .filter(CodegenUnit::integrated)
+        .map(|unit| (unit.member, unit.ci_object))
+        .collect::<Vec<_>>();
+
+    let link_file = hardlink.append_suffix("ci")?;
+    debug!(
+        "replacing object files for static library: {}",
+        link_file.display()
+    );
+    archive::replace_members(toolchain, path, &link_file, &replacements)
+        .with_context(|| format!("{}{}", logs::CRATE_PREFIX, crate_name))?;
+
+    metrics
+        .binaries
+        .lock()
+        .expect("failed to acquire lock")
+        .push(link_file);
+
+    Ok(())
+}
+
+/// Returns true if the input file of the linker is an object file.
+fn is_object(file: &str) -> bool {
+    matches!(
+        Path::new(file).extension().and_then(|e| e.to_str()),
+        Some("o" | "obj")
+    )
+}
+
+/// Handle output from the process and validate output file.
+fn handle_output<P: AsRef<Path>>(
+    observer: &dyn IntegrationObserver,
+    phase: IntegrationPhase,
+    output: anyhow::Result<Output>,
+    output_file: P,
+) -> CIResult<()> {
+    let output_file = output_file.as_ref();
+    let crate_name = crate_name(output_file)?;
+    match output {
+        Ok(output) => {
+            if !output_file.is_file() {
+                // output file does not exist
+                let stderr = String::from_utf8(output.stderr.clone())?;
+
+                observer.on_error(
+                    &crate_name,
+                    &format!("output file does not exist: {}", output_file.display()),
+                );
+
+                bail!(
+                    "process returned success but output file does not exist\n\
+                    process: {:#?}\n\
+                    expected file: {}\n\
+                    --- stderr\n{}",
+                    output,
+                    output_file.display(),
+                    stderr
+                );
+            }
+
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if !stderr.trim().is_empty() {
+                observer.on_warning(&crate_name, stderr.trim());
+            }
+
+            Ok(())
+        }
+        Err(err) => {
+            let proc_err = match err.downcast_ref::<ProcessError>() {
+                Some(proc_err) => proc_err,
+                // killed by the time limit or the cancellation, or not started at all
+                None => {
+                    if let Some(CIError::TimedOut(..)) = CIError::find(&err) {
+                        observer.on_error(&crate_name, &err.to_string());
+                    }
+                    return Err(err);
+                }
+            };
+
+            let desc = ToString::to_string(&proc_err.desc);
+            // `opt` and `llc` are terminated by a signal when crashed
+            let crashed = proc_err.code.is_none() && phase != IntegrationPhase::Linking;
+
+            observer.on_error(&crate_name, &desc);
+
+            if crashed {
+                bail!(CIError::PassCrashed(crate_name, err.into()));
+            }
+
+            bail!(desc);
+        }
+    }
+}
+
+/// Gets the crate name of the compilation unit of the path, e.g. `serde_json` of
+/// `deps/libserde_json-0123456789abcdef.rlib`, or `my_app` of the binary `my-app`.
+///
+/// The name is the one of the unit without the hash of its metadata, with `-` replaced
+/// as `cargo` does for the crate names of the targets. The CI-integrated artifacts have
+/// the name of their original, e.g. `demo` of `deps/demo-0123456789abcdef-ci` or of
+/// `deps/libdemo-0123456789abcdef-ci-medium.rlib`.
+pub fn crate_name<P: AsRef<Path>>(path: P) -> CIResult<String> {
+    let unit = path.unit_name()?;
+    let unit = ["small", "medium", "large"]
+        .iter()
+        .find_map(|model| unit.strip_suffix(model)?.strip_suffix("-ci-"))
+        .or_else(|| unit.strip_suffix("-ci"))
+        .unwrap_or(&unit);
+    let name = match unit.rsplit_once('-') {
+        Some((name, hash)) if hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            name
+        }
+        _ => unit,
+    };
+    Ok(name.replace('-', "_"))
+}
@@ -0,0 +1,308 @@
+//! Conventions of the platforms of the hosts and the targets.
+//!
+//! What depends on the operating system or the architecture, either of the host
+//! running the integration or of the target of the build, is decided here rather
+//! than by `cfg!` across the pipeline.
+
+use std::path::Path;
+
+use cargo_util::ProcessBuilder;
+
+/// Operating system of a host or a target.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Os {
+    /// Linux.
+    Linux,
+    /// Android.
+    Android,
+    /// macOS and the other Apple platforms.
+    Apple,
+    /// FreeBSD.
+    FreeBsd,
+    /// Windows.
+    Windows,
+    /// Other operating systems, or none.
+    Other,
+}
+
+impl Os {
+    /// Gets the operating system of the host.
+    pub fn host() -> Self {
+        match std::env::consts::OS {
+            "linux" => Os::Linux,
+            "android" => Os::Android,
+            "macos" | "ios" => Os::Apple,
+            "freebsd" => Os::FreeBsd,
+            "windows" => Os::Windows,
+            _ => Os::Other,
+        }
+    }
+}
+
+/// Target of a build.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Target {
+    /// Architecture, e.g. `x86_64`.
+    pub arch: String,
+    /// Operating system.
+    pub os: Os,
+    /// Environment, e.g. `gnu` or `musl`, or empty if none.
+    pub env: String,
+}
+
+impl Target {
+    /// Parses the target triple, e.g. `x86_64-unknown-linux-gnu`.
+    pub fn parse(triple: &str) -> Self {
+        let components = triple.split('-').collect::<Vec<_>>();
+        // `<arch>-linux-android` is not a Linux target of the GNU toolchain
+        let os = if triple.contains("-android") {
+            Os::Android
+        } else if triple.contains("-linux") {
+            Os::Linux
+        } else if triple.contains("-apple-") {
+            Os::Apple
+        } else if triple.contains("-freebsd") {
+            Os::FreeBsd
+        } else if triple.contains("-windows") {
+            Os::Windows
+        } else {
+            Os::Other
+        };
+        // `<arch>-<vendor>-<os>-<env>`
+        let env = match components.as_slice() {
+            [_, _, _, env, ..] => env.to_string(),
+            _ => String::new(),
+        };
+        Self {
+            arch: components[0].to_string(),
+            os,
+            env,
+        }
+    }
+
+    /// Gets the target of the host.
+    pub fn host() -> Self {
+        let env = if cfg!(target_env = "musl") {
+            "musl"
+        } else if cfg!(target_env = "gnu") {
+            "gnu"
+        } else {
+            ""
+        };
+        Self {
+            arch: std::env::consts::ARCH.to_string(),
+            os: Os::host(),
+            env: env.to_string(),
+        }
+    }
+
+    /// Returns true if the target can be integrated.
+    ///
+    /// WebAssembly targets have neither the threads the handler relies on nor a
+    /// linker invocation to relink from, so they are rejected before reaching `llc`.
+    pub fn is_supported(&self) -> bool {
+        !self.arch.starts_with("wasm")
+    }
+
+    /// Gets the flags for `llc` to compile object files linkable into the binaries
+    /// of `rustc` for the target.
+    pub fn llc_flags(&self) -> Vec<String> {
+        let musl = self.env.starts_with("musl");
+        match (self.os, self.arch.as_str()) {
+            // fixes mismatch relocation symbols on linux
+            (Os::Linux, "x86_64") if !musl => vec!["-code-model=large".to_string()],
+            // position-independent executables, as `rustc` compiles them; the large
+            // code model is not supported on AArch64, its text relocations are not
+            // applied by the static PIE startup code of musl, and rejected by `lld`
+            // which links on FreeBSD
+            (Os::Linux, "x86_64" | "aarch64") | (Os::FreeBsd, _) => {
+                vec!["-relocation-model=pic".to_string()]
+            }
+            // Android only runs position-independent executables, and `rustc` uses the
+            // emulated thread-local storage of the NDK for the handler
+            (Os::Android, _) => vec![
+                "-relocation-model=pic".to_string(),
+                "-emulated-tls".to_string(),
+            ],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Code models of `llc` tried in order when the linker rejects the relocations of the
+/// object files compiled with another one.
+pub const CODE_MODELS: [&str; 3] = ["small", "medium", "large"];
+
+/// Returns true if the linker failed on the relocations of the object files, which
+/// another code model may fix, e.g. `relocation truncated to fit` of `ld` or
+/// `relocation R_X86_64_32 out of range` of `lld`.
+pub fn is_relocation_error(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    stderr.contains("relocation truncated to fit")
+        || stderr.contains("relocation overflow")
+        || stderr.contains("recompile with -fpic")
+        || stderr.contains("recompile with -fpie")
+        || stderr.contains("read-only segment has dynamic relocations")
+        || (stderr.contains("relocation r_")
+            && (stderr.contains("out of range") || stderr.contains("cannot be used against")))
+}
+
+/// Gets the LLVM triple of a target triple of `rustc`, e.g. `riscv64-unknown-linux-gnu`
+/// for `riscv64gc-unknown-linux-gnu`, or `None` for a path to a target specification.
+///
+/// The architectures of `rustc` naming their extensions are not known to LLVM.
+pub fn llvm_triple(triple: &str) -> Option<String> {
+    if triple.ends_with(".json") {
+        return None;
+    }
+    let (arch, rest) = triple.split_once('-')?;
+    let arch = if arch.starts_with("riscv64") {
+        "riscv64"
+    } else if arch.starts_with("riscv32") {
+        "riscv32"
+    } else {
+        arch
+    };
+    Some(format!("{}-{}", arch, rest))
+}
+
+/// Gets the flags for the compiler to build the library as a module loaded by `opt`
+/// on the host.
+///
+/// A DLL is position-independent by itself, and `clang` rejects `-fPIC` for the
+/// MSVC targets.
+pub fn shared_library_flags() -> &'static [&'static str] {
+    match Os::host() {
+        Os::Apple => &["-bundle", "-undefined", "dynamic_lookup", "-fPIC"],
+        Os::Windows => &["-shared"],
+        _ => &["-shared", "-fPIC"],
+    }
+}
+
+/// Gets the extension of the library loaded by `opt` on the host.
+pub fn shared_library_extension() -> &'static str {
+    match Os::host() {
+        Os::Windows => "dll",
+        _ => "so",
+    }
+}
+
+/// Gets the file name of the prebuilt library for the LLVM major version released for
+/// the host, e.g. `CompilerInterrupt-14-x86_64-linux.so`, or with the `-debug` suffix
+/// for the debug-enabled library.
+pub fn prebuilt_library_name(llvm_major: u64, debug: bool) -> String {
+    format!(
+        "CompilerInterrupt-{}-{}-{}{}.{}",
+        llvm_major,
+        std::env::consts::ARCH,
+        std::env::consts::OS,
+        if debug { "-debug" } else { "" },
+        shared_library_extension()
+    )
+}
+
+/// Gets the flags for the compiler to pass the flags of `llvm-config --ldflags` to
+/// the linker on the host.
+///
+/// They are the flags of `link.exe` on Windows, e.g. `-LIBPATH:<dir>`, which `clang`
+/// does not take by itself.
+pub fn linker_flags(ld_flags: &str) -> Vec<String> {
+    ld_flags
+        .split_ascii_whitespace()
+        .map(|flag| match Os::host() {
+            Os::Windows => format!("-Wl,{}", flag),
+            _ => flag.to_string(),
+        })
+        .collect()
+}
+
+/// Returns true if the path is an executable on the host.
+///
+/// Windows has no permission bits marking the executables, which are the PE files
+/// with the `exe` extension.
+pub fn is_executable(path: &Path) -> bool {
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => return false,
+    };
+    match Os::host() {
+        Os::Windows => path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("exe")),
+        _ => has_executable_mode(&metadata),
+    }
+}
+
+/// Returns true if the permissions of the file allow someone to execute it.
+#[cfg(unix)]
+fn has_executable_mode(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+/// Returns true, as the file has no permission bits to check.
+#[cfg(not(unix))]
+fn has_executable_mode(_metadata: &std::fs::Metadata) -> bool {
+    true
+}
+
+/// Gets the name of an LLVM utility with the version suffix on the host, e.g.
+/// `llvm-config-15` of the Debian packages, or `llvm-config15` of the FreeBSD ports.
+pub fn versioned_utility(name: &str, major: u64) -> String {
+    match Os::host() {
+        Os::FreeBsd => format!("{}{}", name, major),
+        _ => format!("{}-{}", name, major),
+    }
+}
+
+/// Gets the command running a program pinned to the CPUs of a list, e.g. `0,2-3`,
+/// or `None` if the host cannot pin the processes.
+///
+/// `taskset` sets the affinity with `sched_setaffinity` on Linux, like `cpuset` does on
+/// FreeBSD. macOS only takes the affinity of threads as hints, so it is not supported.
+pub fn pin_cpus_command(cpus: &str) -> Option<Vec<String>> {
+    let command = match Os::host() {
+        Os::Linux | Os::Android => ["taskset", "-c", cpus],
+        Os::FreeBsd => ["cpuset", "-l", cpus],
+        _ => return None,
+    };
+    Some(command.iter().map(|s| s.to_string()).collect())
+}
+
+/// Gets the command running a program with an adjusted niceness, or `None` if the
+/// host does not have `nice`.
+pub fn nice_command(niceness: i32) -> Option<Vec<String>> {
+    match Os::host() {
+        Os::Windows => None,
+        _ => Some(vec![
+            "nice".to_string(),
+            "-n".to_string(),
+            niceness.to_string(),
+        ]),
+    }
+}
+
+/// Gets the total physical memory of the host in bytes.
+///
+/// The memory is read from `sysctl` on macOS and FreeBSD, which do not mount procfs
+/// by default, and from `/proc/meminfo` otherwise.
+pub fn total_memory() -> Option<u64> {
+    let sysctl = |name: &str| {
+        let output = ProcessBuilder::new("sysctl")
+            .args(&["-n", name])
+            .exec_with_output()
+            .ok()?;
+        String::from_utf8(output.stdout).ok()?.trim().parse().ok()
+    };
+    match Os::host() {
+        Os::Apple => sysctl("hw.memsize"),
+        Os::FreeBsd => sysctl("hw.physmem"),
+        _ => {
+            // MemTotal:       16318580 kB
+            let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+            let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+            let kib = line.split_ascii_whitespace().nth(1)?.parse::<u64>().ok()?;
+            Some(kib * 1024)
+        }
+    }
+}
@@ -0,0 +1,137 @@
+//! LLVM pass plugins run on the LLVM IR of the crates.
+//!
+//! Compiler Interrupts is one of them, configured by the library installed with
+//! `cargo-lib-ci`. Other plugins are listed in the configuration and run after it
+//! by the same pipeline: emitting LLVM IR, running the passes with `opt`, compiling
+//! with `llc`, and relinking the binaries.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::llvm::PassManager;
+use crate::paths::PathExt;
+use crate::CIResult;
+
+/// Name of the Compiler Interrupts plugin.
+pub const CI_PLUGIN_NAME: &str = "compiler-interrupts";
+
+/// Name of the Compiler Interrupts pass.
+pub const CI_PASS_NAME: &str = "logicalclock";
+
+/// Argument of the Compiler Interrupts pass excluding a function from the probes.
+pub const CI_SKIP_FN_ARG: &str = "-skip-fn";
+
+/// Argument of the Compiler Interrupts pass selecting the instruction granularity.
+pub const CI_INST_GRAN_ARG: &str = "-inst-gran";
+
+/// Argument of the Compiler Interrupts pass setting the commit interval in instructions.
+pub const CI_COMMIT_INTV_ARG: &str = "-commit-intv";
+
+/// Argument of the Compiler Interrupts pass setting the allowed deviation from the
+/// commit interval in instructions.
+pub const CI_ALL_DEV_ARG: &str = "-all-dev";
+
+/// Default pre-optimization passes for Compiler Interrupts.
+pub const DEFAULT_OPT_PASSES: [&str; 6] = [
+    "postdomtree",
+    "mem2reg",
+    "indvars",
+    "loop-simplify",
+    "branch-prob",
+    "scalar-evolution",
+];
+
+/// Analyses among the default pre-optimization passes, which the new pass manager
+/// only runs when required in the pipeline.
+const ANALYSIS_PASSES: [&str; 3] = ["postdomtree", "branch-prob", "scalar-evolution"];
+
+/// Pass of a plugin.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct Pass {
+    /// Name of the pass, e.g. `logicalclock` for `opt --logicalclock`.
+    pub name: String,
+    /// Arguments for the pass.
+    pub args: Vec<String>,
+}
+
+/// LLVM pass plugin.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(default)]
+pub struct PassPlugin {
+    /// Name of the plugin.
+    pub name: String,
+    /// Path to the shared library of the plugin.
+    pub library_path: PathBuf,
+    /// Passes to run in order.
+    pub passes: Vec<Pass>,
+    /// Crates not to run the plugin on.
+    pub skip_crates: Vec<String>,
+}
+
+impl Pass {
+    /// Gets the name of the pass in the pipeline of the new pass manager.
+    fn pipeline_name(&self) -> String {
+        if ANALYSIS_PASSES.contains(&self.name.as_str()) {
+            format!("require<{}>", self.name)
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+impl PassPlugin {
+    /// Creates the plugin of the Compiler Interrupts library in the configuration, running
+    /// the pass with the arguments.
+    pub fn compiler_interrupts(config: &Config, args: Vec<String>) -> Self {
+        let mut passes = vec![Pass {
+            name: String::from(CI_PASS_NAME),
+            args,
+        }];
+        passes.extend(DEFAULT_OPT_PASSES.iter().map(|name| Pass {
+            name: String::from(*name),
+            args: Vec::new(),
+        }));
+
+        Self {
+            name: String::from(CI_PLUGIN_NAME),
+            library_path: config.library_path.clone(),
+            passes,
+            skip_crates: Vec::new(),
+        }
+    }
+
+    /// Gets the arguments for `opt` to load the plugin and run its passes with
+    /// the pass manager.
+    pub fn opt_args(&self, pass_manager: PassManager) -> CIResult<Vec<String>> {
+        let library_path = self.library_path.to_string()?;
+        let mut args = Vec::new();
+        match pass_manager {
+            PassManager::Legacy => {
+                args.push(String::from("--enable-new-pm=0"));
+                args.push(String::from("--load"));
+                args.push(library_path);
+                for pass in &self.passes {
+                    args.push(format!("--{}", pass.name));
+                    args.extend(pass.args.iter().cloned());
+                }
+            }
+            PassManager::New => {
+                // the options of the passes are global once the plugin is loaded
+                let pipeline = self
+                    .passes
+                    .iter()
+                    .map(Pass::pipeline_name)
+                    .collect::<Vec<_>>();
+                args.push(format!("--load-pass-plugin={}", library_path));
+                args.push(format!("--passes={}", pipeline.join(",")));
+                for pass in &self.passes {
+                    args.extend(pass.args.iter().cloned());
+                }
+            }
+        }
+        Ok(args)
+    }
+}
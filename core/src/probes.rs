@@ -0,0 +1,448 @@
+//! Probe placement analysis of the CI-integrated LLVM IR.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use anyhow::Context;
+use cargo_util::paths;
+use serde::{Deserialize, Serialize};
+
+use crate::llvm::{LlvmToolchain, LlvmUtility};
+use crate::plugin;
+use crate::CIResult;
+
+/// Symbol referenced by every probe inserted by the Compiler Interrupts.
+const PROBE_SYMBOL: &str = "@intvActionHook";
+
+/// Probes of a function.
+#[derive(Clone, Debug)]
+pub struct FunctionProbes {
+    /// Symbol name of the function.
+    pub symbol: String,
+    /// Number of probes inserted in the function.
+    pub probes: usize,
+    /// Source file of the function, if debug info is available.
+    pub file: Option<String>,
+    /// Source line of the function, if debug info is available.
+    pub line: Option<u32>,
+}
+
+impl FunctionProbes {
+    /// Gets the source location in the `addr2line` format.
+    pub fn location(&self) -> String {
+        format!(
+            "{}:{}",
+            self.file.as_deref().unwrap_or("??"),
+            self.line.unwrap_or_default()
+        )
+    }
+}
+
+/// Instrumentation report of a build.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Report {
+    /// Crate name -> function symbol -> number of probes.
+    pub crates: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+impl Report {
+    /// Adds the probed functions of a crate.
+    pub fn add(&mut self, crate_name: &str, functions: Vec<FunctionProbes>) {
+        let symbols = self.crates.entry(crate_name.to_string()).or_default();
+        for function in functions.into_iter().filter(|f| f.probes > 0) {
+            *symbols.entry(function.symbol).or_default() += function.probes;
+        }
+    }
+
+    /// Loads the report, if exists.
+    pub fn load<P: AsRef<Path>>(path: P) -> CIResult<Option<Self>> {
+        let path = path.as_ref();
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let s = paths::read(path)?;
+        Ok(serde_json::from_str(&s).ok())
+    }
+
+    /// Saves the report.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> CIResult<()> {
+        let s = serde_json::to_string(self).context("failed to serialize the report")?;
+        paths::write(path, s).context("failed to save the report")
+    }
+
+    /// Gets the human-readable changes of the instrumentation since the previous report.
+    pub fn diff(&self, previous: &Self) -> Vec<String> {
+        let mut changes = Vec::new();
+        let empty = BTreeMap::new();
+
+        let crate_names = self
+            .crates
+            .keys()
+            .chain(previous.crates.keys())
+            .collect::<BTreeSet<_>>();
+        for crate_name in crate_names {
+            let current = self.crates.get(crate_name).unwrap_or(&empty);
+            let old = previous.crates.get(crate_name).unwrap_or(&empty);
+
+            if old.is_empty() && !current.is_empty() {
+                changes.push(format!(
+                    "{}: newly instrumented ({} functions, {} probes)",
+                    crate_name,
+                    current.len(),
+                    current.values().sum::<usize>()
+                ));
+                continue;
+            }
+            if !old.is_empty() && current.is_empty() {
+                changes.push(format!(
+                    "{}: no longer instrumented (lost {} functions, {} probes)",
+                    crate_name,
+                    old.len(),
+                    old.values().sum::<usize>()
+                ));
+                continue;
+            }
+
+            for (symbol, &probes) in current {
+                match old.get(symbol) {
+                    None => changes.push(format!(
+                        "{}: + {} ({} probes)",
+                        crate_name,
+                        demangle(symbol),
+                        probes
+                    )),
+                    Some(&old_probes) if old_probes != probes => changes.push(format!(
+                        "{}: ~ {} ({} -> {} probes)",
+                        crate_name,
+                        demangle(symbol),
+                        old_probes,
+                        probes
+                    )),
+                    _ => {}
+                }
+            }
+            for (symbol, probes) in old {
+                if !current.contains_key(symbol) {
+                    changes.push(format!(
+                        "{}: - {} (lost {} probes)",
+                        crate_name,
+                        demangle(symbol),
+                        probes
+                    ));
+                }
+            }
+        }
+
+        changes
+    }
+
+    /// Gets the probe placement report as a table of crates and their probed functions.
+    pub fn table(&self, library_args: &[String]) -> String {
+        let mut s = String::new();
+        let _ = writeln!(
+            s,
+            "Instruction granularity: {}, commit interval: {}",
+            library_arg(library_args, plugin::CI_INST_GRAN_ARG).unwrap_or("default"),
+            library_arg(library_args, plugin::CI_COMMIT_INTV_ARG).unwrap_or("default"),
+        );
+        let _ = writeln!(s, "Library arguments: {}", library_args.join(" "));
+
+        let width = self
+            .crates
+            .keys()
+            .map(String::len)
+            .chain(std::iter::once("total".len()))
+            .max()
+            .unwrap_or_default();
+
+        let _ = writeln!(s, "\n{:<width$}  {:>8}  function", "crate", "probes");
+        for (crate_name, symbols) in &self.crates {
+            let _ = writeln!(
+                s,
+                "{:<width$}  {:>8}  ({} functions)",
+                crate_name,
+                symbols.values().sum::<usize>(),
+                symbols.len()
+            );
+            for (symbol, probes) in symbols {
+                let _ = writeln!(s, "{:<width$}  {:>8}  {}", "", probes, demangle(symbol));
+            }
+        }
+        let _ = writeln!(
+            s,
+            "{:<width$}  {:>8}  ({} crates)",
+            "total",
+            self.crates
+                .values()
+                .flat_map(|symbols| symbols.values())
+                .sum::<usize>(),
+            self.crates.len()
+        );
+
+        s
+    }
+
+    /// Gets the probe placement report as JSON.
+    pub fn json(&self, library_args: &[String]) -> serde_json::Value {
+        let crates = self
+            .crates
+            .iter()
+            .map(|(crate_name, symbols)| {
+                let functions = symbols
+                    .iter()
+                    .map(|(symbol, probes)| {
+                        serde_json::json!({
+                            "symbol": symbol,
+                            "name": demangle(symbol),
+                            "probes": probes,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let crate_report = serde_json::json!({
+                    "probes": symbols.values().sum::<usize>(),
+                    "functions": functions,
+                });
+                (crate_name.clone(), crate_report)
+            })
+            .collect::<serde_json::Map<_, _>>();
+
+        serde_json::json!({
+            "instruction_granularity": library_arg(library_args, plugin::CI_INST_GRAN_ARG),
+            "commit_interval": library_arg(library_args, plugin::CI_COMMIT_INTV_ARG),
+            "library_args": library_args,
+            "crates": crates,
+        })
+    }
+}
+
+/// Gets the value of an argument for the library, e.g. `2` for `-inst-gran` in `-inst-gran=2`.
+fn library_arg<'a>(library_args: &'a [String], name: &str) -> Option<&'a str> {
+    library_args
+        .iter()
+        .find_map(|arg| arg.strip_prefix(name)?.strip_prefix('='))
+}
+
+/// Demangles a Rust symbol name without the hash.
+pub(crate) fn demangle(symbol: &str) -> String {
+    format!("{:#}", rustc_demangle::demangle(symbol))
+}
+
+/// Analyzes the probes of every function defined in a CI-integrated LLVM IR file.
+///
+/// LLVM bitcode files are disassembled to textual LLVM IR with `llvm-dis` first.
+pub fn analyze<P: AsRef<Path>>(
+    toolchain: &LlvmToolchain,
+    path: P,
+) -> CIResult<Vec<FunctionProbes>> {
+    let path = path.as_ref();
+    if path.extension().is_some_and(|extension| extension == "bc") {
+        let output = LlvmUtility::Disassembler
+            .process_builder(toolchain)
+            .arg(path)
+            .args(&["-o", "-"])
+            .exec_with_output()?;
+        return parse(BufReader::new(output.stdout.as_slice()));
+    }
+
+    let file = File::open(path)
+        .with_context(|| format!("failed to open LLVM IR file `{}`", path.display()))?;
+    parse(BufReader::new(file))
+}
+
+/// Parses the probes of every function defined in textual LLVM IR.
+fn parse<R: BufRead>(reader: R) -> CIResult<Vec<FunctionProbes>> {
+    let mut functions: Vec<FunctionProbes> = Vec::new();
+    // index of the function and its `!dbg` attachment
+    let mut current: Option<(usize, Option<String>)> = None;
+    let mut dbg_attachments = Vec::new();
+    // `!DISubprogram` id -> (`!DIFile` id, line)
+    let mut subprograms = HashMap::new();
+    // `!DIFile` id -> path
+    let mut files = HashMap::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        if current.is_some() {
+            if line == "}" {
+                dbg_attachments.extend(current.take());
+            } else if line.contains(PROBE_SYMBOL) {
+                if let Some((idx, _)) = &current {
+                    functions[*idx].probes += 1;
+                }
+            }
+        } else if line.starts_with("define ") {
+            let symbol = symbol_name(&line).unwrap_or_default();
+            let dbg = metadata_after(&line, "!dbg ");
+            functions.push(FunctionProbes {
+                symbol,
+                probes: 0,
+                file: None,
+                line: None,
+            });
+            current = Some((functions.len() - 1, dbg));
+        } else if line.starts_with('!') {
+            let (id, value) = match line.split_once(" = ") {
+                Some(split) => split,
+                None => continue,
+            };
+            if value.contains("!DISubprogram(") {
+                let file = metadata_after(value, "file: ");
+                let line = field(value, "line: ").and_then(|s| s.parse().ok());
+                subprograms.insert(id.to_string(), (file, line));
+            } else if value.starts_with("!DIFile(") {
+                let name = string_field(value, "filename: ").unwrap_or_default();
+                let dir = string_field(value, "directory: ").unwrap_or_default();
+                let path = if name.starts_with('/') || dir.is_empty() {
+                    name
+                } else {
+                    format!("{}/{}", dir, name)
+                };
+                files.insert(id.to_string(), path);
+            }
+        }
+    }
+
+    for (idx, dbg) in dbg_attachments {
+        if let Some((file, line)) = dbg.and_then(|dbg| subprograms.get(&dbg)) {
+            let function = &mut functions[idx];
+            function.file = file.as_ref().and_then(|file| files.get(file)).cloned();
+            function.line = *line;
+        }
+    }
+
+    Ok(functions)
+}
+
+/// Instructions and loops of the functions defined in an LLVM IR file.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct IrSummary {
+    /// Number of instructions of the functions.
+    pub instructions: usize,
+    /// Number of loops of the functions, i.e. of branches back to a block above them.
+    pub loops: usize,
+}
+
+/// Counts the instructions and the loops of the functions defined in an LLVM IR file.
+///
+/// This is a quick scan of the text without parsing the IR, so that files without
+/// anything worth instrumenting can skip `opt` entirely. LLVM bitcode files are
+/// disassembled to textual LLVM IR with `llvm-dis` first.
+pub fn summarize<P: AsRef<Path>>(toolchain: &LlvmToolchain, path: P) -> CIResult<IrSummary> {
+    let path = path.as_ref();
+    if path.extension().is_some_and(|extension| extension == "bc") {
+        let output = LlvmUtility::Disassembler
+            .process_builder(toolchain)
+            .arg(path)
+            .args(&["-o", "-"])
+            .exec_with_output()?;
+        return summarize_ir(BufReader::new(output.stdout.as_slice()));
+    }
+
+    let file = File::open(path)
+        .with_context(|| format!("failed to open LLVM IR file `{}`", path.display()))?;
+    summarize_ir(BufReader::new(file))
+}
+
+/// Counts the instructions and the loops of the functions defined in textual LLVM IR.
+pub fn summarize_ir<R: BufRead>(reader: R) -> CIResult<IrSummary> {
+    let mut summary = IrSummary::default();
+    let mut in_function = false;
+    // blocks of the function above the current line
+    let mut blocks = HashSet::new();
+    for line in reader.lines() {
+        let line = line?;
+        if !in_function {
+            if line.starts_with("define ") {
+                in_function = true;
+                blocks.clear();
+            }
+        } else if line == "}" {
+            in_function = false;
+        } else if line.starts_with("  ") {
+            // instructions are indented, unlike labels and comments
+            if line.trim_start().starts_with(';') {
+                continue;
+            }
+            summary.instructions += 1;
+            let mut rest = line.as_str();
+            while let Some((_, target)) = rest.split_once("label %") {
+                let label = block_label(target);
+                if blocks.contains(label) {
+                    summary.loops += 1;
+                }
+                rest = target;
+            }
+        } else if !line.starts_with(';') {
+            // `bb1:  ; preds = %start`, `5:`, or `"bb1.i":`
+            if let Some(label) = line.split_once(':').map(|(label, _)| label) {
+                blocks.insert(label.trim_matches('"').to_string());
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Gets the label of a block from the text following `label %`, e.g. `bb1` of
+/// `bb1, label %bb2` or `bb1.i` of `"bb1.i"`.
+fn block_label(s: &str) -> &str {
+    match s.strip_prefix('"') {
+        Some(quoted) => quoted.split('"').next().unwrap_or_default(),
+        None => s.split([',', ' ', ']']).next().unwrap_or_default(),
+    }
+}
+
+/// Writes the map of function symbols to probe counts and source locations.
+///
+/// Each line is tab-separated as `<symbol> <probes> <file>:<line>`. Unknown locations
+/// are written as `??:0` like `addr2line` does. Functions without probes are omitted.
+pub fn write_map<P: AsRef<Path>>(path: P, functions: &[FunctionProbes]) -> CIResult<()> {
+    let mut s = String::from("# symbol\tprobes\tlocation\n");
+    for function in functions.iter().filter(|f| f.probes > 0) {
+        let _ = writeln!(
+            s,
+            "{}\t{}\t{}",
+            function.symbol,
+            function.probes,
+            function.location()
+        );
+    }
+    paths::write(path, s).context("failed to write the probe map")
+}
+
+/// Gets the symbol name from a function definition.
+fn symbol_name(line: &str) -> Option<String> {
+    let start = line.find('@')? + 1;
+    let rest = &line[start..];
+    if let Some(quoted) = rest.strip_prefix('"') {
+        Some(quoted[..quoted.find('"')?].to_string())
+    } else {
+        Some(rest[..rest.find('(')?].to_string())
+    }
+}
+
+/// Gets the metadata id following the given key, e.g. `!12` in `!dbg !12`.
+fn metadata_after(s: &str, key: &str) -> Option<String> {
+    let value = field(s, key)?;
+    value.starts_with('!').then(|| value.to_string())
+}
+
+/// Gets the raw value of a field delimited by `,`, `)`, or whitespace.
+fn field<'a>(s: &'a str, key: &str) -> Option<&'a str> {
+    let start = s.find(key)? + key.len();
+    let rest = &s[start..];
+    let end = rest
+        .find(|c: char| c == ',' || c == ')' || c.is_whitespace())
+        .unwrap_or(rest.len());
+    Some(&rest[..end])
+}
+
+/// Gets the value of a quoted string field.
+fn string_field(s: &str, key: &str) -> Option<String> {
+    let start = s.find(key)? + key.len();
+    let rest = s[start..].strip_prefix('"')?;
+    Some(rest[..rest.find('"')?].to_string())
+}
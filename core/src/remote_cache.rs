@@ -0,0 +1,285 @@
+//! Remote cache of the CI-integrated object files shared between machines.
+//!
+//! The entries are stored compressed as in the local cache, under the same relative
+//! paths. The remote cache is best-effort: after a failure, it is not used for the rest
+//! of the build, which continues with the local cache only.
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use cargo_util::paths;
+use ring::{digest, hmac};
+use tracing::{debug, warn};
+
+use crate::config::CacheRemote;
+use crate::CIResult;
+
+/// Timeout of the requests to the remote cache.
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Environment variable of the bearer token sent to an HTTP remote cache.
+const CACHE_TOKEN_ENV: &str = "CARGO_CI_CACHE_TOKEN";
+
+/// Remote cache of the CI-integrated object files.
+#[derive(Debug)]
+pub struct RemoteCache {
+    /// Location of the entries.
+    remote: CacheRemote,
+    /// Agent of the HTTP requests.
+    agent: ureq::Agent,
+    /// Bearer token of the HTTP endpoint.
+    token: Option<String>,
+    /// Credentials of the S3 bucket, or `None` for a public bucket.
+    credentials: Option<S3Credentials>,
+    /// Whether a request failed, disabling the remote cache.
+    failed: AtomicBool,
+}
+
+/// Credentials of an S3 bucket read from the AWS environment variables.
+#[derive(Debug)]
+struct S3Credentials {
+    /// `AWS_ACCESS_KEY_ID`.
+    access_key_id: String,
+    /// `AWS_SECRET_ACCESS_KEY`.
+    secret_access_key: String,
+    /// `AWS_SESSION_TOKEN` of temporary credentials.
+    session_token: Option<String>,
+}
+
+impl RemoteCache {
+    /// Creates the remote cache.
+    pub fn new(remote: CacheRemote) -> Self {
+        let agent = ureq::AgentBuilder::new().timeout(REMOTE_TIMEOUT).build();
+        let token = std::env::var(CACHE_TOKEN_ENV).ok();
+        let credentials = match (
+            std::env::var("AWS_ACCESS_KEY_ID"),
+            std::env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            (Ok(access_key_id), Ok(secret_access_key)) => Some(S3Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+            }),
+            _ => None,
+        };
+        Self {
+            remote,
+            agent,
+            token,
+            credentials,
+            failed: AtomicBool::new(false),
+        }
+    }
+
+    /// Gets the compressed entry at the relative path, or `None` if it does not exist or
+    /// the remote cache failed.
+    pub fn get(&self, path: &str) -> Option<Vec<u8>> {
+        if self.failed.load(Ordering::Relaxed) {
+            return None;
+        }
+        let result = match &self.remote {
+            CacheRemote::Dir(dir) => read_entry(dir.join(path)),
+            CacheRemote::Http(url) => self.get_http(self.http_request("GET", url, path)),
+            CacheRemote::S3 { bucket, prefix } => {
+                let request = self.s3_request("GET", bucket, prefix, path, &[]);
+                request.and_then(|request| self.get_http(request))
+            }
+        };
+        match result {
+            Ok(data) => data,
+            Err(error) => {
+                self.fail("download", error);
+                None
+            }
+        }
+    }
+
+    /// Puts the compressed entry at the relative path.
+    pub fn put(&self, path: &str, data: &[u8]) {
+        if self.failed.load(Ordering::Relaxed) {
+            return;
+        }
+        let result = match &self.remote {
+            CacheRemote::Dir(dir) => write_entry(dir.join(path), data),
+            CacheRemote::Http(url) => self.put_http(self.http_request("PUT", url, path), data),
+            CacheRemote::S3 { bucket, prefix } => {
+                let request = self.s3_request("PUT", bucket, prefix, path, data);
+                request.and_then(|request| self.put_http(request, data))
+            }
+        };
+        if let Err(error) = result {
+            self.fail("upload", error);
+        }
+    }
+
+    /// Warns about the failure once and disables the remote cache.
+    fn fail(&self, action: &str, error: anyhow::Error) {
+        if !self.failed.swap(true, Ordering::Relaxed) {
+            warn!(
+                "failed to {} a remote cache entry, using the local cache only: {:#}",
+                action, error
+            );
+        }
+    }
+
+    /// Creates the request of the entry at the relative path under the URL.
+    fn http_request(&self, method: &str, url: &str, path: &str) -> ureq::Request {
+        let request = self.agent.request(method, &format!("{}/{}", url, path));
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+
+    /// Sends the `GET` request, returning `None` if the entry does not exist.
+    fn get_http(&self, request: ureq::Request) -> CIResult<Option<Vec<u8>>> {
+        debug!("GET {}", request.url());
+        let resp = match request.call() {
+            Ok(resp) => resp,
+            // S3 answers 403 for a missing key without the permission to list the bucket
+            Err(ureq::Error::Status(404 | 403, _)) => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        let mut data = Vec::new();
+        resp.into_reader()
+            .read_to_end(&mut data)
+            .context("failed to read the response")?;
+        Ok(Some(data))
+    }
+
+    /// Sends the `PUT` request with the data.
+    fn put_http(&self, request: ureq::Request, data: &[u8]) -> CIResult<()> {
+        debug!("PUT {}", request.url());
+        request.send_bytes(data)?;
+        Ok(())
+    }
+
+    /// Creates the request of the entry in the S3 bucket, signed with AWS Signature
+    /// Version 4 if the credentials are set.
+    ///
+    /// The region is read from `AWS_REGION` or `AWS_DEFAULT_REGION`, and the endpoint
+    /// of an S3-compatible storage from `AWS_ENDPOINT_URL`, addressing the bucket in
+    /// the path.
+    fn s3_request(
+        &self,
+        method: &str,
+        bucket: &str,
+        prefix: &str,
+        path: &str,
+        payload: &[u8],
+    ) -> CIResult<ureq::Request> {
+        let region = std::env::var("AWS_REGION")
+            .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| "us-east-1".to_string());
+        let key = if prefix.is_empty() {
+            path.to_string()
+        } else {
+            format!("{}/{}", prefix, path)
+        };
+        let url = match std::env::var("AWS_ENDPOINT_URL") {
+            Ok(endpoint) => format!("{}/{}/{}", endpoint.trim_end_matches('/'), bucket, key),
+            Err(_) => format!("https://{}.s3.{}.amazonaws.com/{}", bucket, region, key),
+        };
+        let url = url::Url::parse(&url).with_context(|| format!("invalid S3 URL `{}`", url))?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => bail!("invalid S3 URL `{}`", url),
+        };
+
+        let mut request = self.agent.request_url(method, &url);
+        let credentials = match &self.credentials {
+            Some(credentials) => credentials,
+            None => return Ok(request),
+        };
+
+        let now = chrono::Utc::now();
+        let date = now.format("%Y%m%d").to_string();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = hex(digest::digest(&digest::SHA256, payload).as_ref());
+        let mut headers = vec![
+            ("host", host),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+        if let Some(session_token) = &credentials.session_token {
+            headers.push(("x-amz-security-token", session_token.clone()));
+        }
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "{}\n{}\n\n{}\n{}\n{}",
+            method,
+            url.path(),
+            headers
+                .iter()
+                .map(|(name, value)| format!("{}:{}\n", name, value))
+                .collect::<String>(),
+            signed_headers,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref())
+        );
+        let signing_key = [date.as_str(), region.as_str(), "s3", "aws4_request"]
+            .iter()
+            .fold(
+                format!("AWS4{}", credentials.secret_access_key).into_bytes(),
+                |key, data| sign(&key, data.as_bytes()),
+            );
+        let signature = hex(&sign(&signing_key, string_to_sign.as_bytes()));
+
+        for (name, value) in &headers[1..] {
+            request = request.set(name, value);
+        }
+        Ok(request.set(
+            "Authorization",
+            &format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                credentials.access_key_id, scope, signed_headers, signature
+            ),
+        ))
+    }
+}
+
+/// Reads the entry in the shared directory, returning `None` if it does not exist.
+fn read_entry(path: PathBuf) -> CIResult<Option<Vec<u8>>> {
+    match fs::read(&path) {
+        Ok(data) => Ok(Some(data)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error).with_context(|| format!("failed to read `{}`", path.display())),
+    }
+}
+
+/// Writes the entry in the shared directory through a temporary file, as other machines
+/// may read it at the same time.
+fn write_entry(path: PathBuf, data: &[u8]) -> CIResult<()> {
+    paths::create_dir_all(path.parent().context("invalid cache entry")?)?;
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    paths::write(&tmp, data)?;
+    fs::rename(&tmp, &path)
+        .with_context(|| format!("failed to store cache entry `{}`", path.display()))?;
+    Ok(())
+}
+
+/// Computes the HMAC-SHA256 of the data.
+fn sign(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+    hmac::sign(&key, data).as_ref().to_vec()
+}
+
+/// Formats the bytes in lowercase hexadecimal.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
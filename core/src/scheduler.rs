@@ -0,0 +1,201 @@
+//! Dependency-aware scheduler of the integration and linking tasks.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+use crate::cargo::Linker;
+use crate::paths::PathExt;
+
+/// Task of the integration.
+#[derive(Debug)]
+pub enum Task {
+    /// Integrate an LLVM IR file.
+    Integrate(PathBuf),
+    /// Link a CI-integrated binary.
+    Link(Linker),
+}
+
+/// Estimated peak memory of `opt` per byte of the LLVM IR file.
+const OPT_MEMORY_FACTOR: u64 = 4;
+
+/// Scheduler of the integration and linking tasks.
+///
+/// A linker is started as soon as every LLVM IR file of its objects and rlibs has
+/// been integrated, so linking overlaps with the integration of the other crates.
+/// Ready linkers have priority over the integration as they finish the binaries.
+/// A linker is dropped once the integration of one of its units fails.
+///
+/// LLVM IR files are integrated from the largest to the smallest. An integration is
+/// only started if its estimated memory fits in the memory budget along with the
+/// running ones, unless nothing else is running.
+#[derive(Debug)]
+pub struct Scheduler {
+    /// State of the tasks.
+    state: Mutex<State>,
+    /// Notified when an integration is finished.
+    finished: Condvar,
+    /// Time when the scheduler is created.
+    start: Instant,
+}
+
+/// State of the tasks.
+#[derive(Debug)]
+struct State {
+    /// LLVM IR files waiting for the integration with their estimated memory.
+    files: VecDeque<(PathBuf, u64)>,
+    /// Linkers waiting for their units to be integrated.
+    linkers: VecDeque<(Linker, HashSet<String>)>,
+    /// Number of LLVM IR files of each unit not integrated yet.
+    pending: HashMap<String, usize>,
+    /// LLVM IR files being integrated with their estimated memory.
+    running: HashMap<PathBuf, u64>,
+    /// Maximum estimated memory of the running integrations.
+    memory_budget: u64,
+    /// Estimated memory of the running integrations.
+    memory_used: u64,
+    /// Elapsed time when all integrations are finished.
+    integrated: Option<Duration>,
+}
+
+impl Scheduler {
+    /// Creates a scheduler for the LLVM IR files and the linkers.
+    pub fn new(files: Vec<PathBuf>, linkers: Vec<Linker>, memory_budget: u64) -> Self {
+        let mut pending = HashMap::new();
+        for file in &files {
+            if let Ok(unit) = file.unit_name() {
+                *pending.entry(unit).or_default() += 1;
+            }
+        }
+
+        let linkers = linkers
+            .into_iter()
+            .map(|linker| {
+                let units = linker_units(&linker)
+                    .into_iter()
+                    .filter(|unit| pending.contains_key(unit))
+                    .collect::<HashSet<_>>();
+                debug!("linker units for {}: {:?}", linker.args.output_file, units);
+                (linker, units)
+            })
+            .collect();
+
+        let mut files = files
+            .into_iter()
+            .map(|file| {
+                let size = file.metadata().map(|m| m.len()).unwrap_or_default();
+                (file, size.saturating_mul(OPT_MEMORY_FACTOR))
+            })
+            .collect::<Vec<_>>();
+        // start the largest files first so that the build does not end with
+        // a single large file integrating while the other workers are idle
+        files.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        Self {
+            state: Mutex::new(State {
+                files: files.into(),
+                linkers,
+                pending,
+                running: HashMap::new(),
+                memory_budget,
+                memory_used: 0,
+                integrated: None,
+            }),
+            finished: Condvar::new(),
+            start: Instant::now(),
+        }
+    }
+
+    /// Takes the next task, waiting for the integration of the pending linkers.
+    ///
+    /// Returns `None` once there is no task left for this worker.
+    pub fn next(&self) -> Option<Task> {
+        let mut state = self.state.lock().expect("failed to acquire lock");
+        loop {
+            let ready = state.linkers.iter().position(|(_, units)| {
+                units
+                    .iter()
+                    .all(|unit| state.pending.get(unit).copied().unwrap_or_default() == 0)
+            });
+            if let Some(idx) = ready {
+                let (linker, _) = state.linkers.remove(idx)?;
+                return Some(Task::Link(linker));
+            }
+
+            let available = state.memory_budget.saturating_sub(state.memory_used);
+            let fits = state
+                .files
+                .iter()
+                .position(|(_, memory)| *memory <= available || state.running.is_empty());
+            if let Some((file, memory)) = fits.and_then(|idx| state.files.remove(idx)) {
+                debug!("estimated memory for {}: {}", file.display(), memory);
+                state.memory_used += memory;
+                state.running.insert(file.clone(), memory);
+                return Some(Task::Integrate(file));
+            }
+
+            if state.running.is_empty() || (state.files.is_empty() && state.linkers.is_empty()) {
+                return None;
+            }
+
+            state = self.finished.wait(state).expect("failed to acquire lock");
+        }
+    }
+
+    /// Marks the integration of the LLVM IR file as finished.
+    pub fn finish<P: AsRef<Path>>(&self, file: P, success: bool) {
+        let mut state = self.state.lock().expect("failed to acquire lock");
+        if let Ok(unit) = file.unit_name() {
+            if let Some(count) = state.pending.get_mut(&unit) {
+                *count = count.saturating_sub(1);
+            }
+            if !success {
+                state.linkers.retain(|(linker, units)| {
+                    let failed = units.contains(&unit);
+                    if failed {
+                        debug!("linking dropped: {}", linker.args.output_file);
+                    }
+                    !failed
+                });
+            }
+        }
+        if let Some(memory) = state.running.remove(file.as_ref()) {
+            state.memory_used -= memory;
+        }
+        if state.running.is_empty() && state.files.is_empty() {
+            state.integrated = Some(self.start.elapsed());
+        }
+        self.finished.notify_all();
+    }
+
+    /// Drops the tasks not started yet, so that the workers stop after their running tasks.
+    pub fn cancel(&self) {
+        let mut state = self.state.lock().expect("failed to acquire lock");
+        state.files.clear();
+        state.linkers.clear();
+        self.finished.notify_all();
+    }
+
+    /// Gets the elapsed time of the integration and the linking after that.
+    pub fn durations(&self) -> (Duration, Duration) {
+        let state = self.state.lock().expect("failed to acquire lock");
+        let total = self.start.elapsed();
+        let integrated = state.integrated.unwrap_or(total);
+        (integrated, total.saturating_sub(integrated))
+    }
+}
+
+/// Gets the compilation units of the objects and rlibs replaced by the linker.
+fn linker_units(linker: &Linker) -> Vec<String> {
+    linker
+        .args
+        .input_files
+        .iter()
+        .chain(&linker.args.rlib_files)
+        .filter(|file| file.contains("deps"))
+        .filter_map(|file| file.unit_name().ok())
+        .collect()
+}
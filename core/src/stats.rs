@@ -0,0 +1,113 @@
+//! Statistics of the interrupts written by the Compiler Interrupts runtime.
+//!
+//! `cargo-run-ci --stats` sets [`STATS_ENV`] to the path of a file, which the runtime of
+//! the `compiler-interrupts` crate writes at exit with a line per interrupt: the thread,
+//! the instruction count given to the handler, i.e. the interval since the previous
+//! interrupt of the thread, and optionally the time since it in nanoseconds.
+//!
+//! ```text
+//! # thread instructions nanoseconds
+//! 1 1003 10452
+//! 2 998
+//! ```
+
+use anyhow::{bail, Context};
+
+use crate::CIResult;
+
+/// Path to the file of the statistics written by the runtime at exit.
+pub const STATS_ENV: &str = "CARGO_CI_STATS";
+
+/// Statistics of the interrupts of a run.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct InterruptStats {
+    /// Number of threads that received an interrupt.
+    pub threads: usize,
+    /// Instruction counts between the interrupts, sorted.
+    pub intervals: Vec<u64>,
+    /// Times between the interrupts in nanoseconds, sorted, if written by the runtime.
+    pub latencies: Vec<u64>,
+}
+
+/// Bucket of a histogram, counting the values from `low` to before `high`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Bucket {
+    /// Lowest value of the bucket.
+    pub low: u64,
+    /// Value after the highest one of the bucket.
+    pub high: u64,
+    /// Number of values in the bucket.
+    pub count: usize,
+}
+
+/// Parses the statistics written by the runtime, ignoring the empty lines and the
+/// comments starting with `#`.
+pub fn parse(s: &str) -> CIResult<InterruptStats> {
+    let mut threads = Vec::new();
+    let mut stats = InterruptStats::default();
+    for (idx, line) in s.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let (thread, interval, latency) = match fields[..] {
+            [thread, interval] => (thread, interval, None),
+            [thread, interval, latency] => (thread, interval, Some(latency)),
+            _ => bail!(
+                "invalid line {} `{}`, expected `<thread> <instructions> [<nanoseconds>]`",
+                idx + 1,
+                line
+            ),
+        };
+        if !threads.contains(&thread) {
+            threads.push(thread);
+        }
+        stats.intervals.push(
+            interval
+                .parse()
+                .with_context(|| format!("invalid instruction count on line {}", idx + 1))?,
+        );
+        if let Some(latency) = latency {
+            stats.latencies.push(
+                latency
+                    .parse()
+                    .with_context(|| format!("invalid nanoseconds on line {}", idx + 1))?,
+            );
+        }
+    }
+    stats.threads = threads.len();
+    stats.intervals.sort_unstable();
+    stats.latencies.sort_unstable();
+    Ok(stats)
+}
+
+/// Gets the value at the percentile of the sorted values with the nearest-rank method.
+pub fn percentile(sorted: &[u64], percent: usize) -> Option<u64> {
+    let rank = (percent.min(100) * sorted.len()).div_ceil(100);
+    sorted.get(rank.saturating_sub(1)).copied()
+}
+
+/// Counts the values in buckets of the powers of two, from the bucket of the lowest
+/// value to the one of the highest, including the empty buckets between them.
+pub fn histogram(values: &[u64]) -> Vec<Bucket> {
+    // bucket `n` counts the values of `n` bits, from 2^(n-1) to before 2^n
+    let bucket = |value: u64| u64::BITS - value.leading_zeros();
+    let mut counts = [0; u64::BITS as usize + 1];
+    for &value in values {
+        counts[bucket(value) as usize] += 1;
+    }
+    let first = values.iter().map(|&value| bucket(value)).min();
+    let last = values.iter().map(|&value| bucket(value)).max();
+    let (first, last) = match (first, last) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Vec::new(),
+    };
+    (first..=last)
+        .map(|n| Bucket {
+            low: if n == 0 { 0 } else { 1 << (n - 1) },
+            high: 1_u64.checked_shl(n).unwrap_or(u64::MAX),
+            count: counts[n as usize],
+        })
+        .collect()
+}
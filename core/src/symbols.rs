@@ -0,0 +1,309 @@
+//! Symbol analysis of the object files compiled by `rustc`.
+//!
+//! `rustc` generates the allocator shim, an object file without LLVM IR forwarding the
+//! allocator symbols to the default allocator `__rdl_*`, or to the global allocator
+//! `__rg_*` of older versions. Newer versions define the allocator symbols in the
+//! object file of the crate declaring `#[global_allocator]` instead, e.g. calling
+//! `_rjem_mallocx` of jemalloc or `mi_malloc_aligned` of mimalloc, and only leave the
+//! `__rust_no_alloc_shim_is_unstable` marker in the shim. Such an object file is code
+//! of the crate and is integrated like the others.
+//!
+//! A binary may have no allocator shim at all, e.g. a `#![no_std]` binary without an
+//! allocator, so none of its object files is recognized as one.
+//!
+//! The analysis of the object files is cached by [`SymbolCache`] in `ci-symbols.json` of
+//! the target directory, and read again only for the changed object files.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use cargo_util::paths;
+use memmap2::Mmap;
+use object::{BinaryFormat, Object, ObjectSection, ObjectSymbol, RelocationTarget};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::CIResult;
+
+/// Symbols of interest defined in an object file.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct ObjectSymbols {
+    /// Defines the Compiler Interrupts handler `intvActionHook`.
+    pub ci_hook: bool,
+    /// Is the allocator shim generated by `rustc`.
+    pub allocator: bool,
+}
+
+/// Allocator symbols only defined by the allocator shim.
+const SHIM_SYMBOLS: [&str; 4] = [
+    "__rust_alloc_error_handler",
+    "__rust_alloc_error_handler_should_panic",
+    "__rust_no_alloc_shim_is_unstable",
+    "__rust_no_alloc_shim_is_unstable_v2",
+];
+
+/// Allocator symbols defined by the allocator shim, or by the crate declaring
+/// `#[global_allocator]` on newer versions.
+const ALLOCATOR_SYMBOLS: [&str; 4] = [
+    "__rust_alloc",
+    "__rust_dealloc",
+    "__rust_realloc",
+    "__rust_alloc_zeroed",
+];
+
+/// Prefixes of the allocators the allocator shim forwards to.
+const FORWARDED_PREFIXES: [&str; 2] = ["__rdl_", "__rg_"];
+
+/// Parses the symbols of an object file.
+pub fn parse(data: &[u8]) -> CIResult<ObjectSymbols> {
+    let object = object::File::parse(data)?;
+    // symbols are prefixed with an underscore on macOS
+    let macho = object.format() == BinaryFormat::MachO;
+    let mut symbols = ObjectSymbols::default();
+    let mut shim = false;
+    let mut allocator = false;
+    let mut forwarded = false;
+    // global symbols only, not e.g. the section symbols, the mapping symbols `$x` and
+    // `$d` of AArch64, or the local symbols referring to them; the CI handler is a
+    // thread-local symbol, so it is not filtered by the kind
+    for symbol in object.symbols().filter(|symbol| symbol.is_global()) {
+        let mut name = symbol.name().unwrap_or_default();
+        if macho {
+            name = name.strip_prefix('_').unwrap_or(name);
+        }
+        let name = unmangle(name);
+        if symbol.is_undefined() {
+            forwarded |= FORWARDED_PREFIXES
+                .iter()
+                .any(|prefix| name.starts_with(prefix));
+        } else {
+            symbols.ci_hook |= name.contains("intvActionHook");
+            shim |= SHIM_SYMBOLS.contains(&name);
+            allocator |= ALLOCATOR_SYMBOLS.contains(&name);
+        }
+    }
+    symbols.allocator = shim || (allocator && forwarded);
+    Ok(symbols)
+}
+
+/// Counts the relocations of an object file referring to the Compiler Interrupts
+/// handler `intvActionHook`, which the pass loads at each probe it inserts.
+pub fn probe_sites(data: &[u8]) -> CIResult<usize> {
+    let object = object::File::parse(data)?;
+    let mut sites = 0;
+    for section in object.sections() {
+        for (_, relocation) in section.relocations() {
+            if let RelocationTarget::Symbol(index) = relocation.target() {
+                let symbol = object.symbol_by_index(index)?;
+                if symbol.name().unwrap_or_default().contains("intvActionHook") {
+                    sites += 1;
+                }
+            }
+        }
+    }
+    Ok(sites)
+}
+
+/// Returns true if a binary defines the Compiler Interrupts handler `intvActionHook`,
+/// or `None` if its symbols were stripped.
+pub fn defines_ci_hook(data: &[u8]) -> CIResult<Option<bool>> {
+    let object = object::File::parse(data)?;
+    let mut symbols = object.symbols().peekable();
+    if symbols.peek().is_none() {
+        return Ok(None);
+    }
+    Ok(Some(symbols.any(|symbol| {
+        !symbol.is_undefined() && symbol.name().unwrap_or_default().contains("intvActionHook")
+    })))
+}
+
+/// Gets the name of the symbol as declared, e.g. `__rust_alloc` of
+/// `_RNvCs1234_7___rustc12___rust_alloc`.
+///
+/// The allocator symbols are mangled in the `__rustc` namespace by newer versions.
+fn unmangle(name: &str) -> &str {
+    if let Some((_, rest)) = name.split_once("7___rustc") {
+        let len = rest.bytes().take_while(u8::is_ascii_digit).count();
+        if let Ok(n) = rest[..len].parse::<usize>() {
+            // `_` separates the length from an identifier starting with `_`
+            let ident = &rest[len..];
+            let ident = ident.strip_prefix('_').unwrap_or(ident);
+            if let Some(ident) = ident.get(..n) {
+                return ident;
+            }
+        }
+    }
+    name
+}
+
+/// Version of the saved analysis, bumped when the analysis of the symbols changes.
+const VERSION: u32 = 1;
+
+/// Fingerprint of an object file to detect changes without reading it.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+struct Fingerprint {
+    /// Size of the file.
+    size: u64,
+    /// Modification time of the file in nanoseconds since the epoch.
+    modified: u128,
+    /// MD5 of the content.
+    hash: String,
+}
+
+/// Saved analysis of the object files.
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct Entries {
+    /// Version of the analysis.
+    #[serde(default)]
+    version: u32,
+    /// MD5 of the object file -> symbols.
+    objects: HashMap<String, ObjectSymbols>,
+    /// Path to the object file -> fingerprint.
+    files: HashMap<PathBuf, Fingerprint>,
+}
+
+/// Symbol analysis of object files cached by their content hash.
+///
+/// Unchanged object files, by size and modification time, are not read again.
+#[derive(Debug)]
+pub struct SymbolCache {
+    /// Path to the saved analysis.
+    path: PathBuf,
+    /// Saved analysis.
+    entries: Mutex<Entries>,
+    /// Whether new entries are added since loaded.
+    dirty: AtomicBool,
+}
+
+impl SymbolCache {
+    /// Loads the saved analysis, e.g. given by the daemon, or else from the file,
+    /// starting over if missing or invalid.
+    pub fn load<P: AsRef<Path>>(path: P, saved: Option<String>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let entries = saved
+            .or_else(|| paths::read(&path).ok())
+            .and_then(|s| serde_json::from_str::<Entries>(&s).ok())
+            .filter(|entries| entries.version == VERSION)
+            .unwrap_or_else(|| Entries {
+                version: VERSION,
+                ..Entries::default()
+            });
+        Self {
+            path,
+            entries: Mutex::new(entries),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Gets the path to the saved analysis.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Saves the analysis if changed, returning it to be shared, e.g. with the daemon.
+    pub fn save(&self) -> CIResult<Option<String>> {
+        if !self.dirty.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let entries = self.entries.lock().expect("failed to acquire lock");
+        let s = serde_json::to_string(&*entries).context("failed to serialize symbols")?;
+        paths::write(&self.path, &s).context("failed to save symbols")?;
+        Ok(Some(s))
+    }
+
+    /// Analyzes the object files in one pass, memory-mapping them for reading.
+    ///
+    /// Object files analyzed before with the same content are not parsed again, and
+    /// the ones with the same size and modification time are not read again.
+    pub fn analyze<P: AsRef<Path>>(&self, files: &[P]) -> CIResult<Vec<ObjectSymbols>> {
+        let mut results = Vec::with_capacity(files.len());
+        for file in files {
+            let file = file.as_ref();
+            let metadata = fs::metadata(file)
+                .with_context(|| format!("failed to read object file `{}`", file.display()))?;
+            let size = metadata.len();
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map(|duration| duration.as_nanos())
+                .unwrap_or_default();
+
+            let known = {
+                let entries = self.entries.lock().expect("failed to acquire lock");
+                entries
+                    .files
+                    .get(file)
+                    .filter(|f| f.size == size && f.modified == modified)
+                    .and_then(|f| entries.objects.get(&f.hash))
+                    .copied()
+            };
+            if let Some(symbols) = known {
+                results.push(symbols);
+                continue;
+            }
+
+            let data = map(file)
+                .with_context(|| format!("failed to read object file `{}`", file.display()))?;
+
+            let key = format!("{:x}", md5::compute(&data[..]));
+            let cached = self
+                .entries
+                .lock()
+                .expect("failed to acquire lock")
+                .objects
+                .get(&key)
+                .copied();
+            let symbols = match cached {
+                Some(symbols) => symbols,
+                None => {
+                    debug!("analyzing symbols: {}", file.display());
+                    let symbols = parse(&data).with_context(|| {
+                        format!("failed to parse object file `{}`", file.display())
+                    })?;
+                    self.entries
+                        .lock()
+                        .expect("failed to acquire lock")
+                        .objects
+                        .insert(key.clone(), symbols);
+                    symbols
+                }
+            };
+            self.entries
+                .lock()
+                .expect("failed to acquire lock")
+                .files
+                .insert(
+                    file.to_path_buf(),
+                    Fingerprint {
+                        size,
+                        modified,
+                        hash: key,
+                    },
+                );
+            self.dirty.store(true, Ordering::Relaxed);
+            results.push(symbols);
+        }
+        Ok(results)
+    }
+}
+
+/// Analyzes the defined symbols of a single object file without saved analysis.
+pub fn analyze_file(path: &Path) -> CIResult<ObjectSymbols> {
+    let data =
+        map(path).with_context(|| format!("failed to read object file `{}`", path.display()))?;
+    parse(&data).with_context(|| format!("failed to parse object file `{}`", path.display()))
+}
+
+/// Maps the file into memory.
+#[allow(unsafe_code)]
+fn map(path: &Path) -> std::io::Result<Mmap> {
+    let file = File::open(path)?;
+    // SAFETY: object files are not modified while being analyzed after the build
+    unsafe { Mmap::map(&file) }
+}
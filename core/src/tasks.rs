@@ -0,0 +1,214 @@
+//! Structured concurrency of the external processes.
+//!
+//! Every process of the integration, i.e. `opt`, `llc`, the linker, and the download of
+//! the library, runs as a task of a [`TaskGroup`]. Cancelling the token of the group,
+//! either by Ctrl-C or by a failed task without `--keep-going`, kills the running
+//! processes and fails the pending ones with [`CIError::Cancelled`]. The time limit of
+//! the group applies to each of its tasks.
+
+use std::io::{ErrorKind, Read};
+use std::process::{Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context};
+use cargo_util::{ProcessBuilder, ProcessError};
+use tracing::debug;
+
+use crate::error::CIError;
+use crate::CIResult;
+
+/// Interval of polling the running processes for the cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Size of the chunks of a download read between the checks for the cancellation.
+const DOWNLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Token cancelling the tasks of a group and of the groups derived from it.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    /// Shared state of the token.
+    inner: Arc<TokenState>,
+}
+
+/// Shared state of a token.
+#[derive(Debug, Default)]
+struct TokenState {
+    /// Whether the token is cancelled.
+    cancelled: AtomicBool,
+    /// Token cancelling this one as well.
+    parent: Option<CancellationToken>,
+}
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a token cancelled along with this one, but not the other way round.
+    pub fn child(&self) -> Self {
+        Self {
+            inner: Arc::new(TokenState {
+                cancelled: AtomicBool::new(false),
+                parent: Some(self.clone()),
+            }),
+        }
+    }
+
+    /// Cancels the token and its children.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns true if the token or one of its parents is cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+            || self
+                .inner
+                .parent
+                .as_ref()
+                .is_some_and(CancellationToken::is_cancelled)
+    }
+
+    /// Fails with [`CIError::Cancelled`] if the token is cancelled.
+    pub fn check(&self) -> CIResult<()> {
+        if self.is_cancelled() {
+            bail!(CIError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+/// Group of tasks sharing a cancellation token and a time limit.
+#[derive(Clone, Debug, Default)]
+pub struct TaskGroup {
+    /// Token cancelling the tasks.
+    pub token: CancellationToken,
+    /// Time limit of each task.
+    pub timeout: Option<Duration>,
+}
+
+impl TaskGroup {
+    /// Creates a group of tasks.
+    pub fn new(token: CancellationToken, timeout: Option<Duration>) -> Self {
+        Self { token, timeout }
+    }
+
+    /// Runs the process as a task, capturing its output like
+    /// [`ProcessBuilder::exec_with_output`].
+    ///
+    /// The process is killed once the group is cancelled or the time limit is exceeded.
+    pub fn exec_with_output(&self, cmd: &ProcessBuilder) -> CIResult<Output> {
+        self.token.check()?;
+
+        let mut child = cmd
+            .build_command()
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("could not execute process {}", cmd))?;
+        // drain the pipes so that the process is not blocked by a full pipe
+        let stdout = drain(child.stdout.take());
+        let stderr = drain(child.stderr.take());
+
+        let start = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+
+            let timed_out = self.timeout.filter(|timeout| start.elapsed() >= *timeout);
+            if timed_out.is_some() || self.token.is_cancelled() {
+                debug!("killing process: {}", cmd);
+                // the process may have exited in the meantime
+                let _ = child.kill();
+                let _ = child.wait();
+                match timed_out {
+                    Some(timeout) => bail!(CIError::TimedOut(program(cmd), timeout)),
+                    None => bail!(CIError::Cancelled),
+                }
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        };
+
+        let output = Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        };
+        if output.status.success() {
+            Ok(output)
+        } else {
+            Err(ProcessError::new(
+                &format!("process didn't exit successfully: {}", cmd),
+                Some(output.status),
+                Some(&output),
+            )
+            .into())
+        }
+    }
+
+    /// Downloads the content of the URL as a task.
+    ///
+    /// The time limit applies to the whole transfer rather than each read.
+    pub fn download(&self, url: &str) -> CIResult<Vec<u8>> {
+        self.token.check()?;
+
+        let start = Instant::now();
+        let timed_out = |error: anyhow::Error| match self.timeout {
+            Some(timeout) if start.elapsed() >= timeout => {
+                anyhow::Error::new(CIError::TimedOut(url.to_string(), timeout))
+            }
+            _ => error,
+        };
+
+        let mut agent = ureq::AgentBuilder::new();
+        if let Some(timeout) = self.timeout {
+            agent = agent.timeout(timeout);
+        }
+        let resp = agent
+            .build()
+            .get(url)
+            .call()
+            .map_err(|error| timed_out(error.into()))?;
+
+        let len = resp
+            .header("Content-Length")
+            .and_then(|s| s.parse::<usize>().ok())
+            .unwrap_or(1_048_576);
+        let mut content = Vec::with_capacity(len);
+        let mut reader = resp.into_reader();
+        let mut chunk = vec![0; DOWNLOAD_CHUNK_SIZE];
+        loop {
+            self.token.check()?;
+            match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => content.extend_from_slice(&chunk[..n]),
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(timed_out(error.into())),
+            }
+        }
+        Ok(content)
+    }
+}
+
+/// Reads the pipe to the end on a separate thread.
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// Gets the name of the program of the process.
+fn program(cmd: &ProcessBuilder) -> String {
+    cmd.get_program().to_string_lossy().into_owned()
+}
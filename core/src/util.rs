@@ -0,0 +1,81 @@
+//! Matching of the crates, and parsing and formatting of the sizes and durations.
+
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+
+use crate::CIResult;
+
+/// Returns true if the crate is one of the crates to skip the integration.
+pub fn is_skipped(skip_crates: &[String], crate_name: &str) -> bool {
+    skip_crates
+        .iter()
+        .any(|skip_crate| skip_crate.replace('-', "_").contains(crate_name))
+}
+
+/// Returns true if the crate is one of the only crates to integrate, or if there are
+/// none, matched like the crates to skip.
+pub fn is_only(only_crates: &[String], crate_name: &str) -> bool {
+    only_crates.is_empty() || is_skipped(only_crates, crate_name)
+}
+
+/// Parses a duration in seconds with an optional `s`, `m`, or `h` suffix.
+pub fn parse_duration(s: &str) -> CIResult<Duration> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => s.split_at(idx),
+        None => (s, ""),
+    };
+    let secs = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => bail!("invalid duration unit `{}`", unit),
+    };
+    let number = number
+        .parse::<u64>()
+        .with_context(|| format!("invalid duration `{}`", s))?;
+    number
+        .checked_mul(secs)
+        .map(Duration::from_secs)
+        .with_context(|| format!("duration is too long `{}`", s))
+}
+
+/// Gets a human readable String for a size in bytes.
+pub fn human_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut unit = 0;
+    let mut scaled = size;
+    let mut remainder = 0;
+    while scaled >= 1024 && unit < UNITS.len() - 1 {
+        remainder = scaled % 1024;
+        scaled /= 1024;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", scaled, UNITS[unit])
+    } else {
+        format!("{}.{} {}", scaled, remainder * 10 / 1024, UNITS[unit])
+    }
+}
+
+/// Gets the jobserver of `make` or `cargo` running the process, if any, from
+/// `CARGO_MAKEFLAGS`, `MAKEFLAGS`, or `MFLAGS`.
+pub fn jobserver() -> Option<jobserver::Client> {
+    // SAFETY: the file descriptors of the jobserver are inherited from the parent and
+    // are not used by anything else in the process
+    #[allow(unsafe_code)]
+    unsafe {
+        jobserver::Client::from_env()
+    }
+}
+
+/// Gets a human readable String for Duration.
+pub fn human_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}.{:02}s", secs, duration.subsec_nanos() / 10_000_000)
+    }
+}
@@ -0,0 +1,62 @@
+use std::path::Path;
+use std::process::Command;
+
+use compiler_interrupts_core::archive;
+use compiler_interrupts_core::paths::PathExt;
+use object::read::archive::ArchiveFile;
+
+/// Library split in several codegen units, one for each module.
+const LIBRARY: &str = "
+pub mod a { pub fn f(x: u64) -> u64 { (0..x).map(|i| i * 3).sum() } }
+pub mod b { pub fn g(x: u64) -> u64 { (0..x).filter(|i| i % 7 == 0).count() as u64 } }
+pub mod c { pub fn h(v: &[u32]) -> u32 { v.iter().copied().max().unwrap_or(0) } }
+pub mod d { pub fn k(s: &str) -> usize { s.split(',').count() } }
+";
+
+/// Lists the member names of the archive.
+fn members(path: &Path) -> Vec<String> {
+    let data = std::fs::read(path).unwrap();
+    ArchiveFile::parse(&*data)
+        .unwrap()
+        .members()
+        .map(|member| String::from_utf8(member.unwrap().name().to_vec()).unwrap())
+        .collect()
+}
+
+#[test]
+fn every_codegen_unit_of_rlib() {
+    let dir = std::env::temp_dir().join(format!("ci-codegen-units-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("lib.rs"), LIBRARY).unwrap();
+    let status = Command::new(std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into()))
+        .args(["--crate-type=rlib", "--crate-name=demo", "-C", "save-temps"])
+        .args(["-C", "codegen-units=16"])
+        .args(["-C", "extra-filename=-0123456789abcdef"])
+        .arg("lib.rs")
+        .current_dir(&dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let rlib = dir.join("libdemo-0123456789abcdef.rlib");
+    let members = members(&rlib);
+    let units = archive::codegen_units(&rlib, &members).unwrap();
+    assert!(units.len() > 1, "{:?}", members);
+    assert_eq!(units.len(), members.len() - 1, "{:?}", members);
+    for unit in &units {
+        assert!(unit.object.is_file());
+        assert!(!unit.integrated());
+        std::fs::copy(&unit.object, unit.object.append_suffix("ci").unwrap()).unwrap();
+    }
+    let units = archive::codegen_units(&rlib, &members).unwrap();
+    assert!(units.iter().all(|unit| unit.integrated()));
+
+    // the members replaced by the CI-integrated object files are not replaced again
+    let members = units
+        .iter()
+        .map(|unit| unit.ci_object.file_name().unwrap())
+        .collect::<Vec<_>>();
+    assert!(archive::codegen_units(&rlib, &members).unwrap().is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
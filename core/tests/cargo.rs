@@ -0,0 +1,733 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use compiler_interrupts_core::cargo::{
+    self, FileFlavor, Linker, LinkerArgs, LinkerFlavor, OutputFile,
+};
+use semver::Version;
+
+/// Reads the lines of the log fixture.
+fn fixture(name: &str) -> Vec<String> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name);
+    std::fs::read_to_string(path)
+        .expect("failed to read fixture")
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Gets a `compiler-artifact` message of `cargo` of a target of the kind.
+fn artifact(kind: &str, filename: &str) -> serde_json::Value {
+    serde_json::json!({
+        "reason": "compiler-artifact",
+        "target": { "kind": [kind] },
+        "filenames": [filename],
+    })
+}
+
+#[test]
+fn compilation_files_target_by_version() {
+    let old = Version::new(1, 77, 2);
+    let new = Version::new(1, 78, 0);
+    let nightly = Version::parse("1.78.0-nightly").unwrap();
+    assert_eq!(
+        cargo::compilation_files_target(&old),
+        "cargo::core::compiler::context::compilation_files"
+    );
+    assert_eq!(
+        cargo::compilation_files_target(&new),
+        "cargo::core::compiler::build_runner::compilation_files"
+    );
+    assert_eq!(
+        cargo::compilation_files_target(&nightly),
+        "cargo::core::compiler::build_runner::compilation_files"
+    );
+    assert_eq!(
+        cargo::compilation_files_log(&new),
+        "cargo::core::compiler::build_runner::compilation_files=debug"
+    );
+}
+
+#[test]
+fn log_lines() {
+    let lines = fixture("tracing-build-runner.log");
+    let logs = lines.iter().filter(|line| cargo::is_log_line(line)).count();
+    assert_eq!(logs, 8);
+    assert!(!cargo::is_log_line(
+        "   Compiling demo v0.1.0 (/home/user/demo)"
+    ));
+}
+
+#[test]
+fn linkers_tracing() {
+    let linkers = cargo::parse_linkers(&fixture("tracing-build-runner.log")).unwrap();
+    assert_eq!(linkers.len(), 1);
+
+    let linker = &linkers[0];
+    assert_eq!(linker.program, "cc");
+    let keys = linker
+        .env
+        .iter()
+        .map(|(key, _)| key.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, ["LC_ALL", "PATH", "VSLANG"]);
+    assert_eq!(linker.env[0].1, "C");
+    assert_eq!(
+        linker.args.output_file,
+        "/home/user/demo/target/debug/deps/demo-2a5919c93e5857b0"
+    );
+    // `symbols.o` in the removed temporary directory is still an input file
+    assert!(linker.args.input_files[0].ends_with("symbols.o"));
+    assert_eq!(linker.args.input_files.len(), 9);
+    assert!(linker.args.input_files[1..]
+        .iter()
+        .all(|file| file.ends_with(".rcgu.o")));
+    assert_eq!(linker.args.rlib_files.len(), 20);
+    assert!(linker.args.rlib_files[0].ends_with("libutil-dbe8f10e3f5b4e8d.rlib"));
+    assert!(linker.args.flags.contains(&"-nodefaultlibs".to_string()));
+}
+
+#[test]
+fn linkers_env_logger() {
+    let linkers = cargo::parse_linkers(&fixture("env-logger-context.log")).unwrap();
+    assert_eq!(linkers.len(), 1);
+
+    let linker = &linkers[0];
+    assert_eq!(linker.program, "cc");
+    assert!(linker.env.is_empty());
+    assert_eq!(
+        linker.args.output_file,
+        "/home/user/demo/target/debug/deps/demo-2a5919c93e5857b0"
+    );
+    assert_eq!(linker.args.input_files.len(), 8);
+    assert_eq!(linker.args.rlib_files.len(), 20);
+}
+
+#[test]
+fn linkers_workspace_with_build_script() {
+    // `cargo` 1.77 with `tracing` and `rustc` logging the `link_natively` target
+    let linkers = cargo::parse_linkers(&fixture("tracing-context-workspace.log")).unwrap();
+    let outputs = linkers
+        .iter()
+        .map(|linker| linker.args.output_file.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        outputs,
+        [
+            "/home/user/ws/target/debug/build/server-5d1c0e7a9f3b2468/build_script_build-5d1c0e7a9f3b2468",
+            "/home/user/ws/target/debug/deps/server-0f1e2d3c4b5a6978",
+            "/home/user/ws/target/debug/deps/client-8a9b0c1d2e3f4a5b",
+        ]
+    );
+
+    let input_files = linkers
+        .iter()
+        .map(|linker| linker.args.input_files.len())
+        .collect::<Vec<_>>();
+    // `symbols.o` and the codegen units
+    assert_eq!(input_files, [2, 5, 3]);
+    let rlib_files = linkers
+        .iter()
+        .map(|linker| linker.args.rlib_files.len())
+        .collect::<Vec<_>>();
+    assert_eq!(rlib_files, [19, 20, 20]);
+    for linker in &linkers {
+        assert_eq!(linker.program, "cc");
+        assert_eq!(linker.env.len(), 3);
+        assert_eq!(linker.args.flavor, LinkerFlavor::Gnu);
+        assert_eq!(
+            linker.args.library_dirs,
+            ["/home/user/.rustup/toolchains/1.77.2-x86_64-unknown-linux-gnu/lib/rustlib/x86_64-unknown-linux-gnu/lib"]
+        );
+    }
+    assert!(linkers[1].args.rlib_files[0].ends_with("libproto-7c0a1e9d3b5f2486.rlib"));
+}
+
+#[test]
+fn linkers_musl_static() {
+    let linkers = cargo::parse_linkers(&fixture("tracing-musl-static.log")).unwrap();
+    assert_eq!(linkers.len(), 1);
+
+    let linker = &linkers[0];
+    let sysroot = "/home/user/.rustup/toolchains/stable-x86_64-unknown-linux-gnu/lib/rustlib/x86_64-unknown-linux-musl/lib";
+    assert_eq!(linker.program, "cc");
+    assert_eq!(
+        linker.args.output_file,
+        "/home/user/demo/target/x86_64-unknown-linux-musl/release/deps/demo-3c4d5e6f7a8b9c0d"
+    );
+    // the startup objects, `symbols.o`, the codegen unit and the ending objects
+    let input_files = linker
+        .args
+        .input_files
+        .iter()
+        .map(|file| Path::new(file).file_name().unwrap().to_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        input_files,
+        [
+            "rcrt1.o",
+            "crti.o",
+            "crtbeginS.o",
+            "symbols.o",
+            "demo-3c4d5e6f7a8b9c0d.demo.5e6f7a8b9c0d1e2f-cgu.0.rcgu.o",
+            "crtendS.o",
+            "crtn.o",
+        ]
+    );
+    assert_eq!(linker.args.rlib_files.len(), 20);
+    assert_eq!(
+        linker.args.library_dirs,
+        [sysroot.to_string(), format!("{}/self-contained", sysroot)]
+    );
+    assert!(linker.args.flags.contains(&"-static-pie".to_string()));
+    assert!(linker.args.flags.contains(&"-nostartfiles".to_string()));
+    // the ending objects stay after the flags
+    let args = linker.args.clone().build();
+    assert!(args[args.len() - 2].ends_with("crtendS.o"));
+    assert!(args[args.len() - 1].ends_with("crtn.o"));
+}
+
+#[test]
+fn linkers_macos() {
+    let linkers = cargo::parse_linkers(&fixture("tracing-macos.log")).unwrap();
+    assert_eq!(linkers.len(), 1);
+
+    let linker = &linkers[0];
+    assert_eq!(linker.program, "cc");
+    assert_eq!(
+        linker.env.last().unwrap(),
+        &("ZERO_AR_DATE".to_string(), "1".to_string())
+    );
+    assert_eq!(
+        linker.args.output_file,
+        "/Users/user/demo/target/debug/deps/demo-6e7f8a9b0c1d2e3f"
+    );
+    assert_eq!(linker.args.input_files.len(), 4);
+    assert!(linker.args.input_files[0].ends_with("symbols.o"));
+    assert_eq!(linker.args.rlib_files.len(), 20);
+    assert_eq!(
+        linker.args.flags,
+        [
+            "-arch",
+            "arm64",
+            "-lSystem",
+            "-lc",
+            "-lm",
+            "-Wl,-dead_strip",
+            "-nodefaultlibs"
+        ]
+    );
+}
+
+#[test]
+fn linkers_msvc() {
+    let linkers = cargo::parse_linkers(&fixture("tracing-msvc.log")).unwrap();
+    assert_eq!(linkers.len(), 1);
+
+    let linker = &linkers[0];
+    let sysroot = r"C:\Users\user\.rustup\toolchains\stable-x86_64-pc-windows-msvc\lib\rustlib\x86_64-pc-windows-msvc\lib";
+    assert_eq!(linker.program, "link.exe");
+    assert_eq!(linker.env, [("VSLANG".to_string(), "1033".to_string())]);
+    assert_eq!(linker.args.flavor, LinkerFlavor::Msvc);
+    // the escaped backslashes of the log are unescaped
+    assert_eq!(
+        linker.args.output_file,
+        r"C:\Users\user\demo\target\debug\deps\demo.exe"
+    );
+    assert_eq!(
+        linker.args.input_files[0],
+        r"C:\Users\user\AppData\Local\Temp\rustcP4qR5s\symbols.o"
+    );
+    assert_eq!(linker.args.input_files.len(), 3);
+    assert_eq!(linker.args.rlib_files.len(), 20);
+    assert_eq!(linker.args.library_dirs, [sysroot]);
+    // system libraries and options are flags
+    assert!(linker.args.flags.contains(&"kernel32.lib".to_string()));
+    assert!(linker
+        .args
+        .flags
+        .contains(&"/PDBALTPATH:%_PDB%".to_string()));
+    assert!(linker
+        .args
+        .flags
+        .iter()
+        .any(|flag| flag.starts_with("/NATVIS:")));
+    let args = linker.args.clone().build();
+    assert!(args.contains(&format!("/OUT:{}", linker.args.output_file)));
+}
+
+#[test]
+fn linker_rebuilds_arguments() {
+    let lines = fixture("tracing-build-runner.log");
+    let linker = cargo::parse_linkers(&lines).unwrap().remove(0);
+    let args = linker.args.clone().build();
+    let output = args.iter().position(|arg| arg == "-o").unwrap();
+    assert_eq!(args[output + 1], linker.args.output_file);
+    assert_eq!(
+        args.len(),
+        linker.args.input_files.len()
+            + 2
+            + linker.args.rlib_files.len()
+            + linker.args.library_dirs.len() * 2
+            + linker.args.flags.len()
+    );
+}
+
+#[test]
+fn linker_ignores_other_lines() {
+    let lines = [
+        r#" INFO rustc_codegen_ssa::back::link preparing Executable to "/home/user/demo/target/debug/deps/demo""#,
+        " INFO rustc_codegen_ssa::back::link linker stderr:",
+        "   Compiling demo v0.1.0 (/home/user/demo)",
+    ];
+    for line in lines {
+        assert_eq!(Linker::parse(line).unwrap(), None);
+    }
+}
+
+#[test]
+fn linker_unescapes_arguments() {
+    let line = r#" INFO rustc_codegen_ssa::back::link "cc" "/home/user/my \"demo\"/main.o" "/home/user/libcompiler_builtins.rlib" "-o" "/home/user/demo""#;
+    let linker = Linker::parse(line).unwrap().unwrap();
+    assert_eq!(linker.args.input_files, [r#"/home/user/my "demo"/main.o"#]);
+    assert_eq!(linker.args.output_file, "/home/user/demo");
+}
+
+#[test]
+fn linker_args_from_captured_invocation() {
+    let args = [
+        "-m64",
+        "/home/user/demo/target/debug/deps/demo.main.rcgu.o",
+        "/home/user/libstd.rlib",
+        "-L",
+        "/home/user/lib",
+        "-o",
+        "/home/user/demo/target/debug/deps/demo",
+        "-nodefaultlibs",
+    ];
+    let linker_args = LinkerArgs::parse(args.map(String::from)).unwrap();
+    assert_eq!(
+        linker_args.input_files,
+        ["/home/user/demo/target/debug/deps/demo.main.rcgu.o"]
+    );
+    assert_eq!(linker_args.rlib_files, ["/home/user/libstd.rlib"]);
+    assert_eq!(linker_args.library_dirs, ["/home/user/lib"]);
+    assert_eq!(
+        linker_args.output_file,
+        "/home/user/demo/target/debug/deps/demo"
+    );
+    assert_eq!(linker_args.build().len(), args.len());
+}
+
+#[test]
+fn linker_args_of_msvc() {
+    let args = [
+        "/NOLOGO",
+        r"C:\Users\user\demo\target\debug\deps\demo.main.rcgu.o",
+        r"C:\Users\user\libstd.rlib",
+        r"/LIBPATH:C:\Users\user\lib",
+        "kernel32.lib",
+        r"/NATVIS:C:\Users\user\etc\intrinsic.natvis",
+        r"/OUT:C:\Users\user\demo\target\debug\deps\demo.exe",
+        "/OPT:REF,NOICF",
+        "/DEBUG",
+    ];
+    let linker_args = LinkerArgs::parse(args.map(String::from)).unwrap();
+    assert_eq!(linker_args.flavor, LinkerFlavor::Msvc);
+    assert_eq!(
+        linker_args.input_files,
+        [r"C:\Users\user\demo\target\debug\deps\demo.main.rcgu.o"]
+    );
+    assert_eq!(linker_args.rlib_files, [r"C:\Users\user\libstd.rlib"]);
+    assert_eq!(linker_args.library_dirs, [r"C:\Users\user\lib"]);
+    assert_eq!(
+        linker_args.output_file,
+        r"C:\Users\user\demo\target\debug\deps\demo.exe"
+    );
+    assert_eq!(linker_args.build(), args);
+}
+
+#[test]
+fn linker_args_of_lld_link() {
+    // cross-linking from a Unix host, whose absolute paths look like options
+    let args = [
+        "-flavor",
+        "link",
+        "/home/user/demo/target/x86_64-pc-windows-msvc/debug/deps/demo.main.rcgu.o",
+        "/LIBPATH:/home/user/xwin/crt/lib/x86_64",
+        "/OUT:/home/user/demo/target/x86_64-pc-windows-msvc/debug/deps/demo.exe",
+        "/NOLOGO",
+    ];
+    let mut linker_args = LinkerArgs::parse(args.map(String::from)).unwrap();
+    assert_eq!(
+        linker_args.input_files,
+        ["/home/user/demo/target/x86_64-pc-windows-msvc/debug/deps/demo.main.rcgu.o"]
+    );
+    assert_eq!(linker_args.flags, ["-flavor", "link", "/NOLOGO"]);
+    linker_args.output_file = "/home/user/demo/target/debug/demo-ci.exe".to_string();
+    assert_eq!(
+        linker_args.build()[4],
+        "/OUT:/home/user/demo/target/debug/demo-ci.exe"
+    );
+}
+
+#[test]
+fn linker_keeps_order_of_static_arguments() {
+    let sysroot = "/home/user/.rustup/toolchains/stable-x86_64-unknown-linux-gnu/lib/rustlib/x86_64-unknown-linux-musl/lib";
+    let deps = "/home/user/demo/target/x86_64-unknown-linux-musl/release/deps";
+    let args = [
+        "-m64".to_string(),
+        format!("{}/self-contained/rcrt1.o", sysroot),
+        format!("{}/self-contained/crti.o", sysroot),
+        format!("{}/self-contained/crtbeginS.o", sysroot),
+        "/tmp/rustcAbCdEf/symbols.o".to_string(),
+        format!("{}/demo-0123456789abcdef.demo.a1b2c3-cgu.0.rcgu.o", deps),
+        "-Wl,--as-needed".to_string(),
+        "-Wl,-Bstatic".to_string(),
+        format!("{}/libutil-0123456789abcdef.rlib", deps),
+        format!("{}/libstd-0123456789abcdef.rlib", sysroot),
+        format!("{}/libcompiler_builtins-0123456789abcdef.rlib", sysroot),
+        "-Wl,-Bdynamic".to_string(),
+        "-nostartfiles".to_string(),
+        "-L".to_string(),
+        format!("{}/self-contained", sysroot),
+        "-o".to_string(),
+        format!("{}/demo-0123456789abcdef", deps),
+        "-Wl,--gc-sections".to_string(),
+        "-static-pie".to_string(),
+        "-nodefaultlibs".to_string(),
+        format!("{}/self-contained/crtendS.o", sysroot),
+        format!("{}/self-contained/crtn.o", sysroot),
+    ];
+    let line = format!(
+        r#" INFO rustc_codegen_ssa::back::link::link_natively "cc" {}"#,
+        args.iter()
+            .map(|arg| format!("{:?}", arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+
+    let mut linker = Linker::parse(&line).unwrap().unwrap();
+    assert_eq!(linker.args.input_files.len(), 7);
+    assert_eq!(linker.args.clone().build(), args);
+
+    // replaced files stay in place
+    linker.args.input_files[4] =
+        format!("{}/demo-0123456789abcdef.demo.a1b2c3-cgu.0.rcgu-ci.o", deps);
+    let built = linker.args.build();
+    assert_eq!(
+        built[5],
+        format!("{}/demo-0123456789abcdef.demo.a1b2c3-cgu.0.rcgu-ci.o", deps)
+    );
+    assert!(built.last().unwrap().ends_with("crtn.o"));
+}
+
+#[test]
+fn linker_adds_input_file_before_rlibs() {
+    let line = r#" INFO rustc_codegen_ssa::back::link "cc" "/home/user/crti.o" "/home/user/demo.rcgu.o" "/home/user/libcompiler_builtins.rlib" "-o" "/home/user/demo" "/home/user/crtn.o""#;
+    let mut linker = Linker::parse(line).unwrap().unwrap();
+    linker
+        .args
+        .add_input_file("/home/user/ci-handler.o".to_string());
+    assert_eq!(
+        linker.args.build(),
+        [
+            "/home/user/crti.o",
+            "/home/user/demo.rcgu.o",
+            "/home/user/ci-handler.o",
+            "/home/user/libcompiler_builtins.rlib",
+            "-o",
+            "/home/user/demo",
+            "/home/user/crtn.o",
+        ]
+    );
+}
+
+#[test]
+fn output_files_tracing() {
+    let output_files = cargo::parse_output_files(&fixture("tracing-build-runner.log")).unwrap();
+    let flavors = output_files
+        .iter()
+        .map(|file| file.flavor)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        flavors,
+        [
+            FileFlavor::Normal,
+            FileFlavor::DebugInfo,
+            FileFlavor::Linkable,
+            FileFlavor::Rmeta
+        ]
+    );
+    assert_eq!(
+        output_files[0].hardlink.as_deref(),
+        Some(Path::new("/home/user/demo/target/debug/demo"))
+    );
+    assert_eq!(
+        cargo::target_dir(&output_files, &HashSet::new()).unwrap(),
+        PathBuf::from("/home/user/demo/target/debug")
+    );
+}
+
+#[test]
+fn output_files_env_logger() {
+    let output_files = cargo::parse_output_files(&fixture("env-logger-context.log")).unwrap();
+    let flavors = output_files
+        .iter()
+        .map(|file| file.flavor)
+        .collect::<Vec<_>>();
+    // `Linkable { rmeta: bool }` of older versions
+    assert_eq!(
+        flavors,
+        [
+            FileFlavor::Normal,
+            FileFlavor::DebugInfo,
+            FileFlavor::Linkable,
+            FileFlavor::Rmeta
+        ]
+    );
+    assert_eq!(
+        cargo::target_dir(&output_files, &HashSet::new()).unwrap(),
+        PathBuf::from("/home/user/demo/target/debug")
+    );
+}
+
+#[test]
+fn output_files_workspace_with_build_script() {
+    let output_files =
+        cargo::parse_output_files(&fixture("tracing-context-workspace.log")).unwrap();
+    assert_eq!(output_files.len(), 7);
+    assert_eq!(output_files[0].flavor, FileFlavor::Normal);
+    let messages = [artifact(
+        "custom-build",
+        "/home/user/ws/target/debug/build/server-5d1c0e7a9f3b2468/build-script-build",
+    )];
+    let host_only_units = cargo::host_only_units(&messages, &output_files);
+    assert_eq!(
+        host_only_units,
+        HashSet::from(["build_script_build-5d1c0e7a9f3b2468".to_string()])
+    );
+    // the build script is not in the target directory
+    assert_eq!(
+        cargo::target_dir(&output_files, &host_only_units).unwrap(),
+        PathBuf::from("/home/user/ws/target/debug")
+    );
+}
+
+#[test]
+fn output_files_musl_static() {
+    let output_files = cargo::parse_output_files(&fixture("tracing-musl-static.log")).unwrap();
+    let flavors = output_files
+        .iter()
+        .map(|file| file.flavor)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        flavors,
+        [FileFlavor::Linkable, FileFlavor::Rmeta, FileFlavor::Normal]
+    );
+    assert_eq!(
+        cargo::target_dir(&output_files, &HashSet::new()).unwrap(),
+        PathBuf::from("/home/user/demo/target/x86_64-unknown-linux-musl/release")
+    );
+}
+
+#[test]
+fn output_files_macos() {
+    let output_files = cargo::parse_output_files(&fixture("tracing-macos.log")).unwrap();
+    assert_eq!(output_files[1].flavor, FileFlavor::DebugInfo);
+    assert_eq!(
+        output_files[1].hardlink.as_deref(),
+        Some(Path::new("/Users/user/demo/target/debug/demo.dSYM"))
+    );
+    assert_eq!(
+        cargo::target_dir(&output_files, &HashSet::new()).unwrap(),
+        PathBuf::from("/Users/user/demo/target/debug")
+    );
+}
+
+#[test]
+fn output_files_msvc() {
+    let output_files = cargo::parse_output_files(&fixture("tracing-msvc.log")).unwrap();
+    assert_eq!(output_files.len(), 2);
+    assert_eq!(
+        output_files[0].path,
+        PathBuf::from(r"C:\Users\user\demo\target\debug\deps\demo.exe")
+    );
+    assert_eq!(
+        output_files[1].hardlink.as_deref(),
+        Some(Path::new(r"C:\Users\user\demo\target\debug\demo.pdb"))
+    );
+    assert_eq!(output_files[1].flavor, FileFlavor::DebugInfo);
+}
+
+#[test]
+fn log_lines_of_every_variant() {
+    let variants = [
+        ("env-logger-context.log", 5),
+        ("tracing-build-runner.log", 8),
+        ("tracing-context-workspace.log", 13),
+        ("tracing-musl-static.log", 4),
+        ("tracing-macos.log", 5),
+        ("tracing-msvc.log", 5),
+    ];
+    for (name, count) in variants {
+        let lines = fixture(name);
+        let logs = lines.iter().filter(|line| cargo::is_log_line(line)).count();
+        assert_eq!(logs, count, "{}", name);
+        // every variant reports the linkers and the output files
+        assert!(
+            !cargo::parse_linkers(&lines).unwrap().is_empty(),
+            "{}",
+            name
+        );
+        assert!(
+            !cargo::parse_output_files(&lines).unwrap().is_empty(),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn output_files_ignore_other_lines() {
+    assert_eq!(OutputFile::parse("   Compiling demo v0.1.0").unwrap(), None);
+}
+
+#[test]
+fn target_dir_with_target() {
+    let line = r#"Target filenames: [OutputFile { path: "/home/user/demo/target/aarch64-unknown-linux-gnu/release/deps/demo-0123456789abcdef", hardlink: Some("/home/user/demo/target/aarch64-unknown-linux-gnu/release/demo"), export_path: None, flavor: Normal }]"#;
+    let output_files = OutputFile::parse(line).unwrap().unwrap();
+    assert_eq!(
+        cargo::target_dir(&output_files, &HashSet::new()).unwrap(),
+        PathBuf::from("/home/user/demo/target/aarch64-unknown-linux-gnu/release")
+    );
+    assert!(cargo::target_dir(&[], &HashSet::new()).is_err());
+}
+
+#[test]
+fn target_dir_with_custom_profile() {
+    let line = r#"Target filenames: [OutputFile { path: "/home/user/demo/target/bench-opt/deps/demo-0123456789abcdef", hardlink: Some("/home/user/demo/target/bench-opt/demo"), export_path: None, flavor: Normal }]"#;
+    let output_files = OutputFile::parse(line).unwrap().unwrap();
+    assert_eq!(
+        cargo::target_dir(&output_files, &HashSet::new()).unwrap(),
+        PathBuf::from("/home/user/demo/target/bench-opt")
+    );
+}
+
+#[test]
+fn host_only_units_by_target_kind() {
+    let line = r#"Target filenames: [OutputFile { path: "/home/user/demo/target/debug/deps/libderive-0123456789abcdef.so", hardlink: None, export_path: None, flavor: Linkable }, OutputFile { path: "/home/user/demo/target/debug/deps/demo-fedcba9876543210", hardlink: Some("/home/user/demo/target/debug/demo"), export_path: None, flavor: Normal }]"#;
+    let output_files = OutputFile::parse(line).unwrap().unwrap();
+    let messages = [
+        artifact(
+            "proc-macro",
+            "/home/user/demo/target/debug/deps/libderive-0123456789abcdef.so",
+        ),
+        artifact("bin", "/home/user/demo/target/debug/demo"),
+        serde_json::json!({ "reason": "build-finished", "success": true }),
+    ];
+    assert_eq!(
+        cargo::host_only_units(&messages, &output_files),
+        HashSet::from(["derive-0123456789abcdef".to_string()])
+    );
+    assert!(cargo::host_only_units(&[], &output_files).is_empty());
+}
+
+#[test]
+fn target_dir_named_build() {
+    // `--target-dir build`, with the build script of the package in `build/debug/build`
+    let lines = [
+        r#"Target filenames: [OutputFile { path: "/home/user/demo/build/debug/build/demo-0123456789abcdef/build_script_build-0123456789abcdef", hardlink: Some("/home/user/demo/build/debug/build/demo-0123456789abcdef/build-script-build"), export_path: None, flavor: Normal }]"#,
+        r#"Target filenames: [OutputFile { path: "/home/user/demo/build/debug/deps/demo-fedcba9876543210", hardlink: Some("/home/user/demo/build/debug/demo"), export_path: None, flavor: Normal }]"#,
+    ];
+    let output_files = cargo::parse_output_files(&lines).unwrap();
+    let messages = [
+        artifact(
+            "custom-build",
+            "/home/user/demo/build/debug/build/demo-0123456789abcdef/build-script-build",
+        ),
+        artifact("bin", "/home/user/demo/build/debug/demo"),
+    ];
+    let host_only_units = cargo::host_only_units(&messages, &output_files);
+    assert_eq!(
+        host_only_units,
+        HashSet::from(["build_script_build-0123456789abcdef".to_string()])
+    );
+    assert_eq!(
+        cargo::target_dir(&output_files, &host_only_units).unwrap(),
+        PathBuf::from("/home/user/demo/build/debug")
+    );
+}
+
+#[test]
+fn ci_artifact_of_binary() {
+    let message = serde_json::json!({
+        "reason": "compiler-artifact",
+        "target": { "kind": ["bin"], "name": "my-app" },
+        "filenames": ["/home/user/demo/target/debug/my-app"],
+        "executable": "/home/user/demo/target/debug/my-app",
+        "fresh": true,
+    });
+    let messages = [
+        artifact("lib", "/home/user/demo/target/debug/libdemo.rlib"),
+        message.clone(),
+    ];
+
+    let binary = Path::new("/home/user/demo/target/debug/my-app-ci");
+    let ci_artifact = cargo::ci_artifact(&messages, binary).unwrap().unwrap();
+    assert_eq!(
+        ci_artifact["executable"],
+        "/home/user/demo/target/debug/my-app-ci"
+    );
+    assert_eq!(
+        ci_artifact["filenames"],
+        serde_json::json!(["/home/user/demo/target/debug/my-app-ci"])
+    );
+    assert_eq!(ci_artifact["fresh"], false);
+    assert_eq!(ci_artifact["target"], message["target"]);
+
+    // the test harnesses left in `deps` have no message of their own
+    let harness = Path::new("/home/user/demo/target/debug/deps/demo-0123456789abcdef-ci");
+    assert!(cargo::ci_artifact(&messages, harness).unwrap().is_none());
+}
+
+#[test]
+fn package_of_ci_harness() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join("nextest-cargo-metadata.json");
+    let metadata = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let packages = cargo::packages(&metadata);
+    assert_eq!(
+        packages[0].binaries,
+        HashSet::from(["demo".to_string(), "demo_server".to_string()])
+    );
+
+    // the harnesses run in the directory of their package, also `CARGO_MANIFEST_DIR`
+    let manifest_dir = |harness: &str| {
+        packages
+            .iter()
+            .find(|package| package.owns(harness))
+            .map(|package| package.manifest_dir.clone())
+    };
+    assert_eq!(
+        manifest_dir("/home/user/demo/target/debug/deps/cli-0123456789abcdef-ci"),
+        Some(PathBuf::from("/home/user/demo"))
+    );
+    assert_eq!(
+        manifest_dir("/home/user/demo/target/debug/deps/demo_server-0123456789abcdef-ci"),
+        Some(PathBuf::from("/home/user/demo"))
+    );
+    assert_eq!(
+        manifest_dir("/home/user/demo/target/debug/deps/demo_ffi-0123456789abcdef-ci"),
+        Some(PathBuf::from("/home/user/demo/ffi"))
+    );
+    assert_eq!(
+        manifest_dir("/home/user/demo/target/debug/deps/other-0123456789abcdef-ci"),
+        None
+    );
+}
@@ -0,0 +1,333 @@
+use compiler_interrupts_core::config::{
+    self, Backend, CacheRemote, Config, GitSource, Library, ProjectConfig,
+};
+use compiler_interrupts_core::llvm::PassManager;
+use compiler_interrupts_core::plugin;
+
+#[test]
+fn project_config_from_package_metadata() {
+    let manifest = r#"
+        [package]
+        name = "demo"
+        version = "0.1.0"
+
+        [package.metadata.compiler-interrupts]
+        library-args = ["-clock-type=1", "-config=2"]
+        skip = ["serde", "regex"]
+        debug = true
+    "#;
+    let project = ProjectConfig::parse(manifest).unwrap();
+    assert_eq!(
+        project,
+        Some(ProjectConfig {
+            library_args: Some(vec!["-clock-type=1".into(), "-config=2".into()]),
+            skip: Some(vec!["serde".into(), "regex".into()]),
+            skip_fn: None,
+            debug: Some(true),
+        })
+    );
+}
+
+#[test]
+fn project_config_without_section() {
+    let manifest = r#"
+        [package]
+        name = "demo"
+        version = "0.1.0"
+
+        [package.metadata.docs.rs]
+        all-features = true
+    "#;
+    assert_eq!(ProjectConfig::parse(manifest).unwrap(), None);
+    assert_eq!(ProjectConfig::parse("[workspace]").unwrap(), None);
+}
+
+#[test]
+fn project_config_rejects_unknown_keys() {
+    let manifest = r#"
+        [package.metadata.compiler-interrupts]
+        library_args = ["-clock-type=1"]
+    "#;
+    assert!(ProjectConfig::parse(manifest).is_err());
+}
+
+#[test]
+fn project_config_overrides_global_config() {
+    let mut config = Config {
+        library_args: vec!["-clock-type=0".into()],
+        ..Config::default()
+    };
+    config.merge(ProjectConfig {
+        skip: Some(vec!["serde".into()]),
+        ..ProjectConfig::default()
+    });
+    assert_eq!(config.library_args, vec!["-clock-type=0".to_string()]);
+    assert_eq!(config.skip_crates, vec!["serde".to_string()]);
+    assert!(!config.debug);
+
+    config.merge(ProjectConfig {
+        library_args: Some(Vec::new()),
+        debug: Some(true),
+        ..ProjectConfig::default()
+    });
+    assert!(config.library_args.is_empty());
+    assert!(config.debug);
+}
+
+#[test]
+fn library_args_are_set_by_name() {
+    let mut config = Config {
+        library_args: vec!["-inst-gran=2".into(), "-commit-intv=100".into()],
+        ..Config::default()
+    };
+    config.set_library_arg(plugin::CI_COMMIT_INTV_ARG, 250);
+    config.set_library_arg(plugin::CI_ALL_DEV_ARG, 50);
+    // a prefix of another argument is not replaced
+    config.set_library_arg("-inst", 1);
+    assert_eq!(
+        config.library_args,
+        ["-inst-gran=2", "-commit-intv=250", "-all-dev=50", "-inst=1"]
+    );
+}
+
+#[test]
+fn crate_args_override_library_args() {
+    let mut config = Config {
+        library_args: vec!["-inst-gran=2".into(), "-commit-intv=100".into()],
+        skip_functions: vec!["hot_loop".into()],
+        ..Config::default()
+    };
+    let (crate_name, args) =
+        config::parse_crate_args("serde-json=-inst-gran=3 -all-dev=10").unwrap();
+    assert_eq!(crate_name, "serde_json");
+    config.crate_args.insert(crate_name, args);
+
+    assert_eq!(
+        config.crate_pass_args("serde_json"),
+        [
+            "-inst-gran=3",
+            "-commit-intv=100",
+            "-all-dev=10",
+            "-skip-fn=hot_loop"
+        ]
+    );
+    assert_eq!(config.crate_pass_args("demo"), config.pass_args());
+    assert_eq!(
+        config.crate_pass_plugins("serde_json")[0].passes[0].args,
+        config.crate_pass_args("serde_json")
+    );
+
+    let config = toml::to_string(&config).unwrap();
+    let config = toml::from_str::<Config>(&config).unwrap();
+    assert_eq!(
+        config.crate_args("serde-json"),
+        ["-inst-gran=3", "-all-dev=10"]
+    );
+
+    assert!(config::parse_crate_args("-inst-gran=3").is_err());
+    assert!(config::parse_crate_args("serde").is_err());
+}
+
+#[test]
+fn config_with_git_source_round_trips() {
+    let config = Config {
+        url: "https://github.com/bitslab/CompilerInterrupts.git".into(),
+        git: Some(GitSource {
+            reference: Some("v1.0".into()),
+            rev: None,
+            commit: "802f01e98076f0275ef1a37a69287b8f6857e109".into(),
+        }),
+        ..Config::default()
+    };
+    let s = toml::to_string_pretty(&config).unwrap();
+    let parsed = toml::from_str::<Config>(&s).unwrap();
+    assert_eq!(parsed.url, config.url);
+    assert_eq!(parsed.git, config.git);
+}
+
+#[test]
+fn plugin_opt_args_per_pass_manager() {
+    let config = Config {
+        library_path: "/ci/libci.so".into(),
+        library_args: vec!["-clock-type=1".into()],
+        ..Config::default()
+    };
+    let plugin = &config.pass_plugins()[0];
+
+    let legacy = plugin.opt_args(PassManager::Legacy).unwrap();
+    assert_eq!(
+        &legacy[..5],
+        [
+            "--enable-new-pm=0",
+            "--load",
+            "/ci/libci.so",
+            "--logicalclock",
+            "-clock-type=1"
+        ]
+    );
+
+    let new = plugin.opt_args(PassManager::New).unwrap();
+    assert_eq!(
+        new,
+        [
+            "--load-pass-plugin=/ci/libci.so",
+            "--passes=logicalclock,require<postdomtree>,mem2reg,indvars,loop-simplify,\
+             require<branch-prob>,require<scalar-evolution>",
+            "-clock-type=1",
+        ]
+    );
+}
+
+#[test]
+fn config_keeps_one_library_per_llvm_version() {
+    let dir = std::env::temp_dir().join("ci-config-libraries");
+    std::fs::create_dir_all(&dir).unwrap();
+    let library = |major: u64| {
+        let library_path = dir.join(format!("CompilerInterrupt-llvm{}.so", major));
+        std::fs::write(&library_path, b"").unwrap();
+        Library {
+            library_path,
+            llvm_version: format!("{}.0.6", major),
+            checksum: format!("checksum-{}", major),
+            ..Library::default()
+        }
+    };
+
+    let mut config = Config::default();
+    config.add_library(library(14));
+    config.add_library(library(12));
+    assert_eq!(config.llvm_version, "12.0.6");
+    assert_eq!(config.libraries.keys().collect::<Vec<_>>(), ["12", "14"]);
+
+    assert!(config.select_library(14));
+    assert_eq!(config.llvm_version, "14.0.6");
+    assert_eq!(config.checksum, "checksum-14");
+    assert!(!config.select_library(15));
+    assert_eq!(config.llvm_version, "14.0.6");
+
+    let s = toml::to_string_pretty(&config).unwrap();
+    let parsed = toml::from_str::<Config>(&s).unwrap();
+    assert_eq!(parsed.libraries, config.libraries);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn skip_functions_are_pass_args() {
+    let mut config = Config {
+        library_args: vec!["-clock-type=1".into()],
+        skip_functions: vec!["hot_loop".into()],
+        ..Config::default()
+    };
+    config.merge(ProjectConfig {
+        skip_fn: Some(vec!["_ZN4demo4spin17h0123456789abcdefE".into()]),
+        ..ProjectConfig::default()
+    });
+    assert_eq!(
+        config.pass_plugins()[0].passes[0].args,
+        [
+            "-clock-type=1",
+            "-skip-fn=_ZN4demo4spin17h0123456789abcdefE"
+        ]
+    );
+}
+
+#[test]
+fn config_keys_are_edited() {
+    let mut config = Config {
+        skip_crates: vec!["serde".into()],
+        ..Config::default()
+    };
+    config
+        .set("library_args", "-clock-type=1 -config=2")
+        .unwrap();
+    config.set("llvm-version", "14.0.6").unwrap();
+    config.set("cache_max_size", "1G").unwrap();
+    config.set("backend", "driver").unwrap();
+    assert_eq!(config.library_args, ["-clock-type=1", "-config=2"]);
+    assert_eq!(config.cache_max_size, Some(1 << 30));
+    assert_eq!(config.backend, Backend::Driver);
+    assert_eq!(config.skip_crates, ["serde"]);
+
+    assert_eq!(
+        config.get("library_args").unwrap().as_deref(),
+        Some("-clock-type=1 -config=2")
+    );
+    assert_eq!(
+        config.get("llvm_version").unwrap().as_deref(),
+        Some("14.0.6")
+    );
+    assert_eq!(
+        config.get("cache_max_size").unwrap().as_deref(),
+        Some("1073741824")
+    );
+    assert_eq!(config.get("llc_cpu").unwrap(), None);
+
+    config.unset("cache_max_size").unwrap();
+    config.unset("backend").unwrap();
+    assert_eq!(config.cache_max_size, None);
+    assert_eq!(config.backend, Backend::SaveTemps);
+    assert_eq!(config.llvm_version, "14.0.6");
+}
+
+#[test]
+fn config_keys_are_validated() {
+    let mut config = Config::default();
+    assert!(config.get("checksum").is_err());
+    assert!(config.set("libraries", "14").is_err());
+    assert!(config.set("llvm_version", "14").is_err());
+    assert!(config.set("llc_code_model", "huge").is_err());
+    assert!(config.set("cache_compression_level", "high").is_err());
+    assert!(config.set("sha256", "0123").is_err());
+    assert!(config.set("url", "").is_err());
+    assert!(config.unset("git").is_err());
+    assert!(config::parse_size("10X").is_err());
+}
+
+#[test]
+fn cache_remotes_are_parsed() {
+    assert_eq!(
+        config::parse_cache_remote("/mnt/ci-cache").unwrap(),
+        CacheRemote::Dir("/mnt/ci-cache".into())
+    );
+    assert_eq!(
+        config::parse_cache_remote("https://cache.example.com/ci/").unwrap(),
+        CacheRemote::Http("https://cache.example.com/ci".into())
+    );
+    assert_eq!(
+        config::parse_cache_remote("s3://ci-cache/objects/").unwrap(),
+        CacheRemote::S3 {
+            bucket: "ci-cache".into(),
+            prefix: "objects".into()
+        }
+    );
+    assert!(config::parse_cache_remote("s3:///objects").is_err());
+    assert!(config::parse_cache_remote("gs://ci-cache").is_err());
+}
+
+#[test]
+fn skipped_crates_are_saved_to_manifest() {
+    let manifest = r#"# demo
+[package]
+name = "demo"
+version = "0.1.0"
+
+[dependencies]
+serde = "1.0"
+"#;
+    let skip = vec!["serde".to_string(), "regex".to_string()];
+    let edited = ProjectConfig::set_skip(manifest, &skip).unwrap();
+    assert!(edited.starts_with("# demo\n[package]\nname = \"demo\""));
+    assert!(!edited.contains("[package.metadata]"));
+    assert_eq!(
+        ProjectConfig::parse(&edited).unwrap().unwrap().skip,
+        Some(skip)
+    );
+
+    let edited = ProjectConfig::set_skip(&edited, &[]).unwrap();
+    assert_eq!(
+        ProjectConfig::parse(&edited).unwrap(),
+        Some(ProjectConfig::default())
+    );
+    assert!(ProjectConfig::set_skip("[workspace]", &[]).is_err());
+}
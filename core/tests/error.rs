@@ -0,0 +1,31 @@
+use compiler_interrupts_core::error::{self, CIError, EXIT_CODES_HELP};
+
+#[test]
+fn diagnostic_codes_are_parsed() {
+    assert_eq!(CIError::LibraryNotInstalled.code(), "CI0010");
+    assert_eq!(CIError::Cancelled.code(), "CI0130");
+    assert_eq!(error::parse_code("CI0010"), Some(10));
+    assert_eq!(error::parse_code("ci10"), Some(10));
+    assert_eq!(error::parse_code("130"), Some(130));
+    assert_eq!(error::parse_code("CI"), None);
+    assert_eq!(error::parse_code("E0433"), None);
+}
+
+#[test]
+fn failure_classes_are_explained() {
+    // every failure class listed in `--help`, without success and usage errors
+    let codes = EXIT_CODES_HELP
+        .lines()
+        .filter_map(|line| line.split_whitespace().next()?.parse::<u8>().ok())
+        .filter(|&code| code >= 10)
+        .collect::<Vec<_>>();
+    assert!(codes.contains(&10));
+    for code in codes {
+        assert!(
+            error::explain(code).is_some(),
+            "code {} is not explained",
+            code
+        );
+    }
+    assert_eq!(error::explain(1), None);
+}
@@ -0,0 +1,37 @@
+; ModuleID = 'demo.1a2b3c4d-cgu.0'
+source_filename = "demo.1a2b3c4d-cgu.0"
+
+declare void @_ZN4core9panicking5panic17h0123456789abcdefE(ptr, i64, ptr)
+
+; demo::glue
+define internal i64 @_ZN4demo4glue17h0123456789abcdefE(i64 %x) unnamed_addr {
+start:
+  %_0 = add i64 %x, 1
+  ret i64 %_0
+}
+
+; demo::sum
+define i64 @_ZN4demo3sum17h0123456789abcdefE(i64 %n) unnamed_addr {
+start:
+  br label %bb1
+
+bb1:                                              ; preds = %"bb2.i", %start
+  %i = phi i64 [ 0, %start ], [ %next, %"bb2.i" ]
+  %done = icmp eq i64 %i, %n
+  br i1 %done, label %bb3, label %"bb2.i"
+
+"bb2.i":                                          ; preds = %bb1
+  %next = add i64 %i, 1
+  br label %bb1
+
+bb3:                                              ; preds = %bb1
+  switch i64 %i, label %bb4 [
+    i64 0, label %bb5
+  ]
+
+bb4:                                              ; preds = %bb3
+  ret i64 %i
+
+bb5:                                              ; preds = %bb3
+  ret i64 0
+}
@@ -0,0 +1,20 @@
+use compiler_interrupts_core::hooks;
+
+#[test]
+fn shell_command_with_env() {
+    // `sh -c`, or `cmd /C` on Windows
+    let command = if cfg!(windows) {
+        r#"if not "%CI_CRATE_NAME%"=="demo" exit 1"#
+    } else {
+        r#"test "$CI_CRATE_NAME" = demo"#
+    };
+    hooks::run("post-link", command, &[("CI_CRATE_NAME", "demo")]).unwrap();
+    assert!(hooks::run("post-link", command, &[("CI_CRATE_NAME", "other")]).is_err());
+}
+
+#[test]
+fn failed_shell_command() {
+    let error = hooks::run("pre-integration", "exit 3", &[]).unwrap_err();
+    assert_eq!(error.to_string(), "pre-integration hook failed");
+    assert!(format!("{:#}", error).contains("exit"));
+}
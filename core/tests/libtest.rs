@@ -0,0 +1,129 @@
+use std::path::Path;
+
+use compiler_interrupts_core::libtest::{TestOutcome, TestReport, TestSuite};
+
+/// Reads the lines of the output fixture.
+fn fixture(name: &str) -> Vec<String> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name);
+    std::fs::read_to_string(path)
+        .expect("failed to read fixture")
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn suite_of_pretty_output() {
+    let suite = TestSuite::parse("demo", &fixture("libtest-pretty.log"));
+    let tests = suite
+        .tests
+        .iter()
+        .map(|test| (test.name.as_str(), test.outcome))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        tests,
+        [
+            ("tests::also_fails", TestOutcome::Failed),
+            ("tests::it_fails", TestOutcome::Failed),
+            ("tests::it_works", TestOutcome::Passed),
+            ("tests::network", TestOutcome::Ignored),
+            // `#[should_panic]` without the suffix
+            ("tests::panics", TestOutcome::Passed),
+            ("tests::slow", TestOutcome::Ignored),
+        ]
+    );
+    assert_eq!(suite.tests[3].reason.as_deref(), Some("needs the network"));
+    assert_eq!(suite.tests[5].reason, None);
+    assert_eq!(suite.time, Some(0.0));
+    assert_eq!(suite.count(TestOutcome::Failed), 2);
+
+    // the output of the failed tests, without the surrounding empty lines
+    let output = suite.tests[1].output.as_deref().unwrap();
+    assert!(output.starts_with(r#"computing <sum> & "more""#));
+    assert!(output.ends_with(" right: 3"));
+    assert!(suite.tests[0]
+        .output
+        .as_deref()
+        .unwrap()
+        .starts_with("thread 'tests::also_fails' panicked"));
+    assert_eq!(suite.tests[2].output, None);
+}
+
+#[test]
+fn suite_of_show_output() {
+    let suite = TestSuite::parse("demo", &fixture("libtest-show-output.log"));
+    assert_eq!(suite.tests.len(), 6);
+    // `--show-output` also prints the output of the passed tests
+    assert_eq!(suite.tests[2].output.as_deref(), Some("two and two"));
+    assert!(suite.tests[4].output.as_deref().unwrap().ends_with("boom"));
+    assert!(suite.tests[1]
+        .output
+        .as_deref()
+        .unwrap()
+        .ends_with(" right: 3"));
+}
+
+#[test]
+fn suite_ignores_other_lines() {
+    let lines = [
+        "   Compiling demo v0.1.0 (/home/user/demo)",
+        "test tests::works has been running for over 60 seconds",
+        "running 0 tests",
+        "test result: ok. 0 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 1.25s",
+    ];
+    let suite = TestSuite::parse("demo", &lines);
+    assert!(suite.tests.is_empty());
+    assert_eq!(suite.time, Some(1.25));
+}
+
+#[test]
+fn junit_report() {
+    let harness = "target/debug/deps/demo-0123456789abcdef-ci";
+    let suite = TestSuite::parse_harness(harness, &fixture("libtest-pretty.log")).unwrap();
+    assert_eq!(suite.name, "demo");
+    let report = TestReport {
+        suites: vec![suite],
+    };
+    let xml = report.to_junit();
+    assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"));
+    assert!(xml.contains(r#"<testsuites tests="6" failures="2" skipped="2">"#));
+    assert!(xml.contains(r#"<testsuite name="demo" tests="6" failures="2" skipped="2" time="0">"#));
+    assert!(xml.contains(r#"<testcase name="tests::it_works" classname="demo"/>"#));
+    assert!(xml.contains(r#"<skipped message="needs the network"/>"#));
+    // the output is escaped
+    assert!(xml.contains(
+        r#"<failure message="test failed">computing &lt;sum&gt; &amp; &quot;more&quot;"#
+    ));
+    assert_eq!(xml.matches("<testcase ").count(), 6);
+    assert_eq!(xml.matches("</testcase>").count(), 4);
+    assert!(xml.ends_with("  </testsuite>\n</testsuites>\n"));
+}
+
+#[test]
+fn junit_report_drops_control_characters() {
+    let lines = [
+        "test tests::colored ... FAILED",
+        "---- tests::colored stdout ----",
+        "\u{1b}[31merror\u{1b}[0m",
+    ];
+    let report = TestReport {
+        suites: vec![TestSuite::parse("demo", &lines)],
+    };
+    assert!(report
+        .to_junit()
+        .contains(r#"<failure message="test failed">[31merror[0m</failure>"#));
+}
+
+#[test]
+fn json_report() {
+    let report = TestReport {
+        suites: vec![TestSuite::parse("demo", &fixture("libtest-pretty.log"))],
+    };
+    let json = report.to_json().unwrap();
+    assert!(json.contains(r#""outcome": "ignored""#));
+    let parsed = serde_json::from_str::<TestReport>(&json).unwrap();
+    assert_eq!(parsed, report);
+}
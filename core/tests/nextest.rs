@@ -0,0 +1,238 @@
+use std::path::{Path, PathBuf};
+
+use compiler_interrupts_core::nextest::{
+    self, ArchiveWriter, BinaryList, RustBuildMeta, RustTestBinary,
+};
+
+/// Reads the fixture.
+fn fixture(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name);
+    std::fs::read_to_string(path).expect("failed to read fixture")
+}
+
+/// Reads the metadata of `cargo` of the fixture.
+fn metadata() -> serde_json::Value {
+    serde_json::from_str(&fixture("nextest-cargo-metadata.json")).unwrap()
+}
+
+/// Reads the path, mode, and data of the entries of a tarball.
+fn entries(tarball: &[u8]) -> Vec<(String, u32, Vec<u8>)> {
+    let field = |header: &[u8]| {
+        let end = header.iter().position(|&b| b == 0).unwrap_or(header.len());
+        String::from_utf8(header[..end].to_vec()).unwrap()
+    };
+    let octal = |header: &[u8]| u64::from_str_radix(field(header).trim(), 8).unwrap();
+
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while tarball[offset..offset + 512].iter().any(|&b| b != 0) {
+        let header = &tarball[offset..offset + 512];
+        // the checksum is of the header with its field filled with spaces
+        let mut blank = header.to_vec();
+        blank[148..156].fill(b' ');
+        let checksum = blank.iter().map(|&b| u64::from(b)).sum::<u64>();
+        assert_eq!(octal(&header[148..156]), checksum);
+        assert_eq!(&header[257..263], b"ustar\0");
+        assert_eq!(header[156], b'0');
+
+        let prefix = field(&header[345..500]);
+        let name = field(&header[..100]);
+        let path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        let mode = u32::try_from(octal(&header[100..108])).unwrap();
+        let size = usize::try_from(octal(&header[124..136])).unwrap();
+        let data = tarball[offset + 512..offset + 512 + size].to_vec();
+        entries.push((path, mode, data));
+        offset += 512 + size.div_ceil(512) * 512;
+    }
+    // the end of the tarball is two empty blocks
+    assert_eq!(tarball.len(), offset + 1024);
+    entries
+}
+
+#[test]
+fn crate_root_of_dep_info() {
+    assert_eq!(
+        nextest::crate_root(&fixture("nextest-demo.d")),
+        Some(PathBuf::from("src/main.rs"))
+    );
+    assert_eq!(
+        nextest::crate_root(
+            r"/home/user/my\ demo/target/debug/deps/demo-0123456789abcdef.d: src/my\ lib.rs src/a.rs"
+        ),
+        Some(PathBuf::from("src/my lib.rs"))
+    );
+    assert_eq!(nextest::crate_root(""), None);
+    assert_eq!(nextest::crate_root("demo.d:"), None);
+}
+
+#[test]
+fn test_binaries_by_crate_root() {
+    let metadata = metadata();
+    let find = |root: &str| {
+        RustTestBinary::find(
+            &metadata,
+            Path::new(root),
+            PathBuf::from("/home/user/demo/target/debug/deps/demo-0123456789abcdef-ci"),
+        )
+        .map(|binary| (binary.binary_id, binary.kind, binary.build_platform))
+    };
+    let binary = |id: &str, kind: &str, platform: &str| {
+        Some((id.to_string(), kind.to_string(), platform.to_string()))
+    };
+
+    // the unit tests of the library and of the binary of the same name
+    assert_eq!(find("src/lib.rs"), binary("demo", "lib", "target"));
+    assert_eq!(
+        find("src/main.rs"),
+        binary("demo::bin/demo", "bin", "target")
+    );
+    assert_eq!(
+        find("src/bin/server.rs"),
+        binary("demo::bin/demo-server", "bin", "target")
+    );
+    assert_eq!(find("tests/cli.rs"), binary("demo::cli", "test", "target"));
+    assert_eq!(
+        find("benches/throughput.rs"),
+        binary("demo::bench/throughput", "bench", "target")
+    );
+    assert_eq!(
+        find("macros/src/lib.rs"),
+        binary("demo-macros", "proc-macro", "host")
+    );
+    // the crate types of a library
+    assert_eq!(find("ffi/src/lib.rs"), binary("demo-ffi", "lib", "target"));
+    // absolute crate roots, and the packages not in the workspace
+    assert_eq!(
+        find("/home/user/demo/tests/cli.rs"),
+        binary("demo::cli", "test", "target")
+    );
+    assert_eq!(
+        find("/home/user/.cargo/registry/src/index.crates.io-6f17d22bba15001f/memchr-2.7.4/src/lib.rs"),
+        None
+    );
+
+    let binary = RustTestBinary::find(
+        &metadata,
+        Path::new("tests/cli.rs"),
+        PathBuf::from("/home/user/demo/target/debug/deps/cli-0123456789abcdef-ci"),
+    )
+    .unwrap();
+    assert_eq!(binary.binary_name, "cli");
+    assert_eq!(binary.package_id, "path+file:///home/user/demo#0.1.0");
+}
+
+#[test]
+fn non_test_binaries_built() {
+    let dir = std::env::temp_dir().join(format!("ci-nextest-{}", std::process::id()));
+    let debug = dir.join("debug");
+    std::fs::create_dir_all(&debug).unwrap();
+    let exe = |name: &str| format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+    std::fs::write(debug.join(exe("demo-server")), b"").unwrap();
+
+    let binaries = nextest::non_test_binaries(&metadata(), &dir, Path::new("debug"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // only the binaries that are built
+    let binaries = &binaries["path+file:///home/user/demo#0.1.0"];
+    assert_eq!(binaries.len(), 1);
+    let binary = binaries.iter().next().unwrap();
+    assert_eq!(binary.name, "demo-server");
+    assert_eq!(binary.kind, "bin");
+    assert_eq!(binary.path, Path::new("debug").join(exe("demo-server")));
+}
+
+#[test]
+fn binaries_metadata_format() {
+    let binary = RustTestBinary::find(
+        &metadata(),
+        Path::new("tests/cli.rs"),
+        PathBuf::from("/home/user/demo/target/debug/deps/cli-0123456789abcdef-ci"),
+    )
+    .unwrap();
+    let list = BinaryList {
+        rust_build_meta: RustBuildMeta {
+            target_directory: PathBuf::from("/home/user/demo/target"),
+            base_output_directories: [PathBuf::from("debug")].into(),
+            ..RustBuildMeta::default()
+        },
+        rust_binaries: [(binary.binary_id.clone(), binary)].into(),
+    };
+    let json = serde_json::to_value(&list).unwrap();
+    // the keys read by `cargo-nextest`
+    assert_eq!(
+        json["rust-build-meta"]["target-directory"],
+        "/home/user/demo/target"
+    );
+    assert_eq!(
+        json["rust-build-meta"]["base-output-directories"],
+        serde_json::json!(["debug"])
+    );
+    assert_eq!(
+        json["rust-build-meta"]["non-test-binaries"],
+        serde_json::json!({})
+    );
+    assert_eq!(
+        json["rust-build-meta"]["linked-paths"],
+        serde_json::json!([])
+    );
+    assert_eq!(
+        json["rust-binaries"]["demo::cli"],
+        serde_json::json!({
+            "binary-id": "demo::cli",
+            "binary-name": "cli",
+            "package-id": "path+file:///home/user/demo#0.1.0",
+            "kind": "test",
+            "binary-path": "/home/user/demo/target/debug/deps/cli-0123456789abcdef-ci",
+            "build-platform": "target",
+        })
+    );
+}
+
+#[test]
+fn archive_tarball() {
+    let harness = std::env::temp_dir().join(format!("ci-nextest-harness-{}", std::process::id()));
+    std::fs::write(&harness, b"\x7fELF harness").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&harness, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let long_path = format!("target/{}/deps/demo-0123456789abcdef-ci", "a".repeat(120));
+    let mut writer = ArchiveWriter::new(Vec::new());
+    writer
+        .append_data(nextest::BINARIES_METADATA_PATH, b"{}")
+        .unwrap();
+    writer
+        .append_file("target/debug/deps/demo-0123456789abcdef-ci", &harness)
+        .unwrap();
+    writer.append_data(&long_path, &[1; 600]).unwrap();
+    assert!(writer.append_data(&"a".repeat(300), b"").is_err());
+    let tarball = writer.finish().unwrap();
+    std::fs::remove_file(&harness).unwrap();
+
+    let entries = entries(&tarball);
+    assert_eq!(entries.len(), 3);
+    assert_eq!(
+        entries[0],
+        (
+            "target/nextest/binaries-metadata.json".to_string(),
+            0o644,
+            b"{}".to_vec()
+        )
+    );
+    assert_eq!(entries[1].0, "target/debug/deps/demo-0123456789abcdef-ci");
+    #[cfg(unix)]
+    assert_eq!(entries[1].1, 0o755);
+    assert_eq!(entries[1].2, b"\x7fELF harness");
+    // paths longer than the name field are split into the prefix
+    assert_eq!(entries[2].0, long_path);
+    assert_eq!(entries[2].2, vec![1; 600]);
+}
@@ -0,0 +1,27 @@
+use std::fs;
+
+use compiler_interrupts_core::paths;
+
+#[test]
+fn log_files_are_rotated() {
+    let dir = std::env::temp_dir().join(format!("ci-rotate-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("cargo-ci.log");
+    let read = |name: &str| fs::read_to_string(dir.join(name)).ok();
+
+    // missing or smaller files are kept
+    assert!(!paths::rotate(&path, 4, 2).unwrap());
+    fs::write(&path, "abc").unwrap();
+    assert!(!paths::rotate(&path, 4, 2).unwrap());
+
+    for content in ["run 1", "run 2", "run 3"] {
+        fs::write(&path, content).unwrap();
+        assert!(paths::rotate(&path, 4, 2).unwrap());
+    }
+    assert!(!path.exists());
+    assert_eq!(read("cargo-ci.log.1").as_deref(), Some("run 3"));
+    assert_eq!(read("cargo-ci.log.2").as_deref(), Some("run 2"));
+    assert_eq!(read("cargo-ci.log.3"), None);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,135 @@
+use std::path::PathBuf;
+
+use compiler_interrupts_core::cargo::{FileFlavor, Linker, OutputFile};
+use compiler_interrupts_core::config::Config;
+use compiler_interrupts_core::pipeline::{self, BuildOutput, IntegrationOptions};
+
+#[test]
+fn llc_flags_of_target() {
+    let config = Config::default();
+    let flags = pipeline::llc_flags(&config, "3", Some("x86_64-unknown-linux-gnu"));
+    assert_eq!(flags[..2], ["-filetype=obj", "-O3"]);
+    assert!(flags.iter().any(|flag| flag == "-code-model=large"));
+
+    // `llc` has no size optimization levels
+    let flags = pipeline::llc_flags(&config, "z", Some("aarch64-unknown-linux-gnu"));
+    assert_eq!(flags[1], "-O2");
+    assert!(flags.iter().any(|flag| flag == "-relocation-model=pic"));
+    assert!(!flags.iter().any(|flag| flag.starts_with("-code-model=")));
+}
+
+#[test]
+fn llc_flags_of_config() {
+    let config = Config {
+        llc_code_model: Some("small".into()),
+        llc_cpu: Some("native".into()),
+        ..Config::default()
+    };
+    let flags = pipeline::llc_flags(&config, "0", Some("x86_64-unknown-linux-gnu"));
+    let code_models = flags
+        .iter()
+        .filter(|flag| flag.starts_with("-code-model="))
+        .collect::<Vec<_>>();
+    assert_eq!(code_models, ["-code-model=small"]);
+    assert_eq!(flags.last().unwrap(), "-mcpu=native");
+}
+
+#[test]
+fn crate_name_of_output_files() {
+    let crate_name = |path: &str| pipeline::crate_name(path).unwrap();
+    assert_eq!(
+        crate_name("target/debug/deps/serde_json-0123456789abcdef.ll"),
+        "serde_json"
+    );
+    assert_eq!(
+        crate_name("target/debug/deps/demo-0123456789abcdef.demo.1a2b3c4d-cgu.0.rcgu.bc"),
+        "demo"
+    );
+    assert_eq!(
+        crate_name("target/debug/deps/demo-0123456789abcdef"),
+        "demo"
+    );
+    assert_eq!(
+        crate_name("target/debug/deps/libserde_json-0123456789abcdef.rlib"),
+        "serde_json"
+    );
+    // the hardlinks of the binaries are named after their target
+    assert_eq!(crate_name("target/debug/my-app"), "my_app");
+    assert_eq!(crate_name("target/debug/my-app.exe"), "my_app");
+    // the CI-integrated artifacts are named after their original
+    assert_eq!(crate_name("target/debug/demo-ci"), "demo");
+    assert_eq!(crate_name("target/debug/my-app-ci.exe"), "my_app");
+    assert_eq!(
+        crate_name("target/debug/deps/demo-0123456789abcdef-ci"),
+        "demo"
+    );
+    assert_eq!(
+        crate_name("target/debug/deps/demo-0123456789abcdef.demo.1a2b3c4d-cgu.0.rcgu-ci.ll"),
+        "demo"
+    );
+    assert_eq!(
+        crate_name("target/debug/deps/libserde_json-0123456789abcdef-ci-medium.rlib"),
+        "serde_json"
+    );
+}
+
+#[test]
+fn static_library_of_output_files() {
+    let output_file = |path: &str, hardlink: Option<&str>, flavor| OutputFile {
+        path: PathBuf::from(path),
+        hardlink: hardlink.map(PathBuf::from),
+        export_path: None,
+        flavor,
+    };
+
+    let file = output_file(
+        "/demo/target/debug/deps/libdemo-0123456789abcdef.a",
+        Some("/demo/target/debug/libdemo.a"),
+        FileFlavor::Normal,
+    );
+    assert_eq!(
+        pipeline::static_library(&file),
+        Some((
+            PathBuf::from("/demo/target/debug/deps/libdemo-0123456789abcdef.a"),
+            PathBuf::from("/demo/target/debug/libdemo.a")
+        ))
+    );
+
+    let file = output_file(
+        "/demo/target/debug/deps/libdemo-0123456789abcdef.rlib",
+        Some("/demo/target/debug/libdemo.rlib"),
+        FileFlavor::Normal,
+    );
+    assert_eq!(pipeline::static_library(&file), None);
+
+    let file = output_file(
+        "/demo/target/debug/deps/libdemo-0123456789abcdef.a",
+        None,
+        FileFlavor::Normal,
+    );
+    assert_eq!(pipeline::static_library(&file), None);
+}
+
+#[test]
+fn tasks_of_build_output() {
+    let line = r#" INFO rustc_codegen_ssa::back::link "cc" "/home/user/main.o" "/home/user/libcompiler_builtins.rlib" "-o" "/home/user/demo""#;
+    let linker = Linker::parse(line).unwrap().unwrap();
+    let build = BuildOutput {
+        llvm_ir_files: vec![
+            PathBuf::from("target/debug/deps/demo-0123456789abcdef.ll"),
+            PathBuf::from("target/debug/deps/serde-0123456789abcdef.ll"),
+        ],
+        linkers: vec![linker],
+        ..BuildOutput::default()
+    };
+    assert_eq!(build.tasks(), 2 * 2 + 1 + 1);
+}
+
+#[test]
+fn default_options_of_cargo_build_ci() {
+    let options = IntegrationOptions::default();
+    assert_eq!(options.min_instructions, 200);
+    assert!(options.skip_crates.is_empty());
+    assert!(!options.keep_going);
+    assert_eq!(options.diffs_dir, None);
+}
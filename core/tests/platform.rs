@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use compiler_interrupts_core::platform::{self, Os, Target};
+
+#[test]
+fn llvm_triple_of_rustc_target() {
+    assert_eq!(
+        platform::llvm_triple("aarch64-unknown-linux-gnu").as_deref(),
+        Some("aarch64-unknown-linux-gnu")
+    );
+    assert_eq!(
+        platform::llvm_triple("riscv64gc-unknown-linux-gnu").as_deref(),
+        Some("riscv64-unknown-linux-gnu")
+    );
+    assert_eq!(platform::llvm_triple("targets/custom.json"), None);
+}
+
+#[test]
+fn llc_flags_of_cross_target() {
+    let target = Target::parse("aarch64-unknown-linux-gnu");
+    assert_eq!(target.os, Os::Linux);
+    assert_eq!(target.llc_flags(), ["-relocation-model=pic"]);
+
+    let target = Target::parse("x86_64-unknown-linux-gnu");
+    assert_eq!(target.llc_flags(), ["-code-model=large"]);
+}
+
+#[test]
+fn relocation_errors_of_linkers() {
+    assert!(platform::is_relocation_error(
+        "demo.rcgu-ci.o: in function `main': relocation truncated to fit: R_X86_64_PC32 against `.bss'"
+    ));
+    assert!(platform::is_relocation_error(
+        "ld.lld: error: relocation R_X86_64_32 out of range: 4294967296 is not in [0, 4294967295]"
+    ));
+    assert!(platform::is_relocation_error(
+        "relocation R_X86_64_32 against `.rodata' can not be used when making a PIE object; \
+        recompile with -fPIE"
+    ));
+    assert!(!platform::is_relocation_error(
+        "undefined reference to `__ci_handler'"
+    ));
+}
+
+#[test]
+fn executables_of_host() {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+    assert!(!platform::is_executable(&manifest));
+    assert!(!platform::is_executable(manifest.parent().unwrap()));
+    assert!(platform::is_executable(&std::env::current_exe().unwrap()));
+}
+
+#[test]
+fn prebuilt_library_names() {
+    let name = platform::prebuilt_library_name(14, false);
+    assert!(name.starts_with("CompilerInterrupt-14-"), "{}", name);
+    assert!(!name.contains("-debug"), "{}", name);
+    let name = platform::prebuilt_library_name(14, true);
+    assert!(
+        name.ends_with(&format!("-debug.{}", platform::shared_library_extension())),
+        "{}",
+        name
+    );
+}
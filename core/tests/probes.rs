@@ -0,0 +1,46 @@
+use std::io::BufReader;
+use std::path::Path;
+
+use compiler_interrupts_core::probes::{self, IrSummary};
+
+/// Reads the fixture.
+fn fixture(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name);
+    std::fs::read_to_string(path).expect("failed to read fixture")
+}
+
+#[test]
+fn summary_of_llvm_ir() {
+    let ir = fixture("ir-summary.ll");
+    let summary = probes::summarize_ir(BufReader::new(ir.as_bytes())).unwrap();
+    // the branch of `bb2.i` back to `bb1` is the only loop
+    assert_eq!(
+        summary,
+        IrSummary {
+            instructions: 13,
+            loops: 1,
+        }
+    );
+}
+
+#[test]
+fn summary_of_glue_code() {
+    let ir = fixture("ir-summary.ll");
+    let glue = ir.split("; demo::sum").next().unwrap();
+    let summary = probes::summarize_ir(BufReader::new(glue.as_bytes())).unwrap();
+    assert_eq!(
+        summary,
+        IrSummary {
+            instructions: 2,
+            loops: 0,
+        }
+    );
+    let declarations = "declare void @foo()\n";
+    assert_eq!(
+        probes::summarize_ir(BufReader::new(declarations.as_bytes())).unwrap(),
+        IrSummary::default()
+    );
+}
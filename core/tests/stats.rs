@@ -0,0 +1,56 @@
+use compiler_interrupts_core::stats::{self, Bucket};
+
+#[test]
+fn stats_of_runtime_are_parsed() {
+    let stats = stats::parse(
+        "# thread instructions nanoseconds
+        1 1003 10452
+        2 998 9871
+
+        1 1500 30000
+        ",
+    )
+    .unwrap();
+    assert_eq!(stats.threads, 2);
+    assert_eq!(stats.intervals, [998, 1003, 1500]);
+    assert_eq!(stats.latencies, [9871, 10452, 30000]);
+
+    let stats = stats::parse("main 1000\nworker-1 1000\n").unwrap();
+    assert_eq!(stats.threads, 2);
+    assert!(stats.latencies.is_empty());
+
+    assert!(stats::parse("1 1000 10 extra").is_err());
+    assert!(stats::parse("1 -1000").is_err());
+}
+
+#[test]
+fn histogram_of_intervals() {
+    let values = [0, 3, 900, 1000, 1023, 1024];
+    assert_eq!(stats::percentile(&values, 0), Some(0));
+    assert_eq!(stats::percentile(&values, 50), Some(900));
+    assert_eq!(stats::percentile(&values, 100), Some(1024));
+    assert_eq!(stats::percentile(&[], 50), None);
+
+    let histogram = stats::histogram(&values);
+    assert_eq!(histogram.len(), 12);
+    assert_eq!(
+        histogram[0],
+        Bucket {
+            low: 0,
+            high: 1,
+            count: 1
+        }
+    );
+    assert_eq!(histogram[2].count, 1);
+    assert_eq!(histogram[3].count, 0);
+    assert_eq!(
+        histogram[10],
+        Bucket {
+            low: 512,
+            high: 1024,
+            count: 3
+        }
+    );
+    assert_eq!(histogram[11].count, 1);
+    assert!(stats::histogram(&[]).is_empty());
+}
@@ -0,0 +1,92 @@
+//! The object files of `allocator-*.o` are compiled by `rustc` 1.95 with
+//! `-C save-temps --emit=link,llvm-ir` for a binary with the default allocator, and
+//! binaries declaring a `#[global_allocator]` calling the functions of jemalloc as
+//! `tikv-jemallocator` does, and of mimalloc as `mimalloc` does. The legacy ones mimic
+//! the allocator shim of older versions forwarding to `__rg_*`. The `nostd` ones are
+//! compiled for `#![no_std]` binaries with a `#[global_allocator]` and without one.
+//! `ci-probes.o` is compiled by `gcc` from C functions with two probes, loading the
+//! thread-local handler and logical clock as the pass does.
+
+use std::path::Path;
+
+use compiler_interrupts_core::symbols::{self, ObjectSymbols};
+
+/// Reads the object file fixture.
+fn read_fixture(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name);
+    std::fs::read(path).expect("failed to read fixture")
+}
+
+/// Parses the symbols of the object file fixture.
+fn fixture(name: &str) -> ObjectSymbols {
+    symbols::parse(&read_fixture(name)).expect("failed to parse fixture")
+}
+
+#[test]
+fn default_allocator() {
+    assert!(fixture("allocator-shim.o").allocator);
+    assert!(!fixture("allocator-default.o").allocator);
+}
+
+#[test]
+fn jemalloc_global_allocator() {
+    // the crate defines `__rust_alloc` calling `_rjem_mallocx`
+    assert!(fixture("allocator-jemalloc-shim.o").allocator);
+    assert!(!fixture("allocator-jemalloc.o").allocator);
+}
+
+#[test]
+fn mimalloc_global_allocator() {
+    // the crate defines `__rust_alloc` calling `mi_malloc_aligned`
+    assert!(fixture("allocator-mimalloc-shim.o").allocator);
+    assert!(!fixture("allocator-mimalloc.o").allocator);
+}
+
+#[test]
+fn legacy_global_allocator() {
+    // the shim defines `__rust_alloc` calling `__rg_alloc` defined by the crate
+    assert!(fixture("allocator-legacy-shim.o").allocator);
+    assert!(!fixture("allocator-legacy-jemalloc.o").allocator);
+}
+
+#[test]
+fn no_std_global_allocator() {
+    // the shim only defines the allocation error handler and the marker
+    assert!(fixture("allocator-nostd-shim.o").allocator);
+    assert!(!fixture("allocator-nostd.o").allocator);
+}
+
+#[test]
+fn no_std_without_allocator() {
+    // no shim is generated, and the only object file is the one of the crate
+    let symbols = fixture("allocator-none-nostd.o");
+    assert!(!symbols.allocator);
+    assert!(!symbols.ci_hook);
+}
+
+#[test]
+fn macho_allocator_shim() {
+    assert!(fixture("allocator-shim-macho.o").allocator);
+}
+
+#[test]
+fn ci_hook() {
+    let symbols = fixture("ci-hook.o");
+    assert!(symbols.ci_hook);
+    assert!(!symbols.allocator);
+    assert!(!fixture("allocator-default.o").ci_hook);
+}
+
+#[test]
+fn probe_sites() {
+    let data = read_fixture("ci-probes.o");
+    assert_eq!(symbols::probe_sites(&data).unwrap(), 2);
+    assert_eq!(symbols::defines_ci_hook(&data).unwrap(), Some(false));
+
+    let data = read_fixture("ci-hook.o");
+    assert_eq!(symbols::probe_sites(&data).unwrap(), 0);
+    assert_eq!(symbols::defines_ci_hook(&data).unwrap(), Some(true));
+}
@@ -1,22 +1,212 @@
 //! Handles arguments for the subcommands.
 
+use std::path::PathBuf;
+
 use clap::builder::PossibleValuesParser;
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Command, CommandFactory, Parser, Subcommand};
 
 use crate::*;
 
 /// Compile and integrate the Compiler Interrupts to a package
-#[derive(Debug, Parser)]
-#[command(name = BUILD_CI_BIN_NAME, author, version)]
+#[derive(Clone, Debug, Parser)]
+#[command(
+    name = BUILD_CI_BIN_NAME,
+    author,
+    version,
+    after_help = error::EXIT_CODES_HELP
+)]
 pub struct BuildArgs {
+    /// Package of the workspace to build and integrate, can be repeated
+    #[arg(short = 'p', long = "package", value_name = "SPEC")]
+    pub packages: Vec<String>,
+
+    /// Binary to build and integrate, can be repeated
+    #[arg(long = "bin", value_name = "NAME")]
+    pub binaries: Vec<String>,
+
+    /// Build and integrate all the binaries
+    #[arg(long)]
+    pub bins: bool,
+
+    /// Features to activate (space or comma separated), can be repeated
+    #[arg(short = 'F', long, value_name = "FEATURES")]
+    pub features: Vec<String>,
+
+    /// Activate all available features
+    #[arg(long)]
+    pub all_features: bool,
+
+    /// Do not activate the `default` feature
+    #[arg(long)]
+    pub no_default_features: bool,
+
+    /// Build with the profile, e.g. `release` or a custom profile of `Cargo.toml`
+    #[arg(long, value_name = "PROFILE-NAME")]
+    pub profile: Option<String>,
+
     /// Crates to skip the integration (space-delimited)
     #[arg(long = "skip", value_delimiter = ' ', value_name = "CRATES")]
     pub skip_crates: Option<Vec<String>>,
 
+    /// Crates to integrate exclusively, leaving the others untouched (space-delimited)
+    #[arg(long = "only", value_delimiter = ' ', value_name = "CRATES")]
+    pub only_crates: Option<Vec<String>>,
+
+    /// Symbol names of the functions to exclude from the probes (space-delimited)
+    #[arg(long = "skip-fn", value_delimiter = ' ', value_name = "NAMES")]
+    pub skip_functions: Option<Vec<String>>,
+
+    /// Instruction granularity of the pass, from 0 to 4 (overrides `-inst-gran`)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(..=4))]
+    pub instruction_granularity: Option<u32>,
+
+    /// Commit interval of the pass in instructions (overrides `-commit-intv`)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub commit_interval: Option<u32>,
+
+    /// Allowed deviation from the commit interval in instructions (overrides `-all-dev`)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32))]
+    pub all_dev: Option<u32>,
+
+    /// Arguments for the library on a crate, e.g. `serde="-inst-gran=3"` (multiple values)
+    #[arg(long, value_name = "CRATE=ARGS")]
+    pub crate_args: Vec<String>,
+
     /// Enable debugging mode for Compiler Interrupts library
     #[arg(long)]
     pub debug: bool,
 
+    /// Write a map of functions to probe counts and source locations for each binary
+    #[arg(long)]
+    pub probe_map: bool,
+
+    /// Write the `perf probe` commands of the probed functions for each binary
+    #[arg(long)]
+    pub perf_probes: bool,
+
+    /// Number of parallel jobs of `cargo` and of the integration [default: number of logical CPUs]
+    #[arg(short = 'j', long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub jobs: Option<u32>,
+
+    /// Maximum memory estimated for the concurrent `opt` jobs, e.g. `8G` [default: 75% of RAM]
+    #[arg(long, value_name = "SIZE")]
+    pub memory_budget: Option<String>,
+
+    /// Skip the integration of LLVM IR files without loops with fewer instructions than this
+    #[arg(long, default_value_t = 200, value_name = "N")]
+    pub min_instructions: usize,
+
+    /// Optimization level of `llc` [default: opt-level of the cargo profile]
+    #[arg(
+        long,
+        value_parser = PossibleValuesParser::new(["0", "1", "2", "3"]),
+        value_name = "LEVEL"
+    )]
+    pub llc_opt_level: Option<String>,
+
+    /// Do not use the cache of the CI-integrated object files
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Install the library for the LLVM version of `rustc` without asking if it changed
+    #[arg(long)]
+    pub auto_reinstall: bool,
+
+    /// Time limit of each `opt`, `llc`, and linker process, e.g. `10m`
+    #[arg(long, value_name = "DURATION")]
+    pub timeout: Option<String>,
+
+    /// Emit LLVM bitcode instead of textual LLVM IR, which is faster to write and read
+    #[arg(long)]
+    pub bitcode: bool,
+
+    /// Keep the temporary files of `-C save-temps` and the integration, e.g. for debugging
+    #[arg(long)]
+    pub keep_temps: bool,
+
+    /// Build with `cargo` and print the files to integrate and the commands, without running them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Choose the crates to integrate after the build, saved to skip in `Cargo.toml`
+    #[arg(long)]
+    pub interactive: bool,
+
+    /// Build and integrate again whenever a file of the workspace changes
+    #[arg(long, conflicts_with = "interactive")]
+    pub watch: bool,
+
+    /// Check that each CI-integrated binary defines the handler and has probes
+    #[arg(long)]
+    pub verify: bool,
+
+    /// Continue with the crates and binaries not depending on a failed one
+    #[arg(long)]
+    pub keep_going: bool,
+
+    /// Build with `cross` and link the binaries in its container image
+    #[arg(long)]
+    pub cross: bool,
+
+    /// Print the changes of the instrumentation since the last build
+    #[arg(long)]
+    pub diff_probes: bool,
+
+    /// Write reports of the integration to `target/ci-reports`, e.g. `probes,timings`
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = PossibleValuesParser::new(["probes", "timings"]),
+        value_name = "KIND"
+    )]
+    pub report: Vec<String>,
+
+    /// Also write the report as JSON
+    #[arg(long, requires = "report")]
+    pub report_json: bool,
+
+    /// Write the outputs of the integration to `target/ci-reports`, e.g. `ir-diff`
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = PossibleValuesParser::new(["ir-diff"]),
+        value_name = "KIND"
+    )]
+    pub emit: Vec<String>,
+
+    /// Stream progress events as JSON lines to the Unix domain socket
+    #[arg(long, value_name = "SOCKET")]
+    pub events: Option<PathBuf>,
+
+    /// Format of the integration messages, `json` for JSON lines on stdout
+    #[arg(
+        long,
+        default_value = "human",
+        value_parser = PossibleValuesParser::new(["human", "json"]),
+        value_name = "FMT"
+    )]
+    pub message_format: String,
+
+    /// Print a summary of the build in the format
+    #[arg(
+        long,
+        value_parser = PossibleValuesParser::new(["markdown"]),
+        value_name = "FORMAT"
+    )]
+    pub summary: Option<String>,
+
+    /// Write build metrics in Prometheus textfile format to the path
+    #[arg(long, value_name = "PATH")]
+    pub metrics: Option<PathBuf>,
+
+    /// Shell command to run after the build, overriding the config
+    #[arg(long, value_name = "COMMAND")]
+    pub notify_command: Option<String>,
+
+    /// URL to post the JSON build summary to after the build, overriding the config
+    #[arg(long, value_name = "URL")]
+    pub notify_url: Option<String>,
+
     /// Arguments for `cargo` invocation
     #[arg(value_name = "CARGO_BUILD_ARGS", raw = true)]
     pub cargo_args: Vec<String>,
@@ -30,16 +220,152 @@ pub struct BuildArgs {
         global = true,
     )]
     pub log_level: String,
+
+    /// Append the logs to the file, or to `<config_dir>/log/cargo-ci.log` if no path is given
+    #[arg(long, value_name = "PATH", global = true)]
+    pub log_file: Option<Option<PathBuf>>,
+
+    /// Coloring
+    #[arg(
+        long = "color",
+        default_value = "auto",
+        value_parser = PossibleValuesParser::new(["auto", "always", "never"]),
+        value_name = "WHEN",
+        global = true,
+    )]
+    pub color: String,
+}
+
+/// Gets the command of `cargo-test-ci`, taking the options of `cargo-build-ci`.
+pub fn test_command() -> Command {
+    TestArgs::augment_args(BuildArgs::command())
+        .name(TEST_CI_BIN_NAME)
+        .about(
+            "Compile and integrate the Compiler Interrupts to the tests of a package, and run them",
+        )
+        .mut_arg("cargo_args", |arg| {
+            arg.value_name("CARGO_TEST_ARGS")
+                .help("Arguments for `cargo test`, then `--` and the arguments for the tests")
+        })
+}
+
+/// Gets the command of `cargo-bench-ci`, taking the options of `cargo-build-ci`.
+pub fn bench_command() -> Command {
+    BuildArgs::command()
+        .name(BENCH_CI_BIN_NAME)
+        .about(
+            "Compile and integrate the Compiler Interrupts to the benchmarks of a package, and run them",
+        )
+        .mut_arg("cargo_args", |arg| {
+            arg.value_name("CARGO_BENCH_ARGS").help(
+                "Arguments for `cargo bench`, then `--` and the arguments for the benchmarks",
+            )
+        })
+}
+
+/// Gets the commands completed by the shells, without the wrapper of `rustc`.
+pub fn commands() -> Vec<Command> {
+    vec![
+        BuildArgs::command(),
+        RunArgs::command(),
+        test_command(),
+        bench_command(),
+        LibraryArgs::command(),
+    ]
 }
 
 /// Run a Compiler Interrupts-integrated binary
 #[derive(Debug, Parser)]
-#[command(name = RUN_CI_BIN_NAME, author, version, trailing_var_arg = true)]
+#[command(
+    name = RUN_CI_BIN_NAME,
+    author,
+    version,
+    trailing_var_arg = true,
+    after_help = error::EXIT_CODES_HELP
+)]
 pub struct RunArgs {
     /// Name of the binary
     #[arg(long = "bin", value_name = "NAME")]
     pub binary_name: Option<String>,
 
+    /// Features to activate (space or comma separated), can be repeated
+    #[arg(short = 'F', long, value_name = "FEATURES")]
+    pub features: Vec<String>,
+
+    /// Activate all available features
+    #[arg(long)]
+    pub all_features: bool,
+
+    /// Do not activate the `default` feature
+    #[arg(long)]
+    pub no_default_features: bool,
+
+    /// Run the binary built with the profile, e.g. `release` or a custom profile of `Cargo.toml`
+    #[arg(long, value_name = "PROFILE-NAME")]
+    pub profile: Option<String>,
+
+    /// Record the binary with `perf` to the file [default: perf-ci.data]
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        default_missing_value = "perf-ci.data"
+    )]
+    pub perf: Option<PathBuf>,
+
+    /// Run the original and the CI-integrated binary, and compare their runs
+    #[arg(long, conflicts_with = "perf")]
+    pub compare: bool,
+
+    /// Print the differences of the standard outputs of the compared binaries
+    #[arg(long, requires = "compare")]
+    pub diff: bool,
+
+    /// Set an environment variable for the binary, can be repeated
+    #[arg(long = "env", value_name = "KEY=VALUE")]
+    pub envs: Vec<String>,
+
+    /// Set the environment variables of the file for the binary
+    #[arg(long, value_name = "FILE")]
+    pub env_file: Option<PathBuf>,
+
+    /// Run the binary in the directory
+    #[arg(long, value_name = "DIR")]
+    pub cwd: Option<PathBuf>,
+
+    /// Pin the binary to the CPUs of the list, e.g. `0,2-3`
+    #[arg(long, value_name = "LIST")]
+    pub pin_cpus: Option<String>,
+
+    /// Run the binary with the niceness, from -20 (highest priority) to 19
+    #[arg(
+        long,
+        value_name = "N",
+        allow_negative_numbers = true,
+        value_parser = clap::value_parser!(i32).range(-20..=19)
+    )]
+    pub nice: Option<i32>,
+
+    /// Have the runtime write the statistics of the interrupts to the file, and print them
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["perf", "compare"])]
+    pub stats: Option<PathBuf>,
+
+    /// Read the arguments for the binary from the file, one per line, before the ones after `--`
+    #[arg(long, value_name = "FILE")]
+    pub bin_args_file: Option<PathBuf>,
+
+    /// Redirect the standard input of the binary from the file
+    #[arg(long, value_name = "FILE", conflicts_with = "perf")]
+    pub stdin: Option<PathBuf>,
+
+    /// Redirect the standard output of the binary to the file
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["perf", "compare"])]
+    pub stdout: Option<PathBuf>,
+
+    /// Redirect the standard error of the binary to the file
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["perf", "compare"])]
+    pub stderr: Option<PathBuf>,
+
     /// Arguments for the binary
     #[arg(raw = true, value_name = "ARGS")]
     pub binary_args: Vec<String>,
@@ -57,16 +383,43 @@ pub struct RunArgs {
         global = true,
     )]
     pub log_level: String,
+
+    /// Append the logs to the file, or to `<config_dir>/log/cargo-ci.log` if no path is given
+    #[arg(long, value_name = "PATH", global = true)]
+    pub log_file: Option<Option<PathBuf>>,
+
+    /// Coloring
+    #[arg(
+        long = "color",
+        default_value = "auto",
+        value_parser = PossibleValuesParser::new(["auto", "always", "never"]),
+        value_name = "WHEN",
+        global = true,
+    )]
+    pub color: String,
 }
 
 /// Manage the Compiler Interrupts library
 #[derive(Debug, Parser)]
-#[command(name = LIB_CI_BIN_NAME, author, version)]
+#[command(
+    name = LIB_CI_BIN_NAME,
+    author,
+    version,
+    after_help = error::EXIT_CODES_HELP
+)]
 pub struct LibraryArgs {
     /// Subcommands for managing the library
     #[command(subcommand)]
     pub command: Option<LibrarySubcommands>,
 
+    /// Time limit of the download of the source code, e.g. `30s`
+    #[arg(long, value_name = "DURATION", global = true)]
+    pub timeout: Option<String>,
+
+    /// Do not access the network, building the library from the vendored source code
+    #[arg(long, global = true)]
+    pub offline: bool,
+
     /// Log level
     #[arg(
         long = "log",
@@ -76,13 +429,27 @@ pub struct LibraryArgs {
         global = true,
     )]
     pub log_level: String,
+
+    /// Append the logs to the file, or to `<config_dir>/log/cargo-ci.log` if no path is given
+    #[arg(long, value_name = "PATH", global = true)]
+    pub log_file: Option<Option<PathBuf>>,
+
+    /// Coloring
+    #[arg(
+        long = "color",
+        default_value = "auto",
+        value_parser = PossibleValuesParser::new(["auto", "always", "never"]),
+        value_name = "WHEN",
+        global = true,
+    )]
+    pub color: String,
 }
 
 /// Subcommands for managing the library
 #[derive(Debug, Subcommand)]
 pub enum LibrarySubcommands {
     /// Install the Compiler Interrupts library
-    Install(InstallArgs),
+    Install(Box<InstallArgs>),
 
     /// Uninstall the Compiler Interrupts library
     Uninstall,
@@ -91,7 +458,25 @@ pub enum LibrarySubcommands {
     Update,
 
     /// Configure the Compiler Interrupts library
-    Config(ConfigArgs),
+    Config(Box<ConfigArgs>),
+
+    /// List and print the failure logs of the integration
+    Logs(LogsArgs),
+
+    /// Show or clear the cache of the CI-integrated object files
+    Cache(CacheArgs),
+
+    /// Run the daemon keeping the toolchain and metadata warm between the builds
+    Daemon(DaemonArgs),
+
+    /// Explain a failure class and how to remedy it
+    Explain(ExplainArgs),
+
+    /// Check the toolchain, the library, and the configuration directory
+    Doctor,
+
+    /// Generate the shell completions of the commands
+    Completions(CompletionsArgs),
 }
 
 /// Arguments for installing the library
@@ -101,14 +486,139 @@ pub struct InstallArgs {
     #[arg(long, value_name = "PATH")]
     pub path: Option<String>,
 
-    /// URL to the source code of the library. Use `file://` for local files.
+    /// URL to the source code of the library, or to its git repository with `--from-git`. Use `file://` for local files.
     #[arg(long, value_name = "URL")]
     pub url: Option<String>,
+
+    /// Clone the git repository of the library and build from the checked-out source
+    #[arg(long)]
+    pub from_git: bool,
+
+    /// Release of the library to download the prebuilt library from, verified by the `SHA256SUMS` of the release, or to build from its source if there is none for the host
+    #[arg(long, value_name = "TAG", conflicts_with = "from_git")]
+    pub release_tag: Option<String>,
+
+    /// URL to the release assets instead of the GitHub release of `--release-tag`. Use `file://` for local files.
+    #[arg(long, value_name = "URL", requires = "release_tag")]
+    pub release_url: Option<String>,
+
+    /// Branch of the git repository to check out
+    #[arg(long, value_name = "BRANCH", requires = "from_git", conflicts_with_all = ["tag", "rev"])]
+    pub branch: Option<String>,
+
+    /// Tag of the git repository to check out
+    #[arg(
+        long,
+        value_name = "TAG",
+        requires = "from_git",
+        conflicts_with = "rev"
+    )]
+    pub tag: Option<String>,
+
+    /// Revision of the git repository to check out
+    #[arg(long, value_name = "REV", requires = "from_git")]
+    pub rev: Option<String>,
+
+    /// Major version of the LLVM toolchain to build against, e.g. `14` for `llvm-config-14`
+    #[arg(long, value_name = "VERSION")]
+    pub llvm_version: Option<u64>,
+
+    /// SHA-256 digest the source code must match before it is compiled
+    #[arg(long, value_name = "DIGEST")]
+    pub sha256: Option<String>,
+
+    /// URL to a detached signature of the source code, verified with `gpg`. Use `file://` for local files.
+    #[arg(long, value_name = "URL")]
+    pub signature: Option<String>,
+
+    /// Path to the vendored source code used with `--offline`
+    #[arg(long, value_name = "PATH")]
+    pub vendored_source: Option<PathBuf>,
+}
+
+/// Arguments for the reports of the test results and the archive of `cargo-test-ci`
+#[derive(Args, Debug, Default)]
+pub struct TestArgs {
+    /// Write a JUnit XML report of the test results to the file
+    #[arg(long, value_name = "FILE")]
+    pub junit: Option<PathBuf>,
+
+    /// Write a JSON report of the test results to the file
+    #[arg(long, value_name = "FILE")]
+    pub json: Option<PathBuf>,
+
+    /// Write a `cargo-nextest` archive of the integrated harnesses to the file
+    /// (`.tar.zst`) instead of running them
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["junit", "json"])]
+    pub archive: Option<PathBuf>,
+}
+
+/// Arguments for browsing the failure logs
+#[derive(Args, Debug)]
+pub struct LogsArgs {
+    /// Print the log at the index (1 is the most recent)
+    #[arg(long, value_name = "INDEX")]
+    pub show: Option<usize>,
+
+    /// Number of logs to list
+    #[arg(long, default_value_t = 10, value_name = "N")]
+    pub limit: usize,
+}
+
+/// Arguments for managing the cache
+#[derive(Args, Debug)]
+pub struct CacheArgs {
+    /// Remove all entries of the cache
+    #[arg(long)]
+    pub clear: bool,
+}
+
+/// Arguments for the daemon
+#[derive(Args, Debug)]
+pub struct DaemonArgs {
+    /// Stop the running daemon
+    #[arg(long)]
+    pub stop: bool,
+}
+
+/// Arguments for explaining a failure class
+#[derive(Args, Debug)]
+pub struct ExplainArgs {
+    /// Diagnostic code of the failure, e.g. `CI0010`, or its exit code
+    #[arg(value_name = "CODE")]
+    pub code: String,
+}
+
+/// Arguments for generating the shell completions
+#[derive(Args, Debug)]
+pub struct CompletionsArgs {
+    /// Shell to generate the completions for
+    #[arg(
+        value_parser = PossibleValuesParser::new(["bash", "zsh", "fish", "powershell"]),
+        value_name = "SHELL"
+    )]
+    pub shell: String,
+
+    /// Directory to write a completion file per command to, instead of stdout
+    #[arg(long, value_name = "PATH")]
+    pub out_dir: Option<PathBuf>,
 }
 
 /// Arguments for configuring the library
 #[derive(Args, Debug)]
 pub struct ConfigArgs {
+    /// Print the value of a config key, e.g. `llvm_version`
+    #[arg(long, value_name = "KEY", conflicts_with_all = ["set", "unset"])]
+    pub get: Option<String>,
+
+    /// Set a config key, e.g. `llc_cpu=native` (multiple values)
+    #[arg(long, value_name = "KEY=VALUE")]
+    pub set: Vec<String>,
+
+    /// Unset a config key, restoring its default (multiple values)
+    #[arg(long, value_name = "KEY")]
+    pub unset: Vec<String>,
+
     /// Default arguments for the library (space-delimited)
     #[arg(
         long,
@@ -118,4 +628,106 @@ pub struct ConfigArgs {
         value_name = "ARGS"
     )]
     pub library_args: Option<Vec<String>>,
+
+    /// Default instruction granularity of the pass, from 0 to 4 (`-inst-gran`)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(..=4))]
+    pub instruction_granularity: Option<u32>,
+
+    /// Default commit interval of the pass in instructions (`-commit-intv`)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub commit_interval: Option<u32>,
+
+    /// Default allowed deviation from the commit interval in instructions (`-all-dev`)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32))]
+    pub all_dev: Option<u32>,
+
+    /// Arguments for the library on a crate, e.g. `serde="-inst-gran=3"` (empty to unset)
+    #[arg(long, value_name = "CRATE=ARGS")]
+    pub crate_args: Vec<String>,
+
+    /// Symbol names of the functions to exclude from the probes (space-delimited)
+    #[arg(
+        long = "skip-fn",
+        use_value_delimiter = true,
+        value_delimiter = ' ',
+        value_name = "NAMES"
+    )]
+    pub skip_functions: Option<Vec<String>>,
+
+    /// Shell command to run after each build (empty to unset)
+    #[arg(long, value_name = "COMMAND")]
+    pub notify_command: Option<String>,
+
+    /// URL to post the JSON build summary to after each build (empty to unset)
+    #[arg(long, value_name = "URL")]
+    pub notify_url: Option<String>,
+
+    /// Path to the vendored source code used with `--offline` (empty to unset)
+    #[arg(long, value_name = "PATH")]
+    pub vendored_source: Option<String>,
+
+    /// Directory of the cache of the CI-integrated object files (empty to unset)
+    #[arg(long, value_name = "PATH")]
+    pub cache_dir: Option<String>,
+
+    /// Maximum size of the cache, e.g. `10G` (empty to unset)
+    #[arg(long, value_name = "SIZE")]
+    pub cache_max_size: Option<String>,
+
+    /// zstd compression level of the cache entries [default: 3] (empty to unset)
+    #[arg(long, value_name = "LEVEL")]
+    pub cache_compression_level: Option<String>,
+
+    /// Remote cache shared between machines: a directory, an `http(s)://` URL, or an
+    /// `s3://bucket/prefix` URL (empty to unset)
+    #[arg(long, value_name = "URL")]
+    pub cache_remote: Option<String>,
+
+    /// Optimization level of `llc`, overriding the cargo profile (empty to unset)
+    #[arg(
+        long,
+        value_parser = PossibleValuesParser::new(["", "0", "1", "2", "3"]),
+        value_name = "LEVEL"
+    )]
+    pub llc_opt_level: Option<String>,
+
+    /// Code model of `llc`, overriding the one of the target (empty to unset)
+    #[arg(
+        long,
+        value_parser = PossibleValuesParser::new(["", "tiny", "small", "kernel", "medium", "large"]),
+        value_name = "MODEL"
+    )]
+    pub llc_code_model: Option<String>,
+
+    /// CPU of `llc`, e.g. `native` or `neoverse-n1` (empty to unset)
+    #[arg(long, value_name = "CPU")]
+    pub llc_cpu: Option<String>,
+
+    /// Pass manager of `opt`, overriding the default of the LLVM version (empty to unset)
+    #[arg(
+        long,
+        value_parser = PossibleValuesParser::new(["", "legacy", "new"]),
+        value_name = "PASS_MANAGER"
+    )]
+    pub pass_manager: Option<String>,
+
+    /// Shell command to run before the integration starts (empty to unset)
+    #[arg(long, value_name = "COMMAND")]
+    pub pre_integration_hook: Option<String>,
+
+    /// Shell command to run after each crate is integrated (empty to unset)
+    #[arg(long, value_name = "COMMAND")]
+    pub post_crate_hook: Option<String>,
+
+    /// Shell command to run after each binary is linked (empty to unset)
+    #[arg(long, value_name = "COMMAND")]
+    pub post_link_hook: Option<String>,
+
+    /// Backend of the integration
+    #[arg(
+        long,
+        value_parser = PossibleValuesParser::new(["save-temps", "driver", "capture"]),
+        value_name = "BACKEND"
+    )]
+    pub backend: Option<String>,
 }
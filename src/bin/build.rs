@@ -1,4 +1,6 @@
+use std::process::ExitCode;
+
 /// Entry function of `cargo-build-ci`.
-fn main() -> anyhow::Result<()> {
-    cargo_compiler_interrupts::ops::build::exec()
+fn main() -> ExitCode {
+    cargo_compiler_interrupts::exit(cargo_compiler_interrupts::ops::build::exec())
 }
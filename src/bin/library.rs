@@ -1,4 +1,6 @@
+use std::process::ExitCode;
+
 /// Entry function of `cargo-lib-ci`.
-fn main() -> anyhow::Result<()> {
-    cargo_compiler_interrupts::ops::library::exec()
+fn main() -> ExitCode {
+    cargo_compiler_interrupts::exit(cargo_compiler_interrupts::ops::library::exec())
 }
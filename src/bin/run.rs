@@ -1,4 +1,6 @@
+use std::process::ExitCode;
+
 /// Entry function of `cargo-run-ci`.
-fn main() -> anyhow::Result<()> {
-    cargo_compiler_interrupts::ops::run::exec()
+fn main() -> ExitCode {
+    cargo_compiler_interrupts::exit(cargo_compiler_interrupts::ops::run::exec())
 }
@@ -0,0 +1,6 @@
+use std::process::ExitCode;
+
+/// Entry function of `cargo-ci-rustc`.
+fn main() -> ExitCode {
+    cargo_compiler_interrupts::exit(cargo_compiler_interrupts::ops::rustc::exec())
+}
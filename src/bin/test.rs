@@ -0,0 +1,6 @@
+use std::process::ExitCode;
+
+/// Entry function of `cargo-test-ci`.
+fn main() -> ExitCode {
+    cargo_compiler_interrupts::exit(cargo_compiler_interrupts::ops::test::exec())
+}
@@ -0,0 +1,183 @@
+//! Capture of the LLVM IR files and the linker invocations by the wrapper.
+//!
+//! With the capture backend, the `RUSTC_WRAPPER` records the LLVM IR files emitted by
+//! each `rustc` invocation, and becomes the linker of the binaries to record the
+//! arguments given by `rustc` before running the real linker. The records are written
+//! to a directory of the target directory, so that neither the build log nor the
+//! layout of the target directory is needed to find them. The records of the units
+//! are kept for the fresh units of the next builds, while the records of the linker
+//! invocations are cleared before each build like the linker log of `rustc`.
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use cargo_util::{paths, ProcessBuilder};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::cargo::{Linker, LinkerArgs};
+use crate::paths::PathExt;
+use crate::{wrapper, CIResult};
+
+/// Directory of the records of the build, set for the wrapper.
+pub const CAPTURE_DIR_ENV: &str = "CARGO_CI_CAPTURE_DIR";
+
+/// Real linker of the binary crate, set when the wrapper is the linker.
+const CAPTURE_LINKER_ENV: &str = "CARGO_CI_CAPTURE_LINKER";
+
+/// Extension of the records of the LLVM IR files of a compilation unit.
+const UNIT_EXTENSION: &str = "unit.json";
+
+/// Extension of the records of the linker invocations.
+const LINKER_EXTENSION: &str = "linker.json";
+
+/// Record of the LLVM IR files emitted by a `rustc` invocation.
+#[derive(Serialize, Deserialize, Debug)]
+struct UnitRecord {
+    /// LLVM IR files of the codegen units.
+    llvm_ir_files: Vec<PathBuf>,
+}
+
+/// Record of a linker invocation of `rustc`.
+#[derive(Serialize, Deserialize, Debug)]
+struct LinkerRecord {
+    /// Real linker.
+    program: String,
+    /// Arguments given by `rustc`.
+    args: Vec<String>,
+}
+
+/// Gets the directory of the records if the wrapper captures the build.
+pub fn capture_dir() -> Option<PathBuf> {
+    std::env::var_os(CAPTURE_DIR_ENV).map(PathBuf::from)
+}
+
+/// Returns true if the wrapper is invoked by `rustc` as the linker.
+pub fn is_linker() -> bool {
+    std::env::var_os(CAPTURE_LINKER_ENV).is_some()
+}
+
+/// Creates the directory of the records, and removes the linker invocations of the
+/// previous build.
+pub fn prepare(dir: &Path) -> CIResult<()> {
+    paths::create_dir_all(dir)?;
+    for path in records(dir, LINKER_EXTENSION)? {
+        paths::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Sets the wrapper as the linker of the crate, keeping the real linker for the
+/// linker invocation.
+pub fn configure_linker(cmd: &mut ProcessBuilder, args: &[OsString]) -> CIResult<()> {
+    let linker = wrapper::codegen_option(args, "linker").unwrap_or_else(|| "cc".to_string());
+    let exe = std::env::current_exe().context("failed to get the current executable")?;
+    cmd.args(&wrapper::without_codegen_option(args, "linker"));
+    cmd.arg(format!("-Clinker={}", exe.display()));
+    cmd.env(CAPTURE_LINKER_ENV, linker);
+    Ok(())
+}
+
+/// Runs `rustc` and records the LLVM IR files it emitted for the crate.
+pub fn compile(cmd: &ProcessBuilder, args: &[OsString], dir: &Path) -> CIResult<()> {
+    let crate_name = wrapper::option(args, "--crate-name").context("missing `--crate-name`")?;
+    let out_dir = PathBuf::from(wrapper::option(args, "--out-dir").context("missing `--out-dir`")?);
+    let extra_filename = wrapper::codegen_option(args, "extra-filename").unwrap_or_default();
+    let unit = format!("{}{}", crate_name, extra_filename);
+
+    // the files of the previous compilation of the unit may have other codegen units
+    let extension = format!(
+        ".rcgu.{}",
+        wrapper::llvm_ir_extension(wrapper::is_bitcode())
+    );
+    let is_llvm_ir_file = |path: &PathBuf| {
+        path.file_name()
+            .is_ok_and(|name| name.starts_with(&format!("{}.", unit)) && name.ends_with(&extension))
+    };
+    for file in out_dir.read_dir(is_llvm_ir_file)? {
+        paths::remove_file(file)?;
+    }
+
+    cmd.exec()?;
+
+    let llvm_ir_files = out_dir.read_dir(is_llvm_ir_file)?;
+    debug!(%unit, ?llvm_ir_files);
+    let record = serde_json::to_string(&UnitRecord { llvm_ir_files })?;
+    paths::write(dir.join(format!("{}.{}", unit, UNIT_EXTENSION)), record)
+}
+
+/// Records the arguments given by `rustc`, then runs the real linker.
+pub fn link() -> CIResult<()> {
+    let program = std::env::var(CAPTURE_LINKER_ENV).context("missing the real linker")?;
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let dir = capture_dir().context("missing the capture directory")?;
+
+    let output_file = args
+        .iter()
+        .position(|arg| arg == "-o")
+        .and_then(|idx| args.get(idx + 1))
+        .context("missing output file")?;
+    let record = LinkerRecord {
+        program: program.clone(),
+        args: args.clone(),
+    };
+    let path = dir.join(format!(
+        "{}.{}",
+        PathBuf::from(output_file).file_name()?,
+        LINKER_EXTENSION
+    ));
+    paths::write(path, serde_json::to_string(&record)?)?;
+
+    ProcessBuilder::new(program).args(&args).exec_replace()
+}
+
+/// Gets the LLVM IR files of the compilation units recorded in the directory.
+pub fn llvm_ir_files(dir: &Path, units: &HashSet<String>) -> CIResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in records(dir, UNIT_EXTENSION)? {
+        let unit = path.file_name()?;
+        let unit = unit.trim_end_matches(&format!(".{}", UNIT_EXTENSION));
+        if !units.contains(unit) {
+            continue;
+        }
+        let record = serde_json::from_str::<UnitRecord>(&paths::read(&path)?)
+            .with_context(|| format!("failed to parse `{}`", path.display()))?;
+        // removed by `cargo clean` or a newer compilation of the unit
+        files.extend(
+            record
+                .llvm_ir_files
+                .into_iter()
+                .filter(|file| file.exists()),
+        );
+    }
+    debug!(?files);
+    Ok(files)
+}
+
+/// Gets the linker invocations recorded in the directory.
+pub fn linkers(dir: &Path) -> CIResult<Vec<Linker>> {
+    let mut linkers = Vec::new();
+    for path in records(dir, LINKER_EXTENSION)? {
+        let record = serde_json::from_str::<LinkerRecord>(&paths::read(&path)?)
+            .with_context(|| format!("failed to parse `{}`", path.display()))?;
+        linkers.push(Linker {
+            program: record.program,
+            env: Vec::new(),
+            args: LinkerArgs::parse(record.args)?,
+        });
+    }
+    debug!(?linkers);
+    Ok(linkers)
+}
+
+/// Gets the records of the kind in the directory, sorted by name.
+fn records(dir: &Path, extension: &str) -> CIResult<Vec<PathBuf>> {
+    let mut records = PathExt::read_dir(&dir, |path| {
+        path.file_name()
+            .is_ok_and(|name| name.ends_with(&format!(".{}", extension)))
+    })?;
+    records.sort();
+    Ok(records)
+}
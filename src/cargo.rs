@@ -1,14 +1,116 @@
 //! Cargo wrapper.
 
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context};
-use cargo_util::ProcessBuilder;
-use serde::{Deserialize, Serialize};
-use tracing::{debug, info};
+use cargo_util::{paths, ProcessBuilder};
+use tracing::{debug, info, warn};
 
+use compiler_interrupts_core::cargo;
+
+use crate::capture;
+use crate::config::{Backend, Config};
+use crate::cross::{self, Cross};
+use crate::error::CIError;
 use crate::paths::PathExt;
-use crate::CIResult;
+use crate::pipeline::{self, BuildOutput};
+use crate::platform::Target;
+use crate::{daemon, driver, util, wrapper, CIResult};
+
+pub use compiler_interrupts_core::cargo::{FileFlavor, Linker, LinkerArgs, OutputFile, Package};
+
+/// Binary targets of the packages to build, all the targets if none are selected.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Binaries {
+    /// Names of the binaries given to `--bin`.
+    pub names: Vec<String>,
+    /// Whether to build all the binaries with `--bins`.
+    pub all: bool,
+    /// Crate names of the selected binaries, whose linker invocations are kept.
+    pub crate_names: HashSet<String>,
+}
+
+impl Binaries {
+    /// Gets the arguments for `cargo`.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for name in &self.names {
+            args.push("--bin".to_string());
+            args.push(name.clone());
+        }
+        if self.all {
+            args.push("--bins".to_string());
+        }
+        args
+    }
+
+    /// Returns true if the binaries are selected with `--bin` or `--bins`.
+    pub fn is_selecting(&self) -> bool {
+        !self.names.is_empty() || self.all
+    }
+}
+
+/// Features of the packages to activate.
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Features {
+    /// Features to activate, each space or comma separated.
+    pub features: Vec<String>,
+    /// Whether to activate all available features.
+    pub all_features: bool,
+    /// Whether not to activate the `default` feature.
+    pub no_default_features: bool,
+}
+
+impl Features {
+    /// Gets the arguments for `cargo`.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if !self.features.is_empty() {
+            args.push("--features".to_string());
+            args.push(self.features.join(","));
+        }
+        if self.all_features {
+            args.push("--all-features".to_string());
+        }
+        if self.no_default_features {
+            args.push("--no-default-features".to_string());
+        }
+        args
+    }
+}
+
+/// Subcommand of `cargo` building the package.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum CargoCommand {
+    /// `cargo build`.
+    #[default]
+    Build,
+    /// `cargo test --no-run`, building the test harnesses without running them.
+    Test,
+    /// `cargo bench --no-run`, building the benchmark harnesses without running them.
+    Bench,
+}
+
+impl CargoCommand {
+    /// Gets the arguments of the subcommand.
+    fn args(self) -> &'static [&'static str] {
+        match self {
+            CargoCommand::Build => &["build"],
+            CargoCommand::Test => &["test", "--no-run"],
+            CargoCommand::Bench => &["bench", "--no-run"],
+        }
+    }
+
+    /// Gets the arguments given to the harnesses by the subcommand, e.g. `--bench` for
+    /// libtest and criterion to run the benchmarks.
+    pub fn harness_args(self) -> &'static [&'static str] {
+        match self {
+            CargoCommand::Bench => &["--bench"],
+            _ => &[],
+        }
+    }
+}
 
 /// Subset of information about the `cargo-build` invocation.
 #[derive(Default, Debug)]
@@ -19,6 +121,39 @@ pub struct Cargo {
     pub linkers: Vec<Linker>,
     /// Target directory.
     pub target_dir: PathBuf,
+    /// `compiler-artifact` messages of executables if JSON messages are requested.
+    pub artifacts: Vec<serde_json::Value>,
+    /// Output files of the compilation units.
+    pub output_files: Vec<OutputFile>,
+    /// Flags for `rustc` of the driver backend instead of emitting LLVM IR.
+    pub driver_flags: Vec<String>,
+    /// Container of `cross` running the build instead of `cargo`.
+    pub cross: Option<Cross>,
+    /// Members of the workspace to build, or all the default members if empty.
+    pub packages: Vec<Package>,
+    /// All members of the workspace, or none if the package is not a workspace.
+    pub members: Vec<Package>,
+    /// Subcommand building the package.
+    pub command: CargoCommand,
+    /// Features of the packages to activate.
+    pub features: Features,
+    /// Binary targets to build.
+    pub binaries: Binaries,
+    /// Directory of the records of the capture backend instead of the build log.
+    pub capture_dir: Option<PathBuf>,
+    /// Number of parallel jobs of the build.
+    pub jobs: Option<u32>,
+    /// Profile of the build, e.g. `release` or a custom profile of the manifest.
+    pub profile: Option<String>,
+    /// Emit LLVM bitcode instead of textual LLVM IR.
+    pub bitcode: bool,
+    /// Crates to integrate exclusively, or all the crates not skipped if empty.
+    pub only_crates: Vec<String>,
+    /// Compilation units of the build scripts and the procedural macros, which are only
+    /// run on the host, e.g. `build_script_build-0123456789abcdef`.
+    pub host_only_units: HashSet<String>,
+    /// Executable of `cargo-build-ci` set as `RUSTC_WRAPPER` instead of the current one.
+    pub wrapper: Option<PathBuf>,
 }
 
 impl Cargo {
@@ -30,47 +165,395 @@ impl Cargo {
         }
     }
 
-    /// Runs `cargo-build`.
-    pub fn build(&mut self) -> CIResult<()> {
-        info!("running cargo build");
+    /// Sets the flags of the driver backend or the directory of the capture backend.
+    pub fn set_backend(&mut self, config: &Config) -> CIResult<()> {
+        match config.backend {
+            Backend::Driver => {
+                // the flags of `rustc` are the same for every crate
+                if !config.crate_args.is_empty() {
+                    warn!("the arguments of the crates are not used by the driver backend");
+                }
+                self.driver_flags = driver::flags(config)?;
+            }
+            Backend::Capture => {
+                self.capture_dir = Some(self.configured_target_dir()?.join("ci-capture"));
+            }
+            Backend::SaveTemps => {}
+        }
+        Ok(())
+    }
 
-        let mut cmd = ProcessBuilder::new("cargo");
-        cmd.arg("build");
-        cmd.args(&self.args);
+    /// Selects the members of the workspace and the binary targets to build and
+    /// integrate, keeping the members to group the progress of a workspace.
+    pub fn select(&mut self, packages: &[String], binaries: &[String], bins: bool) -> CIResult<()> {
+        let members = members()?;
+        if !packages.is_empty() {
+            self.packages = self::packages(&members, packages)?;
+        }
+        // only the linker invocations of the selected binaries are kept
+        self.binaries = Binaries {
+            names: binaries.to_vec(),
+            all: bins,
+            crate_names: binaries.iter().map(|name| name.replace('-', "_")).collect(),
+        };
+        if bins {
+            let packages = if self.packages.is_empty() {
+                &members
+            } else {
+                &self.packages
+            };
+            self.binaries
+                .crate_names
+                .extend(packages.iter().flat_map(|package| package.binaries.clone()));
+        }
+        if members.len() > 1 {
+            self.members = members;
+        }
+        Ok(())
+    }
 
-        // color output
-        cmd.env("CARGO_TERM_COLOR", "always");
+    /// Builds in the container of `cross` for the target given in the arguments.
+    pub fn set_cross(&mut self, config: &Config) -> CIResult<()> {
+        if cfg!(not(unix)) {
+            bail!(CIError::HostNotSupported(
+                "--cross".to_string(),
+                "running the linkers as the owner of the target directory".to_string()
+            ));
+        }
+        if config.backend != Backend::SaveTemps {
+            bail!(
+                "the {} backend does not support `--cross`",
+                config.backend.as_str()
+            );
+        }
+        let target = self
+            .target()
+            .context("`--cross` requires `--target` in the arguments for `cargo`")?;
+        self.cross = Some(Cross::new(&target, self.configured_target_dir()?)?);
+        Ok(())
+    }
+
+    /// Takes the output of the finished build to integrate, compiling the object files
+    /// with the `opt-level` of `llc` if given, or the one of the profile otherwise.
+    pub fn build_output(
+        &mut self,
+        config: &Config,
+        llc_opt_level: Option<&String>,
+    ) -> CIResult<BuildOutput> {
+        let llvm_ir_files = self.llvm_ir_files()?;
+
+        // static libraries are archived by `rustc` without a linker invocation
+        let static_libraries = self
+            .output_files
+            .iter()
+            .filter(|file| self.is_selected(&file.path.to_string_lossy()))
+            .filter_map(pipeline::static_library)
+            .collect();
+
+        let opt_level = match llc_opt_level.or(config.llc_opt_level.as_ref()) {
+            Some(opt_level) => opt_level.clone(),
+            None => self.opt_level(),
+        };
+        debug!(?opt_level);
+        let target = self.target();
+        let llc_flags = pipeline::llc_flags(config, &opt_level, target.as_deref());
+
+        Ok(BuildOutput {
+            llvm_ir_files,
+            linkers: std::mem::take(&mut self.linkers),
+            static_libraries,
+            output_files: self.output_files.clone(),
+            target_dir: self.target_dir.clone(),
+            target,
+            llc_flags,
+            cross: self.cross.clone(),
+        })
+    }
 
-        // print the internal linker invocation
-        cmd.env("RUSTC_LOG", "rustc_codegen_ssa::back::link=info");
+    /// Returns true if cargo emits JSON messages.
+    pub fn json_messages(&self) -> bool {
+        let mut iter = self.args.iter();
+        while let Some(arg) = iter.next() {
+            if let Some(format) = arg.strip_prefix("--message-format") {
+                let format = match format.strip_prefix('=') {
+                    Some(format) => format,
+                    None => iter.next().map(String::as_str).unwrap_or_default(),
+                };
+                if format.starts_with("json") {
+                    return true;
+                }
+            }
+        }
+        false
+    }
 
-        // print the output files
-        cmd.env(
-            "CARGO_LOG",
-            "cargo::core::compiler::context::compilation_files=debug",
+    /// Gets the arguments for `cargo` with JSON messages, which report the kinds of the
+    /// targets, e.g. `custom-build` of the build scripts.
+    ///
+    /// Unless JSON messages are requested, the diagnostics are still rendered by `cargo`,
+    /// in the short format of `--message-format short` if given.
+    fn message_format_args(&self) -> Vec<String> {
+        if self.json_messages() {
+            return self.args.clone();
+        }
+        let mut args = Vec::with_capacity(self.args.len() + 1);
+        let mut short = false;
+        let mut iter = self.args.iter();
+        while let Some(arg) = iter.next() {
+            let format = match arg.strip_prefix("--message-format") {
+                Some("") => iter.next().map(String::as_str),
+                Some(format) => format.strip_prefix('='),
+                None => None,
+            };
+            match format {
+                Some(format) => short |= format.split(',').any(|format| format == "short"),
+                None => args.push(arg.clone()),
+            }
+        }
+        let mut format = "--message-format=json-render-diagnostics".to_string();
+        if short {
+            format.push_str(",json-diagnostic-short");
+        }
+        args.push(format);
+        args
+    }
+
+    /// Gets the value of the option in the arguments, e.g. `--target`.
+    fn option(&self, name: &str) -> Option<String> {
+        let mut iter = self.args.iter();
+        while let Some(arg) = iter.next() {
+            if arg == name {
+                return iter.next().cloned();
+            }
+            // not e.g. `--target-dir` for `--target`
+            if let Some(value) = arg.strip_prefix(name).and_then(|arg| arg.strip_prefix('=')) {
+                return Some(value.to_string());
+            }
+        }
+        None
+    }
+
+    /// Gets the target triple of the build, or `None` for the host.
+    pub fn target(&self) -> Option<String> {
+        self.option("--target")
+            .or_else(|| std::env::var("CARGO_BUILD_TARGET").ok())
+    }
+
+    /// Gets the runner of the target, e.g. a script pushing the binary to an Android
+    /// device with `adb`, or `None` if the binaries run on the host.
+    ///
+    /// The runner is set by `CARGO_TARGET_<TRIPLE>_RUNNER`, or `target.<triple>.runner`
+    /// in the configuration files of `cargo` from the current directory up to
+    /// `CARGO_HOME`.
+    pub fn runner(&self) -> Option<Vec<String>> {
+        let target = self.target()?;
+        let env = format!(
+            "CARGO_TARGET_{}_RUNNER",
+            target.to_uppercase().replace(['-', '.'], "_")
         );
+        if let Ok(runner) = std::env::var(env) {
+            return Some(
+                runner
+                    .split_ascii_whitespace()
+                    .map(str::to_string)
+                    .collect(),
+            );
+        }
 
-        // `--emit=llvm-ir` to emit LLVM IR bitcode
-        // `-C save-temps` to save temporary files during the compilation
-        // https://doc.rust-lang.org/rustc/codegen-options/index.html
+        let current_dir = std::env::current_dir().ok()?;
+        let cargo_home = std::env::var_os("CARGO_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")));
+        let config_files = current_dir
+            .ancestors()
+            .map(|dir| dir.join(".cargo"))
+            .chain(cargo_home)
+            .flat_map(|dir| [dir.join("config.toml"), dir.join("config")]);
+        for config_file in config_files {
+            let runner = paths::read(&config_file)
+                .ok()
+                .and_then(|s| s.parse::<toml::Value>().ok())
+                .and_then(|config| config.get("target")?.get(&target)?.get("runner").cloned());
+            // `runner = "<program> <args>..."` or `runner = ["<program>", "<args>"...]`
+            match runner {
+                Some(toml::Value::String(runner)) => {
+                    return Some(
+                        runner
+                            .split_ascii_whitespace()
+                            .map(str::to_string)
+                            .collect(),
+                    )
+                }
+                Some(toml::Value::Array(runner)) => {
+                    return Some(
+                        runner
+                            .iter()
+                            .filter_map(|arg| arg.as_str().map(str::to_string))
+                            .collect(),
+                    )
+                }
+                _ => {}
+            }
+        }
+        None
+    }
 
-        let rustflags = ["--emit=llvm-ir", "-Csave-temps"];
-        cmd.env("RUSTFLAGS", rustflags.join(" "));
+    /// Gets the profile given to the build, or `None` for the default profile of the
+    /// subcommand, e.g. `dev` for `cargo build`.
+    pub fn profile(&self) -> Option<String> {
+        self.profile
+            .clone()
+            .or_else(|| self.option("--profile"))
+            .or_else(|| {
+                self.args
+                    .iter()
+                    .any(|arg| arg == "--release" || arg == "-r")
+                    .then(|| "release".to_string())
+            })
+    }
+
+    /// Gets the target directory given to the build, e.g. `target` in the workspace,
+    /// before the build reports it.
+    ///
+    /// Without `--target-dir`, it is the one of `cargo metadata`, which also resolves
+    /// `CARGO_TARGET_DIR` and `build.target-dir` of the configuration files of `cargo`.
+    pub fn configured_target_dir(&self) -> CIResult<PathBuf> {
+        match self.option("--target-dir") {
+            Some(target_dir) => Ok(std::env::current_dir()?.join(target_dir)),
+            None => target_directory(),
+        }
+    }
+
+    /// Gets the target triple of the output files of the build that is not supported.
+    pub fn unsupported_target(&self) -> Option<String> {
+        self.output_files
+            .iter()
+            .filter_map(|file| file.hardlink.as_ref())
+            .flat_map(|hardlink| hardlink.components())
+            .filter_map(|component| component.as_os_str().to_str())
+            .find(|component| component.contains('-') && !Target::parse(component).is_supported())
+            .map(str::to_string)
+    }
+
+    /// Runs `cargo-build`, or the subcommand of the build, emitting LLVM IR for the
+    /// crates not skipped.
+    #[allow(clippy::print_stdout)]
+    pub fn build(&mut self, skip_crates: &[String]) -> CIResult<()> {
+        info!("running cargo {}", self.command.args().join(" "));
+
+        let program = match self.cross {
+            Some(_) => cross::PROGRAM,
+            None => "cargo",
+        };
+        let mut cmd = ProcessBuilder::new(program);
+        cmd.args(self.command.args());
+        for package in &self.packages {
+            cmd.arg("--package");
+            cmd.arg(&package.name);
+        }
+        cmd.args(&self.features.args());
+        cmd.args(&self.binaries.args());
+        if let Some(jobs) = self.jobs {
+            cmd.arg(format!("--jobs={}", jobs));
+        }
+        if let Some(profile) = &self.profile {
+            cmd.arg(format!("--profile={}", profile));
+        }
+        cmd.args(&self.message_format_args());
+
+        // color output
+        let color = if util::colors_enabled() {
+            "always"
+        } else {
+            "never"
+        };
+        cmd.env("CARGO_TERM_COLOR", color);
+
+        // print the internal linker invocation, not needed by the driver backend, and
+        // recorded by the wrapper with the capture backend
+        if let Some(dir) = &self.capture_dir {
+            capture::prepare(dir)?;
+            cmd.env(capture::CAPTURE_DIR_ENV, dir);
+        } else if self.driver_flags.is_empty() {
+            cmd.env("RUSTC_LOG", cargo::LINKER_LOG);
+        }
+
+        // print the output files, with the log target of the version
+        let version = cargo::cargo_version(&std::env::current_dir()?)?;
+        debug!(%version);
+        cmd.env("CARGO_LOG", cargo::compilation_files_log(&version));
+
+        // the wrapper adds the flags to emit LLVM IR to the crates not skipped, but
+        // it cannot run in the container of `cross`, so all crates emit LLVM IR there
+        if self.cross.is_none() {
+            wrapper::configure(
+                &mut cmd,
+                self.wrapper.as_deref(),
+                skip_crates,
+                &self.only_crates,
+                &self.driver_flags,
+                self.bitcode,
+            )?;
+        }
+
+        // the wrapper is not part of the fingerprint, so the skipped crates, the only
+        // crates and the driver flags are in the flags to rebuild the crates when changed
+        let mut skip_crates = skip_crates.to_vec();
+        skip_crates.sort();
+        let mut fingerprint = skip_crates.join(" ");
+        if !self.only_crates.is_empty() {
+            let mut only_crates = self.only_crates.clone();
+            only_crates.sort();
+            fingerprint.push_str("\nonly ");
+            fingerprint.push_str(&only_crates.join(" "));
+        }
+        if !self.driver_flags.is_empty() {
+            fingerprint.push('\n');
+            fingerprint.push_str(&self.driver_flags.join(" "));
+        }
+        // the crates compiled without the wrapper as the linker are not captured
+        if self.capture_dir.is_some() {
+            fingerprint.push_str("\ncapture");
+        }
+        if self.bitcode {
+            fingerprint.push_str("\nbitcode");
+        }
+        let mut rustflags = format!("--cfg=cargo_ci=\"{:x}\"", md5::compute(fingerprint));
+        if self.cross.is_some() {
+            for flag in wrapper::emit_flags(self.bitcode) {
+                rustflags.push(' ');
+                rustflags.push_str(flag);
+            }
+            Cross::passthrough(
+                &mut cmd,
+                &["CARGO_TERM_COLOR", "RUSTC_LOG", "CARGO_LOG", "RUSTFLAGS"],
+            );
+        }
+        cmd.env("RUSTFLAGS", rustflags);
 
         debug!(?cmd);
 
-        let mut link_info = Vec::new();
-        let mut compilation_files = Vec::new();
+        let json_messages = self.json_messages();
+        let mut logs = Vec::new();
+        let mut messages = Vec::new();
         cmd.exec_with_streaming(
             &mut |out| {
-                println!("{}", out);
+                match serde_json::from_str::<serde_json::Value>(out) {
+                    Ok(message) => {
+                        if json_messages {
+                            println!("{}", out);
+                        }
+                        if message["reason"] == "compiler-artifact" {
+                            messages.push(message);
+                        }
+                    }
+                    Err(_) => println!("{}", out),
+                }
                 Ok(())
             },
             &mut |err| {
-                if err.contains("rustc_codegen_ssa::back::link") {
-                    link_info.push(err.to_string());
-                } else if err.contains("cargo::core::compiler::context::compilation_files") {
-                    compilation_files.push(err.to_string());
+                if cargo::is_log_line(err) {
+                    logs.push(err.to_string());
                 } else if !err.is_empty() {
                     eprintln!("{}", err);
                 }
@@ -78,227 +561,251 @@ impl Cargo {
             },
             false,
         )
-        .context("failed to execute `cargo build`")?;
-
-        debug!(?link_info);
-        debug!(?compilation_files);
-
-        self.linkers = parse_linkers(link_info)?;
-        let output_files = parse_output_files(compilation_files)?;
-        self.target_dir = target_dir(output_files)?;
+        .context(CIError::CargoBuildFailed)?;
+
+        debug!(?logs);
+
+        let mut linkers = match &self.capture_dir {
+            Some(dir) => capture::linkers(dir)?,
+            None => cargo::parse_linkers(&logs)?,
+        };
+        // the targets of the procedural macros and the build scripts are only run on the
+        // host, so they are not integrated
+        self.output_files = cargo::parse_output_files(&logs)?;
+        self.host_only_units = cargo::host_only_units(&messages, &self.output_files);
+        linkers.retain(|linker| {
+            if self.is_host_only(Path::new(&linker.args.output_file)) {
+                debug!("linker of a host-only target: {}", linker.args.output_file);
+                return false;
+            }
+            let selected = self.is_selected(&linker.args.output_file);
+            if !selected {
+                debug!("linker of another package: {}", linker.args.output_file);
+            }
+            selected
+        });
+        self.linkers = linkers;
+        if let Some(cross) = &self.cross {
+            self.linkers
+                .iter_mut()
+                .for_each(|linker| cross.map_linker(linker));
+            self.output_files
+                .iter_mut()
+                .for_each(|file| cross.map_output_file(file));
+        }
+        self.target_dir = cargo::target_dir(&self.output_files, &self.host_only_units)?;
+        self.artifacts = messages
+            .into_iter()
+            .filter(|message| !message["executable"].is_null())
+            .collect();
+        debug!(?self.artifacts);
 
         Ok(())
     }
-}
 
-/// Linker invocation.
-#[derive(Debug)]
-pub struct Linker {
-    /// Linker program name.
-    pub program: String,
-    /// Arguments for the linker.
-    pub args: LinkerArgs,
-}
+    /// Returns true if the output file, e.g. `deps/demo-0123456789abcdef`, is a target
+    /// of the selected packages and one of the selected binaries, or if none are.
+    pub fn is_selected(&self, output_file: &str) -> bool {
+        let package = self.packages.is_empty()
+            || self
+                .packages
+                .iter()
+                .any(|package| package.owns(output_file));
+        let binary = !self.binaries.is_selecting()
+            || pipeline::crate_name(output_file)
+                .is_ok_and(|crate_name| self.binaries.crate_names.contains(&crate_name));
+        package && binary
+    }
 
-/// Arguments of a linker invocation.
-#[derive(Debug)]
-pub struct LinkerArgs {
-    /// List of input files.
-    pub input_files: Vec<String>,
-    /// Path to the output file.
-    pub output_file: String,
-    /// List of rlib files.
-    pub rlib_files: Vec<String>,
-    /// List of directories for library search.
-    pub library_dirs: Vec<String>,
-    /// Other flags.
-    pub flags: Vec<String>,
-}
+    /// Returns true if the output file, e.g. `deps/demo-0123456789abcdef`, is of a target
+    /// only run on the host, i.e. a build script or a procedural macro by the kind of its
+    /// target in the `compiler-artifact` messages of the build.
+    pub fn is_host_only(&self, output_file: &Path) -> bool {
+        output_file
+            .unit_name()
+            .is_ok_and(|unit| self.host_only_units.contains(&unit))
+    }
 
-impl LinkerArgs {
-    /// Build a complete linker arguments.
-    pub fn build(self) -> Vec<String> {
-        let mut all = Vec::new();
-        all.extend(self.input_files);
-        all.push("-o".to_string());
-        all.push(self.output_file);
-        all.extend(self.rlib_files);
-        for dir in self.library_dirs {
-            all.push("-L".to_string());
-            all.push(dir);
-        }
-        all.extend(self.flags);
-        all
+    /// Gets the member of the workspace of the output file, if any.
+    pub fn member_of(&self, output_file: &str) -> Option<&Package> {
+        self.members.iter().find(|member| member.owns(output_file))
     }
-}
 
-/// Information of a file flavor.
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
-pub enum FileFlavor {
-    /// Not a special file type.
-    Normal,
-    /// Like `Normal`, but not directly executable.
-    /// For example, a `.wasm` file paired with the "normal" `.js` file.
-    Auxiliary,
-    /// Something you can link against (e.g., a library).
-    Linkable,
-    /// An `.rmeta` Rust metadata file.
-    Rmeta,
-    /// Piece of external debug information (e.g., `.dSYM`/`.pdb` file).
-    DebugInfo,
-}
+    /// Gets the `opt-level` of the profile used by the build, e.g. `0` for `dev`.
+    ///
+    /// The profile is the one given to the build, or the one of its output directory
+    /// otherwise. It is resolved from `CARGO_PROFILE_<NAME>_OPT_LEVEL` and the
+    /// `[profile]` tables of the workspace manifest, following `inherits`.
+    pub fn opt_level(&self) -> String {
+        let manifest = paths::read(Path::new("Cargo.toml"))
+            .ok()
+            .and_then(|s| s.parse::<toml::Value>().ok());
+        let mut profile = match (self.profile(), self.target_dir.file_name()) {
+            (Some(profile), _) => profile,
+            (None, Ok(mode)) if mode == "debug" => "dev".to_string(),
+            (None, Ok(mode)) => mode,
+            (None, Err(_)) => "dev".to_string(),
+        };
+
+        // bound the lookup in case of cyclic `inherits`
+        for _ in 0..8 {
+            let env = format!(
+                "CARGO_PROFILE_{}_OPT_LEVEL",
+                profile.to_uppercase().replace('-', "_")
+            );
+            if let Ok(opt_level) = std::env::var(env) {
+                return opt_level;
+            }
 
-/// Information of an output file.
-#[derive(Serialize, Deserialize, Debug)]
-pub struct OutputFile {
-    /// Absolute path to the file that will be produced by the build process.
-    pub path: PathBuf,
-    /// If it should be linked into `target`, and what it should be called
-    /// (e.g., without metadata).
-    pub hardlink: Option<PathBuf>,
-    /// If `--out-dir` is specified, the absolute path to the exported file.
-    pub export_path: Option<PathBuf>,
-    /// Type of the file (library / debug symbol / else).
-    pub flavor: FileFlavor,
-}
+            let table = manifest
+                .as_ref()
+                .and_then(|manifest| manifest.get("profile"))
+                .and_then(|profiles| profiles.get(&profile));
+            match table.and_then(|table| table.get("opt-level")) {
+                Some(toml::Value::Integer(opt_level)) => return opt_level.to_string(),
+                Some(toml::Value::String(opt_level)) => return opt_level.clone(),
+                _ => {}
+            }
 
-/// Parse the linker invocation from raw build output.
-fn parse_linkers(link_info: Vec<String>) -> CIResult<Vec<Linker>> {
-    debug!("parsing linkers");
-    let mut linkers = Vec::new();
-    for line in link_info {
-        if !line.contains("libcompiler_builtins") {
-            // ignore as this is highly not a linker invocation
-            continue;
-        }
-
-        let line = line.replace('\"', "");
-        let mut linker = line
-            .split_ascii_whitespace()
-            .skip(2) // skip "INFO", "rustc_codegen_ssa::back::link"
-            .map(str::to_string);
-        let program = linker.next().context("missing linker program name")?;
-
-        let mut input_files = Vec::new();
-        let mut output_file = String::new();
-        let mut rlib_files = Vec::new();
-        let mut library_dirs = Vec::new();
-        let mut flags = Vec::new();
-
-        while let Some(arg) = linker.next() {
-            if arg.contains("-o") {
-                output_file = linker.next().context("missing output file")?;
-            } else if arg.contains("-L") {
-                library_dirs.push(linker.next().context("missing library dir")?);
-            } else {
-                let path = PathBuf::from(&arg);
-                if path.is_file() {
-                    if path.extension().unwrap_or_default() == "rlib" {
-                        rlib_files.push(arg);
-                    } else {
-                        input_files.push(arg);
-                    }
-                } else {
-                    flags.push(arg);
-                }
+            match table
+                .and_then(|table| table.get("inherits"))
+                .and_then(toml::Value::as_str)
+            {
+                Some(inherits) => profile = inherits.to_string(),
+                None => break,
             }
         }
 
-        linkers.push(Linker {
-            program,
-            args: LinkerArgs {
-                input_files,
-                output_file,
-                rlib_files,
-                library_dirs,
-                flags,
-            },
-        });
-    }
-    debug!(?linkers);
-    Ok(linkers)
-}
-
-/// Parse the output files from raw build output.
-fn parse_output_files(compilation_files: Vec<String>) -> CIResult<Vec<OutputFile>> {
-    debug!("parsing output files");
-    let mut output_files = Vec::new();
-    for line in compilation_files {
-        let line = line.split_inclusive(']').collect::<Vec<_>>()[1]
-            .replace(" Target filenames: ", "")
-            .replace('{', "(")
-            .replace('}', ")");
-        output_files.append(&mut ron::from_str(&line)?);
+        match profile.as_str() {
+            "release" | "bench" => "3".to_string(),
+            _ => "0".to_string(),
+        }
     }
-    debug!(?output_files);
-    Ok(output_files)
-}
 
-/// Gets the target directory of the workspace.
-fn target_dir(output_files: Vec<OutputFile>) -> CIResult<PathBuf> {
-    debug!("parsing target directory");
+    /// Gets the LLVM IR files of the compilation units of the build.
+    ///
+    /// `*.rcgu.ll`, or `*.rcgu.bc` with LLVM bitcode, are intermediate files generated
+    /// by `rustc -C save-temps` next to the output files of each unit. Only the units reported by `cargo` are included,
+    /// so stale files of previous builds in the same directory are ignored. With the
+    /// capture backend, the files recorded by the wrapper for these units are used.
+    pub fn llvm_ir_files(&self) -> CIResult<Vec<PathBuf>> {
+        // output directory -> compilation units
+        let mut units = BTreeMap::<PathBuf, HashSet<String>>::new();
+        for file in &self.output_files {
+            if matches!(file.flavor, FileFlavor::Rmeta | FileFlavor::DebugInfo)
+                || self.is_host_only(&file.path)
+            {
+                continue;
+            }
+            units
+                .entry(file.path.parent()?)
+                .or_default()
+                .insert(file.path.unit_name()?);
+        }
+        debug!(?units);
 
-    let mut target_dirs = Vec::new();
-    let mut targets = Vec::new();
-    let mut modes = Vec::new();
-    for file in output_files {
-        if let Some(hardlink) = &file.hardlink {
-            debug!(?hardlink);
+        // the files emitted by the `rustc` invocations are recorded, including the
+        // fresh units compiled by a previous build
+        if let Some(dir) = &self.capture_dir {
+            let units = units.into_values().flatten().collect::<HashSet<_>>();
+            return capture::llvm_ir_files(dir, &units);
+        }
 
-            if hardlink.file_name()?.contains("build-script-build") {
+        let llvm_ir_extension = wrapper::llvm_ir_extension(self.bitcode);
+        let mut files = Vec::new();
+        for (dir, units) in units {
+            if !dir.is_dir() {
                 continue;
             }
-            let mut dir = hardlink.parent()?;
-            if dir.file_name()? == "examples" {
-                dir = dir.parent()?;
-            }
-            modes.push(dir.file_name()?);
-            dir = dir.parent()?;
-            if dir.file_name()? == "target" {
-                target_dirs.push(dir);
-            } else {
-                targets.push(dir.file_name()?);
-                target_dirs.push(dir.parent()?);
-            }
+            files.append(&mut dir.read_dir(|path| {
+                let file_stem = path.file_stem().unwrap_or_default();
+                let extension = path.extension().unwrap_or_default();
+                // not the bitcode before the optimizations, e.g. `*.rcgu.no-opt.bc`
+                file_stem.ends_with("rcgu")
+                    && extension == llvm_ir_extension
+                    && matches!(path.unit_name(), Ok(unit) if units.contains(&unit))
+            })?);
         }
+        debug!(?files);
+
+        Ok(files)
     }
+}
 
-    let target_dir = &target_dirs[0];
-    let mode = &modes[0];
-    let target = if !targets.is_empty() {
-        Some(&targets[0])
-    } else {
-        None
-    };
+/// Gets the members of the workspace with `cargo metadata`.
+pub fn members() -> CIResult<Vec<Package>> {
+    let output = ProcessBuilder::new("cargo")
+        .args(&["metadata", "--no-deps", "--format-version=1"])
+        .exec_with_output()?;
+    let metadata = serde_json::from_slice::<serde_json::Value>(&output.stdout)
+        .context("failed to parse the metadata of the workspace")?;
+
+    let members = cargo::packages(&metadata);
+    debug!(?members);
+    Ok(members)
+}
+
+/// Gets the target directory of the workspace with `cargo metadata`.
+pub fn target_directory() -> CIResult<PathBuf> {
+    let output = ProcessBuilder::new("cargo")
+        .args(&["metadata", "--no-deps", "--format-version=1"])
+        .exec_with_output()?;
+    let metadata = serde_json::from_slice::<serde_json::Value>(&output.stdout)
+        .context("failed to parse the metadata of the workspace")?;
+    let target_dir = metadata["target_directory"]
+        .as_str()
+        .map(PathBuf::from)
+        .context("failed to get the target directory of the workspace")?;
     debug!(?target_dir);
-    debug!(?mode);
-    debug!(?target);
+    Ok(target_dir)
+}
 
-    // sanity check
-    for dir in &target_dirs {
-        if dir.file_name()? != target_dir.file_name()? {
-            bail!("failed to parse target directory");
-        }
-    }
+/// Gets the output of `cargo metadata` of the package and its dependencies.
+pub fn metadata() -> CIResult<Vec<u8>> {
+    let output = ProcessBuilder::new("cargo")
+        .args(&["metadata", "--format-version=1"])
+        .exec_with_output()?;
+    Ok(output.stdout)
+}
 
-    if !modes.iter().all(|e| e == mode) {
-        bail!("failed to parse target directory");
+/// Selects the members of the workspace by the package specifications of `--package`,
+/// e.g. `demo` or `demo@0.1.0`.
+pub fn packages(members: &[Package], specs: &[String]) -> CIResult<Vec<Package>> {
+    let mut packages = Vec::with_capacity(specs.len());
+    for spec in specs {
+        let (name, version) = match spec.split_once('@') {
+            Some((name, version)) => (name, Some(version)),
+            None => (spec.as_str(), None),
+        };
+        let member = members
+            .iter()
+            .find(|member| {
+                member.name == name
+                    && version.is_none_or(|version| {
+                        // `0.1` matches `0.1.2`, but not `0.10.0`
+                        member.version == version
+                            || member.version.starts_with(&format!("{}.", version))
+                    })
+            })
+            .with_context(|| format!("package `{}` is not a member of the workspace", spec))?;
+        packages.push(member.clone());
     }
+    Ok(packages)
+}
 
-    if let Some(target) = target {
-        if !targets.iter().all(|e| e == target) {
-            bail!("failed to parse target directory");
-        }
-
-        Ok(target_dir.join(target).join(mode))
-    } else {
-        Ok(target_dir.join(mode))
+/// Gets the root directory of the workspace, from the daemon if running.
+pub fn locate_project() -> CIResult<PathBuf> {
+    let dir = std::env::current_dir()?;
+    if let Some(root_dir) = daemon::locate_project(&dir) {
+        return Ok(root_dir);
     }
+    locate_project_in(&dir)
 }
 
-/// Gets the root directory of the workspace.
-pub fn locate_project() -> CIResult<PathBuf> {
+/// Gets the root directory of the workspace of the directory.
+pub fn locate_project_in(dir: &Path) -> CIResult<PathBuf> {
     let mut cmd = ProcessBuilder::new("cargo");
+    cmd.cwd(dir);
     cmd.arg("locate-project");
     cmd.arg("--message-format=plain");
     let output = cmd.exec_with_output()?;
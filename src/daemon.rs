@@ -0,0 +1,315 @@
+//! Optional daemon keeping the toolchain and metadata warm between the builds.
+//!
+//! The subcommands ask the daemon through a Unix domain socket in the configuration
+//! directory, and do the work themselves if the daemon is not running, as they always
+//! do on the hosts without Unix domain sockets.
+
+#[cfg(unix)]
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::io::{BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::time::{Duration, SystemTime};
+
+use anyhow::bail;
+#[cfg(unix)]
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+#[cfg(unix)]
+use tracing::{debug, info, warn};
+
+use crate::config::Config;
+#[cfg(not(unix))]
+use crate::error::CIError;
+use crate::llvm::LlvmToolchain;
+use crate::CIResult;
+#[cfg(unix)]
+use crate::{cargo, llvm};
+
+/// Name of the socket in the configuration directory.
+const SOCKET_NAME: &str = "daemon.sock";
+
+/// Timeout of reading a request or a response.
+#[cfg(unix)]
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Environment variables selecting the `rustc` toolchain.
+const TOOLCHAIN_ENV: [&str; 3] = ["PATH", "RUSTUP_HOME", "RUSTUP_TOOLCHAIN"];
+
+/// Directory, environment variables, and directory of the LLVM utilities of a
+/// toolchain request.
+#[cfg(unix)]
+type ToolchainKey = (PathBuf, Vec<(String, String)>, Option<PathBuf>);
+
+/// Request to the daemon.
+#[derive(Serialize, Deserialize, Debug)]
+enum Request {
+    /// Get the LLVM toolchain of the directory with the environment variables.
+    Toolchain {
+        /// Directory of the workspace.
+        dir: PathBuf,
+        /// Environment variables selecting the `rustc` toolchain.
+        env: Vec<(String, String)>,
+        /// Directory of the LLVM utilities recorded in the configuration.
+        bin_dir: Option<PathBuf>,
+    },
+    /// Get the root directory of the workspace of the directory.
+    LocateProject {
+        /// Current directory.
+        dir: PathBuf,
+    },
+    /// Get the symbol analysis saved to the path.
+    Symbols {
+        /// Path to the saved analysis.
+        path: PathBuf,
+    },
+    /// Keep the symbol analysis saved to the path.
+    SaveSymbols {
+        /// Path to the saved analysis.
+        path: PathBuf,
+        /// Serialized analysis.
+        symbols: String,
+    },
+    /// Stop the daemon.
+    Stop,
+}
+
+/// Response of the daemon.
+#[derive(Serialize, Deserialize, Debug)]
+enum Response {
+    /// LLVM toolchain.
+    Toolchain(LlvmToolchain),
+    /// Root directory of the workspace.
+    Project(PathBuf),
+    /// Symbol analysis, if kept and the file is unchanged.
+    Symbols(Option<String>),
+    /// Request is done.
+    Done,
+    /// Request failed with the error.
+    Error(String),
+}
+
+/// Gets the path to the socket of the daemon.
+pub fn socket_path() -> CIResult<PathBuf> {
+    Ok(Config::dir()?.join(SOCKET_NAME))
+}
+
+/// Gets the environment variables selecting the `rustc` toolchain.
+pub fn toolchain_env() -> Vec<(String, String)> {
+    TOOLCHAIN_ENV
+        .iter()
+        .filter_map(|key| Some((key.to_string(), std::env::var(key).ok()?)))
+        .collect()
+}
+
+/// Gets the LLVM toolchain of the current directory from the daemon.
+pub fn toolchain(bin_dir: Option<&Path>) -> Option<LlvmToolchain> {
+    let dir = std::env::current_dir().ok()?;
+    let env = toolchain_env();
+    let bin_dir = bin_dir.map(Path::to_path_buf);
+    match request(&Request::Toolchain { dir, env, bin_dir })? {
+        Response::Toolchain(toolchain) => Some(toolchain),
+        _ => None,
+    }
+}
+
+/// Gets the root directory of the workspace of the directory from the daemon.
+pub fn locate_project(dir: &Path) -> Option<PathBuf> {
+    let dir = dir.to_path_buf();
+    match request(&Request::LocateProject { dir })? {
+        Response::Project(root_dir) => Some(root_dir),
+        _ => None,
+    }
+}
+
+/// Gets the symbol analysis saved to the path from the daemon.
+pub fn symbols(path: &Path) -> Option<String> {
+    let path = path.to_path_buf();
+    match request(&Request::Symbols { path })? {
+        Response::Symbols(symbols) => symbols,
+        _ => None,
+    }
+}
+
+/// Gives the symbol analysis just saved to the path to the daemon.
+pub fn save_symbols(path: &Path, symbols: String) {
+    let path = path.to_path_buf();
+    request(&Request::SaveSymbols { path, symbols });
+}
+
+/// Stops the daemon, returning false if it is not running.
+pub fn stop() -> bool {
+    request(&Request::Stop).is_some()
+}
+
+/// Sends the request to the daemon, returning `None` if it is not running or failed.
+#[cfg(unix)]
+fn request(request: &Request) -> Option<Response> {
+    let path = socket_path().ok()?;
+    let mut stream = UnixStream::connect(path).ok()?;
+
+    let result = (|| -> CIResult<Response> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        serde_json::to_writer(&mut stream, request)?;
+        stream.write_all(b"\n")?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        Ok(serde_json::from_str(&line)?)
+    })();
+
+    match result {
+        Ok(Response::Error(error)) => {
+            debug!("daemon request failed: {}", error);
+            None
+        }
+        Ok(response) => Some(response),
+        Err(error) => {
+            warn!("failed to communicate with the daemon: {}", error);
+            None
+        }
+    }
+}
+
+/// Sends the request to the daemon, which never runs without Unix domain sockets.
+#[cfg(not(unix))]
+fn request(_request: &Request) -> Option<Response> {
+    None
+}
+
+/// Runs the daemon until stopped, serving the requests one at a time.
+#[cfg(unix)]
+pub fn serve() -> CIResult<()> {
+    let path = socket_path()?;
+    if UnixStream::connect(&path).is_ok() {
+        bail!("daemon is already running on `{}`", path.display());
+    }
+
+    // remove the socket left by a daemon not stopped properly
+    let _ = fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("failed to bind the socket `{}`", path.display()))?;
+    info!("daemon listening on: {}", path.display());
+
+    let mut state = State::default();
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                warn!("failed to accept the connection: {}", error);
+                continue;
+            }
+        };
+        match state.handle(stream) {
+            Ok(true) => break,
+            Ok(false) => {}
+            Err(error) => warn!("failed to handle the request: {:#}", error),
+        }
+    }
+
+    fs::remove_file(&path)
+        .with_context(|| format!("failed to remove the socket `{}`", path.display()))
+}
+
+/// Runs the daemon, which needs Unix domain sockets.
+#[cfg(not(unix))]
+pub fn serve() -> CIResult<()> {
+    bail!(CIError::HostNotSupported(
+        "cargo-lib-ci daemon".to_string(),
+        "Unix domain sockets".to_string()
+    ))
+}
+
+/// Warm state of the daemon.
+#[cfg(unix)]
+#[derive(Default, Debug)]
+struct State {
+    /// Toolchain request -> LLVM toolchain.
+    toolchains: HashMap<ToolchainKey, LlvmToolchain>,
+    /// Directory -> root directory of the workspace.
+    projects: HashMap<PathBuf, PathBuf>,
+    /// Path -> symbol analysis and modification time of the saved file.
+    symbols: HashMap<PathBuf, (String, Option<SystemTime>)>,
+}
+
+#[cfg(unix)]
+impl State {
+    /// Handles the request of the connection, returning true if the daemon is stopped.
+    fn handle(&mut self, mut stream: UnixStream) -> CIResult<bool> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        let request = serde_json::from_str::<Request>(&line)?;
+
+        let stop = matches!(request, Request::Stop);
+        let response = self
+            .respond(request)
+            .unwrap_or_else(|error| Response::Error(format!("{:#}", error)));
+        serde_json::to_writer(&mut stream, &response)?;
+        stream.write_all(b"\n")?;
+
+        Ok(stop)
+    }
+
+    /// Responds to the request from the warm state, filling it if missing.
+    fn respond(&mut self, request: Request) -> CIResult<Response> {
+        let response = match request {
+            Request::Toolchain { dir, env, bin_dir } => {
+                let key = (dir, env, bin_dir);
+                let toolchain = match self.toolchains.get(&key) {
+                    Some(toolchain) => toolchain.clone(),
+                    None => {
+                        debug!("probing the toolchain in: {}", key.0.display());
+                        let toolchain = llvm::probe(&key.0, &key.1, key.2.as_deref())?;
+                        self.toolchains.insert(key, toolchain.clone());
+                        toolchain
+                    }
+                };
+                Response::Toolchain(toolchain)
+            }
+            Request::LocateProject { dir } => {
+                let root_dir = self
+                    .projects
+                    .get(&dir)
+                    .filter(|root_dir| root_dir.join("Cargo.toml").is_file());
+                let root_dir = match root_dir {
+                    Some(root_dir) => root_dir.clone(),
+                    None => {
+                        debug!("locating the project of: {}", dir.display());
+                        let root_dir = cargo::locate_project_in(&dir)?;
+                        self.projects.insert(dir, root_dir.clone());
+                        root_dir
+                    }
+                };
+                Response::Project(root_dir)
+            }
+            Request::Symbols { path } => {
+                // the file may be changed or removed by someone else
+                let modified = modified(&path);
+                let symbols = self
+                    .symbols
+                    .get(&path)
+                    .filter(|(_, time)| modified.is_some() && *time == modified)
+                    .map(|(symbols, _)| symbols.clone());
+                Response::Symbols(symbols)
+            }
+            Request::SaveSymbols { path, symbols } => {
+                let modified = modified(&path);
+                self.symbols.insert(path, (symbols, modified));
+                Response::Done
+            }
+            Request::Stop => Response::Done,
+        };
+        Ok(response)
+    }
+}
+
+/// Gets the modification time of the file.
+#[cfg(unix)]
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
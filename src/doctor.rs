@@ -0,0 +1,268 @@
+//! Checks of the environment of the integration.
+
+use cargo_util::paths;
+use tracing::debug;
+
+use crate::config::Config;
+use crate::error::CIError;
+use crate::llvm::{
+    self, LlvmToolchain, LlvmUtility, PassManager, LLVM_MAX_VERSION, LLVM_MIN_VERSION,
+};
+use crate::paths::PathExt;
+use crate::plugin::PassPlugin;
+use crate::{daemon, CIResult};
+
+/// Outcome of a check.
+#[derive(Debug)]
+pub enum Outcome {
+    /// The check passed, with the details.
+    Pass(String),
+    /// The check failed, with the problem and how to fix it.
+    Fail(String, String),
+    /// The check could not run because of a failed one.
+    Skip(String),
+}
+
+/// Check of the environment.
+#[derive(Debug)]
+pub struct Check {
+    /// Name of the check.
+    pub name: String,
+    /// Outcome of the check.
+    pub outcome: Outcome,
+}
+
+impl Check {
+    /// Creates the check with its outcome.
+    fn new<S: Into<String>>(name: S, outcome: Outcome) -> Self {
+        Self {
+            name: name.into(),
+            outcome,
+        }
+    }
+
+    /// Returns true if the check failed.
+    pub fn failed(&self) -> bool {
+        matches!(self.outcome, Outcome::Fail(..))
+    }
+}
+
+/// Runs the checks of the toolchain, the library, and the configuration directory.
+///
+/// Every check runs even if another one failed, unless it depends on it.
+pub fn run(config: &mut Config) -> CIResult<Vec<Check>> {
+    let mut checks = Vec::new();
+    let dir = std::env::current_dir()?;
+
+    // `rustc` and its LLVM version
+    let rustc_version = llvm::rustc_version(&dir, &daemon::toolchain_env());
+    match &rustc_version {
+        Ok(version) if *version < LLVM_MIN_VERSION || *version >= LLVM_MAX_VERSION => {
+            checks.push(Check::new(
+                "LLVM version of rustc",
+                Outcome::Fail(
+                    format!(
+                        "LLVM {} is not supported, only from {} to before {}",
+                        version, LLVM_MIN_VERSION, LLVM_MAX_VERSION
+                    ),
+                    "Use a Rust toolchain whose LLVM version is supported, \
+                    e.g. with `rustup override set`"
+                        .to_string(),
+                ),
+            ));
+        }
+        Ok(version) => checks.push(Check::new(
+            "LLVM version of rustc",
+            Outcome::Pass(format!("LLVM {}", version)),
+        )),
+        Err(error) => checks.push(Check::new(
+            "LLVM version of rustc",
+            Outcome::Fail(
+                format!("{:#}", error),
+                "Install Rust with `rustup` and check that `rustc -vV` runs".to_string(),
+            ),
+        )),
+    }
+
+    // LLVM toolchain of the same version, and its utilities
+    let toolchain = match &rustc_version {
+        Ok(version) => {
+            if config.select_library(version.major) {
+                debug!("using the library for LLVM {}", version.major);
+            }
+            let toolchain = llvm::probe(
+                &dir,
+                &daemon::toolchain_env(),
+                config.llvm_bin_dir.as_deref(),
+            );
+            match &toolchain {
+                Ok(toolchain) => checks.push(Check::new(
+                    "LLVM toolchain",
+                    Outcome::Pass(format!(
+                        "LLVM {} in {}",
+                        toolchain.version,
+                        toolchain
+                            .bin_dir
+                            .as_ref()
+                            .map_or_else(|| "PATH".to_string(), |dir| dir.display().to_string())
+                    )),
+                )),
+                Err(error) => checks.push(Check::new(
+                    "LLVM toolchain",
+                    Outcome::Fail(format!("{}", error), explain_hint(error)),
+                )),
+            }
+            toolchain.ok()
+        }
+        Err(_) => {
+            checks.push(Check::new(
+                "LLVM toolchain",
+                Outcome::Skip("the LLVM version of rustc is unknown".to_string()),
+            ));
+            None
+        }
+    };
+
+    let utilities = [
+        LlvmUtility::Optimizer,
+        LlvmUtility::StaticCompiler,
+        LlvmUtility::Archiver,
+        LlvmUtility::NameMangling,
+    ];
+    for utility in utilities {
+        let toolchain = match &toolchain {
+            Some(toolchain) => toolchain,
+            None => {
+                checks.push(Check::new(
+                    format!("`{}`", utility.as_str()),
+                    Outcome::Skip("the LLVM toolchain is not found".to_string()),
+                ));
+                continue;
+            }
+        };
+        let cmd = utility.process_builder(toolchain);
+        let outcome = match cmd.clone().arg("--version").exec_with_output() {
+            Ok(_) => Outcome::Pass(cmd.get_program().to_string_lossy().into_owned()),
+            Err(_) => Outcome::Fail(
+                format!("`{}` failed to run", cmd.get_program().to_string_lossy()),
+                format!(
+                    "Install `{}` of the LLVM toolchain {}, e.g. the `llvm-{}` package",
+                    utility.as_str(),
+                    toolchain.version.major,
+                    toolchain.version.major
+                ),
+            ),
+        };
+        checks.push(Check::new(format!("`{}`", utility.as_str()), outcome));
+    }
+
+    // the library compiled for the LLVM version, loadable by `opt`
+    let major = rustc_version.as_ref().ok().map(|version| version.major);
+    let library = if config.library_path.as_os_str().is_empty() {
+        Outcome::Fail(
+            "the library is not installed".to_string(),
+            "Run `cargo-lib-ci install` to install the library".to_string(),
+        )
+    } else if !config.library_path.is_file() {
+        Outcome::Fail(
+            format!("`{}` does not exist", config.library_path.display()),
+            "Run `cargo-lib-ci install` to install the library".to_string(),
+        )
+    } else if major
+        .is_some_and(|major| config.llvm_version.split('.').next() != Some(&major.to_string()))
+    {
+        Outcome::Fail(
+            format!(
+                "the library is compiled with LLVM {}, but rustc uses LLVM {}",
+                config.llvm_version,
+                major.unwrap_or_default()
+            ),
+            "Run `cargo-lib-ci install` to install the library for the LLVM version of rustc"
+                .to_string(),
+        )
+    } else {
+        Outcome::Pass(format!(
+            "{} (LLVM {})",
+            config.library_path.display(),
+            config.llvm_version
+        ))
+    };
+    let library_installed = !matches!(library, Outcome::Fail(..));
+    checks.push(Check::new("Compiler Interrupts library", library));
+
+    for plugin in config.pass_plugins() {
+        let name = format!("pass plugin `{}`", plugin.name);
+        let outcome = match (&toolchain, library_installed) {
+            (Some(toolchain), true) => load_plugin(config, toolchain, &plugin),
+            (None, _) => Outcome::Skip("the LLVM toolchain is not found".to_string()),
+            (_, false) => Outcome::Skip("the library is not installed".to_string()),
+        };
+        checks.push(Check::new(name, outcome));
+    }
+
+    // the configuration, the libraries, and the cache are written there
+    let outcome = match config_dir_writable() {
+        Ok(dir) => Outcome::Pass(dir),
+        Err(error) => Outcome::Fail(
+            format!("{:#}", error),
+            "Check the permissions of the configuration directory, or set `XDG_CONFIG_HOME`"
+                .to_string(),
+        ),
+    };
+    checks.push(Check::new("configuration directory", outcome));
+
+    Ok(checks)
+}
+
+/// Loads the plugin with `opt` printing its help.
+fn load_plugin(config: &Config, toolchain: &LlvmToolchain, plugin: &PassPlugin) -> Outcome {
+    if !plugin.library_path.is_file() {
+        return Outcome::Fail(
+            format!("`{}` does not exist", plugin.library_path.display()),
+            "Build the plugin, or fix its `library-path` in the configuration".to_string(),
+        );
+    }
+    let mut opt = LlvmUtility::Optimizer.process_builder(toolchain);
+    match config.pass_manager(toolchain) {
+        PassManager::Legacy => {
+            opt.args(&["--enable-new-pm=0", "--load"]);
+            opt.arg(&plugin.library_path);
+        }
+        PassManager::New => {
+            opt.arg(format!(
+                "--load-pass-plugin={}",
+                plugin.library_path.display()
+            ));
+        }
+    }
+    opt.arg("--help");
+    match opt.exec_with_output() {
+        Ok(_) => Outcome::Pass(format!(
+            "loaded with the {} pass manager",
+            config.pass_manager(toolchain).as_str()
+        )),
+        Err(error) => Outcome::Fail(
+            format!("`opt` failed to load the plugin: {:#}", error),
+            "Run `cargo-lib-ci update` to compile the library again, or select the other \
+            pass manager with `cargo-lib-ci config --pass-manager`"
+                .to_string(),
+        ),
+    }
+}
+
+/// Writes and removes a file in the configuration directory.
+fn config_dir_writable() -> CIResult<String> {
+    let dir = Config::dir()?;
+    let path = dir.join(format!(".doctor-{}", std::process::id()));
+    paths::write(&path, "")?;
+    paths::remove_file(&path)?;
+    dir.to_string()
+}
+
+/// Gets the hint to `cargo-lib-ci explain` for the failure class of the error.
+fn explain_hint(error: &anyhow::Error) -> String {
+    match CIError::find(error) {
+        Some(ci_error) => format!("Run `cargo-lib-ci explain {}`", ci_error.code()),
+        None => "Run with `--log debug` for the details".to_string(),
+    }
+}
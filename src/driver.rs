@@ -0,0 +1,95 @@
+//! Driver backend running the pass in the codegen of `rustc`.
+//!
+//! Instead of integrating the LLVM IR saved by `-C save-temps` and relinking the
+//! binaries from the linker invocations in the build log, `rustc` loads the library
+//! as an LLVM pass plugin and runs the pass in its own optimization pipeline, so
+//! `cargo build` produces the CI-integrated binaries directly.
+//!
+//! `-Z llvm-plugins` requires a nightly toolchain and a library exposing the new
+//! pass manager plugin interface.
+
+use std::path::PathBuf;
+
+use anyhow::Context;
+use cargo_util::paths;
+use tracing::{debug, info};
+
+use crate::cargo::{Cargo, FileFlavor};
+use crate::config::Config;
+use crate::metrics::Metrics;
+use crate::paths::PathExt;
+use crate::{hooks, logs, CIResult};
+
+/// Name of the Compiler Interrupts pass registered by the library.
+const PASS_NAME: &str = "logicalclock";
+
+/// Gets the flags for `rustc` to run the pass in its codegen.
+pub fn flags(config: &Config) -> CIResult<Vec<String>> {
+    let mut flags = vec![
+        format!("-Zllvm-plugins={}", config.library_path.to_string()?),
+        format!("-Cpasses={}", PASS_NAME),
+    ];
+    flags.extend(
+        config
+            .pass_args()
+            .iter()
+            .map(|arg| format!("-Cllvm-args={}", arg)),
+    );
+    Ok(flags)
+}
+
+/// Hard links the binaries built by `cargo` with the driver backend to their
+/// CI-integrated names, returning the linked files.
+pub fn link_binaries(config: &Config, cargo: &Cargo, metrics: &Metrics) -> CIResult<Vec<PathBuf>> {
+    let mut binaries = Vec::new();
+    for file in &cargo.output_files {
+        let hardlink = match (&file.flavor, &file.hardlink) {
+            (FileFlavor::Normal, Some(hardlink)) => hardlink,
+            // the test harnesses are left in `deps` by `cargo`
+            (FileFlavor::Normal, None) => {
+                if matches!(file.path.extension().as_deref(), Err(_) | Ok("exe")) {
+                    binaries.push(file.path.clone());
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        // libraries and build scripts are not CI-integrated binaries
+        let crate_name = hardlink.file_stem()?;
+        let executable = match hardlink.extension() {
+            Ok(extension) => extension == "exe",
+            Err(_) => true,
+        };
+        if !executable || cargo.is_host_only(&file.path) {
+            continue;
+        }
+
+        // `append_suffix` keeps the extension of the target, e.g. `.exe`
+        let link_file = hardlink.append_suffix("ci")?;
+        info!("linking: {}", crate_name);
+        debug!(?link_file);
+        paths::link_or_copy(&file.path, &link_file)?;
+
+        if let Some(hook) = &config.post_link_hook {
+            hooks::run(
+                "post-link",
+                hook,
+                &[
+                    ("CI_CRATE_NAME", &crate_name),
+                    ("CI_BINARY", &link_file.to_string()?),
+                ],
+            )
+            .with_context(|| format!("{}{}", logs::CRATE_PREFIX, crate_name))?;
+        }
+
+        binaries.push(link_file);
+    }
+
+    metrics
+        .binaries
+        .lock()
+        .expect("failed to acquire lock")
+        .extend(binaries.iter().cloned());
+
+    Ok(binaries)
+}
@@ -0,0 +1,422 @@
+//! Programmatic API of the integration for embedding it without the CLI binaries, e.g.
+//! in other tools driving the integration.
+//!
+//! ``` no_run
+//! use cargo_compiler_interrupts::integrate::Builder;
+//! use cargo_compiler_interrupts::Config;
+//!
+//! let report = Builder::new(Config::load()?)
+//!     .library_path("/opt/ci/libCompilerInterrupt.so")
+//!     .library_args(["-clock-type=1"])
+//!     .skip(["serde", "regex"])
+//!     .target_dir("target/ci")
+//!     .run()?;
+//! println!("integrated {} LLVM IR files", report.integrated);
+//! for binary in &report.binaries {
+//!     println!("{}", binary.display());
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::bail;
+use cargo_util::paths;
+
+use crate::cargo::{Cargo, Features};
+use crate::config::{Backend, Config};
+use crate::error::CIError;
+use crate::metrics::{CrateResult, Metrics, Phase};
+use crate::observer::IntegrationObserver;
+use crate::ops::build;
+use crate::pipeline::{self, IntegrationOptions};
+use crate::platform::Target;
+use crate::tasks::CancellationToken;
+use crate::{driver, llvm, symbols, util, PassPlugin};
+
+/// Report of a finished integration.
+#[derive(Clone, Debug)]
+pub struct Report {
+    /// Number of integrated LLVM IR files.
+    pub integrated: usize,
+    /// Number of skipped LLVM IR files.
+    pub skipped: usize,
+    /// Number of LLVM IR files restored from the cache.
+    pub cache_hits: usize,
+    /// CI-integrated binaries.
+    pub binaries: Vec<PathBuf>,
+    /// Duration of the whole integration, including `cargo build`.
+    pub duration: Duration,
+}
+
+/// Builder of an integration of the package in the current directory, equivalent to
+/// `cargo-build-ci` with the same options.
+///
+/// The build is run by `cargo` and its output is integrated by [`pipeline::run`].
+pub struct Builder {
+    /// Configuration of the library.
+    config: Config,
+    /// Options of the integration.
+    options: IntegrationOptions,
+    /// Library used instead of the installed one.
+    library_path: Option<PathBuf>,
+    /// Arguments for `cargo build`.
+    cargo_args: Vec<String>,
+    /// Members of the workspace to build and integrate.
+    packages: Vec<String>,
+    /// Binary targets to build and integrate.
+    binaries: Vec<String>,
+    /// Features of the packages to activate.
+    features: Features,
+    /// Profile of the build.
+    profile: Option<String>,
+    /// Build with `cross`.
+    cross: bool,
+    /// Keep the temporary files.
+    keep_temps: bool,
+    /// Executable of `cargo-build-ci` set as `RUSTC_WRAPPER`.
+    wrapper: Option<PathBuf>,
+    /// Observers of the progress.
+    observers: Vec<Box<dyn IntegrationObserver>>,
+    /// Token cancelling the integration.
+    token: CancellationToken,
+}
+
+impl fmt::Debug for Builder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Builder")
+            .field("config", &self.config)
+            .field("options", &self.options)
+            .field("library_path", &self.library_path)
+            .field("cargo_args", &self.cargo_args)
+            .field("packages", &self.packages)
+            .field("binaries", &self.binaries)
+            .field("features", &self.features)
+            .field("profile", &self.profile)
+            .field("cross", &self.cross)
+            .field("keep_temps", &self.keep_temps)
+            .field("wrapper", &self.wrapper)
+            .field("observers", &self.observers.len())
+            .field("token", &self.token)
+            .finish()
+    }
+}
+
+impl Builder {
+    /// Creates a builder with the configuration and the default options.
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            options: IntegrationOptions::default(),
+            library_path: None,
+            cargo_args: Vec::new(),
+            packages: Vec::new(),
+            binaries: Vec::new(),
+            features: Features::default(),
+            profile: None,
+            cross: false,
+            keep_temps: false,
+            wrapper: None,
+            observers: Vec::new(),
+            token: CancellationToken::new(),
+        }
+    }
+
+    /// Builds and integrates the members of the workspace instead of the default ones.
+    pub fn packages<I, S>(mut self, packages: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.packages.extend(packages.into_iter().map(Into::into));
+        self
+    }
+
+    /// Builds and integrates the binaries instead of all the targets.
+    pub fn binaries<I, S>(mut self, binaries: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.binaries.extend(binaries.into_iter().map(Into::into));
+        self
+    }
+
+    /// Activates the features of the packages.
+    pub fn features<I, S>(mut self, features: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.features
+            .features
+            .extend(features.into_iter().map(Into::into));
+        self
+    }
+
+    /// Activates all available features of the packages.
+    pub fn all_features(mut self, all_features: bool) -> Self {
+        self.features.all_features = all_features;
+        self
+    }
+
+    /// Does not activate the `default` feature of the packages.
+    pub fn no_default_features(mut self, no_default_features: bool) -> Self {
+        self.features.no_default_features = no_default_features;
+        self
+    }
+
+    /// Builds with the profile, e.g. `release` or a custom profile of the manifest.
+    pub fn profile<S: Into<String>>(mut self, profile: S) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+
+    /// Skips the integration of the crates.
+    pub fn skip<I, S>(mut self, crates: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options
+            .skip_crates
+            .extend(crates.into_iter().map(Into::into));
+        self
+    }
+
+    /// Integrates only the crates, leaving the others untouched.
+    pub fn only<I, S>(mut self, crates: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options
+            .only_crates
+            .extend(crates.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets the target directory of `cargo build`.
+    pub fn target_dir<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.cargo_args.push("--target-dir".to_string());
+        self.cargo_args
+            .push(path.into().to_string_lossy().into_owned());
+        self
+    }
+
+    /// Adds arguments for `cargo build`.
+    pub fn cargo_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.cargo_args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Sets the number of parallel jobs of `cargo build` and of the integration.
+    pub fn jobs(mut self, jobs: u32) -> Self {
+        self.options.jobs = Some(jobs.max(1));
+        self
+    }
+
+    /// Uses the library at the path instead of the installed one.
+    ///
+    /// The LLVM version of the library is not checked against the one of `rustc`.
+    pub fn library_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.library_path = Some(path.into());
+        self
+    }
+
+    /// Sets the arguments for the library, replacing the configured ones.
+    pub fn library_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.library_args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Excludes the functions from the probes by their symbol names.
+    pub fn skip_fn<I, S>(mut self, functions: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config
+            .skip_functions
+            .extend(functions.into_iter().map(Into::into));
+        self
+    }
+
+    /// Uses the debugging mode of the library.
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.config.debug = debug;
+        self
+    }
+
+    /// Disables the cache of the CI-integrated object files.
+    pub fn no_cache(mut self, no_cache: bool) -> Self {
+        self.options.no_cache = no_cache;
+        self
+    }
+
+    /// Emits LLVM bitcode instead of textual LLVM IR.
+    pub fn bitcode(mut self, bitcode: bool) -> Self {
+        self.options.bitcode = bitcode;
+        self
+    }
+
+    /// Keeps the temporary files of `-C save-temps` and the integration.
+    pub fn keep_temps(mut self, keep_temps: bool) -> Self {
+        self.keep_temps = keep_temps;
+        self
+    }
+
+    /// Continues with the crates and binaries not depending on a failed one.
+    pub fn keep_going(mut self, keep_going: bool) -> Self {
+        self.options.keep_going = keep_going;
+        self
+    }
+
+    /// Builds with `cross`, linking the binaries in its container image.
+    pub fn cross(mut self, cross: bool) -> Self {
+        self.cross = cross;
+        self
+    }
+
+    /// Cancels the integration with the token, killing the running processes.
+    ///
+    /// The integration then fails with `CIError::Cancelled`.
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Adds an LLVM pass plugin run after Compiler Interrupts and the configured plugins.
+    pub fn plugin(mut self, plugin: PassPlugin) -> Self {
+        self.config.plugins.push(plugin);
+        self
+    }
+
+    /// Sets the executable of `cargo-build-ci` used as `RUSTC_WRAPPER` of the build.
+    ///
+    /// By default, the current executable is used if it is one of the `cargo-*-ci`
+    /// binaries, or `cargo-build-ci` in `PATH` otherwise.
+    pub fn wrapper<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.wrapper = Some(path.into());
+        self
+    }
+
+    /// Adds an observer of the progress.
+    pub fn observer<O: IntegrationObserver + 'static>(mut self, observer: O) -> Self {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    /// Runs the integration.
+    ///
+    /// The current directory is changed to the root directory of the workspace. The
+    /// crates skipped by the configuration of the project are skipped unless some are
+    /// given with [`skip`](Self::skip).
+    pub fn run(self) -> anyhow::Result<Report> {
+        let Self {
+            mut config,
+            mut options,
+            library_path,
+            cargo_args,
+            packages,
+            binaries,
+            features,
+            profile,
+            cross,
+            keep_temps,
+            wrapper,
+            observers,
+            token,
+        } = self;
+
+        util::set_current_workspace_root_dir()?;
+        let toolchain = match library_path {
+            Some(library_path) => {
+                config.library_path = library_path;
+                llvm::toolchain(&config)?
+            }
+            // the same checks as `cargo build-ci`, never reinstalling without a confirmation
+            None => build::select_toolchain(&mut config, false)?,
+        };
+        if !config.library_path.is_file() {
+            bail!(CIError::LibraryNotInstalled);
+        }
+        if options.skip_crates.is_empty() {
+            options.skip_crates = config.skip_crates.clone();
+        }
+
+        let metrics = Metrics::default();
+        let time = Instant::now();
+
+        let mut cargo = Cargo::with_args(cargo_args);
+        cargo.features = features;
+        cargo.jobs = options.jobs;
+        cargo.profile = profile;
+        cargo.bitcode = options.bitcode;
+        cargo.only_crates = options.only_crates.clone();
+        cargo.wrapper = wrapper;
+        cargo.select(&packages, &binaries, false)?;
+        if let Some(target) = cargo.target() {
+            if !Target::parse(&target).is_supported() {
+                bail!(CIError::TargetNotSupported(target));
+            }
+        }
+        if cross {
+            cargo.set_cross(&config)?;
+        }
+        cargo.set_backend(&config)?;
+        let result = cargo.build(&options.skip_crates);
+        // `cargo` also fails when interrupted
+        token.check()?;
+        result?;
+        metrics.record(Phase::Cargo, time.elapsed());
+        if let Some(target) = cargo.unsupported_target() {
+            bail!(CIError::TargetNotSupported(target));
+        }
+
+        if config.backend == Backend::Driver {
+            // the binaries are already integrated by `rustc`
+            driver::link_binaries(&config, &cargo, &metrics)?;
+            observers.on_finished();
+        } else {
+            let build = cargo.build_output(&config, None)?;
+            // the temporary files are looked up next to the LLVM IR files once linked
+            let llvm_ir_files = build.llvm_ir_files.clone();
+            let symbols = symbols::load(build.target_dir.join("ci-symbols.json"));
+            pipeline::run(
+                &config, &toolchain, &options, build, &symbols, &metrics, &observers, &token,
+            )?;
+            symbols::save(&symbols)?;
+            if !keep_temps {
+                for file in pipeline::temporary_files(&llvm_ir_files)? {
+                    paths::remove_file(file)?;
+                }
+            }
+        }
+
+        let count = |counter: &AtomicUsize| counter.load(Ordering::Relaxed);
+        let binaries = metrics
+            .binaries
+            .lock()
+            .expect("failed to acquire lock")
+            .clone();
+        Ok(Report {
+            integrated: metrics.count(CrateResult::Integrated),
+            skipped: metrics.count(CrateResult::Skipped),
+            cache_hits: count(&metrics.cache_hits),
+            binaries,
+            duration: time.elapsed(),
+        })
+    }
+}
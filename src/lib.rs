@@ -8,13 +8,15 @@
 //!
 //! ## Requirements
 //!
-//! * [Rust 1.45.0 - 1.64.0][rust] and [LLVM 9 - 14][llvm] are required. 
-//! Both must have the same LLVM version.
-//! Later LLVM versions are currently not supported due to the new LLVM pass manager.
+//! * [Rust 1.85.0][rust] or later is required to build `cargo-compiler-interrupts` itself.
+//! * The packages are integrated with their own Rust toolchain, e.g. set by `rustup override`,
+//!   which must be Rust 1.45.0 - 1.64.0, and [LLVM 9 - 14][llvm].
+//!   Both must have the same LLVM version.
+//!   Later LLVM versions are currently not supported due to the new LLVM pass manager.
 //! * You can check the LLVM version from Rust and LLVM toolchains by running `rustc -vV`
-//! and `llvm-config --version` respectively.
+//!   and `llvm-config --version` respectively.
 //! * x86-64 architecture with Linux or macOS is highly recommended.
-//! Other architectures and platforms have not been tested and not guaranteed to work.
+//!   Other architectures and platforms have not been tested and not guaranteed to work.
 //!
 //! ## Installation
 //!
@@ -44,7 +46,7 @@
 //! * `cargo-lib-ci` — manage the Compiler Interrupts library.
 //! * `cargo-build-ci` — build and integrate the Compiler Interrupts to the package.
 //! * `cargo-run-ci` — run the integrated binary.
-//! You can specify which binary to run by passing `--bin <BINARY>`.
+//!   You can specify which binary to run by passing `--bin <BINARY>`.
 //!
 //! Run `cargo-lib-ci install` to install the Compiler Interrupts library first.
 //! Before running `cargo-build-ci`, add the Compiler Interrupts API package as the dependency for
@@ -52,7 +54,7 @@
 //! Compiler Interrupts API is provided through the [`compiler-interrupts`][compiler-interrupts-rs]
 //! package.
 //!
-//! ``` rust
+//! ``` rust,ignore
 //! fn interrupt_handler(ic: i64) {
 //!     println!("Compiler interrupt called with instruction count: {}", ic);
 //! }
@@ -121,17 +123,36 @@
     clippy::unwrap_used
 )]
 
+use std::process::ExitCode;
+
 /// Compiler Interrupts result.
 type CIResult<T> = anyhow::Result<T>;
 
 mod args;
+mod capture;
 mod cargo;
-mod config;
-mod error;
+mod daemon;
+mod doctor;
+mod driver;
+pub mod integrate;
 mod llvm;
+mod notify;
 pub mod ops;
-mod paths;
+mod symbols;
+pub mod tasks;
 mod util;
+mod watch;
+mod wrapper;
+
+pub use compiler_interrupts_core::config::Config;
+pub use compiler_interrupts_core::error::CIError;
+pub use compiler_interrupts_core::observer;
+pub use compiler_interrupts_core::plugin::{Pass, PassPlugin};
+
+use compiler_interrupts_core::{
+    archive, cache, config, cross, error, handler, hooks, ir_diff, logs, metrics, paths, perf,
+    pipeline, platform, plugin, probes, stats,
+};
 
 /// Name of the cargo-build-ci.
 const BUILD_CI_BIN_NAME: &str = "cargo-build-ci";
@@ -139,5 +160,39 @@ const BUILD_CI_BIN_NAME: &str = "cargo-build-ci";
 /// Name of the cargo-run-ci.
 const RUN_CI_BIN_NAME: &str = "cargo-run-ci";
 
+/// Name of the cargo-test-ci.
+const TEST_CI_BIN_NAME: &str = "cargo-test-ci";
+
+/// Name of the cargo-bench-ci.
+const BENCH_CI_BIN_NAME: &str = "cargo-bench-ci";
+
 /// Name of the cargo-lib-ci.
 const LIB_CI_BIN_NAME: &str = "cargo-lib-ci";
+
+/// Reports the error of a subcommand with its diagnostic code, and converts its result
+/// to the exit code.
+pub fn exit(result: anyhow::Result<()>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            print_error(&error);
+            util::log_error_to_file(&error);
+            ExitCode::from(error::exit_code(&error))
+        }
+    }
+}
+
+/// Prints the error with its diagnostic code and the hint to explain it.
+fn print_error(error: &anyhow::Error) {
+    match CIError::find(error) {
+        Some(ci_error) => {
+            let code = ci_error.code();
+            eprintln!("Error[{}]: {:?}", code, error);
+            eprintln!(
+                "For more information about this error, run `{} explain {}`",
+                LIB_CI_BIN_NAME, code
+            );
+        }
+        None => eprintln!("Error: {:?}", error),
+    }
+}
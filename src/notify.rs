@@ -0,0 +1,42 @@
+//! Notifications of the build completion.
+
+use tracing::{debug, info, warn};
+
+use crate::hooks;
+
+/// Notifies the build completion by running the shell command and posting the
+/// JSON summary to the URL.
+///
+/// The shell command receives the status (`success` or `failure`) and the JSON summary
+/// in the `CI_BUILD_STATUS` and `CI_BUILD_SUMMARY` environment variables.
+/// Failed notifications are reported as warnings and never fail the build.
+pub fn notify(command: Option<&String>, url: Option<&String>, summary: &serde_json::Value) {
+    let status = if summary["success"] == true {
+        "success"
+    } else {
+        "failure"
+    };
+    let summary = summary.to_string();
+    debug!(?summary);
+
+    if let Some(command) = command {
+        info!("running notification command: {}", command);
+        let result = hooks::exec_shell(
+            command,
+            &[("CI_BUILD_STATUS", status), ("CI_BUILD_SUMMARY", &summary)],
+        );
+        if let Err(error) = result {
+            warn!("notification command failed: {:#}", error);
+        }
+    }
+
+    if let Some(url) = url {
+        info!("posting build summary to: {}", url);
+        let result = ureq::post(url)
+            .set("Content-Type", "application/json")
+            .send_string(&summary);
+        if let Err(error) = result {
+            warn!("failed to post build summary: {}", error);
+        }
+    }
+}
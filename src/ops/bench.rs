@@ -0,0 +1,22 @@
+//! Implementation of `cargo-bench-ci`.
+//!
+//! `cargo bench --no-run` builds the benchmark harnesses with the `bench` profile,
+//! e.g. of libtest or criterion, which are integrated and run with `--bench` like
+//! `cargo bench` runs them.
+
+use crate::cargo::CargoCommand;
+use crate::ops::test;
+use crate::{args, wrapper, CIResult, BENCH_CI_BIN_NAME};
+
+/// Main routine for `cargo-bench-ci`.
+pub fn exec() -> CIResult<()> {
+    if wrapper::is_wrapper() {
+        return wrapper::exec();
+    }
+
+    test::run_harnesses(
+        BENCH_CI_BIN_NAME,
+        args::bench_command(),
+        CargoCommand::Bench,
+    )
+}
@@ -1,301 +1,655 @@
 //! Implementation of `cargo-build-ci`.
 
+use std::collections::{BTreeSet, HashMap, HashSet};
+#[cfg(unix)]
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
 use std::path::{Path, PathBuf};
-use std::process::Output;
 use std::str::FromStr;
-use std::sync::mpsc::{Receiver, Sender};
-use std::sync::{mpsc, Arc, Mutex};
-use std::vec::IntoIter;
+use std::sync::Mutex;
+use std::time::Instant;
 
 use anyhow::{bail, Context};
-use cargo_util::{paths, ProcessBuilder, ProcessError};
+use cargo_util::paths;
 use clap::Parser;
 use colored::Colorize;
-use crossbeam_utils::thread;
+use console::Term;
+use dialoguer::theme::ColorfulTheme;
+use dialoguer::MultiSelect;
 use indicatif::{ProgressBar, ProgressStyle};
 use tracing::{debug, info, warn, Level};
 
 use crate::args::BuildArgs;
-use crate::cargo::{Cargo, Linker};
-use crate::config::Config;
-use crate::error::Error;
+use crate::cargo::{Cargo, CargoCommand, Features};
+use crate::config::{self, Backend, Config, ProjectConfig};
+use crate::error::CIError;
 use crate::llvm::{LlvmToolchain, LlvmUtility};
+use crate::metrics::{Metrics, Phase};
+use crate::observer::{IntegrationObserver, IntegrationPhase};
+use crate::ops::library;
 use crate::paths::PathExt;
-use crate::{llvm, util, CIResult, BUILD_CI_BIN_NAME};
-
-/// Default pre-optimization passes for Compiler Interrupts.
-const DEFAULT_OPT_PASSES: [&str; 6] = [
-    "--postdomtree",
-    "--mem2reg",
-    "--indvars",
-    "--loop-simplify",
-    "--branch-prob",
-    "--scalar-evolution",
-];
-
-/// State of the stage.
-#[derive(Debug)]
-enum State {
-    /// Started.
-    Started,
-    /// Finished.
-    Finished,
-}
-
-/// Stage of the integration.
-#[derive(Debug)]
-enum Stage {
-    /// Integrating.
-    Integrating(State),
-    /// Static compiling.
-    StaticCompiling(State),
-    /// Linking
-    Linking(State),
-    /// Crate is skipped.
-    Skipped,
-    /// Error occurred.
-    Error(String),
-}
-
-/// Shared context of the integration.
-#[derive(Debug)]
-struct IntegrationContext {
-    /// Name of the crate.
-    crate_name: Arc<String>,
-    /// Current stage.
-    stage: Stage,
-}
+use crate::pipeline::{self, BuildOutput, IntegrationOptions};
+use crate::platform::Target;
+use crate::plugin;
+use crate::probes::Report;
+use crate::symbols;
+use crate::tasks::CancellationToken;
+use crate::{
+    driver, ir_diff, llvm, notify, perf, tasks, util, watch, wrapper, CIResult, BUILD_CI_BIN_NAME,
+};
+
+/// Number of the slowest crates printed in the timings at the end of the build.
+const TIMINGS_ROWS: usize = 10;
 
 /// Main routine for `cargo-build-ci`.
 pub fn exec() -> CIResult<()> {
+    if wrapper::is_wrapper() {
+        return wrapper::exec();
+    }
+
     let args = if std::env::args().next().unwrap_or_default() == BUILD_CI_BIN_NAME {
         BuildArgs::parse()
     } else {
         BuildArgs::parse_from(std::env::args().skip(1))
     };
 
+    if args.watch {
+        init(&args)?;
+        let target_dir = Cargo::with_args(args.cargo_args.clone()).configured_target_dir()?;
+        return watch::watch(&target_dir, || build(&args, CargoCommand::Build).map(drop));
+    }
+
+    run(&args, CargoCommand::Build).map(drop)
+}
+
+/// Runs the integration of the build of the subcommand of `cargo`, reporting the
+/// result, and returns the CI-integrated binaries.
+pub(crate) fn run(args: &BuildArgs, command: CargoCommand) -> CIResult<Vec<PathBuf>> {
+    if args.watch {
+        bail!("`--watch` is only supported by `{}`", BUILD_CI_BIN_NAME);
+    }
+    init(args)?;
+    build(args, command)
+}
+
+/// Initializes the terminal, the logger, and the Ctrl-C handler of the process, and
+/// moves to the root of the workspace.
+fn init(args: &BuildArgs) -> CIResult<()> {
+    util::init_color(&args.color);
     util::init_logger(&args.log_level)?;
+    // relative to the directory of the invocation, not of the workspace root
+    if let Some(path) = &args.log_file {
+        util::init_log_file(path.as_deref())?;
+    }
     util::set_current_workspace_root_dir()?;
+    tasks::handle_interrupt()
+}
+
+/// Runs a build once the process is initialized, reloading the configuration.
+#[allow(clippy::print_stdout)]
+fn build(args: &BuildArgs, command: CargoCommand) -> CIResult<Vec<PathBuf>> {
+    let mut config = Config::load()?;
+    util::init_config_log_file(&config)?;
+    if let Some(skip_functions) = &args.skip_functions {
+        config.skip_functions = skip_functions.clone();
+    }
+    if let Some(granularity) = args.instruction_granularity {
+        config.set_library_arg(plugin::CI_INST_GRAN_ARG, granularity);
+    }
+    if let Some(interval) = args.commit_interval {
+        config.set_library_arg(plugin::CI_COMMIT_INTV_ARG, interval);
+    }
+    if let Some(deviation) = args.all_dev {
+        config.set_library_arg(plugin::CI_ALL_DEV_ARG, deviation);
+    }
+    for crate_args in &args.crate_args {
+        let (crate_name, crate_args) = config::parse_crate_args(crate_args)?;
+        config.crate_args.insert(crate_name, crate_args);
+    }
+    let toolchain = select_toolchain(&mut config, args.auto_reinstall)?;
+
+    let metrics = Metrics::default();
+    let time = Instant::now();
+    let json_output = json_output(args);
+    let result = _exec(
+        &config,
+        args,
+        command,
+        &toolchain,
+        &metrics,
+        tasks::interrupt(),
+    );
+
+    if let Some(path) = &args.metrics {
+        info!("writing metrics to: {}", path.display());
+        if let Err(error) = metrics.write_prometheus(path, time.elapsed(), result.is_ok()) {
+            // the error of a failed build is returned instead
+            if result.is_ok() {
+                return Err(error);
+            }
+            warn!("could not write metrics: {:#}", error);
+        }
+    }
+
+    let notify_command = args
+        .notify_command
+        .as_ref()
+        .or(config.notify_command.as_ref());
+    let notify_url = args.notify_url.as_ref().or(config.notify_url.as_ref());
+    if notify_command.is_some() || notify_url.is_some() {
+        let summary = metrics.summary(time.elapsed(), &result);
+        notify::notify(notify_command, notify_url, &summary);
+    }
+
+    if args.summary.is_some() {
+        let summary = metrics.markdown(time.elapsed(), &result, &config);
+        if json_output {
+            eprintln!("{}", summary);
+        } else {
+            println!("{}", summary);
+        }
+
+        // GitHub Actions job summary
+        if let Some(path) = std::env::var_os("GITHUB_STEP_SUMMARY") {
+            paths::append(Path::new(&path), summary.as_bytes())?;
+        }
+    }
+
+    let binaries = metrics
+        .binaries
+        .into_inner()
+        .expect("failed to acquire lock");
+    if args.message_format == "json" {
+        println!(
+            "{}",
+            serde_json::json!({
+                "reason": "ci-build-finished",
+                "success": result.is_ok(),
+                "binaries": binaries,
+                "duration": time.elapsed().as_secs_f64(),
+                "error": result.as_ref().err().map(|error| format!("{:#}", error)),
+                "code": result.as_ref().err().and_then(CIError::find).map(CIError::code),
+            })
+        );
+    }
+    result?;
+    Ok(binaries)
+}
 
-    let config = Config::load()?;
-    let toolchain = llvm::toolchain()?;
+/// Selects the library for the LLVM version of `rustc` and gets its toolchain, checking
+/// that the library is built for it, or reinstalling it if `auto_reinstall` is set.
+pub(crate) fn select_toolchain(
+    config: &mut Config,
+    auto_reinstall: bool,
+) -> CIResult<LlvmToolchain> {
+    let toolchain = llvm::select(config)?;
+    library::check_llvm_version(config, &toolchain, auto_reinstall)?;
+    Ok(toolchain)
+}
 
-    _exec(&config, &args, &toolchain)
+/// Returns true if stdout is kept for the JSON messages of `cargo` or the integration.
+fn json_output(args: &BuildArgs) -> bool {
+    args.message_format == "json" || Cargo::with_args(args.cargo_args.clone()).json_messages()
 }
 
-/// Core routine for `cargo-build-ci`.
-fn _exec(config: &Config, args: &BuildArgs, toolchain: &LlvmToolchain) -> CIResult<()> {
+/// Core routine for `cargo-build-ci`, building with `cargo` and running the
+/// [`pipeline`] on its output.
+#[allow(clippy::print_stdout)]
+fn _exec(
+    config: &Config,
+    args: &BuildArgs,
+    command: CargoCommand,
+    toolchain: &LlvmToolchain,
+    metrics: &Metrics,
+    token: &CancellationToken,
+) -> CIResult<()> {
     if !config.library_path.is_file() {
-        bail!(Error::LibraryNotInstalled);
+        bail!(CIError::LibraryNotInstalled);
     }
 
+    // the configuration of the project applies unless given in the arguments
+    let args = &BuildArgs {
+        skip_crates: args.skip_crates.clone().or_else(|| {
+            Some(config.skip_crates.clone()).filter(|skip_crates| !skip_crates.is_empty())
+        }),
+        debug: args.debug || config.debug,
+        ..args.clone()
+    };
+
+    let timeout = args
+        .timeout
+        .as_deref()
+        .map(util::parse_duration)
+        .transpose()?;
+
     if args.debug {
         warn!("Debugging mode is enabled");
     }
 
+    if args.interactive {
+        if config.backend == Backend::Driver {
+            bail!("the driver backend does not support `--interactive`");
+        }
+        if json_output(args) || !util::progress_enabled() {
+            bail!("`--interactive` requires a terminal");
+        }
+    }
+
+    let build_time = Instant::now();
+
     let mut cargo = Cargo::with_args(args.cargo_args.clone());
-    cargo.build()?;
+    cargo.command = command;
+    cargo.features = Features {
+        features: args.features.clone(),
+        all_features: args.all_features,
+        no_default_features: args.no_default_features,
+    };
+    cargo.jobs = args.jobs;
+    cargo.profile = args.profile.clone();
+    cargo.bitcode = args.bitcode;
+    cargo.only_crates = args.only_crates.clone().unwrap_or_default();
+    cargo.select(&args.packages, &args.binaries, args.bins)?;
+    if let Some(target) = cargo.target() {
+        if !Target::parse(&target).is_supported() {
+            bail!(CIError::TargetNotSupported(target));
+        }
+    }
+    if cfg!(not(unix)) && args.events.is_some() {
+        bail!(CIError::HostNotSupported(
+            "--events".to_string(),
+            "Unix domain sockets".to_string()
+        ));
+    }
+    if args.cross {
+        cargo.set_cross(config)?;
+    }
+    cargo.set_backend(config)?;
+    // every crate emits LLVM IR to be chosen with `--interactive`
+    let skip_crates = match &args.skip_crates {
+        Some(skip_crates) if !args.interactive => skip_crates.as_slice(),
+        _ => &[],
+    };
+    let result = cargo.build(skip_crates);
+    // `cargo` also fails when interrupted
+    token.check()?;
+    result?;
+    metrics.record(Phase::Cargo, build_time.elapsed());
+
+    // the target may also be set in the configuration of `cargo`
+    if let Some(target) = cargo.unsupported_target() {
+        bail!(CIError::TargetNotSupported(target));
+    }
 
-    let time = std::time::Instant::now();
+    // the binaries are already integrated by `rustc`
+    if config.backend == Backend::Driver {
+        if args.dry_run {
+            bail!("the driver backend does not support `--dry-run`");
+        }
+        if args.bitcode {
+            bail!("the driver backend does not support `--bitcode`");
+        }
+        if args.verify {
+            bail!("the driver backend does not support `--verify`");
+        }
+        if args.probe_map || args.perf_probes || args.diff_probes || reports(args, "probes") {
+            warn!("probe analysis is not supported by the driver backend");
+        }
+        if emits(args, "ir-diff") {
+            warn!("IR diffs are not supported by the driver backend");
+        }
+
+        let binaries = driver::link_binaries(config, &cargo, metrics)?;
+
+        let status = format!(
+            "{:>12} integrated {} binaries in {}",
+            "Finished".green().bold(),
+            binaries.len(),
+            util::human_duration(build_time.elapsed())
+        );
+        if cargo.json_messages() {
+            emit_artifacts(&cargo.artifacts, &binaries)?;
+        }
+        if json_output(args) {
+            eprintln!("{}", status);
+        } else {
+            println!("{}", status);
+        }
+        return Ok(());
+    }
 
-    let target_dir = cargo.target_dir;
+    let time = Instant::now();
 
-    let llvm_predicate = |path: &PathBuf| -> bool {
-        let file_stem = path.file_stem().unwrap_or_default();
-        let extension = path.extension().unwrap_or_default();
-        file_stem.contains("rcgu") && !file_stem.contains("-ci") && extension == "ll"
+    let build = cargo.build_output(config, args.llc_opt_level.as_ref())?;
+    let target_dir = build.target_dir.clone();
+
+    // the crates to skip are chosen among the crates built
+    let args = &if args.interactive {
+        BuildArgs {
+            skip_crates: Some(select_crates(args, &build.llvm_ir_files)?),
+            ..args.clone()
+        }
+    } else {
+        args.clone()
     };
 
-    // *.rcgu.ll are intermediate files generated by `rustc -C save-temps`
-    let mut llvm_ir_files = target_dir.join("deps").read_dir(llvm_predicate)?;
-    llvm_ir_files.append(&mut target_dir.join("examples").read_dir(llvm_predicate)?);
+    let mut options = IntegrationOptions {
+        skip_crates: args.skip_crates.clone().unwrap_or_default(),
+        only_crates: args.only_crates.clone().unwrap_or_default(),
+        min_instructions: args.min_instructions,
+        bitcode: args.bitcode,
+        verify: args.verify,
+        probe_map: args.probe_map,
+        perf_probes: args.perf_probes,
+        probe_report: args.diff_probes || reports(args, "probes"),
+        diffs_dir: None,
+        no_cache: args.no_cache,
+        keep_going: args.keep_going,
+        jobs: args.jobs,
+        timeout,
+        memory_budget: args
+            .memory_budget
+            .as_deref()
+            .map(config::parse_size)
+            .transpose()?,
+    };
 
-    // parse cargo build output to get the linker invocation
-    let linkers = cargo.linkers;
+    if args.dry_run {
+        return dry_run(config, args, toolchain, &options, &build);
+    }
 
     // total length of the process bar
-    let length = llvm_ir_files.len() * 2 + linkers.len() + 1;
-
-    let llvm_ir_iter = Arc::new(Mutex::new(llvm_ir_files.into_iter()));
-    let linker_iter = Arc::new(Mutex::new(linkers.into_iter()));
-
-    thread::scope(move |s| -> CIResult<()> {
-        let timestamp = chrono::Local::now().format("%y%m%dT%H%M%S").to_string();
-        let mut path = Config::dir()?;
-        path.push(format!("CI-{}.log", timestamp));
-
-        let verify = |results: Vec<CIResult<()>>| -> CIResult<()> {
-            let mut ok = true;
-            for result in results {
-                if let Err(error) = result {
-                    paths::append(&path, error.to_string().as_ref())?;
-                    ok = false;
-                }
+    let length = build.tasks();
+
+    // the progress bar is the first observer to render before the others
+    let mut progress = ProgressObserver::new(length as u64, &args.log_level)?;
+    for member in &cargo.members {
+        progress.members.extend(
+            member
+                .targets
+                .iter()
+                .map(|target| (target.clone(), member.name.clone())),
+        );
+    }
+    {
+        let mut state = progress.state.lock().expect("failed to acquire lock");
+        for linker in &build.linkers {
+            if let Some(member) = cargo.member_of(&linker.args.output_file) {
+                *state.binaries.entry(member.name.clone()).or_default() += 1;
             }
+        }
+    }
+    let mut observers: Vec<Box<dyn IntegrationObserver>> = vec![Box::new(progress)];
+    #[cfg(unix)]
+    if let Some(path) = &args.events {
+        info!("streaming events to: {}", path.display());
+        let stream = UnixStream::connect(path).with_context(|| {
+            format!("failed to connect to the event socket `{}`", path.display())
+        })?;
+        observers.push(Box::new(EventObserver {
+            stream: Mutex::new(Some(stream)),
+        }));
+    }
+    if args.message_format == "json" {
+        observers.push(Box::new(JsonObserver::default()));
+    }
 
-            if !ok {
-                bail!(format!(
-                    "Consider filing an issue report on \
-                        https://github.com/bitslab/CompilerInterrupts \
-                        with the LLVM IR file and log attached.\n\
-                        Path to the log: {}",
-                    path.display(),
-                ));
-            }
+    // the diffs of a previous build are replaced, as the cache is bypassed
+    options.diffs_dir = if emits(args, "ir-diff") {
+        let dir = cargo
+            .configured_target_dir()?
+            .join("ci-reports")
+            .join("diffs");
+        if dir.exists() {
+            paths::remove_dir_all(&dir)?;
+        }
+        paths::create_dir_all(&dir)?;
+        Some(dir)
+    } else {
+        None
+    };
 
-            Ok(())
-        };
+    // the temporary files are looked up next to the LLVM IR files once linked
+    let ir_files = build.llvm_ir_files.clone();
+
+    let symbols = symbols::load(target_dir.join("ci-symbols.json"));
+    let report = pipeline::run(
+        config, toolchain, &options, build, &symbols, metrics, &observers, token,
+    )?;
+    symbols::save(&symbols)?;
+
+    let temps = pipeline::temporary_files(&ir_files)?;
+    let temps_size = temps
+        .iter()
+        .filter_map(|file| file.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum::<u64>();
+    if !args.keep_temps {
+        for file in &temps {
+            paths::remove_file(file)?;
+        }
+    }
 
-        // communication between the progress bar thread and other threads
-        let (tx, rx) = mpsc::channel::<IntegrationContext>();
+    let status = format!(
+        "{:>12} integrated {} target(s) in {}",
+        "Finished".green().bold(),
+        length,
+        util::human_duration(time.elapsed())
+    );
 
-        // number of threads based on number of logical cores in CPU
-        let num_cpus = num_cpus::get();
+    let binaries = metrics
+        .binaries
+        .lock()
+        .expect("failed to acquire lock")
+        .clone();
+    if cargo.json_messages() {
+        emit_artifacts(&cargo.artifacts, &binaries)?;
+    }
+    // keep stdout as a stream of JSON messages
+    if json_output(args) {
+        eprintln!("{}", status);
+    } else {
+        println!("{}", status);
+    }
 
-        // progress bar rendering
-        let pb_thread =
-            s.spawn(move |_| -> CIResult<()> { progress_bar(rx, length as u64, &args.log_level) });
+    if !temps.is_empty() {
+        let (status, hint) = if args.keep_temps {
+            ("Kept", " (`--keep-temps`)")
+        } else {
+            ("Removed", "")
+        };
+        eprintln!(
+            "{:>12} {} temporary file(s), {}{}",
+            status.cyan().bold(),
+            temps.len(),
+            util::human_size(temps_size),
+            hint
+        );
+    }
 
-        // integration
-        let mut threads = Vec::new();
-        for _ in 0..num_cpus {
-            let tx = tx.clone();
-            let files = Arc::clone(&llvm_ir_iter);
-            let thread =
-                s.spawn(move |_| -> CIResult<()> { integrate(config, args, toolchain, tx, files) });
-            threads.push(thread);
-        }
+    if args.verify {
+        eprintln!(
+            "{:>12} {} binary(ies) with probes",
+            "Verified".cyan().bold(),
+            binaries.len()
+        );
+    }
 
-        let mut results = Vec::new();
-        for thread in threads {
-            let result = thread.join().expect("integration thread panicked");
-            results.push(result);
+    // the slowest crates, the full breakdown being in the timing report
+    if !metrics.timings().is_empty() {
+        for line in metrics.timings_table(Some(TIMINGS_ROWS)).lines() {
+            eprintln!("{:>12} {}", "Timings".cyan().bold(), line);
         }
-        verify(results)?;
+    }
 
-        // linking
-        let mut threads = Vec::new();
-        for _ in 0..num_cpus {
-            let tx = tx.clone();
-            let linkers = Arc::clone(&linker_iter);
-            let thread = s.spawn(move |_| -> CIResult<()> { link(toolchain, tx, linkers) });
-            threads.push(thread);
+    if args.diff_probes {
+        let path = target_dir.join("ci-probes.json");
+        if let Some(previous) = Report::load(&path)? {
+            let changes = report.diff(&previous);
+            if changes.is_empty() {
+                eprintln!(
+                    "{:>12} no changes since the last build",
+                    "Probes".cyan().bold()
+                );
+            }
+            for change in changes {
+                eprintln!("{:>12} {}", "Probes".cyan().bold(), change);
+            }
         }
+        report.save(&path)?;
+    }
 
-        let mut results = Vec::new();
-        for thread in threads {
-            let result = thread.join().expect("linking thread panicked");
-            results.push(result);
+    let reports_dir = cargo.configured_target_dir()?.join("ci-reports");
+    if reports(args, "probes") {
+        paths::create_dir_all(&reports_dir)?;
+
+        let path = reports_dir.join("probes.txt");
+        paths::write(&path, report.table(&config.library_args))
+            .context("failed to write the probe report")?;
+        eprintln!("{:>12} {}", "Report".cyan().bold(), path.display());
+
+        if args.report_json {
+            let path = reports_dir.join("probes.json");
+            let json = serde_json::to_string_pretty(&report.json(&config.library_args))
+                .context("failed to serialize the probe report")?;
+            paths::write(&path, json).context("failed to write the probe report")?;
+            eprintln!("{:>12} {}", "Report".cyan().bold(), path.display());
         }
-        verify(results)?;
+    }
 
-        drop(tx);
+    if reports(args, "timings") {
+        paths::create_dir_all(&reports_dir)?;
 
-        pb_thread
-            .join()
-            .expect("progress bar thread panicked")
-            .context("progress bar failed")?;
+        let path = reports_dir.join("timings.txt");
+        paths::write(&path, metrics.timings_table(None))
+            .context("failed to write the timing report")?;
+        eprintln!("{:>12} {}", "Report".cyan().bold(), path.display());
 
-        Ok(())
-    })
-    .expect("main scoped thread panicked")?;
+        if args.report_json {
+            let path = reports_dir.join("timings.json");
+            let json = serde_json::to_string_pretty(&metrics.timings_json())
+                .context("failed to serialize the timing report")?;
+            paths::write(&path, json).context("failed to write the timing report")?;
+            eprintln!("{:>12} {}", "Report".cyan().bold(), path.display());
+        }
+    }
 
-    println!(
-        "{:>12} integrated {} target(s) in {}",
-        "Finished".green().bold(),
-        length,
-        util::human_duration(time.elapsed())
-    );
+    if let Some(dir) = &options.diffs_dir {
+        let path = ir_diff::write_summary(dir)?;
+        eprintln!("{:>12} {}", "IR diffs".cyan().bold(), dir.display());
+        eprintln!("{:>12} {}", "Report".cyan().bold(), path.display());
+    }
+
+    if args.perf_probes {
+        for binary in &binaries {
+            eprintln!(
+                "{:>12} {}",
+                "Perf probes".cyan().bold(),
+                perf::probes_path(binary).display()
+            );
+            eprintln!(
+                "{:>12} {}",
+                "Record".cyan().bold(),
+                perf::record_command(binary)
+            );
+            eprintln!(
+                "{:>12} {}",
+                "Probe".cyan().bold(),
+                perf::probe_command(binary, "<SYMBOL>")
+            );
+        }
+    }
 
     Ok(())
 }
 
-/// Handle the progress bar rendering.
-fn progress_bar(rx: Receiver<IntegrationContext>, len: u64, log_level: &String) -> CIResult<()> {
-    let log_level = Level::from_str(&log_level)?;
-    // progress bar
-    let pb = if log_level <= Level::WARN {
-        ProgressBar::new(len)
-    } else {
-        ProgressBar::hidden()
-    };
-    pb.set_prefix("Building");
+/// Running jobs of the progress bar.
+#[derive(Default, Debug)]
+struct ProgressState {
+    /// Names of the running jobs.
+    names: Vec<String>,
+    /// Member of the workspace -> number of its binaries left to link.
+    binaries: HashMap<String, usize>,
+    /// Whether an error occurred, halting the progress bar.
+    error: bool,
+}
+
+/// Progress bar rendering of the integration.
+#[derive(Debug)]
+struct ProgressObserver {
+    /// Progress bar.
+    pb: ProgressBar,
+    /// Log level.
+    log_level: Level,
+    /// Whether stderr is a terminal.
+    attended: bool,
+    /// Running jobs.
+    state: Mutex<ProgressState>,
+    /// Crate name of the targets of the workspace -> name of their member.
+    members: HashMap<String, String>,
+}
+
+impl ProgressObserver {
+    /// Creates the progress bar of the length.
+    fn new(len: u64, log_level: &str) -> CIResult<Self> {
+        let log_level = Level::from_str(log_level)?;
+        // progress bar is not rendered if stderr is not a terminal
+        let attended = util::progress_enabled();
+        let pb = if log_level <= Level::WARN && attended {
+            ProgressBar::new(len)
+        } else {
+            ProgressBar::hidden()
+        };
+        pb.set_prefix("Building");
+
+        Ok(Self {
+            pb,
+            log_level,
+            attended,
+            state: Mutex::new(ProgressState::default()),
+            members: HashMap::new(),
+        })
+    }
 
-    let mut names: Vec<String> = Vec::new();
-    let mut error = false;
+    /// Gets the member of the workspace of the crate, e.g. of `libdemo` of a C dynamic
+    /// library.
+    fn member(&self, crate_name: &str) -> Option<&String> {
+        self.members.get(crate_name).or_else(|| {
+            crate_name
+                .strip_prefix("lib")
+                .and_then(|name| self.members.get(name))
+        })
+    }
 
-    while let Ok(integration) = rx.recv() {
-        if error {
-            // halt updating status until rx closed
-            continue;
+    /// Gets the name of the crate with its member of the workspace, if any.
+    fn crate_label(&self, crate_name: &str) -> String {
+        match self.member(crate_name) {
+            Some(member) => format!("{} ({})", crate_name, member),
+            None => crate_name.to_string(),
         }
+    }
 
-        let name = integration.crate_name;
-        let status_line =
-            |status: &str| -> String { format!("{:>12} {}", status.green().bold(), name) };
-        let mut remove = |name: &String| -> CIResult<()> {
-            let idx = names
-                .iter()
-                .position(|e| e == name)
-                .context("failed to find crate name to be removed")?;
-            names.remove(idx);
-            Ok(())
-        };
-        let llc_name = format!("{}(llc)", name);
-        let ld_name = format!("{}(bin)", name);
-
-        use Stage::*;
-        use State::*;
-        match integration.stage {
-            Integrating(state) => match state {
-                Started => {
-                    pb.println(status_line("Integrating"));
-                    pb.inc(1);
-                    names.insert(0, name.to_string());
-                }
-                Finished => remove(&name)?,
-            },
-            StaticCompiling(state) => match state {
-                Started => {
-                    pb.inc(1);
-                    names.insert(0, llc_name);
-                }
-                Finished => remove(&llc_name)?,
-            },
-            Linking(state) => match state {
-                Started => {
-                    pb.println(status_line("Linking"));
-                    pb.inc(1);
-                    names.insert(0, ld_name);
-                }
-                Finished => remove(&ld_name)?,
-            },
-            Skipped => {
-                // redundant to print `compiler_interrupts` status as it is always skipped
-                if *name != "compiler_interrupts" {
-                    pb.println(status_line("Skipped"));
-                }
-                pb.inc(1);
-            }
-            Error(_) => {
-                pb.finish_and_clear();
-                println!(
-                    "{:>12} Compiler Interrupts integration has unexpectedly failed",
-                    "Error".red().bold(),
-                );
-                println!(
-                    "{:>12} Waiting for other jobs to finish",
-                    "Warning".yellow().bold()
-                );
+    /// Prints the status line of the crate above the progress bar.
+    fn status(&self, status: &str, crate_name: &str) {
+        let line = format!("{:>12} {}", status.green().bold(), crate_name);
+        if self.attended {
+            self.pb.println(line);
+        } else if self.log_level <= Level::WARN {
+            eprintln!("{}", line);
+        }
+    }
 
-                // we must not prematurely close the channel
-                // channel must live until all threads are done sending signals
-                error = true;
-                continue;
-            }
+    /// Gets the name of the job of the crate in the phase.
+    fn job_name(crate_name: &str, phase: IntegrationPhase) -> String {
+        match phase {
+            IntegrationPhase::Integrating => crate_name.to_string(),
+            IntegrationPhase::StaticCompiling => format!("{}(llc)", crate_name),
+            IntegrationPhase::Linking => format!("{}(bin)", crate_name),
         }
+    }
 
-        // progress bar message
+    /// Updates the message of the progress bar with the running jobs.
+    fn update(&self, names: &[String]) {
         let term_size = terminal_size::terminal_size()
             .map(|(w, h)| (w.0.into(), h.0.into()))
             .unwrap_or((80, 24));
@@ -322,305 +676,371 @@ fn progress_bar(rx: Receiver<IntegrationContext>, len: u64, log_level: &String)
                 break;
             }
         }
-        pb.set_style(ProgressStyle::with_template(template)?.progress_chars("=> "));
-        pb.set_message(message);
+        let style = ProgressStyle::with_template(template).expect("invalid progress bar template");
+        self.pb.set_style(style.progress_chars("=> "));
+        self.pb.set_message(message);
     }
-
-    if !error {
-        pb.inc(1);
-        pb.finish_and_clear();
-    }
-
-    Ok(())
 }
 
-/// Handle the integration process.
-fn integrate(
-    config: &Config,
-    args: &BuildArgs,
-    toolchain: &LlvmToolchain,
-    tx: Sender<IntegrationContext>,
-    files: Arc<Mutex<IntoIter<PathBuf>>>,
-) -> CIResult<()> {
-    loop {
-        let file = files.lock().expect("failed to acquire lock").next();
-        if let Some(file) = file {
-            let mut integrate = true;
-            let crate_name = Arc::new(crate_name(&file)?);
-            let ci_file = file.append_suffix("ci")?;
-
-            // `nm -jU` displays defined symbol names
-            let output = LlvmUtility::NameMangling
-                .process_builder(toolchain)
-                .arg("-jU")
-                .arg(file.with_extension("o"))
-                .exec_with_output()?;
-            let stdout = String::from_utf8(output.stdout)?;
-            if stdout.contains("intvActionHook") {
-                // skip the crate that has CI symbols defined
-                integrate = false;
+impl IntegrationObserver for ProgressObserver {
+    fn on_crate_started(&self, crate_name: &str, phase: IntegrationPhase) {
+        let mut state = self.state.lock().expect("failed to acquire lock");
+        if state.error {
+            return;
+        }
+        match phase {
+            IntegrationPhase::Integrating => {
+                self.status("Integrating", &self.crate_label(crate_name));
             }
+            IntegrationPhase::StaticCompiling => {}
+            IntegrationPhase::Linking => self.status("Linking", &self.crate_label(crate_name)),
+        }
+        self.pb.inc(1);
+        state.names.insert(0, Self::job_name(crate_name, phase));
+        self.update(&state.names);
+    }
 
-            if let Some(skip_crates) = &args.skip_crates {
-                for skip_crate in skip_crates {
-                    if skip_crate.replace('-', "_").contains(&*crate_name) {
-                        // skip the given crates
-                        integrate = false;
-                        break;
-                    }
+    fn on_phase_finished(&self, crate_name: &str, phase: IntegrationPhase) {
+        let mut state = self.state.lock().expect("failed to acquire lock");
+        if state.error {
+            return;
+        }
+        let name = Self::job_name(crate_name, phase);
+        if let Some(idx) = state.names.iter().position(|e| *e == name) {
+            state.names.remove(idx);
+        }
+
+        // the member is done once all of its binaries are linked
+        if let (IntegrationPhase::Linking, Some(member)) = (phase, self.member(crate_name)) {
+            if let Some(left) = state.binaries.get_mut(member) {
+                *left = left.saturating_sub(1);
+                if *left == 0 {
+                    state.binaries.remove(member);
+                    self.status("Integrated", member);
                 }
             }
+        }
+        self.update(&state.names);
+    }
 
-            if integrate {
-                info!("integrating: {}", file.display());
-                tx.send(IntegrationContext {
-                    crate_name: Arc::clone(&crate_name),
-                    stage: Stage::Integrating(State::Started),
-                })?;
-
-                // `opt` runs the integration
-                let mut opt = LlvmUtility::Optimizer.process_builder(toolchain);
-                opt.args(&[
-                    "-S",
-                    "--enable-new-pm=0",
-                    "--load",
-                    &config.library_path.to_string()?,
-                    "--logicalclock",
-                ]);
-                opt.args(&DEFAULT_OPT_PASSES);
-                opt.args(&config.library_args);
-                opt.arg(&file);
-                opt.arg("-o");
-                opt.arg(&ci_file);
-                // debug!("opt: opt {:#?}", opt.get_args());
-                let output = opt.exec_with_output();
-                handle_output(&tx, output, &ci_file)?;
-
-                tx.send(IntegrationContext {
-                    crate_name: Arc::clone(&crate_name),
-                    stage: Stage::Integrating(State::Finished),
-                })?;
-            } else {
-                info!("integration skipped: {}", file.display());
-                tx.send(IntegrationContext {
-                    crate_name: Arc::clone(&crate_name),
-                    stage: Stage::Skipped,
-                })?;
-                paths::copy(&file, &ci_file)?;
-            }
+    fn on_skipped(&self, crate_name: &str) {
+        let state = self.state.lock().expect("failed to acquire lock");
+        if state.error {
+            return;
+        }
+        // redundant to print `compiler_interrupts` status as it is always skipped
+        if crate_name != "compiler_interrupts" {
+            self.status("Skipped", &self.crate_label(crate_name));
+        }
+        self.pb.inc(1);
+        self.update(&state.names);
+    }
 
-            // `llc` transforms integrated IR bitcode to object file
-            debug!("run llc on: {}", ci_file.display());
-            tx.send(IntegrationContext {
-                crate_name: Arc::clone(&crate_name),
-                stage: Stage::StaticCompiling(State::Started),
-            })?;
+    fn on_cached(&self, crate_name: &str) {
+        let state = self.state.lock().expect("failed to acquire lock");
+        if state.error {
+            return;
+        }
+        self.status("Fresh", &self.crate_label(crate_name));
+        // both integration and static compiling are done
+        self.pb.inc(2);
+        self.update(&state.names);
+    }
 
-            let mut llc = LlvmUtility::StaticCompiler.process_builder(toolchain);
-            llc.arg("-filetype=obj");
-            llc.arg(&ci_file);
+    fn on_error(&self, _crate_name: &str, _message: &str) {
+        let mut state = self.state.lock().expect("failed to acquire lock");
+        if state.error {
+            return;
+        }
+        self.pb.finish_and_clear();
+        eprintln!(
+            "{:>12} Compiler Interrupts integration has unexpectedly failed",
+            "Error".red().bold(),
+        );
+        eprintln!(
+            "{:>12} Waiting for other jobs to finish",
+            "Warning".yellow().bold()
+        );
+        state.error = true;
+    }
 
-            // fixes mismatch relocation symbols on linux
-            if cfg!(target_os = "linux") {
-                llc.arg("-code-model=large");
-            }
+    fn on_finished(&self) {
+        let state = self.state.lock().expect("failed to acquire lock");
+        if !state.error {
+            self.pb.inc(1);
+            self.pb.finish_and_clear();
+        }
+    }
+}
 
-            let output = llc.exec_with_output();
-            handle_output(&tx, output, &ci_file)?;
+/// Streaming of the integration events as JSON lines to a Unix domain socket.
+#[cfg(unix)]
+#[derive(Debug)]
+struct EventObserver {
+    /// Connected socket, closed on the first failed write.
+    stream: Mutex<Option<UnixStream>>,
+}
 
-            tx.send(IntegrationContext {
-                crate_name: Arc::clone(&crate_name),
-                stage: Stage::StaticCompiling(State::Finished),
-            })?;
-        } else {
-            break;
+#[cfg(unix)]
+impl EventObserver {
+    /// Writes the event of the crate to the socket.
+    fn send(&self, crate_name: &str, stage: &str, state: Option<&str>, message: Option<&str>) {
+        let mut stream = self.stream.lock().expect("failed to acquire lock");
+        if let Some(socket) = stream.as_mut() {
+            let event = serde_json::json!({
+                "crate": crate_name,
+                "stage": stage,
+                "state": state,
+                "message": message,
+                "timestamp": chrono::Local::now().to_rfc3339(),
+            });
+            if let Err(error) = writeln!(socket, "{}", event) {
+                warn!("event stream closed: {}", error);
+                *stream = None;
+            }
         }
     }
+}
 
-    Ok(())
+#[cfg(unix)]
+impl IntegrationObserver for EventObserver {
+    fn on_crate_started(&self, crate_name: &str, phase: IntegrationPhase) {
+        self.send(crate_name, phase.as_str(), Some("started"), None);
+    }
+
+    fn on_phase_finished(&self, crate_name: &str, phase: IntegrationPhase) {
+        self.send(crate_name, phase.as_str(), Some("finished"), None);
+    }
+
+    fn on_skipped(&self, crate_name: &str) {
+        self.send(crate_name, "skipped", None, None);
+    }
+
+    fn on_cached(&self, crate_name: &str) {
+        self.send(crate_name, "cached", None, None);
+    }
+
+    fn on_warning(&self, crate_name: &str, message: &str) {
+        self.send(crate_name, "warning", None, Some(message));
+    }
+
+    fn on_error(&self, crate_name: &str, message: &str) {
+        self.send(crate_name, "error", None, Some(message));
+    }
 }
 
-/// Handle the linking process.
-fn link(
-    toolchain: &LlvmToolchain,
-    tx: Sender<IntegrationContext>,
-    linkers: Arc<Mutex<IntoIter<Linker>>>,
-) -> CIResult<()> {
-    loop {
-        let linker = linkers.lock().expect("failed to acquire lock").next();
-        if let Some(mut linker) = linker {
-            if linker
-                .args
-                .input_files
-                .iter()
-                .any(|e| e.contains("build_script_build"))
-            {
-                debug!("linking skipped: {}", linker.args.output_file);
-                continue;
-            }
+/// Printing of the integration events as JSON lines to stdout, like the JSON messages
+/// of `cargo`.
+#[derive(Default, Debug)]
+struct JsonObserver {
+    /// Start times of the running phases of the crates.
+    started: Mutex<HashMap<(String, IntegrationPhase), Instant>>,
+}
 
-            let output_file = linker.args.output_file.clone();
-            let _crate_name = crate_name(&output_file)?;
-            let crate_name = Arc::new(_crate_name.clone());
-            info!("linking: {}", crate_name);
+impl JsonObserver {
+    /// Prints the message with the reason and the crate.
+    #[allow(clippy::print_stdout)]
+    fn emit(&self, reason: &str, crate_name: &str, mut fields: serde_json::Value) {
+        fields["reason"] = reason.into();
+        fields["crate"] = crate_name.into();
+        println!("{}", fields);
+    }
+}
 
-            tx.send(IntegrationContext {
-                crate_name: Arc::clone(&crate_name),
-                stage: Stage::Linking(State::Started),
-            })?;
+impl IntegrationObserver for JsonObserver {
+    fn on_crate_started(&self, crate_name: &str, phase: IntegrationPhase) {
+        self.started
+            .lock()
+            .expect("failed to acquire lock")
+            .insert((crate_name.to_string(), phase), Instant::now());
+        let fields = serde_json::json!({ "phase": phase.as_str() });
+        self.emit("ci-phase-started", crate_name, fields);
+    }
 
-            for file in &mut linker.args.input_files {
-                if !file.contains("deps") {
-                    continue;
-                }
+    fn on_phase_finished(&self, crate_name: &str, phase: IntegrationPhase) {
+        let started = self
+            .started
+            .lock()
+            .expect("failed to acquire lock")
+            .remove(&(crate_name.to_string(), phase));
+        let fields = serde_json::json!({
+            "phase": phase.as_str(),
+            "duration": started.map(|started| started.elapsed().as_secs_f64()),
+        });
+        self.emit("ci-phase-finished", crate_name, fields);
+    }
 
-                let output = LlvmUtility::NameMangling
-                    .process_builder(toolchain)
-                    .arg("-jU")
-                    .arg(&file)
-                    .exec_with_output()?;
-                let stdout = String::from_utf8(output.stdout)?;
-                if stdout.contains("__rust_alloc") {
-                    // skip the object file contains the symbol for memory allocator
-                    debug!("found allocator shim: {}", file);
-                } else {
-                    *file = file.append_suffix("ci")?.to_string()?;
-                }
-            }
+    fn on_skipped(&self, crate_name: &str) {
+        self.emit("ci-crate-skipped", crate_name, serde_json::json!({}));
+    }
 
-            // make a copy and replace *.o with *-ci.o in the rlib files
-            for file in &mut linker.args.rlib_files {
-                if !file.contains("deps") {
-                    continue;
-                }
+    fn on_cached(&self, crate_name: &str) {
+        self.emit("ci-crate-cached", crate_name, serde_json::json!({}));
+    }
 
-                debug!("original rlib: {}", file);
-                let ci_file = file.append_suffix("ci")?;
-                paths::copy(&file, &ci_file)?;
-
-                debug!("replacing object file for rlib: {}", ci_file.display());
-                // list all object files inside rlib
-                let output = LlvmUtility::Archiver
-                    .process_builder(toolchain)
-                    .arg("-t")
-                    .arg(&ci_file)
-                    .exec_with_output()?;
-                let stdout = String::from_utf8(output.stdout)?;
-                if let Some(rcgu_obj_file_name) = stdout
-                    .lines()
-                    .find(|e| e.contains("rcgu") && !e.contains("-ci"))
+    fn on_warning(&self, crate_name: &str, message: &str) {
+        let fields = serde_json::json!({ "message": message });
+        self.emit("ci-warning", crate_name, fields);
+    }
+
+    fn on_error(&self, crate_name: &str, message: &str) {
+        let fields = serde_json::json!({ "message": message });
+        self.emit("ci-error", crate_name, fields);
+    }
+}
+
+/// Prints the LLVM IR files to integrate or skip, and the `opt`, `llc`, and linker
+/// commands of the integration, without running them.
+#[allow(clippy::print_stdout)]
+fn dry_run(
+    config: &Config,
+    args: &BuildArgs,
+    toolchain: &LlvmToolchain,
+    options: &IntegrationOptions,
+    build: &BuildOutput,
+) -> CIResult<()> {
+    // keep stdout as a stream of JSON messages
+    let print = |status: &str, message: &dyn std::fmt::Display| {
+        let line = format!("{:>12} {}", status.cyan().bold(), message);
+        if json_output(args) {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    };
+
+    let symbols = symbols::load(build.target_dir.join("ci-symbols.json"));
+    let pass_manager = config.pass_manager(toolchain);
+    for file in &build.llvm_ir_files {
+        let crate_name = pipeline::crate_name(file)?;
+        let ci_file = file.append_suffix("ci")?;
+        let plugins = pipeline::pass_plugins(config, &crate_name);
+        match pipeline::skip_reason(options, toolchain, &symbols, &plugins, &crate_name, file)? {
+            Some(reason) => {
+                print(
+                    "Skip",
+                    &format!("{} ({}): {}", crate_name, reason, file.display()),
+                );
+            }
+            None => {
+                print("Integrate", &format!("{}: {}", crate_name, file.display()));
+                for opt in
+                    pipeline::opt_commands(toolchain, pass_manager, &plugins, file, &ci_file)?
                 {
-                    let rcgu_obj_file = ci_file.parent()?.join(rcgu_obj_file_name);
-                    let rcgu_obj_ci_file = rcgu_obj_file.append_suffix("ci")?;
-
-                    // replace *.o with *-ci.o
-                    LlvmUtility::Archiver
-                        .process_builder(toolchain)
-                        .arg("-rb")
-                        .arg(&rcgu_obj_file)
-                        .arg(&ci_file)
-                        .arg(&rcgu_obj_ci_file)
-                        .exec_with_output()?;
-
-                    // delete old *.o
-                    LlvmUtility::Archiver
-                        .process_builder(toolchain)
-                        .arg("-d")
-                        .arg(&ci_file)
-                        .arg(&rcgu_obj_file)
-                        .exec_with_output()?;
+                    print("Running", &opt);
                 }
-
-                *file = ci_file.to_string()?;
             }
+        }
+        let mut llc = LlvmUtility::StaticCompiler.process_builder(toolchain);
+        llc.args(&build.llc_flags);
+        llc.arg(&ci_file);
+        print("Running", &llc);
+    }
 
-            let output_ci_file = output_file.append_suffix("ci")?.to_string()?;
-            linker.args.output_file = output_ci_file.clone();
-
-            // execute the linker
-            debug!("linker: {:#?}", linker);
-            let mut builder = ProcessBuilder::new(&linker.program);
-            builder.args(&linker.args.build());
-            let output = builder.exec_with_output();
-            handle_output(&tx, output, &output_ci_file)?;
-
-            // hard link the CI-integrated binary file to the parent directory
-            let link_file = output_file
-                .parent()?
-                .parent()?
-                .join(_crate_name.append_suffix("ci")?);
-            debug!(?output_file);
-            debug!(?link_file);
-            paths::link_or_copy(&output_file, &link_file)?;
-
-            tx.send(IntegrationContext {
-                crate_name: Arc::clone(&crate_name),
-                stage: Stage::Linking(State::Finished),
-            })?;
-        } else {
-            break;
+    // the object files of the LLVM IR files and the rlibs are replaced by the
+    // CI-integrated ones
+    let ir_files = build.llvm_ir_files.iter().collect::<HashSet<_>>();
+    let llvm_ir_extension = wrapper::llvm_ir_extension(options.bitcode);
+    for linker in &build.linkers {
+        let mut linker = linker.clone();
+        print("Link", &pipeline::crate_name(&linker.args.output_file)?);
+        for file in &mut linker.args.input_files {
+            if ir_files.contains(&Path::new(file).with_extension(llvm_ir_extension)) {
+                *file = file.append_suffix("ci")?.to_string()?;
+            }
+        }
+        for file in &mut linker.args.rlib_files {
+            if file.contains("deps") {
+                *file = file.append_suffix("ci")?.to_string()?;
+            }
         }
+        linker.args.output_file = linker.args.output_file.append_suffix("ci")?.to_string()?;
+        print(
+            "Running",
+            &pipeline::linker_command(build.cross.as_ref(), linker),
+        );
+    }
+
+    for (path, hardlink) in &build.static_libraries {
+        print(
+            "Archive",
+            &format!(
+                "{}: {}",
+                pipeline::crate_name(path)?,
+                hardlink.append_suffix("ci")?.display()
+            ),
+        );
     }
 
     Ok(())
 }
 
-/// Handle output from the process and validate output file.
-fn handle_output<P: AsRef<Path>>(
-    tx: &Sender<IntegrationContext>,
-    output: anyhow::Result<Output>,
-    output_file: P,
-) -> CIResult<()> {
-    let output_file = output_file.as_ref();
-    let crate_name = Arc::new(crate_name(output_file)?);
-    match output {
-        Ok(output) => {
-            if !output_file.is_file() {
-                // output file does not exist
-                let stderr = String::from_utf8(output.stderr.clone())?;
-
-                tx.send(IntegrationContext {
-                    crate_name: Arc::clone(&crate_name),
-                    stage: Stage::Error(String::new()),
-                })?;
-
-                bail!(
-                    "process returned success but output file does not exist\n\
-                    process: {:#?}\n\
-                    expected file: {}\n\
-                    --- stderr\n{}",
-                    output,
-                    output_file.display(),
-                    stderr
-                );
-            }
+/// Returns true if the report of the kind is requested with `--report`.
+fn reports(args: &BuildArgs, kind: &str) -> bool {
+    args.report.iter().any(|report| report == kind)
+}
+
+/// Returns true if the output of the kind is requested with `--emit`.
+fn emits(args: &BuildArgs, kind: &str) -> bool {
+    args.emit.iter().any(|emit| emit == kind)
+}
 
-            Ok(())
+/// Lets the user choose the crates to integrate, saving the other ones to skip to
+/// `Cargo.toml`, and returns them.
+#[allow(clippy::print_stdout)]
+fn select_crates(args: &BuildArgs, llvm_ir_files: &[PathBuf]) -> CIResult<Vec<String>> {
+    let skip_crates = args.skip_crates.clone().unwrap_or_default();
+    let crate_names = llvm_ir_files
+        .iter()
+        .map(pipeline::crate_name)
+        .collect::<CIResult<BTreeSet<_>>>()?
+        .into_iter()
+        .collect::<Vec<_>>();
+    let defaults = crate_names
+        .iter()
+        .map(|crate_name| !util::is_skipped(&skip_crates, crate_name))
+        .collect::<Vec<_>>();
+
+    let selection = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Crates to integrate (space to toggle, enter to confirm)")
+        .items(&crate_names)
+        .defaults(&defaults)
+        .interact_on_opt(&Term::stderr())?;
+    let selection = match selection {
+        Some(selection) => selection,
+        None => {
+            // cancelled with escape, the crates to skip are unchanged
+            info!("selection of the crates cancelled");
+            return Ok(skip_crates);
         }
-        Err(err) => {
-            let proc_err = err
-                .downcast_ref::<ProcessError>()
-                .context("failed to downcast to ProcessError")?;
+    };
 
-            tx.send(IntegrationContext {
-                crate_name: Arc::clone(&crate_name),
-                stage: Stage::Error(String::new()),
-            })?;
+    let skip_crates = crate_names
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| !selection.contains(idx))
+        .map(|(_, crate_name)| crate_name.clone())
+        .collect::<Vec<_>>();
+    debug!(?skip_crates);
+    ProjectConfig::save_skip("Cargo.toml", &skip_crates)?;
+    println!(
+        "{:>12} {} crates to skip to `Cargo.toml`",
+        "Saved".green().bold(),
+        skip_crates.len()
+    );
 
-            bail!(ToString::to_string(&proc_err.desc));
+    Ok(skip_crates)
+}
+
+/// Emit `compiler-artifact` messages pointing at the CI-integrated binaries.
+#[allow(clippy::print_stdout)]
+fn emit_artifacts(artifacts: &[serde_json::Value], binaries: &[PathBuf]) -> CIResult<()> {
+    for binary in binaries {
+        match compiler_interrupts_core::cargo::ci_artifact(artifacts, binary)? {
+            Some(artifact) => println!("{}", artifact),
+            None => debug!("no artifact message found for: {}", binary.display()),
         }
     }
-}
 
-/// Get the binary name from path.
-fn crate_name<P: AsRef<Path>>(path: P) -> CIResult<String> {
-    Ok(path
-        .file_stem()?
-        .split('.')
-        .next()
-        .context("invalid crate name, expected '.'")?
-        .split('-')
-        .next()
-        .context("invalid crate name, expected '-'")?
-        .to_string())
+    Ok(())
 }
@@ -1,7 +1,9 @@
 //! Implementation of `cargo-lib-ci`.
 
+// the subcommands print their output to stdout
+#![allow(clippy::print_stdout)]
+
 use std::fs;
-use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::Duration;
@@ -9,22 +11,43 @@ use std::time::Duration;
 use anyhow::{bail, Context};
 use cargo_util::{paths, ProcessBuilder};
 use clap::Parser;
+use clap_complete::Shell;
 use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
-use tracing::{debug, info, Level};
+use tracing::{debug, info, warn, Level};
 use url::Url;
 
-use crate::args::{ConfigArgs, InstallArgs, LibraryArgs, LibrarySubcommands::*};
-use crate::config::Config;
-use crate::error::Error;
-use crate::llvm::{LlvmToolchain, LlvmUtility};
+use crate::args::{
+    self, CacheArgs, CompletionsArgs, ConfigArgs, DaemonArgs, ExplainArgs, InstallArgs,
+    LibraryArgs, LibrarySubcommands::*, LogsArgs,
+};
+use crate::cache::{Cache, DEFAULT_CACHE_MAX_SIZE};
+use crate::config::{self, Backend, Config, GitSource, Library};
+use crate::doctor::{self, Outcome};
+use crate::error::{self, CIError};
+use crate::llvm::{LlvmToolchain, LlvmUtility, PassManager};
 use crate::paths::PathExt;
-use crate::{llvm, util, CIResult, LIB_CI_BIN_NAME};
+use crate::platform;
+use crate::plugin;
+use crate::tasks::TaskGroup;
+use crate::{daemon, llvm, logs, tasks, util, CIResult, LIB_CI_BIN_NAME};
 
 /// Default URL for the Compiler Interrupts source code.
 const DEFAULT_CI_URL: &str = "https://raw.githubusercontent.com/bitslab/\
     CompilerInterrupts/main/src/CompilerInterrupt.cpp";
 
+/// URL for the files of Compiler Interrupts at a tag.
+const CI_RAW_URL: &str = "https://raw.githubusercontent.com/bitslab/CompilerInterrupts";
+
+/// URL for the assets of the GitHub releases of Compiler Interrupts.
+const CI_RELEASES_URL: &str = "https://github.com/bitslab/CompilerInterrupts/releases/download";
+
+/// Default URL for the git repository of Compiler Interrupts.
+const DEFAULT_CI_GIT_URL: &str = "https://github.com/bitslab/CompilerInterrupts.git";
+
+/// Path to the source code in the git repository of Compiler Interrupts.
+const CI_GIT_SOURCE_PATH: &str = "src/CompilerInterrupt.cpp";
+
 /// Default arguments for the Compiler Interrupts library.
 const DEFAULT_CI_ARGS: [&str; 3] = ["-inst-gran=2", "-commit-intv=100", "-all-dev=100"];
 
@@ -36,22 +59,35 @@ pub fn exec() -> CIResult<()> {
         LibraryArgs::parse_from(std::env::args().skip(1))
     };
 
+    util::init_color(&args.color);
     util::init_logger(&args.log_level)?;
+    if let Some(path) = &args.log_file {
+        util::init_log_file(path.as_deref())?;
+    }
 
-    let config = Config::load()?;
-    let toolchain = llvm::toolchain()?;
+    // the configuration of the project is not saved to the global one
+    let config = Config::load_global()?;
+    util::init_config_log_file(&config)?;
 
-    _exec(config, args, toolchain)
+    _exec(config, args)
 }
 
 /// Core routine for `cargo-lib-ci`.
-fn _exec(config: Config, args: LibraryArgs, toolchain: LlvmToolchain) -> CIResult<()> {
+///
+/// The toolchain is only probed by the subcommands compiling the library.
+fn _exec(config: Config, args: LibraryArgs) -> CIResult<()> {
     if let Some(command) = &args.command {
         match command {
-            Install(install_args) => install(config, &args, install_args, &toolchain)?,
+            Install(install_args) => install(config, &args, install_args)?,
             Uninstall => uninstall(config)?,
-            Update => update(config, &args, &toolchain)?,
+            Update => update(config, &args)?,
             Config(config_args) => configure(config, config_args)?,
+            Logs(logs_args) => logs(logs_args)?,
+            Cache(cache_args) => cache(&config, cache_args)?,
+            Daemon(daemon_args) => run_daemon(daemon_args)?,
+            Explain(explain_args) => explain(explain_args)?,
+            Doctor => doctor(config)?,
+            Completions(completions_args) => completions(completions_args)?,
         }
     } else {
         print_info(&config)?;
@@ -61,20 +97,24 @@ fn _exec(config: Config, args: LibraryArgs, toolchain: LlvmToolchain) -> CIResul
 }
 
 /// Installs the Compiler Interrupts library.
-fn install(
-    mut config: Config,
-    args: &LibraryArgs,
-    install_args: &InstallArgs,
-    toolchain: &LlvmToolchain,
-) -> CIResult<()> {
-    if Path::new(&config.library_path).is_file() {
-        bail!(Error::LibraryAlreadyInstalled);
+fn install(mut config: Config, args: &LibraryArgs, install_args: &InstallArgs) -> CIResult<()> {
+    // the toolchain recorded for the library in use may be of another LLVM version
+    let dir = std::env::current_dir()?;
+    let toolchain = match install_args.llvm_version {
+        Some(major) => llvm::probe_version(&dir, &daemon::toolchain_env(), major)?,
+        None => llvm::probe(&dir, &daemon::toolchain_env(), None)?,
+    };
+    debug!(?toolchain);
+
+    // one library is kept for each LLVM major version
+    if config.select_library(toolchain.version.major) {
+        bail!(CIError::LibraryAlreadyInstalled);
     }
 
     let time = std::time::Instant::now();
 
     // progress bar
-    let pb = if Level::from_str(&args.log_level)? != Level::DEBUG {
+    let pb = if Level::from_str(&args.log_level)? != Level::DEBUG && util::progress_enabled() {
         ProgressBar::new_spinner()
     } else {
         ProgressBar::hidden()
@@ -88,13 +128,53 @@ fn install(
     pb.set_message("Fetching the source code");
 
     info!("fetching the source code");
-    let url = Url::parse(
-        &install_args
+    let group = download_group(args)?;
+    if let Some(path) = &install_args.vendored_source {
+        config.vendored_source = Some(path.clone());
+    }
+    // the prebuilt libraries of a release are built from the source code at its tag
+    let release_url = install_args.release_tag.as_ref().map(|tag| {
+        install_args
+            .release_url
+            .clone()
+            .unwrap_or_else(|| format!("{}/{}", CI_RELEASES_URL, tag))
+    });
+    let (url, src_code, git) = if args.offline {
+        if install_args.from_git || install_args.url.is_some() || release_url.is_some() {
+            bail!(
+                "`--offline` builds from the vendored source code instead of `--url`, \
+                `--from-git`, or `--release-tag`"
+            );
+        }
+        let (url, src_code) = vendored_source_code(&config)?;
+        (url, src_code, None)
+    } else if install_args.from_git {
+        let url = install_args
             .url
             .clone()
-            .unwrap_or_else(|| DEFAULT_CI_URL.to_string()),
-    )?;
-    let src_code = fetch_source_code(&url)?;
+            .unwrap_or_else(|| DEFAULT_CI_GIT_URL.to_string());
+        let git = GitSource {
+            reference: install_args
+                .branch
+                .clone()
+                .or_else(|| install_args.tag.clone()),
+            rev: install_args.rev.clone(),
+            commit: String::new(),
+        };
+        let (src_code, commit) = clone_source_code(&url, &git, &group)?;
+        (url, src_code, Some(GitSource { commit, ..git }))
+    } else {
+        let url = install_args
+            .url
+            .clone()
+            .unwrap_or_else(|| match &install_args.release_tag {
+                Some(tag) => format!("{}/{}/{}", CI_RAW_URL, tag, CI_GIT_SOURCE_PATH),
+                None => DEFAULT_CI_URL.to_string(),
+            });
+        let url = Url::parse(&url)?;
+        let src_code = fetch_source_code(&url, &group, false)?;
+        (url.to_string(), src_code, None)
+    };
 
     let src_dir = std::env::temp_dir()
         .join("CompilerInterrupt.cpp")
@@ -105,9 +185,25 @@ fn install(
     let checksum = format!("{:x}", md5::compute(&src_code));
     info!(?checksum);
 
+    pb.set_message("Verifying the source code");
+    let sha256 = verify_source_code(
+        &src_code,
+        &src_dir,
+        install_args.sha256.as_deref(),
+        install_args.signature.as_deref(),
+        &group,
+        args.offline,
+    )?;
+    let verified = install_args.sha256.is_some() || install_args.signature.is_some();
+
     info!("getting the destination library path");
     let library_path = {
-        let file_name = format!("CompilerInterrupt-{}.so", checksum);
+        let file_name = format!(
+            "CompilerInterrupt-{}-llvm{}.{}",
+            checksum,
+            toolchain.version.major,
+            platform::shared_library_extension()
+        );
         if let Some(args_path) = &install_args.path {
             // user-provided library path
             let mut path = PathBuf::from(args_path);
@@ -124,30 +220,28 @@ fn install(
     };
     info!(?library_path);
 
-    let out_dir = library_path.to_string()?;
-    let out_debug_dir = library_path.append_suffix("debug")?.to_string()?;
-
-    info!("getting the compiler config");
-    pb.set_message("Getting the compiler configuration");
-    let clang = compiler(toolchain)?;
-    // debug!("clang_args: {:?}", clang.get_args());
-
-    info!("compiling the library");
-    pb.set_message("Compiling the Compiler Interrupts library");
-    compile(clang.clone(), &src_dir, &out_dir, false, &pb)?;
-
-    info!("compiling the library with debugging mode");
-    pb.set_message("Compiling the Compiler Interrupts library with debugging mode");
-    compile(clang, &src_dir, &out_debug_dir, true, &pb)?;
+    let library = install_library(
+        release_url.as_deref(),
+        &toolchain,
+        &src_dir,
+        &library_path,
+        checksum,
+        &group,
+        &pb,
+    )?;
 
     // update config
     info!("updating configuration");
-    config.library_path = PathBuf::from(&out_dir);
-    config.library_debug_path = PathBuf::from(&out_debug_dir);
-    config.library_args = DEFAULT_CI_ARGS.iter().map(|&s| s.to_string()).collect();
-    config.llvm_version = toolchain.version.to_string();
-    config.checksum = checksum;
-    config.url = url.to_string();
+    config.add_library(library);
+    // the arguments are shared by the libraries of every LLVM version
+    if config.library_args.is_empty() {
+        config.library_args = DEFAULT_CI_ARGS.iter().map(|&s| s.to_string()).collect();
+    }
+    config.url = url;
+    config.git = git;
+    config.release_url = release_url;
+    config.sha256 = verified.then_some(sha256);
+    config.signature_url = install_args.signature.clone();
 
     Config::save(&config)?;
 
@@ -168,10 +262,15 @@ fn install(
 fn uninstall(config: Config) -> CIResult<()> {
     // remove the library
     info!("uninstalling the library");
-    if Path::new(&config.library_path).is_file() {
-        paths::remove_file(config.library_path).context("failed to uninstall the library")?;
-    } else {
-        bail!(Error::LibraryNotInstalled);
+    if !Path::new(&config.library_path).is_file() {
+        bail!(CIError::LibraryNotInstalled);
+    }
+    let mut libraries = vec![config.library()];
+    libraries.extend(config.libraries.into_values());
+    for library in libraries {
+        if library.library_path.is_file() {
+            paths::remove_file(library.library_path).context("failed to uninstall the library")?;
+        }
     }
 
     // update config
@@ -187,15 +286,18 @@ fn uninstall(config: Config) -> CIResult<()> {
 }
 
 /// Updates the Compiler Interrupts library.
-fn update(mut config: Config, args: &LibraryArgs, toolchain: &LlvmToolchain) -> CIResult<()> {
+fn update(mut config: Config, args: &LibraryArgs) -> CIResult<()> {
     if !Path::new(&config.library_path).is_file() {
-        bail!(Error::LibraryAlreadyInstalled);
+        bail!(CIError::LibraryAlreadyInstalled);
     }
 
+    // the library of the LLVM version of `rustc` is updated
+    let toolchain = llvm::select(&mut config)?;
+
     let time = std::time::Instant::now();
 
     // progress bar
-    let pb = if Level::from_str(&args.log_level)? != Level::DEBUG {
+    let pb = if Level::from_str(&args.log_level)? != Level::DEBUG && util::progress_enabled() {
         ProgressBar::new_spinner()
     } else {
         ProgressBar::hidden()
@@ -209,8 +311,29 @@ fn update(mut config: Config, args: &LibraryArgs, toolchain: &LlvmToolchain) ->
     pb.set_message("Checking for update");
 
     info!("fetching the source code");
-    let url = Url::parse(&config.url)?;
-    let src_code = fetch_source_code(&url)?;
+    let group = download_group(args)?;
+    // the git checkout follows the branch or the tag it was installed from
+    let (src_code, git) = match &config.git {
+        // the vendored source code replaces the source of the library in use
+        _ if args.offline => {
+            let (url, src_code) = vendored_source_code(&config)?;
+            config.url = url;
+            config.release_url = None;
+            (src_code, None)
+        }
+        Some(git) => {
+            let (src_code, commit) = clone_source_code(&config.url, git, &group)?;
+            let git = GitSource {
+                commit,
+                ..git.clone()
+            };
+            (src_code, Some(git))
+        }
+        None => (
+            fetch_source_code(&Url::parse(&config.url)?, &group, false)?,
+            None,
+        ),
+    };
 
     let src_dir = std::env::temp_dir()
         .join("CompilerInterrupt.cpp")
@@ -222,6 +345,11 @@ fn update(mut config: Config, args: &LibraryArgs, toolchain: &LlvmToolchain) ->
     info!(?checksum);
 
     if config.checksum == checksum {
+        // the source code may be unchanged by a newer commit
+        if config.git != git {
+            config.git = git;
+            Config::save(&config)?;
+        }
         pb.finish_and_clear();
         println!(
             "{:>12} Compiler Interrupts library is up-to-date",
@@ -230,11 +358,31 @@ fn update(mut config: Config, args: &LibraryArgs, toolchain: &LlvmToolchain) ->
         return Ok(());
     }
 
+    // the digest pins the source code unless its signature is verified
+    pb.set_message("Verifying the source code");
+    let pinned = match &config.signature_url {
+        Some(_) => None,
+        None => config.sha256.as_deref(),
+    };
+    let sha256 = verify_source_code(
+        &src_code,
+        &src_dir,
+        pinned,
+        config.signature_url.as_deref(),
+        &group,
+        args.offline,
+    )?;
+
     info!("getting the destination library path");
     let library_path = {
-        let file_name = format!("CompilerInterrupt-{}.so", checksum);
+        let file_name = format!(
+            "CompilerInterrupt-{}-llvm{}.{}",
+            checksum,
+            toolchain.version.major,
+            platform::shared_library_extension()
+        );
         if config.library_path.is_file() {
-            config.library_path
+            config.library_path.clone()
         } else {
             let mut path = Config::dir()?;
             path.push(file_name);
@@ -243,29 +391,23 @@ fn update(mut config: Config, args: &LibraryArgs, toolchain: &LlvmToolchain) ->
     };
     info!(?library_path);
 
-    let out_dir = library_path.to_string()?;
-    let out_debug_dir = library_path.append_suffix("debug")?.to_string()?;
-
-    pb.set_message("Compiling the Compiler Interrupts library");
-
-    // compile
-    info!("getting the compiler config");
-    let clang = compiler(toolchain)?;
-
-    info!("compiling the library");
-    pb.set_message("Compiling the Compiler Interrupts library");
-    compile(clang.clone(), &src_dir, &out_dir, false, &pb)?;
-
-    info!("compiling the library with debugging mode");
-    pb.set_message("Compiling the Compiler Interrupts library with debugging mode");
-    compile(clang, &src_dir, &out_debug_dir, true, &pb)?;
+    let library = install_library(
+        config.release_url.as_deref(),
+        &toolchain,
+        &src_dir,
+        &library_path,
+        checksum,
+        &group,
+        &pb,
+    )?;
 
     // update config
     info!("updating configuration");
-    config.library_path = PathBuf::from(&out_dir);
-    config.library_debug_path = PathBuf::from(&out_debug_dir);
-    config.llvm_version = toolchain.version.to_string();
-    config.checksum = checksum;
+    config.add_library(library);
+    config.git = git;
+    if config.sha256.is_some() {
+        config.sha256 = Some(sha256);
+    }
 
     Config::save(&config)?;
 
@@ -282,19 +424,276 @@ fn update(mut config: Config, args: &LibraryArgs, toolchain: &LlvmToolchain) ->
     Ok(())
 }
 
+/// Checks that the library in use is compiled for the LLVM version of `rustc`, and
+/// otherwise installs the library for it if confirmed or `auto_reinstall` is set.
+pub(crate) fn check_llvm_version(
+    config: &mut Config,
+    toolchain: &LlvmToolchain,
+    auto_reinstall: bool,
+) -> CIResult<()> {
+    let major = toolchain.version.major.to_string();
+    if !config.library_path.is_file() || config.llvm_version.split('.').next() == Some(&major) {
+        return Ok(());
+    }
+
+    let reinstall = auto_reinstall
+        || util::confirm(&format!(
+            "Compiler Interrupts library is compiled with LLVM {}, but `rustc` uses LLVM {}. \
+            Install the library for LLVM {}?",
+            config.llvm_version, toolchain.version, major
+        ))?;
+    if !reinstall {
+        bail!(CIError::LibraryVersionNotMatch(
+            config.llvm_version.clone(),
+            toolchain.version.clone()
+        ));
+    }
+
+    eprintln!(
+        "{:>12} Compiler Interrupts library for LLVM {}",
+        "Installing".green().bold(),
+        toolchain.version
+    );
+    reinstall_library(config, toolchain)
+}
+
+/// Installs the library for the toolchain from the source code of the library in use,
+/// next to it.
+fn reinstall_library(config: &mut Config, toolchain: &LlvmToolchain) -> CIResult<()> {
+    info!("fetching the source code");
+    let group = TaskGroup::new(tasks::interrupt().clone(), None);
+    let src_code = match &config.git {
+        Some(git) => {
+            // the same commit as the library in use
+            let git = GitSource {
+                reference: None,
+                rev: Some(git.commit.clone()),
+                commit: String::new(),
+            };
+            clone_source_code(&config.url, &git, &group)?.0
+        }
+        None => fetch_source_code(&Url::parse(&config.url)?, &group, false)?,
+    };
+
+    let src_dir = std::env::temp_dir()
+        .join("CompilerInterrupt.cpp")
+        .to_string()?;
+    paths::write(&src_dir, &src_code).context("failed to save the library")?;
+    let checksum = format!("{:x}", md5::compute(&src_code));
+    verify_source_code(
+        &src_code,
+        &src_dir,
+        config.sha256.as_deref(),
+        config.signature_url.as_deref(),
+        &group,
+        false,
+    )?;
+
+    let file_name = format!(
+        "CompilerInterrupt-{}-llvm{}.{}",
+        checksum,
+        toolchain.version.major,
+        platform::shared_library_extension()
+    );
+    let library_path = config.library_path.parent()?.join(file_name);
+    info!(?library_path);
+
+    let pb = ProgressBar::hidden();
+    let library = install_library(
+        config.release_url.as_deref(),
+        toolchain,
+        &src_dir,
+        &library_path,
+        checksum,
+        &group,
+        &pb,
+    )?;
+
+    // the configuration in use has the one of the project and the arguments merged,
+    // so only the library is added to the global configuration
+    info!("updating configuration");
+    let mut global_config = Config::load_global()?;
+    global_config.add_library(library.clone());
+    Config::save(&global_config)?;
+    config.add_library(library);
+    Ok(())
+}
+
 /// Configures the Compiler Interrupts library.
 fn configure(mut config: Config, config_args: &ConfigArgs) -> CIResult<()> {
+    // printed alone for scripts, even if the library is not installed
+    if let Some(key) = &config_args.get {
+        if let Some(value) = config.get(key)? {
+            println!("{}", value);
+        }
+        return Ok(());
+    }
+
     if !Path::new(&config.library_path).is_file() {
-        bail!(Error::LibraryNotInstalled);
+        bail!(CIError::LibraryNotInstalled);
     }
 
     info!("configuring the library");
 
+    for key_value in &config_args.set {
+        debug!(?key_value);
+        let (key, value) = key_value
+            .split_once('=')
+            .with_context(|| format!("invalid `{}`, expected `KEY=VALUE`", key_value))?;
+        config.set(key, value)?;
+    }
+
+    for key in &config_args.unset {
+        debug!(?key);
+        config.unset(key)?;
+    }
+
     if let Some(library_args) = &config_args.library_args {
         debug!(?library_args);
         config.library_args = library_args.clone();
     }
 
+    if let Some(granularity) = config_args.instruction_granularity {
+        debug!(?granularity);
+        config.set_library_arg(plugin::CI_INST_GRAN_ARG, granularity);
+    }
+
+    if let Some(interval) = config_args.commit_interval {
+        debug!(?interval);
+        config.set_library_arg(plugin::CI_COMMIT_INTV_ARG, interval);
+    }
+
+    if let Some(deviation) = config_args.all_dev {
+        debug!(?deviation);
+        config.set_library_arg(plugin::CI_ALL_DEV_ARG, deviation);
+    }
+
+    for crate_args in &config_args.crate_args {
+        debug!(?crate_args);
+        let (crate_name, crate_args) = config::parse_crate_args(crate_args)?;
+        if crate_args.is_empty() {
+            config.crate_args.remove(&crate_name);
+        } else {
+            config.crate_args.insert(crate_name, crate_args);
+        }
+    }
+
+    if let Some(skip_functions) = &config_args.skip_functions {
+        debug!(?skip_functions);
+        config.skip_functions = skip_functions
+            .iter()
+            .filter(|function| !function.is_empty())
+            .cloned()
+            .collect();
+    }
+
+    if let Some(notify_command) = &config_args.notify_command {
+        debug!(?notify_command);
+        config.notify_command = Some(notify_command.clone()).filter(|s| !s.is_empty());
+    }
+
+    if let Some(notify_url) = &config_args.notify_url {
+        debug!(?notify_url);
+        config.notify_url = Some(notify_url.clone()).filter(|s| !s.is_empty());
+    }
+
+    if let Some(vendored_source) = &config_args.vendored_source {
+        debug!(?vendored_source);
+        config.vendored_source =
+            Some(PathBuf::from(vendored_source)).filter(|_| !vendored_source.is_empty());
+    }
+
+    if let Some(cache_dir) = &config_args.cache_dir {
+        debug!(?cache_dir);
+        config.cache_dir = Some(PathBuf::from(cache_dir)).filter(|_| !cache_dir.is_empty());
+    }
+
+    if let Some(level) = &config_args.cache_compression_level {
+        debug!(?level);
+        config.cache_compression_level = if level.is_empty() {
+            None
+        } else {
+            let level = level
+                .parse::<i32>()
+                .ok()
+                .filter(|level| zstd::compression_level_range().contains(level))
+                .with_context(|| format!("invalid compression level `{}`", level))?;
+            Some(level)
+        };
+    }
+
+    if let Some(cache_remote) = &config_args.cache_remote {
+        debug!(?cache_remote);
+        config.cache_remote = Some(cache_remote.clone()).filter(|s| !s.is_empty());
+    }
+
+    if let Some(llc_opt_level) = &config_args.llc_opt_level {
+        debug!(?llc_opt_level);
+        config.llc_opt_level = Some(llc_opt_level.clone()).filter(|s| !s.is_empty());
+    }
+
+    if let Some(llc_code_model) = &config_args.llc_code_model {
+        debug!(?llc_code_model);
+        config.llc_code_model = Some(llc_code_model.clone()).filter(|s| !s.is_empty());
+    }
+
+    if let Some(llc_cpu) = &config_args.llc_cpu {
+        debug!(?llc_cpu);
+        config.llc_cpu = Some(llc_cpu.clone()).filter(|s| !s.is_empty());
+    }
+
+    if let Some(pass_manager) = &config_args.pass_manager {
+        debug!(?pass_manager);
+        config.pass_manager = match pass_manager.as_str() {
+            "legacy" => Some(PassManager::Legacy),
+            "new" => Some(PassManager::New),
+            _ => None,
+        };
+    }
+
+    if let Some(hook) = &config_args.pre_integration_hook {
+        debug!(?hook);
+        config.pre_integration_hook = Some(hook.clone()).filter(|s| !s.is_empty());
+    }
+
+    if let Some(hook) = &config_args.post_crate_hook {
+        debug!(?hook);
+        config.post_crate_hook = Some(hook.clone()).filter(|s| !s.is_empty());
+    }
+
+    if let Some(hook) = &config_args.post_link_hook {
+        debug!(?hook);
+        config.post_link_hook = Some(hook.clone()).filter(|s| !s.is_empty());
+    }
+
+    if let Some(backend) = &config_args.backend {
+        debug!(?backend);
+        config.backend = match backend.as_str() {
+            "driver" => Backend::Driver,
+            "capture" => Backend::Capture,
+            _ => Backend::SaveTemps,
+        };
+    }
+
+    if let Some(cache_max_size) = &config_args.cache_max_size {
+        debug!(?cache_max_size);
+        config.cache_max_size = if cache_max_size.is_empty() {
+            None
+        } else {
+            Some(config::parse_size(cache_max_size)?)
+        };
+    }
+
+    // also set with `--set`
+    if let Some(level) = config.cache_compression_level {
+        if !zstd::compression_level_range().contains(&level) {
+            bail!("invalid compression level `{}`", level);
+        }
+    }
+    if let Some(cache_remote) = &config.cache_remote {
+        config::parse_cache_remote(cache_remote)?;
+    }
+
     Config::save(&config)?;
 
     print_info(&config)?;
@@ -307,35 +706,549 @@ fn configure(mut config: Config, config_args: &ConfigArgs) -> CIResult<()> {
     Ok(())
 }
 
+/// Shows or clears the cache.
+fn cache(config: &Config, cache_args: &CacheArgs) -> CIResult<()> {
+    let cache = Cache::open(config)?;
+
+    if cache_args.clear {
+        info!("clearing the cache");
+        cache.clear()?;
+        println!("{:>12} Cache has been cleared", "Finished".green().bold());
+        return Ok(());
+    }
+
+    let stats = cache.stats();
+    let max_size = config.cache_max_size.unwrap_or(DEFAULT_CACHE_MAX_SIZE);
+    println!("Cache path: {}", cache.dir().display());
+    println!("Cache entries: {}", stats.entries);
+    println!(
+        "Cache size: {} / {}",
+        util::human_size(stats.size),
+        util::human_size(max_size)
+    );
+    if let Some(cache_remote) = &config.cache_remote {
+        println!("Remote cache: {}", cache_remote);
+    }
+
+    Ok(())
+}
+
+/// Runs or stops the daemon.
+fn run_daemon(daemon_args: &DaemonArgs) -> CIResult<()> {
+    if daemon_args.stop {
+        if daemon::stop() {
+            println!("{:>12} Daemon has been stopped", "Finished".green().bold());
+        } else {
+            println!("Daemon is not running");
+        }
+        return Ok(());
+    }
+
+    println!(
+        "{:>12} daemon on {}",
+        "Running".green().bold(),
+        daemon::socket_path()?.display()
+    );
+    daemon::serve()
+}
+
+/// Generates the shell completions of the commands to stdout or to the directory.
+fn completions(completions_args: &CompletionsArgs) -> CIResult<()> {
+    let shell = Shell::from_str(&completions_args.shell).map_err(anyhow::Error::msg)?;
+    for mut cmd in args::commands() {
+        let name = cmd.get_name().to_string();
+        match &completions_args.out_dir {
+            Some(out_dir) => {
+                paths::create_dir_all(out_dir)?;
+                let path = clap_complete::generate_to(shell, &mut cmd, &name, out_dir)
+                    .with_context(|| format!("failed to write the completions of `{}`", name))?;
+                println!("{:>12} {}", "Generated".green().bold(), path.display());
+            }
+            None => clap_complete::generate(shell, &mut cmd, &name, &mut std::io::stdout()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the checklist of the environment, failing if any check failed.
+fn doctor(mut config: Config) -> CIResult<()> {
+    let checks = doctor::run(&mut config)?;
+    for check in &checks {
+        match &check.outcome {
+            Outcome::Pass(details) => {
+                println!("{:>6}  {}: {}", "PASS".green().bold(), check.name, details);
+            }
+            Outcome::Fail(problem, fix) => {
+                println!("{:>6}  {}: {}", "FAIL".red().bold(), check.name, problem);
+                println!("{:>6}  {} {}", "", "fix:".bold(), fix);
+            }
+            Outcome::Skip(reason) => {
+                println!("{:>6}  {}: {}", "SKIP".yellow().bold(), check.name, reason);
+            }
+        }
+    }
+
+    let failed = checks.iter().filter(|check| check.failed()).count();
+    if failed > 0 {
+        bail!("{} of {} checks failed", failed, checks.len());
+    }
+    Ok(())
+}
+
+/// Prints the explanation of a failure class and its remediation.
+fn explain(explain_args: &ExplainArgs) -> CIResult<()> {
+    let code = error::parse_code(&explain_args.code);
+    let explanation = code.and_then(error::explain).with_context(|| {
+        format!(
+            "no failure class has the code `{}`, see the exit codes in `--help`",
+            explain_args.code
+        )
+    })?;
+    println!(
+        "{}\n\n{}",
+        error::diagnostic_code(code.unwrap_or_default()).bold(),
+        explanation
+    );
+    Ok(())
+}
+
+/// Lists or prints the failure logs.
+fn logs(logs_args: &LogsArgs) -> CIResult<()> {
+    let logs = logs::list()?;
+
+    if let Some(index) = logs_args.show {
+        let log = index
+            .checked_sub(1)
+            .and_then(|idx| logs.get(idx))
+            .with_context(|| format!("failure log #{} does not exist", index))?;
+        println!("{}", paths::read(&log.path)?.trim_end());
+        return Ok(());
+    }
+
+    if logs.is_empty() {
+        println!("No failure logs found");
+        return Ok(());
+    }
+
+    for (idx, log) in logs.iter().take(logs_args.limit).enumerate() {
+        let time = log
+            .time
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let crates = if log.crates.is_empty() {
+            "unknown crate".to_string()
+        } else {
+            log.crates.join(", ")
+        };
+        println!("{:>4}  {}  {}", idx + 1, time.bold(), crates);
+        if let Some(command) = &log.command {
+            println!("      {}", command);
+        }
+        println!("      {}", log.path.display());
+    }
+
+    Ok(())
+}
+
 /// Outputs the configuration about the library.
 fn print_info(config: &Config) -> CIResult<()> {
     if !Path::new(&config.library_path).is_file() {
-        bail!(Error::LibraryNotInstalled);
+        bail!(CIError::LibraryNotInstalled);
     }
 
     println!("Library path: {}", config.library_path.display());
     println!("Library arguments: {}", config.library_args.join(" "));
+    for (crate_name, args) in &config.crate_args {
+        println!("Arguments of `{}`: {}", crate_name, args.join(" "));
+    }
+    if !config.skip_functions.is_empty() {
+        println!("Skipped functions: {}", config.skip_functions.join(" "));
+    }
     println!("LLVM version: {}", config.llvm_version);
+    if let Some(bin_dir) = &config.llvm_bin_dir {
+        println!("LLVM toolchain: {}", bin_dir.display());
+    }
+    for (major, library) in &config.libraries {
+        if library.library_path != config.library_path {
+            println!(
+                "Library for LLVM {}: {}",
+                major,
+                library.library_path.display()
+            );
+        }
+    }
     println!("Checksum: {}", config.checksum);
     println!("URL: {}", config.url);
+    if let Some(sha256) = &config.sha256 {
+        println!("SHA-256: {}", sha256);
+    }
+    if let Some(signature_url) = &config.signature_url {
+        println!("Signature: {}", signature_url);
+    }
+    if let Some(release_url) = &config.release_url {
+        println!("Release: {}", release_url);
+    }
+    if let Some(vendored_source) = &config.vendored_source {
+        println!("Vendored source: {}", vendored_source.display());
+    }
+    if let Some(git) = &config.git {
+        match (&git.reference, &git.rev) {
+            (Some(reference), _) => println!("Git reference: {}", reference),
+            (None, Some(rev)) => println!("Git revision: {}", rev),
+            (None, None) => {}
+        }
+        println!("Git commit: {}", git.commit);
+    }
+    if let Some(notify_command) = &config.notify_command {
+        println!("Notification command: {}", notify_command);
+    }
+    if let Some(notify_url) = &config.notify_url {
+        println!("Notification URL: {}", notify_url);
+    }
+    if let Some(log_file) = &config.log_file {
+        println!("Log file: {}", log_file.display());
+    }
+    if let Some(cache_remote) = &config.cache_remote {
+        println!("Remote cache: {}", cache_remote);
+    }
+    if let Some(llc_opt_level) = &config.llc_opt_level {
+        println!("llc optimization level: {}", llc_opt_level);
+    }
+    if let Some(llc_code_model) = &config.llc_code_model {
+        println!("llc code model: {}", llc_code_model);
+    }
+    if let Some(llc_cpu) = &config.llc_cpu {
+        println!("llc CPU: {}", llc_cpu);
+    }
+    if let Some(pass_manager) = &config.pass_manager {
+        println!("Pass manager: {}", pass_manager.as_str());
+    }
+    if let Some(hook) = &config.pre_integration_hook {
+        println!("Pre-integration hook: {}", hook);
+    }
+    if let Some(hook) = &config.post_crate_hook {
+        println!("Post-crate hook: {}", hook);
+    }
+    if let Some(hook) = &config.post_link_hook {
+        println!("Post-link hook: {}", hook);
+    }
+    println!("Backend: {}", config.backend.as_str());
+    for plugin in &config.plugins {
+        println!(
+            "Plugin: {} ({})",
+            plugin.name,
+            plugin.library_path.display()
+        );
+    }
 
     Ok(())
 }
 
-/// Fetch the source code given the URL.
-fn fetch_source_code(url: &Url) -> CIResult<Vec<u8>> {
+/// Get the task group of the download, interrupted by Ctrl-C.
+fn download_group(args: &LibraryArgs) -> CIResult<TaskGroup> {
+    tasks::handle_interrupt()?;
+    let timeout = args
+        .timeout
+        .as_deref()
+        .map(util::parse_duration)
+        .transpose()?;
+    Ok(TaskGroup::new(tasks::interrupt().clone(), timeout))
+}
+
+/// Fetch the source code given the URL, failing for a remote URL if offline.
+fn fetch_source_code(url: &Url, group: &TaskGroup, offline: bool) -> CIResult<Vec<u8>> {
     if let Ok(path) = url.to_file_path() {
         Ok(fs::read(path)?)
+    } else if offline {
+        bail!("cannot download `{}` with `--offline`", url)
     } else {
-        let resp = ureq::get(url.as_str()).call()?;
-        let len = resp
-            .header("Content-Length")
-            .and_then(|s| s.parse::<usize>().ok())
-            .unwrap_or(1_048_576);
-        let mut src_code = Vec::with_capacity(len);
-        resp.into_reader().read_to_end(&mut src_code)?;
-        Ok(src_code)
+        group.download(url.as_str())
+    }
+}
+
+/// Read the vendored source code, and get its `file://` URL with the source code.
+fn vendored_source_code(config: &Config) -> CIResult<(String, Vec<u8>)> {
+    let path = config.vendored_source_path()?;
+    info!("reading the vendored source code: {}", path.display());
+    let path = fs::canonicalize(&path).with_context(|| {
+        format!(
+            "vendored source code `{}` not found\n\
+            Copy `CompilerInterrupt.cpp` there or pass `--vendored-source <PATH>`",
+            path.display()
+        )
+    })?;
+    let src_code = fs::read(&path)?;
+    let url = Url::from_file_path(&path)
+        .map_err(|_| anyhow::anyhow!("invalid path `{}`", path.display()))?;
+    Ok((url.to_string(), src_code))
+}
+
+/// Verifies the source code saved at the path against the SHA-256 digest and the
+/// detached signature at the URL, if given, and gets its SHA-256 digest.
+fn verify_source_code(
+    src_code: &[u8],
+    src_path: &str,
+    sha256: Option<&str>,
+    signature_url: Option<&str>,
+    group: &TaskGroup,
+    offline: bool,
+) -> CIResult<String> {
+    let digest = sha256_digest(src_code);
+    info!(sha256 = ?digest);
+
+    if let Some(sha256) = sha256 {
+        if !sha256.eq_ignore_ascii_case(&digest) {
+            bail!(CIError::SourceVerificationFailed(format!(
+                "expected SHA-256 digest `{}`, found `{}`",
+                sha256, digest
+            )));
+        }
+    }
+
+    if let Some(url) = signature_url {
+        info!("verifying the signature: {}", url);
+        let signature = fetch_source_code(&Url::parse(url)?, group, offline)?;
+        let signature_path = format!("{}.sig", src_path);
+        paths::write(&signature_path, signature).context("failed to save the signature")?;
+
+        let mut gpg = ProcessBuilder::new("gpg");
+        gpg.args(&["--batch", "--verify"]);
+        gpg.arg(&signature_path).arg(src_path);
+        debug!(?gpg);
+        let result = group.exec_with_output(&gpg);
+        paths::remove_file(&signature_path)?;
+        result.map_err(|error| {
+            CIError::SourceVerificationFailed(format!(
+                "signature `{}` does not match: {:#}",
+                url, error
+            ))
+        })?;
+    }
+
+    Ok(digest)
+}
+
+/// Clone the git repository given the URL to a temporary directory, and get the source
+/// code with the commit it is checked out at.
+fn clone_source_code(url: &str, git: &GitSource, group: &TaskGroup) -> CIResult<(Vec<u8>, String)> {
+    let dir = std::env::temp_dir().join(format!("CompilerInterrupts-{}", std::process::id()));
+    if dir.exists() {
+        paths::remove_dir_all(&dir)?;
+    }
+    info!(?dir);
+
+    let result = checkout(url, git, &dir, group).and_then(|commit| {
+        let src_code = fs::read(dir.join(CI_GIT_SOURCE_PATH))
+            .with_context(|| format!("`{}` not found in `{}`", CI_GIT_SOURCE_PATH, url))?;
+        Ok((src_code, commit))
+    });
+
+    paths::remove_dir_all(&dir)?;
+    result
+}
+
+/// Clone the git repository to the directory and check out the branch, the tag, or the
+/// revision, returning the commit hash.
+fn checkout(url: &str, git: &GitSource, dir: &Path, group: &TaskGroup) -> CIResult<String> {
+    let mut clone = ProcessBuilder::new("git");
+    clone.args(&["clone", "--quiet"]);
+    // a revision may not be reachable from a shallow clone
+    if git.rev.is_none() {
+        clone.args(&["--depth", "1"]);
+    }
+    if let Some(reference) = &git.reference {
+        clone.arg("--branch").arg(reference);
+    }
+    clone.arg(url).arg(dir);
+    debug!(?clone);
+    group
+        .exec_with_output(&clone)
+        .with_context(|| format!("failed to clone `{}`", url))?;
+
+    if let Some(rev) = &git.rev {
+        let mut checkout = ProcessBuilder::new("git");
+        checkout.arg("-C").arg(dir);
+        checkout.args(&["checkout", "--quiet", "--detach", rev]);
+        group
+            .exec_with_output(&checkout)
+            .with_context(|| format!("failed to check out `{}`", rev))?;
+    }
+
+    let mut rev_parse = ProcessBuilder::new("git");
+    rev_parse.arg("-C").arg(dir).args(&["rev-parse", "HEAD"]);
+    let output = group.exec_with_output(&rev_parse)?;
+    let commit = String::from_utf8(output.stdout)?.trim().to_string();
+    info!(?commit);
+    Ok(commit)
+}
+
+/// Gets the SHA-256 digest of the data in lowercase hexadecimal.
+fn sha256_digest(data: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, data)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Downloads the prebuilt library for the toolchain from the release at the URL if
+/// given, or otherwise compiles the library from the source code.
+///
+/// The library is compiled from the source code if the release has no prebuilt library
+/// for the LLVM version of the toolchain on the host.
+fn install_library(
+    release_url: Option<&str>,
+    toolchain: &LlvmToolchain,
+    src_dir: &str,
+    library_path: &Path,
+    checksum: String,
+    group: &TaskGroup,
+    pb: &ProgressBar,
+) -> CIResult<Library> {
+    if let Some(release_url) = release_url {
+        info!("downloading the prebuilt library");
+        pb.set_message("Downloading the prebuilt Compiler Interrupts library");
+        if let Some(library) =
+            download_library(release_url, toolchain, library_path, &checksum, group)?
+        {
+            return Ok(library);
+        }
+        warn!(
+            "prebuilt library `{}` is not available in `{}`, compiling it from the source code",
+            platform::prebuilt_library_name(toolchain.version.major, false),
+            release_url
+        );
+    }
+    compile_library(toolchain, src_dir, library_path, checksum, pb)
+}
+
+/// Downloads the prebuilt library and the debug-enabled library for the toolchain from
+/// the release, verified by the `SHA256SUMS` of the release, or returns `None` if the
+/// release has no prebuilt library for the host.
+///
+/// The library is also used as the debug-enabled library if the release has none.
+fn download_library(
+    release_url: &str,
+    toolchain: &LlvmToolchain,
+    library_path: &Path,
+    checksum: &str,
+    group: &TaskGroup,
+) -> CIResult<Option<Library>> {
+    let release_url = Url::parse(&format!("{}/", release_url.trim_end_matches('/')))?;
+    let sums = match download_asset(&release_url, "SHA256SUMS", group)? {
+        Some(sums) => String::from_utf8(sums).context("invalid `SHA256SUMS`")?,
+        None => return Ok(None),
+    };
+
+    let major = toolchain.version.major;
+    let name = platform::prebuilt_library_name(major, false);
+    let library = match download_verified_asset(&release_url, &name, &sums, group)? {
+        Some(library) => library,
+        None => return Ok(None),
+    };
+    paths::write(library_path, library).context("failed to save the library")?;
+
+    let name = platform::prebuilt_library_name(major, true);
+    let library_debug_path = match download_verified_asset(&release_url, &name, &sums, group)? {
+        Some(library) => {
+            let path = library_path.append_suffix("debug")?;
+            paths::write(&path, library).context("failed to save the library")?;
+            path
+        }
+        None => library_path.to_path_buf(),
+    };
+
+    Ok(Some(Library {
+        library_path: library_path.to_path_buf(),
+        library_debug_path,
+        llvm_version: toolchain.version.to_string(),
+        llvm_bin_dir: toolchain.bin_dir.clone(),
+        checksum: checksum.to_string(),
+    }))
+}
+
+/// Downloads the asset of the release listed in its `SHA256SUMS` and verifies its
+/// digest, or returns `None` if the asset is not available.
+fn download_verified_asset(
+    release_url: &Url,
+    name: &str,
+    sums: &str,
+    group: &TaskGroup,
+) -> CIResult<Option<Vec<u8>>> {
+    // lines of `sha256sum`, `<digest>  <name>` or `<digest> *<name>` for binary mode
+    let sha256 = sums.lines().find_map(|line| {
+        let (digest, file) = line.trim().split_once(char::is_whitespace)?;
+        (file.trim_start().trim_start_matches('*') == name).then_some(digest)
+    });
+    let sha256 = match sha256 {
+        Some(sha256) => sha256,
+        None => {
+            debug!("`{}` not found in `SHA256SUMS`", name);
+            return Ok(None);
+        }
+    };
+    let data = match download_asset(release_url, name, group)? {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+    let digest = sha256_digest(&data);
+    info!(name, sha256 = ?digest);
+    if !sha256.eq_ignore_ascii_case(&digest) {
+        bail!(CIError::SourceVerificationFailed(format!(
+            "prebuilt library `{}` expected SHA-256 digest `{}`, found `{}`",
+            name, sha256, digest
+        )));
     }
+    Ok(Some(data))
+}
+
+/// Downloads the asset of the release, or returns `None` if it cannot be downloaded.
+fn download_asset(release_url: &Url, name: &str, group: &TaskGroup) -> CIResult<Option<Vec<u8>>> {
+    let url = release_url.join(name)?;
+    info!("downloading the asset: {}", url);
+    match fetch_source_code(&url, group, false) {
+        Ok(data) => Ok(Some(data)),
+        // the installation is cancelled rather than compiled from the source code
+        Err(error) if matches!(CIError::find(&error), Some(CIError::Cancelled)) => Err(error),
+        Err(error) => {
+            debug!("failed to download `{}`: {:#}", url, error);
+            Ok(None)
+        }
+    }
+}
+
+/// Compiles the library and the debug-enabled library from the source code with the
+/// toolchain.
+fn compile_library(
+    toolchain: &LlvmToolchain,
+    src_dir: &str,
+    library_path: &Path,
+    checksum: String,
+    pb: &ProgressBar,
+) -> CIResult<Library> {
+    let out_dir = library_path.to_string()?;
+    let out_debug_dir = library_path.append_suffix("debug")?.to_string()?;
+
+    info!("getting the compiler config");
+    pb.set_message("Getting the compiler configuration");
+    let clang = compiler(toolchain)?;
+
+    info!("compiling the library");
+    pb.set_message("Compiling the Compiler Interrupts library");
+    compile(clang.clone(), src_dir, &out_dir, false, pb)?;
+
+    info!("compiling the library with debugging mode");
+    pb.set_message("Compiling the Compiler Interrupts library with debugging mode");
+    compile(clang, src_dir, &out_debug_dir, true, pb)?;
+
+    Ok(Library {
+        library_path: PathBuf::from(out_dir),
+        library_debug_path: PathBuf::from(out_debug_dir),
+        llvm_version: toolchain.version.to_string(),
+        llvm_bin_dir: toolchain.bin_dir.clone(),
+        checksum,
+    })
 }
 
 /// Get the compiler with required arguments.
@@ -354,20 +1267,18 @@ fn compiler(toolchain: &LlvmToolchain) -> CIResult<ProcessBuilder> {
     let ld_flags = String::from_utf8(output.stdout)?;
     debug!(?ld_flags);
 
-    let common_flags = "-O3 -Wall -Wextra -Wno-unused-parameter -Wno-implicit-fallthrough -fPIC";
-
-    let so_flags = if cfg!(target_os = "macos") {
-        "-bundle -undefined dynamic_lookup"
-    } else {
-        "-shared"
-    };
+    let common_flags = "-O3 -Wall -Wextra -Wno-unused-parameter -Wno-implicit-fallthrough";
 
     let mut clang = LlvmUtility::Clang.process_builder(toolchain);
-    clang.args(&so_flags.split_ascii_whitespace().collect::<Vec<_>>());
+    clang.args(platform::shared_library_flags());
     clang.args(&cxx_flags.split_ascii_whitespace().collect::<Vec<_>>());
-    clang.args(&ld_flags.split_ascii_whitespace().collect::<Vec<_>>());
+    clang.args(&platform::linker_flags(&ld_flags));
     clang.args(&common_flags.split_ascii_whitespace().collect::<Vec<_>>());
-    clang.arg("-fdiagnostics-color=always");
+    if util::colors_enabled() {
+        clang.arg("-fdiagnostics-color=always");
+    } else {
+        clang.arg("-fdiagnostics-color=never");
+    }
     clang.arg(format!("-DLLVM{}", toolchain.version.major));
 
     Ok(clang)
@@ -391,11 +1302,19 @@ fn compile<P: AsRef<Path>>(
     clang
         .exec_with_streaming(
             &mut |out| {
-                pb.println(out);
+                if pb.is_hidden() {
+                    eprintln!("{}", out);
+                } else {
+                    pb.println(out);
+                }
                 Ok(())
             },
             &mut |err| {
-                pb.println(err);
+                if pb.is_hidden() {
+                    eprintln!("{}", err);
+                } else {
+                    pb.println(err);
+                }
                 Ok(())
             },
             false,
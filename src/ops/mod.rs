@@ -1,5 +1,8 @@
 //! Implementation for the subcommands.
 
+pub mod bench;
 pub mod build;
 pub mod library;
 pub mod run;
+pub mod rustc;
+pub mod test;
@@ -3,44 +3,101 @@
 use anyhow::{bail, Context};
 use cargo_util::ProcessBuilder;
 use clap::Parser;
-use std::path::PathBuf;
+use colored::Colorize;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
 
 use crate::args::RunArgs;
-use crate::error::Error;
+use crate::cargo::{Cargo, Features, FileFlavor};
+use crate::config::Config;
+use crate::error::CIError;
 use crate::paths::PathExt;
-use crate::{cargo, util, CIResult, RUN_CI_BIN_NAME};
+use crate::stats::{self, InterruptStats};
+use crate::{handler, perf, platform, util, wrapper, CIResult, RUN_CI_BIN_NAME};
 
 /// Main routine for `cargo-run-ci`.
 pub fn exec() -> CIResult<()> {
-    let args = if std::env::args().next().unwrap_or_default() == RUN_CI_BIN_NAME {
+    if wrapper::is_wrapper() {
+        return wrapper::exec();
+    }
+
+    let mut args = if std::env::args().next().unwrap_or_default() == RUN_CI_BIN_NAME {
         RunArgs::parse()
     } else {
         RunArgs::parse_from(std::env::args().skip(1))
     };
 
+    util::init_color(&args.color);
     util::init_logger(&args.log_level)?;
+
+    // relative to the directory of the invocation, not of the workspace root
+    if let Some(path) = &args.log_file {
+        util::init_log_file(path.as_deref())?;
+    }
+    let current_dir = std::env::current_dir()?;
+    args.env_file = args.env_file.map(|path| current_dir.join(path));
+    args.cwd = args.cwd.map(|path| current_dir.join(path));
+    args.stdin = args.stdin.map(|path| current_dir.join(path));
+    args.stdout = args.stdout.map(|path| current_dir.join(path));
+    args.stderr = args.stderr.map(|path| current_dir.join(path));
+    args.stats = args.stats.map(|path| current_dir.join(path));
+    if let Some(path) = &args.bin_args_file {
+        let mut binary_args = read_args_file(&current_dir.join(path))?;
+        binary_args.append(&mut args.binary_args);
+        args.binary_args = binary_args;
+    }
     util::set_current_workspace_root_dir().context("failed to set the root directory")?;
 
-    _exec(args)
+    let config = Config::load()?;
+    util::init_config_log_file(&config)?;
+
+    _exec(&config, args)
 }
 
 /// Core routine for `cargo-run-ci`.
-fn _exec(args: RunArgs) -> CIResult<()> {
-    let mut cargo = cargo::Cargo::with_args(args.cargo_args);
-    cargo.build()?;
-
-    let binaries = cargo.target_dir.read_dir(|path| path.executable())?;
+fn _exec(config: &Config, args: RunArgs) -> CIResult<()> {
+    let mut cargo = Cargo::with_args(args.cargo_args.clone());
+    cargo.features = Features {
+        features: args.features.clone(),
+        all_features: args.all_features,
+        no_default_features: args.no_default_features,
+    };
+    cargo.profile = args.profile.clone();
+    // the same flags as the integration, not to rebuild the crates
+    cargo.set_backend(config)?;
+    cargo.build(&[])?;
 
-    let (integrates, originals): (Vec<PathBuf>, _) = binaries
-        .into_iter()
-        .partition(|binary| binary.file_stem().unwrap_or_default().contains("-ci"));
+    // the binaries of this build, not the ones left in the target directory by the
+    // builds of other features, e.g. of the binaries with `required-features`
+    let binaries = binaries(&cargo)?;
+    if binaries.is_empty() {
+        bail!(CIError::BinaryNotFound);
+    }
 
-    if originals.is_empty() {
-        bail!(Error::BinaryNotFound);
+    let mut originals = Vec::new();
+    let mut integrates = Vec::new();
+    for original in binaries {
+        let integrated = original.append_suffix("ci")?;
+        if !integrated.is_file() {
+            continue;
+        }
+        // the binary is rebuilt, e.g. with other features than the integration
+        if modified(&integrated) < modified(&original) {
+            warn!(
+                "`{}` is older than `{}`, run `cargo-build-ci` with the same arguments",
+                integrated.display(),
+                original.display()
+            );
+        }
+        originals.push(original);
+        integrates.push(integrated);
     }
 
     if integrates.is_empty() {
-        bail!(Error::IntegratedBinaryNotFound);
+        bail!(CIError::IntegratedBinaryNotFound);
     }
 
     let names = originals
@@ -50,21 +107,543 @@ fn _exec(args: RunArgs) -> CIResult<()> {
         .collect::<Vec<_>>()
         .join(", ");
 
-    if let Some(binary_name) = args.binary_name {
+    let runner = cargo.runner();
+
+    if let Some(binary_name) = &args.binary_name {
         for (integrated, original) in integrates.iter().zip(originals.iter()) {
-            if binary_name == original.file_name()? {
-                return ProcessBuilder::new(integrated)
-                    .args(&args.binary_args)
-                    .exec_replace();
+            if *binary_name == original.file_name()? {
+                return run(config, &args, runner, original, integrated);
             }
         }
 
-        bail!(Error::BinaryNotAvailable(binary_name, names));
+        bail!(CIError::BinaryNotAvailable(binary_name.clone(), names));
     } else if integrates.len() == 1 {
-        return ProcessBuilder::new(&integrates[0])
-            .args(&args.binary_args)
-            .exec_replace();
+        return run(config, &args, runner, &originals[0], &integrates[0]);
+    }
+
+    bail!(CIError::BinaryNotDetermine(names));
+}
+
+/// Gets the binaries of the build in the target directory.
+fn binaries(cargo: &Cargo) -> CIResult<Vec<PathBuf>> {
+    let mut binaries = Vec::new();
+    for file in &cargo.output_files {
+        let hardlink = match (&file.hardlink, file.flavor) {
+            (Some(hardlink), FileFlavor::Normal) => hardlink,
+            _ => continue,
+        };
+        // the libraries may be executable, and the examples are in a subdirectory
+        let library = matches!(
+            hardlink.extension().as_deref(),
+            Ok("so" | "dylib" | "dll" | "a" | "lib")
+        );
+        if !library && hardlink.executable() && hardlink.parent()? == cargo.target_dir {
+            binaries.push(hardlink.clone());
+        }
+    }
+    binaries.sort();
+    binaries.dedup();
+    Ok(binaries)
+}
+
+/// Gets the modification time of the file, if any.
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Runs the CI-integrated binary, with the runner of the target if any.
+fn run(
+    config: &Config,
+    args: &RunArgs,
+    runner: Option<Vec<String>>,
+    original: &Path,
+    binary: &Path,
+) -> CIResult<()> {
+    // report a binary running without a registered handler, unless set by the user
+    if std::env::var_os(handler::HANDLER_ENV).is_none() {
+        std::env::set_var(handler::HANDLER_ENV, "warn");
+    }
+    for (key, value) in environment(args)? {
+        std::env::set_var(key, value);
+    }
+    let cwd = args.cwd.as_deref();
+    if let Some(cwd) = cwd {
+        if !cwd.is_dir() {
+            bail!("`{}` is not a directory", cwd.display());
+        }
+        info!("running in the directory: {}", cwd.display());
+    }
+
+    if args.perf.is_some() && runner.is_some() {
+        bail!("`--perf` is not supported for the binaries of a target runner");
+    }
+    let runner = launcher(args, runner)?;
+
+    if let Some(output) = &args.perf {
+        if cfg!(not(target_os = "linux")) {
+            bail!(CIError::HostNotSupported(
+                "--perf".to_string(),
+                "the `perf` profiler of Linux".to_string()
+            ));
+        }
+        // the output stays relative to the workspace root with `--cwd`
+        let output = std::env::current_dir()?.join(output);
+        info!("recording with perf to: {}", output.display());
+        let launcher = runner.unwrap_or_default();
+        return perf::record(
+            config,
+            binary,
+            &args.binary_args,
+            output.as_path(),
+            cwd,
+            &launcher,
+        );
+    }
+
+    if args.compare {
+        return compare(args, runner.as_deref(), original, binary);
+    }
+
+    let mut cmd = command(runner.as_deref(), binary);
+    cmd.args(&args.binary_args);
+    if let Some(cwd) = cwd {
+        cmd.cwd(cwd);
+    }
+    if let Some(path) = &args.stats {
+        // not to print the statistics of a previous run
+        if path.exists() {
+            cargo_util::paths::remove_file(path)?;
+        }
+        cmd.env(stats::STATS_ENV, path);
+    }
+    if args.stdin.is_none()
+        && args.stdout.is_none()
+        && args.stderr.is_none()
+        && args.stats.is_none()
+    {
+        return cmd.exec_replace();
+    }
+
+    // the binary runs as a child to redirect its standard streams, and to read the
+    // statistics once it exits
+    let mut cmd = cmd.build_command();
+    redirect(&mut cmd, args)?;
+    let status = cmd
+        .status()
+        .with_context(|| format!("failed to run `{}`", binary.display()))?;
+    if let Some(path) = &args.stats {
+        if path.is_file() {
+            print_stats(&stats::parse(&cargo_util::paths::read(path)?)?, path);
+        } else {
+            warn!(
+                "`{}` was not written, the runtime of the `compiler-interrupts` crate writes it \
+                at exit once `{}` is set",
+                path.display(),
+                stats::STATS_ENV
+            );
+        }
+    }
+    match status.code() {
+        Some(0) => Ok(()),
+        // exit like the binary, as with the process replaced without redirections
+        Some(code) => std::process::exit(code),
+        None => bail!("`{}` was terminated: {}", binary.display(), status),
+    }
+}
+
+/// Prints the statistics of the interrupts with the histograms of their intervals.
+fn print_stats(stats: &InterruptStats, path: &Path) {
+    eprintln!();
+    eprintln!(
+        "{:>12} {} interrupt(s) on {} thread(s), written to {}",
+        "Stats".green().bold(),
+        stats.intervals.len(),
+        stats.threads,
+        path.display()
+    );
+    print_histogram("Instructions", &stats.intervals, |n| n.to_string());
+    print_histogram("Latency", &stats.latencies, human_nanos);
+}
+
+/// Gets a human readable String for a duration in nanoseconds, below the seconds of
+/// [`util::human_duration`].
+fn human_nanos(nanos: u64) -> String {
+    match nanos {
+        0..=999 => format!("{}ns", nanos),
+        1_000..=999_999 => format!("{}.{}us", nanos / 1_000, nanos % 1_000 / 100),
+        1_000_000..=999_999_999 => {
+            format!("{}.{}ms", nanos / 1_000_000, nanos % 1_000_000 / 100_000)
+        }
+        _ => util::human_duration(Duration::from_nanos(nanos)),
+    }
+}
+
+/// Prints the summary and the histogram of the sorted values, if any.
+fn print_histogram<F: Fn(u64) -> String>(name: &str, values: &[u64], format: F) {
+    /// Width of the bar of the largest bucket.
+    const BAR_WIDTH: usize = 40;
+
+    let summary = [("min", 0), ("p50", 50), ("p99", 99), ("max", 100)]
+        .iter()
+        .filter_map(|&(label, percent)| {
+            let value = stats::percentile(values, percent)?;
+            Some(format!("{} {}", label, format(value)))
+        })
+        .collect::<Vec<_>>();
+    if summary.is_empty() {
+        return;
+    }
+    eprintln!("{:>12} {}", name.bold(), summary.join(", "));
+
+    let buckets = stats::histogram(values);
+    let ranges = buckets
+        .iter()
+        .map(|bucket| format!("{}..{}", format(bucket.low), format(bucket.high)))
+        .collect::<Vec<_>>();
+    let width = ranges.iter().map(String::len).max().unwrap_or_default();
+    let max_count = buckets.iter().map(|bucket| bucket.count).max().unwrap_or(1);
+    for (bucket, range) in buckets.iter().zip(ranges) {
+        // a bucket with values has a bar, however small
+        let bar = (bucket.count * BAR_WIDTH).div_ceil(max_count);
+        eprintln!(
+            "{:>12} {:>width$}  {} {}",
+            "",
+            range,
+            "#".repeat(bar).cyan(),
+            bucket.count,
+            width = width
+        );
+    }
+}
+
+/// Reads the arguments of the binary from the file of `--bin-args-file`, one per line.
+///
+/// The empty lines and the comments starting with `#` are skipped, and the arguments are
+/// taken as is without quoting, so they may contain spaces.
+fn read_args_file(path: &Path) -> CIResult<Vec<String>> {
+    let s = cargo_util::paths::read(path)?;
+    Ok(s.lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Redirects the standard streams of the binary from and to the files of `--stdin`,
+/// `--stdout`, and `--stderr`.
+fn redirect(cmd: &mut Command, args: &RunArgs) -> CIResult<()> {
+    if let Some(path) = &args.stdin {
+        let file =
+            File::open(path).with_context(|| format!("failed to open `{}`", path.display()))?;
+        cmd.stdin(file);
+    }
+    let create = |path: &Path| {
+        File::create(path).with_context(|| format!("failed to create `{}`", path.display()))
+    };
+    match (&args.stdout, &args.stderr) {
+        // both streams are interleaved in the file instead of overwriting each other
+        (Some(stdout), Some(stderr)) if stdout == stderr => {
+            let file = create(stdout)?;
+            cmd.stderr(file.try_clone()?);
+            cmd.stdout(file);
+        }
+        (stdout, stderr) => {
+            if let Some(path) = stdout {
+                cmd.stdout(create(path)?);
+            }
+            if let Some(path) = stderr {
+                cmd.stderr(create(path)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Gets the programs launching the binary: `nice` of `--nice`, the command pinning
+/// to the CPUs of `--pin-cpus`, and then the runner of the target if any.
+fn launcher(args: &RunArgs, runner: Option<Vec<String>>) -> CIResult<Option<Vec<String>>> {
+    let mut launcher = Vec::new();
+    if let Some(niceness) = args.nice {
+        match platform::nice_command(niceness) {
+            Some(command) => launcher.extend(command),
+            None => {
+                warn!("`--nice` is not supported on this host, running with the default priority")
+            }
+        }
+    }
+    if let Some(cpus) = &args.pin_cpus {
+        let valid = !cpus.is_empty()
+            && cpus
+                .split(',')
+                .flat_map(|range| range.splitn(2, '-'))
+                .all(|cpu| !cpu.is_empty() && cpu.chars().all(|c| c.is_ascii_digit()));
+        if !valid {
+            bail!("invalid list of CPUs `{}`, expected e.g. `0,2-3`", cpus);
+        }
+        match platform::pin_cpus_command(cpus) {
+            Some(command) => launcher.extend(command),
+            None => warn!("`--pin-cpus` is not supported on this host, running without pinning"),
+        }
     }
+    launcher.extend(runner.unwrap_or_default());
+    Ok((!launcher.is_empty()).then_some(launcher))
+}
 
-    bail!(Error::BinaryNotDetermine(names));
+/// Gets the environment variables for the binary, of `--env-file` and then of `--env`.
+fn environment(args: &RunArgs) -> CIResult<Vec<(String, String)>> {
+    let mut envs = Vec::new();
+    if let Some(env_file) = &args.env_file {
+        let s = cargo_util::paths::read(env_file)?;
+        for (idx, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = parse_env(line)
+                .with_context(|| format!("invalid line {} of `{}`", idx + 1, env_file.display()))?;
+            // the values may be quoted like in the shell
+            let value = ['"', '\'']
+                .iter()
+                .find_map(|&quote| value.strip_prefix(quote)?.strip_suffix(quote))
+                .unwrap_or(value);
+            envs.push((key.to_string(), value.to_string()));
+        }
+    }
+    for env in &args.envs {
+        let (key, value) = parse_env(env)?;
+        envs.push((key.to_string(), value.to_string()));
+    }
+    Ok(envs)
+}
+
+/// Parses an environment variable given as `KEY=VALUE`.
+fn parse_env(s: &str) -> CIResult<(&str, &str)> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => Ok((key.trim(), value)),
+        _ => bail!("invalid environment variable `{}`, expected `KEY=VALUE`", s),
+    }
+}
+
+/// Run of a binary compared by `--compare`.
+struct Outcome {
+    /// Wall-clock time of the run.
+    duration: Duration,
+    /// Exit status of the binary.
+    status: ExitStatus,
+    /// Standard output of the binary.
+    stdout: String,
+}
+
+/// Runs the original and the CI-integrated binary back-to-back with the same
+/// arguments, and prints the report of their runs.
+///
+/// Fails if the exit statuses or the standard outputs differ.
+fn compare(
+    args: &RunArgs,
+    runner: Option<&[String]>,
+    original: &Path,
+    integrated: &Path,
+) -> CIResult<()> {
+    let cwd = args.cwd.as_deref();
+    let stdin = args.stdin.as_deref();
+    let original_outcome = execute(runner, original, &args.binary_args, cwd, stdin)?;
+    let integrated_outcome = execute(runner, integrated, &args.binary_args, cwd, stdin)?;
+
+    let overhead = (integrated_outcome.duration.as_secs_f64()
+        / original_outcome.duration.as_secs_f64().max(f64::EPSILON)
+        - 1.0)
+        * 100.0;
+    let same_status = original_outcome.status.code() == integrated_outcome.status.code()
+        && original_outcome.status.success() == integrated_outcome.status.success();
+    let same_stdout = original_outcome.stdout == integrated_outcome.stdout;
+
+    let rows = [
+        (
+            "Binary",
+            PathExt::file_name(&original)?,
+            PathExt::file_name(&integrated)?,
+            String::new(),
+        ),
+        (
+            "Time",
+            format!("{:.3}s", original_outcome.duration.as_secs_f64()),
+            format!("{:.3}s", integrated_outcome.duration.as_secs_f64()),
+            format!("{:+.1}%", overhead),
+        ),
+        (
+            "Exit status",
+            status(original_outcome.status),
+            status(integrated_outcome.status),
+            if same_status { "same" } else { "differs" }.to_string(),
+        ),
+        (
+            "Stdout",
+            format!("{} lines", original_outcome.stdout.lines().count()),
+            format!("{} lines", integrated_outcome.stdout.lines().count()),
+            if same_stdout { "same" } else { "differs" }.to_string(),
+        ),
+    ];
+    let widths = rows
+        .iter()
+        .fold((0, 0), |(a, b), (_, original, integrated, _)| {
+            (a.max(original.len()), b.max(integrated.len()))
+        });
+    eprintln!();
+    for (name, original, integrated, change) in rows {
+        let line = format!(
+            "{:>12}  {:<a$}  {:<b$}  {}",
+            name.bold(),
+            original,
+            integrated,
+            change,
+            a = widths.0,
+            b = widths.1
+        );
+        eprintln!("{}", line.trim_end());
+    }
+
+    if args.diff && !same_stdout {
+        eprintln!();
+        for line in diff_lines(&original_outcome.stdout, &integrated_outcome.stdout) {
+            match line.chars().next() {
+                Some('-') => eprintln!("{}", line.red()),
+                Some('+') => eprintln!("{}", line.green()),
+                _ => eprintln!("{}", line.cyan()),
+            }
+        }
+    }
+
+    if !same_status || !same_stdout {
+        bail!(
+            "the runs of `{}` and `{}` differ",
+            original.display(),
+            integrated.display()
+        );
+    }
+    Ok(())
+}
+
+/// Runs the binary to completion, capturing its standard output, with the standard
+/// input read from the file if any.
+fn execute(
+    runner: Option<&[String]>,
+    binary: &Path,
+    binary_args: &[String],
+    cwd: Option<&Path>,
+    stdin: Option<&Path>,
+) -> CIResult<Outcome> {
+    let current_dir = std::env::current_dir()?;
+    let display = binary.strip_prefix(&current_dir).unwrap_or(binary);
+    eprintln!("{:>12} {}", "Running".green().bold(), display.display());
+
+    let mut cmd = command(runner, binary);
+    cmd.args(binary_args);
+    if let Some(cwd) = cwd {
+        cmd.cwd(cwd);
+    }
+    // the standard error is not compared, so it is shown as the binary runs
+    let mut cmd = cmd.build_command();
+    cmd.stderr(Stdio::inherit());
+    // both binaries read the whole input
+    if let Some(path) = stdin {
+        let file =
+            File::open(path).with_context(|| format!("failed to open `{}`", path.display()))?;
+        cmd.stdin(file);
+    }
+
+    let time = Instant::now();
+    let output = cmd
+        .output()
+        .with_context(|| format!("failed to run `{}`", binary.display()))?;
+    Ok(Outcome {
+        duration: time.elapsed(),
+        status: output.status,
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+    })
+}
+
+/// Gets the exit code, or the description of the exit status if killed by a signal.
+fn status(status: ExitStatus) -> String {
+    match status.code() {
+        Some(code) => code.to_string(),
+        None => status.to_string(),
+    }
+}
+
+/// Gets the lines removed from the original output with `-` and added by the
+/// CI-integrated one with `+`, grouped under the line numbers of the changes.
+fn diff_lines(original: &str, integrated: &str) -> Vec<String> {
+    let a = original.lines().collect::<Vec<_>>();
+    let b = integrated.lines().collect::<Vec<_>>();
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let a = &a[prefix..a.len() - suffix];
+    let b = &b[prefix..b.len() - suffix];
+
+    // the changed region is dumped as a whole if it is too large for the table of the
+    // longest common subsequence
+    if a.len().saturating_mul(b.len()) > 1 << 22 {
+        let mut lines = vec![format!("@@ line {} @@", prefix + 1)];
+        lines.extend(a.iter().map(|line| format!("-{}", line)));
+        lines.extend(b.iter().map(|line| format!("+{}", line)));
+        return lines;
+    }
+
+    // longest common subsequence of the changed region
+    let mut lcs = vec![vec![0_u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut in_hunk = false;
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] && lcs[i][j] == lcs[i + 1][j + 1] + 1 {
+            i += 1;
+            j += 1;
+            in_hunk = false;
+            continue;
+        }
+        if !in_hunk {
+            lines.push(format!("@@ line {} @@", prefix + i + 1));
+            in_hunk = true;
+        }
+        if j == b.len() || (i < a.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(format!("-{}", a[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+{}", b[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// Creates the command running the binary, with the runner of the target or the
+/// launcher of `cargo-run-ci` if any.
+pub(crate) fn command(runner: Option<&[String]>, binary: &Path) -> ProcessBuilder {
+    // the runner takes the path of the binary like `cargo run` gives it
+    match runner {
+        Some([program, runner_args @ ..]) => {
+            info!("running with: {}", program);
+            let mut cmd = ProcessBuilder::new(program);
+            cmd.args(runner_args);
+            cmd.arg(binary);
+            cmd
+        }
+        _ => ProcessBuilder::new(binary),
+    }
 }
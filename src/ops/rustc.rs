@@ -0,0 +1,274 @@
+//! Implementation of `cargo-ci-rustc`.
+//!
+//! Set as `RUSTC_WRAPPER`, it integrates the Compiler Interrupts during a plain
+//! `cargo build` or `cargo test`. The LLVM IR of a library crate is integrated right
+//! after `rustc`, replacing the object files in its rlib or static library. For a
+//! binary crate or a `cdylib`, it is set as the linker, so that the object files of
+//! the crate are integrated before the real linker runs, along with the rlibs already
+//! integrated.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use cargo_util::{paths, ProcessBuilder, ProcessError};
+use tracing::{debug, info};
+
+use crate::config::Config;
+use crate::error::CIError;
+use crate::llvm::{LlvmToolchain, LlvmUtility};
+use crate::ops::library;
+use crate::paths::PathExt;
+use crate::platform::Target;
+use crate::{archive, llvm, pipeline, symbols, util, wrapper, CIResult};
+
+/// Real linker of the binary crate, set when invoked as the linker.
+const LINKER_ENV: &str = "CARGO_CI_RUSTC_LINKER";
+
+/// Optimization level of the crate for `llc`, set when invoked as the linker.
+const OPT_LEVEL_ENV: &str = "CARGO_CI_RUSTC_OPT_LEVEL";
+
+/// Target triple of the crate for `llc`, set when invoked as the linker.
+const TARGET_ENV: &str = "CARGO_CI_RUSTC_TARGET";
+
+/// Log level of the wrapper.
+const LOG_ENV: &str = "CARGO_CI_LOG";
+
+/// Main routine for `cargo-ci-rustc`.
+pub fn exec() -> CIResult<()> {
+    util::init_logger(&std::env::var(LOG_ENV).unwrap_or_else(|_| "warn".to_string()))?;
+
+    let result = match std::env::var_os(LINKER_ENV) {
+        Some(linker) => link(linker),
+        None => compile(),
+    };
+
+    // `rustc` and the linker have already reported their errors
+    if let Some(code) = result
+        .as_ref()
+        .err()
+        .and_then(|error| error.downcast_ref::<ProcessError>())
+        .and_then(|error| error.code)
+    {
+        std::process::exit(code);
+    }
+
+    result
+}
+
+/// Runs `rustc`, integrating the crate unless skipped.
+fn compile() -> CIResult<()> {
+    let (rustc, args) = wrapper::rustc_args()?;
+    let crate_name = wrapper::option(&args, "--crate-name");
+    let crate_types = args
+        .windows(2)
+        .filter(|pair| pair[0] == "--crate-type")
+        .filter_map(|pair| pair[1].to_str())
+        .flat_map(|types| types.split(','))
+        .collect::<Vec<_>>();
+    let test = args.iter().any(|arg| arg == "--test");
+    // the object files of the binaries and the C dynamic libraries are linked, and the
+    // ones of the rlibs and the static libraries are archived
+    let linked = test || crate_types.contains(&"bin") || crate_types.contains(&"cdylib");
+    let archives = crate_types
+        .iter()
+        .filter_map(|crate_type| match *crate_type {
+            "lib" | "rlib" => Some("rlib"),
+            "staticlib" => Some("a"),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    // e.g. not the procedural macros and the Rust dynamic libraries
+    let supported = test
+        || (!crate_types.is_empty()
+            && crate_types.iter().all(|crate_type| {
+                matches!(*crate_type, "bin" | "lib" | "rlib" | "staticlib" | "cdylib")
+            }));
+    // `cargo check` does not generate code
+    let codegen = args
+        .iter()
+        .filter_map(|arg| arg.to_str()?.strip_prefix("--emit="))
+        .any(|kinds| kinds.split(',').any(|kind| kind == "link"));
+
+    let target_supported = wrapper::option(&args, "--target")
+        .is_none_or(|target| Target::parse(target).is_supported());
+
+    let integrate = match crate_name {
+        // `___` is the crate name of the target information probe
+        Some("___") | None => false,
+        _ if !target_supported => false,
+        _ if wrapper::is_host_only(&args) => false,
+        Some(name) => codegen && supported && !util::is_skipped(&skip_crates()?, name),
+    };
+
+    let mut cmd = wrapper::rustc_command(rustc);
+    if !integrate {
+        cmd.args(&args);
+        return cmd.exec_replace();
+    }
+
+    let crate_name = crate_name.unwrap_or_default().to_string();
+    let opt_level = wrapper::codegen_option(&args, "opt-level").unwrap_or_else(|| "0".to_string());
+    let target = wrapper::option(&args, "--target");
+    debug!(%crate_name, ?crate_types, %opt_level, ?target);
+
+    if linked {
+        // be the linker to integrate the object files of the crate before linking
+        let linker = wrapper::codegen_option(&args, "linker").unwrap_or_else(|| "cc".to_string());
+        let exe = std::env::current_exe().context("failed to get the current executable")?;
+        cmd.args(&wrapper::without_codegen_option(&args, "linker"));
+        cmd.arg(format!("-Clinker={}", exe.display()));
+        cmd.env(LINKER_ENV, linker);
+        cmd.env(OPT_LEVEL_ENV, &opt_level);
+        if let Some(target) = target {
+            cmd.env(TARGET_ENV, target);
+        }
+    } else {
+        cmd.args(&args);
+    }
+    cmd.args(&wrapper::EMIT_FLAGS);
+    cmd.exec()?;
+
+    if !archives.is_empty() {
+        let out_dir = wrapper::option(&args, "--out-dir").context("missing `--out-dir`")?;
+        let extra_filename = wrapper::codegen_option(&args, "extra-filename").unwrap_or_default();
+        for extension in archives {
+            let archive = Path::new(out_dir)
+                .join(format!("lib{}{}.{}", crate_name, extra_filename, extension));
+            integrate_archive(&archive, &opt_level, target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Integrates the object files of the rlib or the static library in place.
+///
+/// The members of a static library copied from the rlibs of its dependencies are
+/// already integrated, and the ones of the standard library have no LLVM IR.
+fn integrate_archive(rlib: &Path, opt_level: &str, target: Option<&str>) -> CIResult<()> {
+    let (config, toolchain) = setup()?;
+    let llc_flags = pipeline::llc_flags(
+        &config,
+        &config
+            .llc_opt_level
+            .clone()
+            .unwrap_or_else(|| opt_level.to_string()),
+        target,
+    );
+
+    let mut replacements = Vec::new();
+    for unit in archive::codegen_units(rlib, &archive::members(&toolchain, rlib)?)? {
+        if let Some(ci_object) = integrate_object(&config, &toolchain, &llc_flags, &unit.object)? {
+            replacements.push((unit.member, ci_object));
+        }
+    }
+
+    if !replacements.is_empty() {
+        info!("replacing object files for archive: {}", rlib.display());
+        archive::replace_members(&toolchain, rlib, rlib, &replacements)?;
+    }
+
+    Ok(())
+}
+
+/// Integrates the object files of the crate, then runs the real linker.
+fn link(linker: OsString) -> CIResult<()> {
+    let mut args = std::env::args_os().skip(1).collect::<Vec<_>>();
+    let opt_level = std::env::var(OPT_LEVEL_ENV).unwrap_or_else(|_| "0".to_string());
+    let target = std::env::var(TARGET_ENV).ok();
+
+    let objects = args
+        .iter()
+        .enumerate()
+        .filter(|(_, arg)| {
+            let arg = arg.to_string_lossy();
+            arg.ends_with(".rcgu.o") && !arg.contains("-ci")
+        })
+        .map(|(idx, arg)| (idx, PathBuf::from(arg)))
+        .filter(|(_, object)| object.with_extension("ll").is_file())
+        .collect::<Vec<_>>();
+
+    if !objects.is_empty() {
+        let (config, toolchain) = setup()?;
+        let opt_level = config.llc_opt_level.clone().unwrap_or(opt_level);
+        let llc_flags = pipeline::llc_flags(&config, &opt_level, target.as_deref());
+        for (idx, object) in objects {
+            if let Some(ci_object) = integrate_object(&config, &toolchain, &llc_flags, &object)? {
+                args[idx] = ci_object.into_os_string();
+            }
+        }
+    }
+
+    ProcessBuilder::new(linker).args(&args).exec_replace()
+}
+
+/// Gets the crates to skip the integration from `CARGO_CI_SKIP`, or the configuration
+/// of the project if not set.
+fn skip_crates() -> CIResult<Vec<String>> {
+    let skip_crates = wrapper::skip_crates();
+    if !skip_crates.is_empty() {
+        return Ok(skip_crates);
+    }
+    Ok(Config::load()?.skip_crates)
+}
+
+/// Loads the configuration and the toolchain for the integration.
+fn setup() -> CIResult<(Config, LlvmToolchain)> {
+    let mut config = Config::load()?;
+    let toolchain = llvm::select(&mut config)?;
+    if !config.library_path.is_file() {
+        bail!(CIError::LibraryNotInstalled);
+    }
+    // `cargo` captures the output of `rustc`, so this never prompts
+    library::check_llvm_version(&mut config, &toolchain, false)?;
+    Ok((config, toolchain))
+}
+
+/// Integrates the LLVM IR file of the object file, returning the CI-integrated object
+/// file, or `None` if the object file is not to be integrated.
+fn integrate_object(
+    config: &Config,
+    toolchain: &LlvmToolchain,
+    llc_flags: &[String],
+    object: &Path,
+) -> CIResult<Option<PathBuf>> {
+    let file = object.with_extension("ll");
+    if !file.is_file() {
+        return Ok(None);
+    }
+
+    // the allocator shim and the crate defining the CI handler are not integrated
+    let symbols = symbols::analyze_file(object)?;
+    if symbols.allocator || symbols.ci_hook {
+        debug!("integration skipped: {}", object.display());
+        return Ok(None);
+    }
+
+    let plugins = pipeline::pass_plugins(config, &pipeline::crate_name(&file)?);
+    if plugins.is_empty() {
+        debug!("integration skipped by the plugins: {}", object.display());
+        return Ok(None);
+    }
+
+    info!("integrating: {}", file.display());
+    let ci_file = file.append_suffix("ci")?;
+    if ci_file.is_file() {
+        paths::remove_file(&ci_file)?;
+    }
+    let pass_manager = config.pass_manager(toolchain);
+    for opt in pipeline::opt_commands(toolchain, pass_manager, &plugins, &file, &ci_file)? {
+        opt.exec_with_output()?;
+    }
+
+    let ci_object = ci_file.with_extension("o");
+    LlvmUtility::StaticCompiler
+        .process_builder(toolchain)
+        .args(llc_flags)
+        .arg(&ci_file)
+        .arg("-o")
+        .arg(&ci_object)
+        .exec_with_output()?;
+
+    Ok(Some(ci_object))
+}
@@ -0,0 +1,296 @@
+//! Implementation of `cargo-test-ci`.
+//!
+//! `cargo test --no-run` builds the test harnesses, which are integrated and relinked
+//! like the binaries of `cargo-build-ci`, then run with the arguments for libtest.
+//! `cargo-bench-ci` runs the benchmark harnesses the same way.
+//!
+//! With `--junit` or `--json`, the output of the test harnesses is also parsed into a
+//! report of their results by [`compiler_interrupts_core::libtest`]. With `--archive`,
+//! the integrated harnesses are written to a `cargo-nextest` archive by
+//! [`compiler_interrupts_core::nextest`] instead of being run.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Context};
+use cargo_util::paths;
+use clap::{Command, FromArgMatches};
+use colored::Colorize;
+use compiler_interrupts_core::libtest::{TestReport, TestSuite};
+use compiler_interrupts_core::nextest::{
+    self, ArchiveWriter, BinaryList, RustBuildMeta, RustTestBinary,
+};
+use tracing::{debug, info, warn};
+
+use crate::args::{self, BuildArgs, TestArgs};
+use crate::cargo::{self, Cargo, CargoCommand};
+use crate::error::CIError;
+use crate::ops::{build, run};
+use crate::paths::PathExt;
+use crate::{wrapper, CIResult, TEST_CI_BIN_NAME};
+
+/// Main routine for `cargo-test-ci`.
+pub fn exec() -> CIResult<()> {
+    if wrapper::is_wrapper() {
+        return wrapper::exec();
+    }
+
+    run_harnesses(TEST_CI_BIN_NAME, args::test_command(), CargoCommand::Test)
+}
+
+/// Builds the harnesses with the subcommand of `cargo`, integrates them, and runs them
+/// with the arguments after the second `--`.
+#[allow(clippy::print_stdout)]
+pub(crate) fn run_harnesses(
+    bin_name: &str,
+    command: Command,
+    cargo_command: CargoCommand,
+) -> CIResult<()> {
+    let argv = if std::env::args().next().unwrap_or_default() == bin_name {
+        std::env::args_os().collect::<Vec<_>>()
+    } else {
+        std::env::args_os().skip(1).collect::<Vec<OsString>>()
+    };
+    let matches = command.get_matches_from(argv);
+    let mut args = BuildArgs::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    // only `cargo-test-ci` has the options of the reports and the archive
+    let report_args = match cargo_command {
+        CargoCommand::Test => {
+            TestArgs::from_arg_matches(&matches).unwrap_or_else(|error| error.exit())
+        }
+        _ => TestArgs::default(),
+    };
+    if let Some(path) = &report_args.archive {
+        if !path.to_string_lossy().ends_with(nextest::ARCHIVE_EXTENSION) {
+            bail!(
+                "archive `{}` is not a `{}` file",
+                path.display(),
+                nextest::ARCHIVE_EXTENSION
+            );
+        }
+    }
+
+    // `-- <CARGO_TEST_ARGS>... -- <TEST_ARGS>...`, or the same for `cargo bench`
+    let test_args = match args.cargo_args.iter().position(|arg| arg == "--") {
+        Some(idx) => {
+            let test_args = args.cargo_args.split_off(idx + 1);
+            args.cargo_args.pop();
+            test_args
+        }
+        None => Vec::new(),
+    };
+    let no_fail_fast = args.cargo_args.iter().any(|arg| arg == "--no-fail-fast");
+
+    let binaries = build::run(&args, cargo_command)?;
+
+    // the binaries of the package are also built for its integration tests, but only
+    // the test harnesses are left in `deps`
+    let mut tests = binaries
+        .into_iter()
+        .filter(|binary| {
+            binary
+                .parent()
+                .and_then(|dir| dir.file_name())
+                .is_ok_and(|name| name == "deps")
+        })
+        .collect::<Vec<_>>();
+    tests.sort();
+    debug!(?tests);
+    if tests.is_empty() {
+        bail!(CIError::IntegratedBinaryNotFound);
+    }
+
+    if let Some(path) = &report_args.archive {
+        return write_archive(path, &args.cargo_args, &tests);
+    }
+
+    let members = cargo::members()?;
+    let runner = Cargo::with_args(args.cargo_args.clone()).runner();
+    let current_dir = std::env::current_dir()?;
+    let mut failures = Vec::<PathBuf>::new();
+    let mut report = TestReport::default();
+    for test in tests {
+        let display = test.strip_prefix(&current_dir).unwrap_or(&test);
+        eprintln!("{:>12} {}", "Running".green().bold(), display.display());
+
+        let mut cmd = run::command(runner.as_deref(), &test);
+        // tests run in the directory of their package like `cargo test` runs them
+        if let Some(member) = members.iter().find(|member| member.owns(&test)) {
+            cmd.cwd(&member.manifest_dir);
+            cmd.env("CARGO_MANIFEST_DIR", &member.manifest_dir);
+        }
+        cmd.args(cargo_command.harness_args());
+        cmd.args(&test_args);
+
+        let result = if report_args.junit.is_some() || report_args.json.is_some() {
+            // the results are parsed from the output printed by the harness
+            let mut lines = Vec::new();
+            let result = cmd.exec_with_streaming(
+                &mut |out| {
+                    println!("{}", out);
+                    lines.push(out.to_string());
+                    Ok(())
+                },
+                &mut |err| {
+                    eprintln!("{}", err);
+                    Ok(())
+                },
+                false,
+            );
+            report.suites.push(TestSuite::parse_harness(&test, &lines)?);
+            result.map(drop)
+        } else {
+            cmd.exec()
+        };
+
+        if let Err(error) = result {
+            if !no_fail_fast {
+                write_reports(&report_args, &report)?;
+                return Err(error.context(format!("harness failed: `{}`", display.display())));
+            }
+            failures.push(display.to_path_buf());
+        }
+    }
+    write_reports(&report_args, &report)?;
+
+    if !failures.is_empty() {
+        bail!(
+            "{} harnesses failed: {}",
+            failures.len(),
+            failures
+                .iter()
+                .map(|test| format!("`{}`", test.display()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Writes the reports of the test results to the files of the arguments.
+fn write_reports(report_args: &TestArgs, report: &TestReport) -> CIResult<()> {
+    if let Some(path) = &report_args.junit {
+        info!("writing JUnit report to: {}", path.display());
+        paths::write(path, report.to_junit())
+            .with_context(|| format!("failed to write the JUnit report `{}`", path.display()))?;
+    }
+    if let Some(path) = &report_args.json {
+        info!("writing JSON report to: {}", path.display());
+        paths::write(path, report.to_json()?)
+            .with_context(|| format!("failed to write the JSON report `{}`", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Writes a `cargo-nextest` archive of the integrated harnesses to the file.
+fn write_archive(path: &Path, cargo_args: &[String], tests: &[PathBuf]) -> CIResult<()> {
+    let cargo_metadata = cargo::metadata()?;
+    let metadata = serde_json::from_slice::<serde_json::Value>(&cargo_metadata)
+        .context("failed to parse the metadata of the package")?;
+    let target_dir = metadata["target_directory"]
+        .as_str()
+        .map(PathBuf::from)
+        .context("failed to get the target directory of the package")?;
+
+    let mut binary_list = BinaryList {
+        rust_build_meta: RustBuildMeta {
+            target_directory: target_dir.clone(),
+            target_platform: Cargo::with_args(cargo_args.to_vec()).target(),
+            ..RustBuildMeta::default()
+        },
+        ..BinaryList::default()
+    };
+    for test in tests {
+        // the dep-info of the original harness, `deps/<crate>-<hash>.d`
+        let stem = test.file_stem()?;
+        let crate_root = stem
+            .strip_suffix("-ci")
+            .and_then(|stem| fs::read_to_string(test.with_file_name(format!("{}.d", stem))).ok())
+            .and_then(|dep_info| nextest::crate_root(&dep_info));
+        let binary = crate_root
+            .and_then(|crate_root| RustTestBinary::find(&metadata, &crate_root, test.clone()));
+        let binary = match binary {
+            Some(binary) => binary,
+            None => {
+                warn!(
+                    "harness is not a target of the workspace: {}",
+                    test.display()
+                );
+                continue;
+            }
+        };
+        // `<target_dir>/<base_dir>/deps/<harness>`
+        if let Ok(base_dir) = test.parent()?.parent()?.strip_prefix(&target_dir) {
+            binary_list
+                .rust_build_meta
+                .base_output_directories
+                .insert(base_dir.to_path_buf());
+        }
+        binary_list
+            .rust_binaries
+            .insert(binary.binary_id.clone(), binary);
+    }
+    debug!(?binary_list);
+    if binary_list.rust_binaries.is_empty() {
+        bail!(CIError::IntegratedBinaryNotFound);
+    }
+    let meta = &mut binary_list.rust_build_meta;
+    for base_dir in &meta.base_output_directories {
+        for (package_id, binaries) in nextest::non_test_binaries(&metadata, &target_dir, base_dir) {
+            meta.non_test_binaries
+                .entry(package_id)
+                .or_default()
+                .extend(binaries);
+        }
+    }
+
+    info!("writing nextest archive to: {}", path.display());
+    let file =
+        File::create(path).with_context(|| format!("failed to create `{}`", path.display()))?;
+    let mut archive = ArchiveWriter::new(zstd::stream::write::Encoder::new(file, 0)?);
+    archive.append_data(nextest::CARGO_METADATA_PATH, &cargo_metadata)?;
+    archive.append_data(
+        nextest::BINARIES_METADATA_PATH,
+        serde_json::to_string_pretty(&binary_list)?.as_bytes(),
+    )?;
+    for binary in binary_list.rust_binaries.values() {
+        let relative_path = binary
+            .binary_path
+            .strip_prefix(&target_dir)
+            .with_context(|| {
+                format!(
+                    "harness is not in the target directory: {}",
+                    binary.binary_path.display()
+                )
+            })?;
+        archive.append_file(&archive_path(relative_path), &binary.binary_path)?;
+    }
+    let non_test_binaries = binary_list.rust_build_meta.non_test_binaries.values();
+    for binary in non_test_binaries.flatten() {
+        archive.append_file(&archive_path(&binary.path), &target_dir.join(&binary.path))?;
+    }
+    archive.finish()?.finish()?;
+
+    eprintln!(
+        "{:>12} {} harnesses to {}",
+        "Archived".green().bold(),
+        binary_list.rust_binaries.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Gets the path in the archive of the file at the path relative to the target
+/// directory, with `/` separators on every host.
+fn archive_path(relative_path: &Path) -> String {
+    let mut path = nextest::TARGET_DIR.to_string();
+    for component in relative_path.components() {
+        if let Component::Normal(name) = component {
+            path.push('/');
+            path.push_str(&name.to_string_lossy());
+        }
+    }
+    path
+}
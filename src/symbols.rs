@@ -0,0 +1,21 @@
+//! Symbol analysis of object files, shared with the daemon.
+
+use std::path::Path;
+
+use crate::{daemon, CIResult};
+
+pub use compiler_interrupts_core::symbols::{analyze_file, SymbolCache};
+
+/// Loads the saved analysis from the daemon if running, or from the file.
+pub fn load<P: AsRef<Path>>(path: P) -> SymbolCache {
+    let path = path.as_ref();
+    SymbolCache::load(path, daemon::symbols(path))
+}
+
+/// Saves the analysis if changed, also to the daemon if running.
+pub fn save(symbols: &SymbolCache) -> CIResult<()> {
+    if let Some(s) = symbols.save()? {
+        daemon::save_symbols(symbols.path(), s);
+    }
+    Ok(())
+}
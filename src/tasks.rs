@@ -0,0 +1,34 @@
+//! Cancellation of the tasks by Ctrl-C.
+//!
+//! The tasks of the integration run in the [`TaskGroup`]s of
+//! [`compiler_interrupts_core::tasks`], cancelled by the [`interrupt`] token.
+
+use std::sync::OnceLock;
+
+use anyhow::Context;
+
+use crate::error::CIError;
+use crate::CIResult;
+
+pub use compiler_interrupts_core::tasks::{CancellationToken, TaskGroup};
+
+/// Gets the token cancelled by Ctrl-C once [`handle_interrupt`] is called.
+pub fn interrupt() -> &'static CancellationToken {
+    static TOKEN: OnceLock<CancellationToken> = OnceLock::new();
+    TOKEN.get_or_init(CancellationToken::new)
+}
+
+/// Cancels the [`interrupt`] token on Ctrl-C instead of terminating the process, so
+/// that the tasks are stopped and the build reports the interruption.
+///
+/// Ctrl-C on an already cancelled token terminates the process right away.
+pub fn handle_interrupt() -> CIResult<()> {
+    ctrlc::set_handler(|| {
+        let token = interrupt();
+        if token.is_cancelled() {
+            std::process::exit(CIError::Cancelled.exit_code().into());
+        }
+        token.cancel();
+    })
+    .context("failed to set the Ctrl-C handler")
+}
@@ -1,41 +1,208 @@
 //! Miscellaneous utilities.
 
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::{Mutex, OnceLock, PoisonError};
 
 use anyhow::Context;
+use cargo_util::paths;
 use tracing::{debug, info, Level};
-use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::filter::{self, LevelFilter};
+use tracing_subscriber::prelude::*;
 
+use crate::config::{Config, DEFAULT_LOG_FILE};
 use crate::{cargo, CIResult};
 
+pub use compiler_interrupts_core::util::{
+    human_duration, human_size, is_only, is_skipped, parse_duration,
+};
+
+/// Size of the log file from which it is rotated, 10 MiB.
+const LOG_FILE_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Number of the rotated log files kept.
+const LOG_FILE_ROTATIONS: usize = 5;
+
+/// Log file opened by [`init_log_file`] or [`init_config_log_file`].
+static LOG_FILE: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Initializes the terminal coloring.
+///
+/// With `auto`, colors are disabled if `NO_COLOR` is set or the output is not a terminal,
+/// and `CARGO_TERM_COLOR` is honored otherwise.
+pub fn init_color(when: &str) {
+    let enabled = match when {
+        "always" => true,
+        "never" => false,
+        _ => {
+            let no_color = matches!(std::env::var_os("NO_COLOR"), Some(s) if !s.is_empty());
+            match std::env::var("CARGO_TERM_COLOR").as_deref() {
+                _ if no_color => false,
+                Ok("always") => true,
+                Ok("never") => false,
+                _ => console::user_attended() && console::user_attended_stderr(),
+            }
+        }
+    };
+    debug!("colors enabled: {}", enabled);
+
+    colored::control::set_override(enabled);
+    console::set_colors_enabled(enabled);
+    console::set_colors_enabled_stderr(enabled);
+}
+
+/// Returns true if colors are enabled.
+pub fn colors_enabled() -> bool {
+    colored::control::SHOULD_COLORIZE.should_colorize()
+}
+
+/// Returns true if the progress bar can be rendered.
+pub fn progress_enabled() -> bool {
+    console::user_attended_stderr()
+}
+
+/// Asks the question on the terminal, returning false if not answered with yes or if
+/// the user is not attended.
+pub fn confirm(question: &str) -> CIResult<bool> {
+    let term = console::Term::stderr();
+    if !console::user_attended() || !term.is_term() {
+        return Ok(false);
+    }
+    term.write_str(&format!("{} [y/N] ", question))?;
+    let answer = term.read_line()?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
 /// Initializes the logger.
+///
+/// The logs are also written to the log file once opened by [`init_log_file`] or
+/// [`init_config_log_file`], at least from the `info` level.
 pub fn init_logger(level: &String) -> CIResult<()> {
     info!("initializing logger with log level: {}", level);
 
     let level = Level::from_str(level)?;
 
-    let builder = tracing_subscriber::fmt()
+    // the standard output is kept for the JSON messages and the output of the binaries
+    let stderr = tracing_subscriber::fmt::layer()
         .with_target(false)
         .with_level(true)
-        .with_max_level(level);
-
-    if level <= Level::WARN {
-        builder
+        .with_ansi(colors_enabled())
+        .with_writer(io::stderr);
+    let stderr = if level <= Level::WARN {
+        stderr
             .without_time()
-            .finish()
-            .try_init()
-            .context("failed to initialize the logger")?;
+            .with_filter(LevelFilter::from_level(level))
+            .boxed()
     } else {
-        builder
+        stderr
             .with_timer(tracing_subscriber::fmt::time::uptime())
-            .finish()
-            .try_init()
-            .context("failed to initialize the logger")?;
+            .with_filter(LevelFilter::from_level(level))
+            .boxed()
+    };
+
+    // the logs of the past runs are kept for the post-mortem debugging
+    let file_level = level.max(Level::INFO);
+    let file = tracing_subscriber::fmt::layer()
+        .with_target(false)
+        .with_level(true)
+        .with_ansi(false)
+        .with_writer(|| LogFileWriter)
+        .with_filter(
+            filter::filter_fn(move |metadata| {
+                LOG_FILE.get().is_some() && *metadata.level() <= file_level
+            })
+            .with_max_level_hint(file_level),
+        );
+
+    tracing_subscriber::registry()
+        .with(stderr)
+        .with(file)
+        .try_init()
+        .context("failed to initialize the logger")?;
+
+    Ok(())
+}
+
+/// Opens the log file of `--log-file`, relative to the current directory, or the
+/// default one in the configuration directory if no path is given.
+pub fn init_log_file(path: Option<&Path>) -> CIResult<()> {
+    match path {
+        Some(path) => open_log_file(path),
+        None => open_log_file(&Config::dir()?.join(DEFAULT_LOG_FILE)),
+    }
+}
+
+/// Opens the log file of the configuration, relative to the configuration directory,
+/// unless a log file is already opened by `--log-file`.
+pub fn init_config_log_file(config: &Config) -> CIResult<()> {
+    match &config.log_file {
+        Some(path) => open_log_file(&Config::dir()?.join(path)),
+        None => Ok(()),
     }
+}
 
+/// Opens the log file to append the logs to, rotating it first if it reached
+/// [`LOG_FILE_MAX_SIZE`], and writes the command line of the process.
+fn open_log_file(path: &Path) -> CIResult<()> {
+    if LOG_FILE.get().is_some() {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        paths::create_dir_all(dir)?;
+    }
+    if crate::paths::rotate(path, LOG_FILE_MAX_SIZE, LOG_FILE_ROTATIONS)? {
+        debug!("rotated the log file: {}", path.display());
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("failed to open the log file `{}`", path.display()))?;
+    // the processes of several commands may write to the same file
+    let header = format!(
+        "\n==> {} [{}] {}\n",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        std::process::id(),
+        std::env::args().collect::<Vec<_>>().join(" ")
+    );
+    file.write_all(header.as_bytes())
+        .with_context(|| format!("failed to write the log file `{}`", path.display()))?;
+    let _ = LOG_FILE.set(Mutex::new(file));
+    info!("logging to file: {}", path.display());
     Ok(())
 }
 
+/// Writes the error ending the command to the log file if opened, as it is printed to
+/// the standard error without the logger.
+pub fn log_error_to_file(error: &anyhow::Error) {
+    let line = format!(
+        "{}  ERROR {:?}\n",
+        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.6fZ"),
+        error
+    );
+    let _ = LogFileWriter.write_all(line.as_bytes());
+}
+
+/// Writer of the log file, discarding the logs until the file is opened.
+struct LogFileWriter;
+
+impl Write for LogFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(file) = LOG_FILE.get() {
+            // each event is written at once, not interleaved with the other processes
+            let mut file = file.lock().unwrap_or_else(PoisonError::into_inner);
+            file.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 /// Sets the current directory to the root directory of the workspace.
 pub fn set_current_workspace_root_dir() -> CIResult<()> {
     let root_dir = cargo::locate_project()?;
@@ -50,13 +217,3 @@ pub fn set_current_workspace_root_dir() -> CIResult<()> {
 
     Ok(())
 }
-
-/// Gets a human readable String for Duration.
-pub fn human_duration(duration: std::time::Duration) -> String {
-    let secs = duration.as_secs();
-    if secs >= 60 {
-        format!("{}m {:02}s", secs / 60, secs % 60)
-    } else {
-        format!("{}.{:02}s", secs, duration.subsec_nanos() / 10_000_000)
-    }
-}
@@ -0,0 +1,105 @@
+//! Watches the files of the workspace to integrate again on changes.
+
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use colored::Colorize;
+use notify::event::EventKind;
+use notify::{Event, RecursiveMode, Watcher};
+use tracing::debug;
+
+use crate::{tasks, CIResult};
+
+/// Delay to coalesce the changes of a save, e.g. the temporary files of an editor.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Interval to check for Ctrl-C while waiting for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Maximum number of changed files printed before a build.
+const CHANGED_ROWS: usize = 5;
+
+/// Runs the build, then again whenever a file of the workspace changes, until
+/// interrupted by Ctrl-C.
+///
+/// A failed build is reported and waits for the next change, unless interrupted.
+pub fn watch<F>(target_dir: &Path, mut build: F) -> CIResult<()>
+where
+    F: FnMut() -> CIResult<()>,
+{
+    let root_dir = std::env::current_dir()?;
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("failed to watch the files")?;
+    watcher
+        .watch(&root_dir, RecursiveMode::Recursive)
+        .with_context(|| format!("failed to watch `{}`", root_dir.display()))?;
+
+    let token = tasks::interrupt();
+    loop {
+        if let Err(error) = build() {
+            if token.is_cancelled() {
+                return Err(error);
+            }
+            crate::print_error(&error);
+        }
+        eprintln!(
+            "{:>12} for changes, press Ctrl-C to stop",
+            "Watching".cyan().bold()
+        );
+
+        let mut changed = Vec::new();
+        while changed.is_empty() {
+            match rx.recv_timeout(POLL_INTERVAL) {
+                Ok(event) => changed.extend(changed_files(event?, &root_dir, target_dir)),
+                Err(RecvTimeoutError::Timeout) => token.check()?,
+                Err(RecvTimeoutError::Disconnected) => bail!("the watcher of the files stopped"),
+            }
+        }
+        std::thread::sleep(DEBOUNCE);
+        for event in rx.try_iter() {
+            changed.extend(changed_files(event?, &root_dir, target_dir));
+        }
+        changed.sort();
+        changed.dedup();
+        debug!(?changed);
+
+        for path in changed.iter().take(CHANGED_ROWS) {
+            let display = path.strip_prefix(&root_dir).unwrap_or(path);
+            eprintln!("{:>12} {}", "Changed".cyan().bold(), display.display());
+        }
+        if changed.len() > CHANGED_ROWS {
+            eprintln!(
+                "{:>12} and {} other files",
+                "Changed".cyan().bold(),
+                changed.len() - CHANGED_ROWS
+            );
+        }
+    }
+}
+
+/// Gets the files changed by the event, without the target directory, the hidden
+/// files and directories, e.g. `.git`, and the backup files of the editors.
+fn changed_files(event: Event, root_dir: &Path, target_dir: &Path) -> Vec<PathBuf> {
+    // reading the files does not change them
+    if matches!(event.kind, EventKind::Access(_)) {
+        return Vec::new();
+    }
+    event
+        .paths
+        .into_iter()
+        .filter(|path| !path.starts_with(target_dir))
+        .filter(|path| {
+            !path
+                .strip_prefix(root_dir)
+                .unwrap_or(path)
+                .components()
+                .any(|component| match component {
+                    Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+                    _ => false,
+                })
+        })
+        .filter(|path| !path.to_string_lossy().ends_with('~'))
+        .collect()
+}
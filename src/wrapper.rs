@@ -0,0 +1,275 @@
+//! `RUSTC_WRAPPER` emitting LLVM IR only for the crates to be integrated.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use cargo_util::ProcessBuilder;
+
+use crate::{
+    capture, util, CIResult, BENCH_CI_BIN_NAME, BUILD_CI_BIN_NAME, RUN_CI_BIN_NAME,
+    TEST_CI_BIN_NAME,
+};
+
+pub use compiler_interrupts_core::cargo::llvm_ir_extension;
+
+/// Set when `cargo-build-ci` is invoked by `cargo` as `RUSTC_WRAPPER`.
+const WRAPPER_ENV: &str = "CARGO_CI_WRAPPER";
+
+/// Space-delimited crates to skip the integration.
+const SKIP_ENV: &str = "CARGO_CI_SKIP";
+
+/// Space-delimited crates to integrate exclusively.
+const ONLY_ENV: &str = "CARGO_CI_ONLY";
+
+/// `RUSTC_WRAPPER` set by the user, which is run by the wrapper.
+const USER_WRAPPER_ENV: &str = "CARGO_CI_USER_WRAPPER";
+
+/// Flags of the driver backend delimited by `0x1f`, like `CARGO_ENCODED_RUSTFLAGS`.
+const DRIVER_FLAGS_ENV: &str = "CARGO_CI_DRIVER_FLAGS";
+
+/// Set when the crates emit LLVM bitcode instead of textual LLVM IR.
+const BITCODE_ENV: &str = "CARGO_CI_BITCODE";
+
+/// Flags for `rustc` to emit LLVM IR and keep the intermediate files.
+///
+/// `--emit=llvm-ir` to emit textual LLVM IR
+/// `-C save-temps` to save temporary files during the compilation
+/// <https://doc.rust-lang.org/rustc/codegen-options/index.html>
+pub const EMIT_FLAGS: [&str; 2] = ["--emit=llvm-ir", "-Csave-temps"];
+
+/// Flags for `rustc` to emit LLVM bitcode and keep the intermediate files.
+pub const BITCODE_EMIT_FLAGS: [&str; 2] = ["--emit=llvm-bc", "-Csave-temps"];
+
+/// Gets the flags for `rustc` to emit LLVM IR, or LLVM bitcode.
+pub fn emit_flags(bitcode: bool) -> [&'static str; 2] {
+    if bitcode {
+        BITCODE_EMIT_FLAGS
+    } else {
+        EMIT_FLAGS
+    }
+}
+
+/// Returns true if the wrapper emits LLVM bitcode.
+pub fn is_bitcode() -> bool {
+    std::env::var_os(BITCODE_ENV).is_some()
+}
+
+/// Returns true if invoked as `RUSTC_WRAPPER`.
+pub fn is_wrapper() -> bool {
+    std::env::var_os(WRAPPER_ENV).is_some()
+}
+
+/// Sets the executable of `cargo-build-ci` as `RUSTC_WRAPPER` of the `cargo` command:
+/// the given one, the current executable, or `cargo-build-ci` in `PATH` if embedded in
+/// another program.
+///
+/// The driver flags are added instead of emitting LLVM IR if not empty.
+pub fn configure(
+    cmd: &mut ProcessBuilder,
+    exe: Option<&Path>,
+    skip_crates: &[String],
+    only_crates: &[String],
+    driver_flags: &[String],
+    bitcode: bool,
+) -> CIResult<()> {
+    let current_exe = std::env::current_exe().context("failed to get the current executable")?;
+    let bin_names = [
+        BUILD_CI_BIN_NAME,
+        RUN_CI_BIN_NAME,
+        TEST_CI_BIN_NAME,
+        BENCH_CI_BIN_NAME,
+    ];
+    let exe = match (exe, current_exe.file_stem().and_then(|stem| stem.to_str())) {
+        (Some(exe), _) => exe.to_path_buf(),
+        (None, Some(stem)) if bin_names.contains(&stem) => current_exe,
+        _ => PathBuf::from(BUILD_CI_BIN_NAME),
+    };
+    if let Some(user_wrapper) = std::env::var_os("RUSTC_WRAPPER").filter(|s| !s.is_empty()) {
+        cmd.env(USER_WRAPPER_ENV, user_wrapper);
+    }
+    cmd.env("RUSTC_WRAPPER", exe);
+    cmd.env(WRAPPER_ENV, "1");
+    cmd.env(SKIP_ENV, skip_crates.join(" "));
+    cmd.env(ONLY_ENV, only_crates.join(" "));
+    if !driver_flags.is_empty() {
+        cmd.env(DRIVER_FLAGS_ENV, driver_flags.join("\x1f"));
+    }
+    if bitcode {
+        cmd.env(BITCODE_ENV, "1");
+    }
+    Ok(())
+}
+
+/// Gets `rustc` and its arguments given by `cargo` to the wrapper.
+pub fn rustc_args() -> CIResult<(OsString, Vec<OsString>)> {
+    // `$RUSTC_WRAPPER $RUSTC <args>...`
+    let mut args = std::env::args_os().skip(1);
+    let rustc = args
+        .next()
+        .context("missing rustc in the wrapper arguments")?;
+    Ok((rustc, args.collect()))
+}
+
+/// Gets the value of the `rustc` option, e.g. `--crate-name`.
+pub fn option<'a>(args: &'a [OsString], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|idx| args.get(idx + 1))
+        .and_then(|value| value.to_str())
+}
+
+/// Returns true if the `rustc` invocation compiles a target only run on the host, i.e.
+/// a procedural macro by its crate type, or a build script.
+///
+/// The kinds of the targets are not given to the wrapper, so a build script is the
+/// `build-script-<name>` target that `cargo` names after its file, compiled to the
+/// directory of its unit in `build`, e.g. `target/debug/build/demo-0123456789abcdef`.
+pub fn is_host_only(args: &[OsString]) -> bool {
+    let proc_macro = args
+        .windows(2)
+        .any(|pair| pair[0] == "--crate-type" && pair[1] == "proc-macro");
+    let build_script = option(args, "--crate-name")
+        .is_some_and(|name| name.starts_with("build_script_"))
+        && option(args, "--out-dir").is_some_and(|dir| {
+            Path::new(dir)
+                .parent()
+                .and_then(Path::file_name)
+                .is_some_and(|parent| parent == "build")
+        });
+    proc_macro || build_script
+}
+
+/// Gets the value of the codegen option, e.g. `opt-level` of `-C opt-level=3`.
+pub fn codegen_option(args: &[OsString], name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
+    let mut value = None;
+    let mut iter = args.iter().filter_map(|arg| arg.to_str());
+    while let Some(arg) = iter.next() {
+        let option = match arg {
+            "-C" | "--codegen" => iter.next(),
+            arg => arg.strip_prefix("-C"),
+        };
+        // the last one takes effect
+        if let Some(v) = option.and_then(|option| option.strip_prefix(&prefix)) {
+            value = Some(v.to_string());
+        }
+    }
+    value
+}
+
+/// Removes the codegen option from the arguments.
+pub fn without_codegen_option(args: &[OsString], name: &str) -> Vec<OsString> {
+    let prefix = format!("{}=", name);
+    let mut result = Vec::with_capacity(args.len());
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.to_str() {
+            Some("-C" | "--codegen") => {
+                let next = iter.next();
+                match next.and_then(|next| next.to_str()) {
+                    Some(option) if option.starts_with(&prefix) => {}
+                    _ => {
+                        result.push(arg.clone());
+                        result.extend(next.cloned());
+                    }
+                }
+            }
+            Some(option)
+                if option
+                    .strip_prefix("-C")
+                    .is_some_and(|o| o.starts_with(&prefix)) => {}
+            _ => result.push(arg.clone()),
+        }
+    }
+    result
+}
+
+/// Gets the crates to skip the integration.
+pub fn skip_crates() -> Vec<String> {
+    crates(SKIP_ENV)
+}
+
+/// Gets the crates to integrate exclusively, or none to integrate all of them.
+pub fn only_crates() -> Vec<String> {
+    crates(ONLY_ENV)
+}
+
+/// Gets the space-delimited crates of the environment variable.
+fn crates(key: &str) -> Vec<String> {
+    std::env::var(key)
+        .unwrap_or_default()
+        .split_ascii_whitespace()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Creates the `rustc` command, run by the `RUSTC_WRAPPER` set by the user if any.
+pub fn rustc_command(rustc: OsString) -> ProcessBuilder {
+    match std::env::var_os(USER_WRAPPER_ENV) {
+        Some(user_wrapper) => {
+            let mut cmd = ProcessBuilder::new(user_wrapper);
+            cmd.arg(rustc);
+            cmd
+        }
+        None => ProcessBuilder::new(rustc),
+    }
+}
+
+/// Runs `rustc` with the arguments given by `cargo`, adding the flags to emit
+/// LLVM IR, or the flags of the driver backend, unless the crate is skipped or not
+/// one of the only crates to integrate.
+///
+/// With the capture backend, the LLVM IR files and the linker invocations are
+/// recorded, and the wrapper is also run by `rustc` as the linker.
+pub fn exec() -> CIResult<()> {
+    if capture::is_linker() {
+        return capture::link();
+    }
+
+    let (rustc, args) = rustc_args()?;
+    let crate_name = option(&args, "--crate-name");
+    let skip_crates = skip_crates();
+    let only_crates = only_crates();
+    let (emit, skipped) = match crate_name {
+        // `___` is the crate name of the target information probe
+        Some("___") | None => (false, false),
+        _ if is_host_only(&args) => (false, false),
+        Some(name)
+            if util::is_skipped(&skip_crates, name) || !util::is_only(&only_crates, name) =>
+        {
+            (false, true)
+        }
+        Some(_) => (true, false),
+    };
+    let linked = args.iter().any(|arg| arg == "--test")
+        || args
+            .windows(2)
+            .any(|pair| pair[0] == "--crate-type" && (pair[1] == "bin" || pair[1] == "cdylib"));
+    let capture_dir = capture::capture_dir().filter(|_| emit || (skipped && linked));
+
+    let mut cmd = rustc_command(rustc);
+    if capture_dir.is_some() && linked {
+        capture::configure_linker(&mut cmd, &args)?;
+    } else {
+        cmd.args(&args);
+    }
+    match (emit, std::env::var(DRIVER_FLAGS_ENV).ok()) {
+        // the pass runs in the codegen of `rustc`
+        (true, Some(driver_flags)) => {
+            cmd.args(&driver_flags.split('\x1f').collect::<Vec<_>>());
+        }
+        (true, None) => {
+            cmd.args(&emit_flags(is_bitcode()).map(OsString::from));
+        }
+        // keep the object files of a skipped binary or C dynamic library to link it again
+        (false, None) if skipped && linked => {
+            cmd.arg("-Csave-temps");
+        }
+        _ => {}
+    }
+    match capture_dir {
+        Some(dir) if emit => capture::compile(&cmd, &args, &dir),
+        _ => cmd.exec_replace(),
+    }
+}
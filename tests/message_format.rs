@@ -0,0 +1,47 @@
+// `XDG_CONFIG_HOME` is only honored for the configuration directory on Linux
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Creates a package of a binary in a new temporary directory.
+fn package(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::write(
+        dir.join("Cargo.toml"),
+        format!(
+            "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n",
+            name
+        ),
+    )
+    .unwrap();
+    fs::write(dir.join("src").join("main.rs"), "fn main() {}\n").unwrap();
+    dir
+}
+
+#[test]
+fn json_messages_only_on_stdout() {
+    let dir = package("ci-json-messages");
+    // a new configuration directory, so that its creation is logged
+    let output = Command::new(env!("CARGO_BIN_EXE_cargo-build-ci"))
+        .args(["build-ci", "--message-format", "json", "--log", "debug"])
+        .current_dir(&dir)
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .output()
+        .unwrap();
+    let _ = fs::remove_dir_all(&dir);
+
+    // the logs and the diagnostics are on stderr, whether the build succeeds or not
+    assert!(!output.stderr.is_empty());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    for line in stdout.lines() {
+        assert!(
+            serde_json::from_str::<serde_json::Value>(line).is_ok(),
+            "not a JSON message: {}",
+            line
+        );
+    }
+}